@@ -0,0 +1,391 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::config::{self, Config};
+use crate::diff;
+use crate::errors::Result;
+use crate::ignore::{path_str, Glob};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AttrValue {
+  Set,           // attr
+  Unset,         // -attr
+  Value(String), // attr=value
+}
+
+// The attributes that apply to one path. Anything not in here is unspecified.
+#[derive(Debug, Default)]
+pub struct Attributes {
+  values: HashMap<String, AttrValue>,
+}
+
+// Every line from every attributes file that could apply, lowest precedence
+// first, so later lines override earlier ones.
+#[derive(Debug, Default)]
+pub struct AttributeList {
+  rules: Vec<Rule>,
+}
+
+// All the attributes for a work tree, with the config that goes with them:
+// what it takes to convert content going in and out of it. Load this once and
+// use it for every path; the .gitattributes in each directory only gets read
+// the first time we look at something under it.
+#[derive(Debug)]
+pub struct WorkspaceAttributes {
+  root:   PathBuf,
+  config: Config,
+  global: AttributeList, // core.attributesFile
+  info:   AttributeList, // info/attributes, which beats everything else
+  dirs:   RefCell<HashMap<PathBuf, AttributeList>>,
+}
+
+#[derive(Debug)]
+struct Rule {
+  glob:  Glob,
+  attrs: Vec<(String, Option<AttrValue>)>, // None is !attr: back to unspecified
+}
+
+// What we do with the content of a path, going in or out of the database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Content {
+  Text,
+  Binary,
+  Auto, // text, unless it looks binary
+}
+
+impl AttributeList {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  // Read the attributes file at path (which needn't exist), whose patterns
+  // apply to paths under base.
+  pub fn add_file(&mut self, path: &Path, base: &Path) -> Result<()> {
+    let text = match std::fs::read(path) {
+      Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+      Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+      Err(e) => return Err(e.into()),
+    };
+
+    let base = path_str(base);
+
+    for line in text.lines() {
+      if let Some(rule) = Rule::parse(line, &base) {
+        self.rules.push(rule);
+      }
+    }
+
+    Ok(())
+  }
+
+  // Override whatever's in attrs with the lines here that match path (which is
+  // relative to the top of the work tree).
+  fn apply(&self, path: &str, attrs: &mut Attributes) {
+    for rule in self.rules.iter().filter(|r| r.glob.matches(path, false)) {
+      for (name, value) in &rule.attrs {
+        match value {
+          Some(value) => attrs.values.insert(name.clone(), value.clone()),
+          None => attrs.values.remove(name),
+        };
+      }
+    }
+  }
+}
+
+impl WorkspaceAttributes {
+  pub fn load(root: &Path, git_dir: &Path, config: Config) -> Result<Self> {
+    let mut global = AttributeList::new();
+    if let Some(file) = attributes_file(&config) {
+      global.add_file(&file, Path::new(""))?;
+    }
+
+    let mut info = AttributeList::new();
+    info.add_file(&git_dir.join("info/attributes"), Path::new(""))?;
+
+    Ok(Self {
+      root: root.to_path_buf(),
+      config,
+      global,
+      info,
+      dirs: RefCell::new(HashMap::new()),
+    })
+  }
+
+  pub fn config(&self) -> &Config {
+    &self.config
+  }
+
+  // The attributes for path (relative to the top), from core.attributesFile,
+  // the .gitattributes files from the top down to path, then info/attributes.
+  pub fn for_path(&self, path: &Path) -> Result<Attributes> {
+    let name = path_str(path);
+    let mut attrs = Attributes::default();
+
+    self.global.apply(&name, &mut attrs);
+
+    let mut current = PathBuf::new();
+    let dirs = path.parent().map(|p| p.components()).into_iter().flatten();
+
+    self.apply_dir(&current, &name, &mut attrs)?;
+    for component in dirs {
+      current.push(component);
+      self.apply_dir(&current, &name, &mut attrs)?;
+    }
+
+    self.info.apply(&name, &mut attrs);
+
+    Ok(attrs)
+  }
+
+  fn apply_dir(
+    &self,
+    dir: &Path,
+    path: &str,
+    attrs: &mut Attributes,
+  ) -> Result<()> {
+    let mut dirs = self.dirs.borrow_mut();
+
+    if !dirs.contains_key(dir) {
+      let mut list = AttributeList::new();
+      list.add_file(&self.root.join(dir).join(".gitattributes"), dir)?;
+      dirs.insert(dir.to_path_buf(), list);
+    }
+
+    dirs[dir].apply(path, attrs);
+    Ok(())
+  }
+
+  // Read the .gitattributes in dir again next time, because it's changed.
+  pub fn forget(&self, dir: &Path) {
+    self.dirs.borrow_mut().remove(dir);
+  }
+
+  // The content we'd store for path, given what's in the work tree.
+  pub fn convert_to_git(&self, path: &Path, content: Vec<u8>) -> Result<Vec<u8>> {
+    Ok(self.for_path(path)?.convert_to_git(&self.config, content))
+  }
+
+  // What we'd put in the work tree at path, given what's stored.
+  pub fn convert_to_worktree(
+    &self,
+    path: &Path,
+    content: Vec<u8>,
+  ) -> Result<Vec<u8>> {
+    Ok(
+      self
+        .for_path(path)?
+        .convert_to_worktree(&self.config, content),
+    )
+  }
+}
+
+impl Rule {
+  // "pattern attr -attr !attr attr=value". Negated patterns aren't allowed,
+  // and we don't do macros other than the built-in "binary".
+  fn parse(line: &str, base: &str) -> Option<Self> {
+    let mut words = line.split_whitespace();
+    let pattern = words.next()?;
+
+    if pattern.starts_with('#') || pattern.starts_with('!') {
+      return None;
+    }
+
+    if pattern.starts_with("[attr]") {
+      return None;
+    }
+
+    let mut attrs = vec![];
+
+    for word in words {
+      let (name, value) = if let Some(name) = word.strip_prefix('-') {
+        (name, Some(AttrValue::Unset))
+      } else if let Some(name) = word.strip_prefix('!') {
+        (name, None)
+      } else if let Some((name, value)) = word.split_once('=') {
+        (name, Some(AttrValue::Value(value.to_string())))
+      } else {
+        (word, Some(AttrValue::Set))
+      };
+
+      if name == "binary" && value == Some(AttrValue::Set) {
+        for unset in &["diff", "merge", "text"] {
+          attrs.push((unset.to_string(), Some(AttrValue::Unset)));
+        }
+      }
+
+      attrs.push((name.to_string(), value));
+    }
+
+    Some(Rule {
+      glob: Glob::new(pattern, base)?,
+      attrs,
+    })
+  }
+}
+
+impl Attributes {
+  pub fn get(&self, name: &str) -> Option<&AttrValue> {
+    self.values.get(name)
+  }
+
+  fn value(&self, name: &str) -> Option<&str> {
+    match self.get(name) {
+      Some(AttrValue::Value(v)) => Some(v),
+      _ => None,
+    }
+  }
+
+  // Without a text attribute, an eol attribute means it's text, and otherwise
+  // core.autocrlf decides.
+  fn text(&self, config: &Config) -> Content {
+    match self.get("text") {
+      Some(AttrValue::Set) => Content::Text,
+      Some(AttrValue::Unset) => Content::Binary,
+      Some(AttrValue::Value(v)) if v == "auto" => Content::Auto,
+      _ if self.value("eol").is_some() => Content::Text,
+      _ => match config.get("core.autocrlf") {
+        Some("true") | Some("input") => Content::Auto,
+        _ => Content::Binary,
+      },
+    }
+  }
+
+  fn is_text(&self, config: &Config, content: &[u8]) -> bool {
+    match self.text(config) {
+      Content::Text => true,
+      Content::Binary => false,
+      Content::Auto => !diff::is_binary(content),
+    }
+  }
+
+  // Going into the database, text always has LF line endings.
+  pub fn convert_to_git(&self, config: &Config, content: Vec<u8>) -> Vec<u8> {
+    if !self.is_text(config, &content) || !content.contains(&b'\r') {
+      return content;
+    }
+
+    let mut out = Vec::with_capacity(content.len());
+    let mut bytes = content.iter().peekable();
+
+    while let Some(&b) = bytes.next() {
+      if b == b'\r' && bytes.peek() == Some(&&b'\n') {
+        continue;
+      }
+      out.push(b);
+    }
+
+    out
+  }
+
+  // Coming out, it gets CRLF if eol=crlf says so (or if the config does and
+  // the attributes don't say either way).
+  pub fn convert_to_worktree(
+    &self,
+    config: &Config,
+    content: Vec<u8>,
+  ) -> Vec<u8> {
+    let crlf = match self.value("eol") {
+      Some(eol) => eol == "crlf",
+      None => {
+        config.get("core.autocrlf") == Some("true")
+          || config.get("core.eol") == Some("crlf")
+      },
+    };
+
+    if !crlf || !self.is_text(config, &content) {
+      return content;
+    }
+
+    let mut out = Vec::with_capacity(content.len());
+    let mut prev = None;
+
+    for &b in &content {
+      if b == b'\n' && prev != Some(b'\r') {
+        out.push(b'\r');
+      }
+      out.push(b);
+      prev = Some(b);
+    }
+
+    out
+  }
+}
+
+// core.attributesFile, or git's default if that's not set.
+pub fn attributes_file(config: &Config) -> Option<PathBuf> {
+  if let Some(path) = config.get("core.attributesFile") {
+    return config::expand_path(path);
+  }
+
+  let xdg = std::env::var_os("XDG_CONFIG_HOME")
+    .map(PathBuf::from)
+    .or_else(|| std::env::var_os("HOME").map(|h| Path::new(&h).join(".config")));
+
+  xdg.map(|dir| dir.join("git/attributes"))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn list(lines: &[(&str, &str)]) -> AttributeList {
+    let mut list = AttributeList::new();
+    for (base, line) in lines {
+      list.rules.extend(Rule::parse(line, base));
+    }
+    list
+  }
+
+  fn for_path(list: &AttributeList, path: &str) -> Attributes {
+    let mut attrs = Attributes::default();
+    list.apply(path, &mut attrs);
+    attrs
+  }
+
+  #[test]
+  fn precedence() {
+    let l = list(&[
+      ("", "* text=auto"),
+      ("", "*.png binary"),
+      ("", "*.txt eol=crlf diff=words"),
+      ("docs", "*.txt -text !diff"),
+    ]);
+
+    let attrs = for_path(&l, "a.txt");
+    assert_eq!(attrs.get("text"), Some(&AttrValue::Value("auto".into())));
+    assert_eq!(attrs.get("eol"), Some(&AttrValue::Value("crlf".into())));
+    assert_eq!(attrs.get("diff"), Some(&AttrValue::Value("words".into())));
+
+    let attrs = for_path(&l, "docs/b.txt");
+    assert_eq!(attrs.get("text"), Some(&AttrValue::Unset));
+    assert_eq!(attrs.get("diff"), None);
+
+    let attrs = for_path(&l, "img/c.png");
+    assert_eq!(attrs.get("binary"), Some(&AttrValue::Set));
+    assert_eq!(attrs.get("diff"), Some(&AttrValue::Unset));
+    assert_eq!(attrs.get("text"), Some(&AttrValue::Unset));
+  }
+
+  #[test]
+  fn line_endings() {
+    let config = Config::default();
+    let l = list(&[("", "*.txt text eol=crlf"), ("", "*.bat text")]);
+
+    let attrs = for_path(&l, "a.txt");
+    let clean = attrs.convert_to_git(&config, b"one\r\ntwo\n".to_vec());
+    assert_eq!(clean, b"one\ntwo\n");
+
+    let smudged = attrs.convert_to_worktree(&config, clean);
+    assert_eq!(smudged, b"one\r\ntwo\r\n");
+
+    // text, but with no eol, comes out as it went in
+    let attrs = for_path(&l, "a.bat");
+    let clean = attrs.convert_to_git(&config, b"x\r\n".to_vec());
+    assert_eq!(attrs.convert_to_worktree(&config, clean), b"x\n");
+
+    // no attributes at all: leave it alone
+    let attrs = for_path(&l, "a.bin");
+    assert_eq!(attrs.convert_to_git(&config, b"x\r\n".to_vec()), b"x\r\n");
+  }
+}
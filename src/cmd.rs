@@ -11,18 +11,34 @@ use std::{
 mod add;
 mod branch;
 mod cat_file;
+mod check_ignore;
 mod check_ref_format;
+mod checkout;
 mod commit;
+mod commit_graph;
+mod config;
 mod diff;
 mod diff_tree;
 mod dump_index;
 mod dump_tree;
+mod fsck;
+mod gc;
 mod hash_object;
 mod init;
 mod log;
 mod ls_files;
+mod merge;
+mod merge_base;
+mod pack_refs;
+mod reflog;
+mod repack;
+mod reset;
+mod rev_list;
 mod rev_parse;
+mod show;
 mod status;
+mod switch;
+mod tag;
 
 pub type ClapApp = clap::App<'static, 'static>;
 
@@ -41,6 +57,12 @@ pub struct CommandSet {
   commands: BTreeMap<&'static str, Command>,
 }
 
+impl Default for CommandSet {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
 impl CommandSet {
   pub fn new() -> Self {
     let mut commands = BTreeMap::new();
@@ -48,18 +70,34 @@ impl CommandSet {
     commands.insert("add", add::command());
     commands.insert("branch", branch::command());
     commands.insert("cat-file", cat_file::command());
+    commands.insert("check-ignore", check_ignore::command());
     commands.insert("check-ref-format", check_ref_format::command());
+    commands.insert("checkout", checkout::command());
     commands.insert("commit", commit::command());
+    commands.insert("commit-graph", commit_graph::command());
+    commands.insert("config", config::command());
     commands.insert("diff", diff::command());
     commands.insert("diff-tree", diff_tree::command());
     commands.insert("dump-index", dump_index::command());
     commands.insert("dump-tree", dump_tree::command());
+    commands.insert("fsck", fsck::command());
+    commands.insert("gc", gc::command());
     commands.insert("hash-object", hash_object::command());
     commands.insert("init", init::command());
     commands.insert("log", log::command());
     commands.insert("ls-files", ls_files::command());
+    commands.insert("merge", merge::command());
+    commands.insert("merge-base", merge_base::command());
+    commands.insert("pack-refs", pack_refs::command());
+    commands.insert("reflog", reflog::command());
+    commands.insert("repack", repack::command());
+    commands.insert("reset", reset::command());
+    commands.insert("rev-list", rev_list::command());
     commands.insert("rev-parse", rev_parse::command());
+    commands.insert("show", show::command());
     commands.insert("status", status::command());
+    commands.insert("switch", switch::command());
+    commands.insert("tag", tag::command());
 
     Self { commands }
   }
@@ -92,12 +130,29 @@ impl<'w> Context<'w> {
       .ok_or_else(|| PidgitError::Generic("not a pidgit repository".to_string()))
   }
 
+  // paths on the command line are relative to where we are, but the index
+  // wants them relative to the top of the workspace
+  pub fn relative_path(&self, raw: &str) -> Result<PathBuf> {
+    let root = self.repo()?.workspace().root();
+    let path = self.pwd.join(raw);
+    let path = path.canonicalize().unwrap_or(path);
+
+    match path.strip_prefix(root) {
+      Ok(rel) => Ok(rel.to_path_buf()),
+      Err(_) => Err(PidgitError::Generic(format!(
+        "{}: is outside repository at {}",
+        raw,
+        root.display()
+      ))),
+    }
+  }
+
   pub fn has_pager(&self) -> bool {
     self.pager.borrow().is_some()
   }
 
   // assumes that we _have_ a pager!
-  fn pager_handle(&self) -> RefMut<std::process::ChildStdin> {
+  fn pager_handle(&self) -> RefMut<'_, std::process::ChildStdin> {
     RefMut::map(self.pager.borrow_mut(), |p| {
       p.as_mut().unwrap().stdin.as_mut().unwrap()
     })
@@ -124,7 +179,7 @@ impl<'w> Context<'w> {
   pub fn println_raw(&self, out: &[u8]) -> Result<()> {
     let mut writer = self.writer.borrow_mut();
     writer.write_all(out)?;
-    writer.write(b"\n")?;
+    writer.write_all(b"\n")?;
     Ok(())
   }
 
@@ -157,7 +212,7 @@ fn new_pager() -> Child {
   use std::process::{Command, Stdio};
   // TODO: allow customization of this.
   let process = Command::new("less")
-    .args(&["-R"])
+    .args(["-R"])
     .stdin(Stdio::piped())
     .spawn()
     .expect("could not open pager!");
@@ -1,8 +1,7 @@
 use clap::{App, Arg, ArgMatches};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use crate::index::IndexEntry;
-use crate::object::Blob;
 use crate::prelude::*;
 
 pub fn command() -> Command {
@@ -21,8 +20,11 @@ fn app() -> ClapApp {
 fn run(matches: &ArgMatches, ctx: &Context) -> Result<()> {
   let repo = ctx.repo()?;
   let workspace = repo.workspace();
+  let attrs = workspace.attributes()?;
+  let mut ignore = workspace.ignore_list()?;
 
   let mut index = repo.index_mut();
+  let mut ignored = vec![];
 
   for raw_path in matches.values_of("pathspec").unwrap() {
     let pb = PathBuf::from(raw_path);
@@ -32,17 +34,46 @@ fn run(matches: &ArgMatches, ctx: &Context) -> Result<()> {
       ctx.pwd.join(raw_path).canonicalize()?
     };
 
-    for path in workspace.list_files_from_base(&base)? {
-      let entry = IndexEntry::new(path.clone(), &workspace.canonicalize(&path))?;
+    // naming an ignored file outright is probably a mistake, unless we're
+    // already tracking it; for directories (including the top of the work
+    // tree, which a pattern like * would match), we just leave out whatever's
+    // ignored inside them
+    let rel = base
+      .strip_prefix(workspace.root())
+      .map(Path::to_path_buf)
+      .unwrap_or_default();
 
-      let blob = Blob::from_path(&ctx.pwd.join(&path))?;
+    let is_ignored = base.is_file()
+      && workspace
+        .check_ignore(&mut ignore, &rel)?
+        .is_some_and(|pattern| !pattern.negated);
+
+    let paths = if !is_ignored {
+      workspace.list_files_from_base(&base, &mut ignore)?
+    } else if base.is_file() && index.is_tracked(rel.as_os_str()) {
+      std::iter::once(rel.into_os_string()).collect()
+    } else {
+      ignored.push(raw_path);
+      continue;
+    };
+
+    for path in paths {
+      let blob = workspace.read_blob(&path, &attrs)?;
       repo.write_object(&blob)?;
 
-      index.add(entry);
+      let stat = workspace.stat(&path.clone().into())?;
+      index.add(IndexEntry::new_from_data(path, blob.sha(), stat));
     }
   }
 
   index.write()?;
 
+  if !ignored.is_empty() {
+    return Err(PidgitError::Generic(format!(
+      "the following paths are ignored by one of your ignore files:\n{}",
+      ignored.join("\n")
+    )));
+  }
+
   Ok(())
 }
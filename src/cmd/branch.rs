@@ -25,7 +25,12 @@ fn run(matches: &ArgMatches, ctx: &Context) -> Result<()> {
   if let Some(name) = matches.value_of("branch-name") {
     let start_ref = matches.value_of("start-point").unwrap_or("HEAD");
     let start = repo.resolve_object(start_ref)?;
-    repo.grefs().create_branch(name, &start.sha())?;
+    repo.grefs().create_branch(
+      name,
+      &start.sha(),
+      &repo.whoami()?,
+      &format!("branch: Created from {}", start_ref),
+    )?;
     return Ok(());
   }
 
@@ -48,7 +48,7 @@ fn run(m: &ArgMatches, ctx: &Context) -> Result<()> {
   let inner = object.into_inner();
 
   match inner {
-    _ if m.is_present("type") => ctx.println(format!("{}", inner.type_str())),
+    _ if m.is_present("type") => ctx.println(inner.type_str().to_string()),
     _ if m.is_present("size") => ctx.println(format!("{}", inner.size())),
     _ if m.is_present("debug") => ctx.println(format!("{:#?}", inner)),
     _ if m.is_present("pretty") => {
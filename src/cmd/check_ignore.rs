@@ -0,0 +1,178 @@
+use clap::{App, Arg, ArgMatches};
+
+use crate::prelude::*;
+
+pub fn command() -> Command {
+  (app, run)
+}
+
+fn app() -> ClapApp {
+  App::new("check-ignore")
+    .about("debug ignore files")
+    .arg(
+      Arg::with_name("verbose")
+        .short("v")
+        .long("verbose")
+        .help("show the pattern that matched, and where it came from"),
+    )
+    .arg(
+      Arg::with_name("no-index")
+        .long("no-index")
+        .help("don't look in the index (so tracked files can be ignored too)"),
+    )
+    .arg(
+      Arg::with_name("pathname")
+        .required(true)
+        .multiple(true)
+        .help("path(s) to check"),
+    )
+}
+
+fn run(matches: &ArgMatches, ctx: &Context) -> Result<()> {
+  let repo = ctx.repo()?;
+  let verbose = matches.is_present("verbose");
+  let use_index = !matches.is_present("no-index");
+
+  let mut ignore = repo.workspace().ignore_list()?;
+  let mut any_ignored = false;
+
+  for raw in matches.values_of("pathname").unwrap() {
+    let path = ctx.relative_path(raw)?;
+
+    // tracked files aren't ignored, whatever the patterns say
+    if use_index && repo.index().is_tracked_file(path.as_os_str()) {
+      continue;
+    }
+
+    let pattern = match repo.workspace().check_ignore(&mut ignore, &path)? {
+      Some(pattern) => pattern,
+      None => continue,
+    };
+
+    any_ignored |= !pattern.negated;
+
+    // like git, -v shows negated patterns too, since they're what decided
+    if verbose {
+      ctx.println(format!(
+        "{}:{}:{}\t{}",
+        pattern.source.display(),
+        pattern.line,
+        pattern.text,
+        raw
+      ));
+    } else if !pattern.negated {
+      ctx.println(raw.to_string());
+    }
+  }
+
+  if !any_ignored {
+    std::process::exit(1); // like git, this is how we say "nothing"
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::test_prelude::*;
+
+  fn new_with_ignores() -> TestRepo {
+    let tr = new_empty_repo();
+
+    tr.write_file(".gitignore", "*.o\n!keep.o\nbuild/\n/top\n");
+    tr.write_file("a/.pidgitignore", "x*\n");
+    tr.write_file(".pidgit/info/exclude", "secret\n");
+
+    for f in &[
+      "x.o", "keep.o", "a/b/xy", "top", "a/top", "build/f", "secret",
+    ] {
+      tr.write_file(f, "content\n");
+    }
+
+    tr
+  }
+
+  #[test]
+  fn verbose() {
+    let tr = new_with_ignores();
+
+    let out = tr
+      .run_pidgit(vec![
+        "check-ignore",
+        "-v",
+        "x.o",
+        "keep.o",
+        "a/b/xy",
+        "top",
+        "a/top",
+        "build/f",
+        "secret",
+      ])
+      .unwrap();
+
+    assert_eq!(
+      out,
+      [
+        ".gitignore:1:*.o\tx.o",
+        ".gitignore:2:!keep.o\tkeep.o",
+        "a/.pidgitignore:1:x*\ta/b/xy",
+        ".gitignore:4:/top\ttop",
+        ".gitignore:3:build/\tbuild/f",
+        ".pidgit/info/exclude:1:secret\tsecret",
+        "",
+      ]
+      .join("\n")
+    );
+
+    let out = tr
+      .run_pidgit(vec!["check-ignore", "x.o", "keep.o", "a/top"])
+      .unwrap();
+    assert_eq!(out, "x.o\n");
+  }
+
+  #[test]
+  fn status_and_add() {
+    let tr = new_with_ignores();
+
+    let out = tr.run_pidgit(vec!["status", "--porcelain"]).unwrap();
+    assert_eq!(out, "?? .gitignore\n?? a/\n?? keep.o\n");
+
+    let err = tr.run_pidgit(vec!["add", "x.o", "keep.o"]).unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      "the following paths are ignored by one of your ignore files:\nx.o"
+    );
+
+    tr.run_pidgit(vec!["add", "."]).unwrap();
+    let out = tr.run_pidgit(vec!["ls-files"]).unwrap();
+    assert_eq!(out, ".gitignore\na/.pidgitignore\na/top\nkeep.o\n");
+
+    // once something's tracked, ignoring it doesn't hide it
+    tr.write_file(".gitignore", "*.o\nbuild/\n/top\n");
+    tr.write_file("keep.o", "changed\n");
+    let out = tr.run_pidgit(vec!["status", "--porcelain"]).unwrap();
+    assert_eq!(
+      out,
+      "AM .gitignore\nA  a/.pidgitignore\nA  a/top\nAM keep.o\n"
+    );
+  }
+
+  #[test]
+  fn allowlist() {
+    let tr = new_empty_repo();
+
+    // ignore everything but what we ask for; * matches the top, too
+    tr.write_file(".gitignore", "*\n!.gitignore\n!*.rs\n!docs/\n");
+
+    for f in &["a.txt", "b.rs", "sub/c.rs", "docs/d.rs", "docs/e.txt"] {
+      tr.write_file(f, "content\n");
+    }
+
+    tr.run_pidgit(vec!["add", "."]).unwrap();
+    let out = tr.run_pidgit(vec!["ls-files"]).unwrap();
+    assert_eq!(out, ".gitignore\nb.rs\ndocs/d.rs\n");
+
+    let out = tr.run_pidgit(vec!["status", "--porcelain"]).unwrap();
+    assert_eq!(out, "A  .gitignore\nA  b.rs\nA  docs/d.rs\n");
+  }
+}
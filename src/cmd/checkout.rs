@@ -0,0 +1,264 @@
+use clap::{App, Arg, ArgMatches};
+
+use crate::object::Commit;
+use crate::prelude::*;
+
+pub fn command() -> Command {
+  (app, run)
+}
+
+fn app() -> ClapApp {
+  App::new("checkout")
+    .about("switch branches or check out a commit")
+    .arg(
+      Arg::with_name("new-branch")
+        .short("b")
+        .takes_value(true)
+        .value_name("new-branch")
+        .help("create a new branch and check it out"),
+    )
+    .arg(
+      Arg::with_name("target")
+        .required_unless("new-branch")
+        .help("branch or commit to check out"),
+    )
+}
+
+fn run(matches: &ArgMatches, ctx: &Context) -> Result<()> {
+  let target = matches.value_of("target").unwrap_or("HEAD");
+
+  match matches.value_of("new-branch") {
+    Some(name) => checkout_new_branch(ctx, name, target),
+    None => checkout(ctx, target, false),
+  }
+}
+
+// These are shared with switch, which is really just a more opinionated
+// version of checkout.
+
+pub(super) fn checkout(ctx: &Context, target: &str, detach: bool) -> Result<()> {
+  let repo = ctx.repo()?;
+  let commit = repo.resolve_commit(target)?;
+
+  let branch = if detach {
+    None
+  } else if target == "HEAD" {
+    Some(repo.grefs().current_branch()?).filter(|name| name != "HEAD")
+  } else {
+    repo
+      .grefs()
+      .path_for_name(target)
+      .filter(|name| name.starts_with("refs/heads/"))
+  };
+
+  let current = repo.grefs().current_branch()?;
+
+  if branch.as_ref() == Some(&current) {
+    repo.migrate_to(&commit)?;
+    ctx.println(format!("Already on '{}'", short_name(&current)));
+    return Ok(());
+  }
+
+  repo.migrate_to(&commit)?;
+  move_head(repo, &commit, branch.as_deref(), target)?;
+
+  match branch {
+    Some(name) => {
+      ctx.println(format!("Switched to branch '{}'", short_name(&name)))
+    },
+    None => ctx.println(format!(
+      "HEAD is now at {} {}",
+      commit.sha().short(8),
+      commit.title()
+    )),
+  }
+
+  Ok(())
+}
+
+pub(super) fn checkout_new_branch(
+  ctx: &Context,
+  name: &str,
+  start: &str,
+) -> Result<()> {
+  let repo = ctx.repo()?;
+  let commit = repo.resolve_commit(start)?;
+
+  // make sure we can actually move before we create anything
+  repo.migrate_to(&commit)?;
+
+  repo.grefs().create_branch(
+    name,
+    &commit.sha(),
+    &repo.whoami()?,
+    &format!("branch: Created from {}", start),
+  )?;
+
+  let full_name = format!("refs/heads/{}", name);
+  move_head(repo, &commit, Some(&full_name), name)?;
+
+  ctx.println(format!("Switched to a new branch '{}'", name));
+  Ok(())
+}
+
+// The workspace should already have been migrated by the time we get here.
+fn move_head(
+  repo: &Repository,
+  commit: &Commit,
+  branch: Option<&str>,
+  target: &str,
+) -> Result<()> {
+  let from = match repo.grefs().current_branch()?.as_str() {
+    "HEAD" => repo.grefs().resolve("HEAD")?.hexdigest(),
+    name => short_name(name).to_string(),
+  };
+
+  repo.grefs().set_head(
+    branch,
+    &commit.sha(),
+    &repo.whoami()?,
+    &format!("checkout: moving from {} to {}", from, target),
+  )
+}
+
+fn short_name(refname: &str) -> &str {
+  refname.strip_prefix("refs/heads/").unwrap_or(refname)
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::test_prelude::*;
+
+  // main has two commits; topic branches off the first and adds its own
+  fn new_with_branches() -> TestRepo {
+    let tr = new_empty_repo();
+
+    tr.write_file("shared.txt", "one\n");
+    tr.write_file("dir/main-only.txt", "main\n");
+    tr.commit_all();
+
+    tr.run_pidgit(vec!["branch", "topic"]).unwrap();
+
+    tr.write_file("shared.txt", "two\n");
+    tr.commit_all();
+
+    tr.run_pidgit(vec!["checkout", "topic"]).unwrap();
+    tr.rm_rf("dir");
+    tr.write_file("topic.sh", "#!/bin/sh\n");
+    tr.chmod("topic.sh", 0o755);
+    tr.run_pidgit(vec!["add", "."]).unwrap();
+    tr.repo.index_mut().remove("dir/main-only.txt".as_ref());
+    tr.repo.write_index().unwrap();
+    tr.commit("topic commit").unwrap();
+
+    tr.run_pidgit(vec!["checkout", "main"]).unwrap();
+    tr
+  }
+
+  fn read(tr: &TestRepo, path: &str) -> String {
+    std::fs::read_to_string(tr.repo.workspace().canonicalize(&path)).unwrap()
+  }
+
+  #[test]
+  fn switch_branches() {
+    let tr = new_with_branches();
+    let root = tr.repo.workspace().root().clone();
+
+    assert_eq!(read(&tr, "shared.txt"), "two\n");
+    assert!(root.join("dir/main-only.txt").is_file());
+    assert!(!root.join("topic.sh").exists());
+
+    let out = tr.run_pidgit(vec!["switch", "topic"]).unwrap();
+    assert_eq!(out, "Switched to branch 'topic'\n");
+
+    assert_eq!(read(&tr, "shared.txt"), "one\n");
+    assert!(!root.join("dir").exists());
+    assert!(root.join("topic.sh").is_file());
+
+    use std::os::unix::fs::PermissionsExt;
+    let mode = root
+      .join("topic.sh")
+      .metadata()
+      .unwrap()
+      .permissions()
+      .mode();
+    assert_eq!(mode & 0o777, 0o755);
+
+    assert_eq!(
+      tr.repo.grefs().current_branch().unwrap(),
+      "refs/heads/topic"
+    );
+    assert_eq!(tr.run_pidgit(vec!["status", "-s"]).unwrap(), "");
+
+    let out = tr.run_pidgit(vec!["checkout", "topic"]).unwrap();
+    assert_eq!(out, "Already on 'topic'\n");
+  }
+
+  #[test]
+  fn detached_head() {
+    let tr = new_with_branches();
+    let first = tr.run_pidgit(vec!["rev-parse", "main^"]).unwrap();
+
+    assert!(tr.run_pidgit(vec!["switch", "main^"]).is_err());
+
+    let out = tr.run_pidgit(vec!["checkout", "main^"]).unwrap();
+    assert!(out.starts_with(&format!("HEAD is now at {}", &first[0..8])));
+    assert_eq!(tr.repo.grefs().current_branch().unwrap(), "HEAD");
+    assert_eq!(tr.run_pidgit(vec!["rev-parse", "HEAD"]).unwrap(), first);
+    assert_eq!(read(&tr, "shared.txt"), "one\n");
+    assert_eq!(tr.run_pidgit(vec!["status", "-s"]).unwrap(), "");
+
+    let reflog = tr.run_pidgit(vec!["reflog"]).unwrap();
+    assert!(reflog.contains("HEAD@{0}: checkout: moving from main to main^\n"));
+  }
+
+  #[test]
+  fn new_branch() {
+    let tr = new_with_branches();
+
+    let out = tr
+      .run_pidgit(vec!["checkout", "-b", "feature", "topic"])
+      .unwrap();
+    assert_eq!(out, "Switched to a new branch 'feature'\n");
+    assert_eq!(
+      tr.run_pidgit(vec!["rev-parse", "feature"]).unwrap(),
+      tr.run_pidgit(vec!["rev-parse", "topic"]).unwrap(),
+    );
+    assert!(tr.repo.workspace().root().join("topic.sh").is_file());
+
+    tr.run_pidgit(vec!["switch", "-c", "another"]).unwrap();
+    assert_eq!(
+      tr.repo.grefs().current_branch().unwrap(),
+      "refs/heads/another"
+    );
+  }
+
+  #[test]
+  fn refuse_to_clobber() {
+    let tr = new_with_branches();
+
+    // unstaged change to a file that differs between branches
+    tr.write_file("shared.txt", "local change\n");
+    let err = tr.run_pidgit(vec!["checkout", "topic"]).unwrap_err();
+    assert!(format!("{}", err).contains("\tshared.txt\n"));
+    assert_eq!(read(&tr, "shared.txt"), "local change\n");
+    assert_eq!(tr.repo.grefs().current_branch().unwrap(), "refs/heads/main");
+
+    // a staged one is no better
+    tr.run_pidgit(vec!["add", "shared.txt"]).unwrap();
+    assert!(tr.run_pidgit(vec!["checkout", "topic"]).is_err());
+    tr.write_file("shared.txt", "two\n");
+    tr.run_pidgit(vec!["add", "shared.txt"]).unwrap();
+
+    // nor is an untracked file in the way
+    tr.write_file("topic.sh", "mine\n");
+    let err = tr.run_pidgit(vec!["checkout", "topic"]).unwrap_err();
+    assert!(format!("{}", err).contains("untracked working tree files"));
+    tr.rm_file("topic.sh");
+
+    // but changes to files that don't differ come along for the ride
+    tr.write_file("untouched.txt", "new\n");
+    tr.run_pidgit(vec!["checkout", "topic"]).unwrap();
+    assert_eq!(read(&tr, "untouched.txt"), "new\n");
+  }
+}
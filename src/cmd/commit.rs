@@ -1,7 +1,5 @@
-use chrono::Local;
 use clap::{App, Arg, ArgMatches};
 
-use crate::object::Person;
 use crate::prelude::*;
 
 pub fn command() -> Command {
@@ -17,27 +15,22 @@ fn app() -> ClapApp {
         .long("message")
         .takes_value(true)
         .value_name("msg")
-        .required(true)
-        .help("use this as the message"),
+        .help("use this as the message (required, unless concluding a merge)"),
     )
 }
 
 fn run(matches: &ArgMatches, ctx: &Context) -> Result<()> {
   let repo = ctx.repo()?;
 
-  let now = Local::now();
-  let fixed = now.with_timezone(now.offset());
-
-  // first pass, will improve later
-  let who = Person {
-    name:  std::env::var("GIT_AUTHOR_NAME").unwrap(),
-    email: std::env::var("GIT_AUTHOR_EMAIL").unwrap(),
-    date:  fixed,
+  // if we're finishing up a merge, we have a message ready to go
+  let msg = match matches.value_of("message") {
+    Some(msg) => msg.to_string(),
+    None => repo.merge_message().ok_or_else(|| {
+      PidgitError::Generic("no commit message given (use -m)".into())
+    })?,
   };
 
-  let msg = matches.value_of("message").unwrap().to_string();
-
-  let commit = repo.commit(&msg, who.clone(), who)?;
+  let commit = repo.commit(&msg, repo.author()?, repo.whoami()?)?;
 
   ctx.println(format!(
     "[{}] {}",
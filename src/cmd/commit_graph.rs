@@ -0,0 +1,89 @@
+use clap::{App, AppSettings, ArgMatches, SubCommand};
+
+use crate::prelude::*;
+
+pub fn command() -> Command {
+  (app, run)
+}
+
+fn app() -> ClapApp {
+  App::new("commit-graph")
+    .about("write and verify the commit-graph file")
+    .setting(AppSettings::SubcommandRequiredElseHelp)
+    .subcommand(
+      SubCommand::with_name("write")
+        .about("write a commit-graph of every commit reachable from a ref"),
+    )
+    .subcommand(
+      SubCommand::with_name("verify")
+        .about("check the commit-graph against the object database"),
+    )
+}
+
+fn run(matches: &ArgMatches, ctx: &Context) -> Result<()> {
+  let repo = ctx.repo()?;
+
+  match matches.subcommand_name() {
+    Some("write") => {
+      let count = repo.write_commit_graph()?;
+      ctx.println(format!("wrote commit-graph with {} commits", count));
+    },
+    Some("verify") => repo.verify_commit_graph()?,
+    _ => unreachable!("clap requires a subcommand"),
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::object::GitObject;
+  use crate::test_prelude::*;
+  use std::fs::OpenOptions;
+  use std::io::prelude::*;
+
+  #[test]
+  fn write_and_read() {
+    let tr = new_with_merge();
+    let before = tr.run_pidgit(vec!["log", "--format=%h %p %s"]).unwrap();
+
+    let out = tr.run_pidgit(vec!["commit-graph", "write"]).unwrap();
+    assert_eq!(out, "wrote commit-graph with 6 commits\n");
+    tr.run_pidgit(vec!["commit-graph", "verify"]).unwrap();
+
+    let graph = tr.repo.commit_graph();
+    let graph = graph.as_ref().unwrap();
+    assert_eq!(graph.count(), 6);
+
+    let head = tr.repo.resolve_object("HEAD").unwrap().as_commit().unwrap();
+    let node = graph.lookup(&head.sha()).unwrap().unwrap();
+    assert_eq!(node.parents, head.parent_shas);
+    assert_eq!(&node.tree, head.tree());
+    assert_eq!(node.generation, 5);
+
+    // a new commit isn't in the graph, but we should still be able to walk
+    // through it and into the ones that are
+    tr.commit_file("G");
+    let after = tr.run_pidgit(vec!["log", "--format=%h %p %s"]).unwrap();
+    assert!(after.ends_with(&before));
+
+    let topic = tr.repo.resolve_object("topic").unwrap().sha();
+    let main = tr.repo.resolve_object("main").unwrap().sha();
+    assert!(tr.repo.is_ancestor(&topic, &main).unwrap());
+    assert!(!tr.repo.is_ancestor(&main, &topic).unwrap());
+  }
+
+  #[test]
+  fn verify_corruption() {
+    let tr = new_with_merge();
+    tr.run_pidgit(vec!["commit-graph", "write"]).unwrap();
+
+    let path = tr.repo.git_dir().join("objects/info/commit-graph");
+    let mut file = OpenOptions::new().write(true).open(&path).unwrap();
+    file.seek(std::io::SeekFrom::End(-1)).unwrap();
+    file.write_all(b"x").unwrap();
+
+    let err = tr.run_pidgit(vec!["commit-graph", "verify"]).unwrap_err();
+    assert_eq!(err.to_string(), "bad commit-graph: incorrect checksum");
+  }
+}
@@ -0,0 +1,282 @@
+use clap::{App, Arg, ArgGroup, ArgMatches};
+
+use crate::config::{self, Config, ConfigFile, Key, Scope};
+use crate::prelude::*;
+
+pub fn command() -> Command {
+  (app, run)
+}
+
+fn app() -> ClapApp {
+  App::new("config")
+    .about("get and set repository or global options")
+    .arg(
+      Arg::with_name("get")
+        .long("get")
+        .help("get the value for a key"),
+    )
+    .arg(
+      Arg::with_name("get-all")
+        .long("get-all")
+        .help("get all the values for a multi-valued key"),
+    )
+    .arg(
+      Arg::with_name("list")
+        .short("l")
+        .long("list")
+        .help("list every variable set, with its value"),
+    )
+    .arg(
+      Arg::with_name("unset")
+        .long("unset")
+        .help("remove the value for a key"),
+    )
+    .arg(
+      Arg::with_name("add")
+        .long("add")
+        .requires("value")
+        .help("add a value for a key, without replacing existing ones"),
+    )
+    .group(
+      ArgGroup::with_name("action")
+        .args(&["get", "get-all", "list", "unset", "add"]),
+    )
+    .arg(
+      Arg::with_name("local")
+        .long("local")
+        .help("use the repository config file"),
+    )
+    .arg(
+      Arg::with_name("global")
+        .long("global")
+        .help("use the global config file (~/.gitconfig)"),
+    )
+    .arg(
+      Arg::with_name("system")
+        .long("system")
+        .help("use the system-wide config file"),
+    )
+    .group(ArgGroup::with_name("scope").args(&["local", "global", "system"]))
+    .arg(
+      Arg::with_name("name")
+        .required_unless("list")
+        .conflicts_with("list")
+        .help("the key, like section.name or section.subsection.name"),
+    )
+    .arg(
+      Arg::with_name("value")
+        .conflicts_with_all(&["get", "get-all", "unset"])
+        .help("the value to set"),
+    )
+}
+
+fn run(matches: &ArgMatches, ctx: &Context) -> Result<()> {
+  let git_dir = ctx.repo().ok().map(|repo| repo.git_dir().as_path());
+
+  let scope = if matches.is_present("local") {
+    Some(Scope::Local)
+  } else if matches.is_present("global") {
+    Some(Scope::Global)
+  } else if matches.is_present("system") {
+    Some(Scope::System)
+  } else {
+    None
+  };
+
+  let scope_path = |scope: Scope| {
+    scope.path(git_dir).ok_or_else(|| {
+      PidgitError::Generic("--local can only be used inside a repository".into())
+    })
+  };
+
+  if matches.is_present("list") {
+    for (key, value) in read_entries(scope.map(scope_path).transpose()?, git_dir)?
+    {
+      match value {
+        Some(value) => ctx.println(format!("{}={}", key, value)),
+        None => ctx.println(key),
+      }
+    }
+
+    return Ok(());
+  }
+
+  let name = matches.value_of("name").unwrap();
+  let value = matches.value_of("value");
+
+  let reading = matches.is_present("get")
+    || matches.is_present("get-all")
+    || (value.is_none() && !matches.is_present("unset"));
+
+  if reading {
+    let key = Key::parse(name)?.canonical();
+    let mut values = read_entries(scope.map(scope_path).transpose()?, git_dir)?
+      .into_iter()
+      .filter(|(k, _)| *k == key)
+      .map(|(_, v)| v)
+      .collect::<Vec<_>>();
+
+    if values.is_empty() {
+      std::process::exit(1); // like git, not finding it isn't an error as such
+    }
+
+    if !matches.is_present("get-all") {
+      values.drain(..values.len() - 1);
+    }
+
+    // a key with no value at all has nothing to print
+    for value in values.into_iter().flatten() {
+      ctx.println(value);
+    }
+
+    return Ok(());
+  }
+
+  let mut file = ConfigFile::open(&scope_path(scope.unwrap_or(Scope::Local))?)?;
+
+  if matches.is_present("unset") {
+    if !file.unset(name)? {
+      std::process::exit(5); // this is what git does
+    }
+  } else if matches.is_present("add") {
+    file.add_value(name, value.unwrap())?;
+  } else {
+    file.set(name, value.unwrap())?;
+  }
+
+  file.save()
+}
+
+// (key, value) from just the one file, if we were given one (in which case we
+// don't follow includes, just like git), or from everywhere.
+fn read_entries(
+  path: Option<std::path::PathBuf>,
+  git_dir: Option<&std::path::Path>,
+) -> Result<Vec<(String, Option<String>)>> {
+  let entries = match path {
+    Some(path) => ConfigFile::open(&path)?
+      .entries()
+      .map(|(k, v)| (k.to_string(), v.map(String::from)))
+      .collect(),
+    None => Config::load(&config::files(git_dir))?
+      .entries()
+      .iter()
+      .map(|e| (e.key.clone(), e.value.clone()))
+      .collect(),
+  };
+
+  Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::config::{Config, Scope};
+  use crate::object::Person;
+  use crate::test_prelude::*;
+
+  #[test]
+  fn set_get_unset() {
+    let tr = new_empty_repo();
+
+    tr.run_pidgit(vec!["config", "user.name", "Some One"])
+      .unwrap();
+    tr.run_pidgit(vec!["config", "Branch.Main.remote", "origin"])
+      .unwrap();
+
+    let out = tr.run_pidgit(vec!["config", "user.NAME"]).unwrap();
+    assert_eq!(out, "Some One\n");
+
+    let out = tr
+      .run_pidgit(vec!["config", "--get", "branch.Main.remote"])
+      .unwrap();
+    assert_eq!(out, "origin\n");
+
+    let out = tr.run_pidgit(vec!["config", "--local", "--list"]).unwrap();
+    assert!(out.ends_with("user.name=Some One\nbranch.Main.remote=origin\n"));
+
+    tr.run_pidgit(vec!["config", "--unset", "user.name"])
+      .unwrap();
+    // just the repository's own file, whatever the user's config says
+    let local = Scope::Local.path(Some(tr.repo.git_dir())).unwrap();
+    let config = Config::load(&[(Scope::Local, local)]).unwrap();
+    assert_eq!(config.get("user.name"), None);
+    assert_eq!(config.get("core.bare"), Some("false"));
+
+    let err = tr.run_pidgit(vec!["config", "nosection", "x"]).unwrap_err();
+    assert_eq!(err.to_string(), "key does not contain a section: nosection");
+  }
+
+  #[test]
+  fn multiple_values() {
+    let tr = new_empty_repo();
+
+    tr.run_pidgit(vec!["config", "remote.origin.fetch", "one"])
+      .unwrap();
+    tr.run_pidgit(vec!["config", "--add", "remote.origin.fetch", "two"])
+      .unwrap();
+
+    let out = tr
+      .run_pidgit(vec!["config", "--get-all", "remote.origin.fetch"])
+      .unwrap();
+    assert_eq!(out, "one\ntwo\n");
+
+    let out = tr
+      .run_pidgit(vec!["config", "--get", "remote.origin.fetch"])
+      .unwrap();
+    assert_eq!(out, "two\n");
+
+    assert!(tr
+      .run_pidgit(vec!["config", "remote.origin.fetch", "three"])
+      .is_err());
+    assert!(tr
+      .run_pidgit(vec!["config", "--unset", "remote.origin.fetch"])
+      .is_err());
+  }
+
+  #[test]
+  fn identity_from_config() {
+    let tr = new_empty_repo();
+    tr.run_pidgit(vec!["config", "user.name", "Config Person"])
+      .unwrap();
+    tr.run_pidgit(vec!["config", "user.email", "config@example.com"])
+      .unwrap();
+
+    let who = tr.repo.whoami().unwrap();
+
+    // the environment still wins, if it says anything
+    if std::env::var("GIT_COMMITTER_NAME").is_err() {
+      assert_eq!(who.name, "Config Person");
+    }
+
+    if std::env::var("GIT_COMMITTER_EMAIL").is_err() {
+      assert_eq!(who.email, "config@example.com");
+    }
+  }
+
+  #[test]
+  fn author_and_committer_differ() {
+    let tr = new_empty_repo();
+    tr.run_pidgit(vec!["config", "user.name", "Config Person"])
+      .unwrap();
+    tr.run_pidgit(vec!["config", "user.email", "config@example.com"])
+      .unwrap();
+
+    let config = tr.repo.config().unwrap();
+    let env = |name: &str| match name {
+      "GIT_AUTHOR_NAME" => Some("Author Person".to_string()),
+      "GIT_AUTHOR_EMAIL" => Some("author@example.com".to_string()),
+      "GIT_COMMITTER_NAME" => Some("Committer Person".to_string()),
+      _ => None,
+    };
+
+    let author = Person::from_env("AUTHOR", &config, env);
+    assert_eq!(author.to_string(), "Author Person <author@example.com>");
+
+    // no GIT_COMMITTER_EMAIL, so that comes from the config, not the author
+    let committer = Person::from_env("COMMITTER", &config, env);
+    assert_eq!(
+      committer.to_string(),
+      "Committer Person <config@example.com>"
+    );
+  }
+}
@@ -1,31 +1,63 @@
 use ansi_term::Style;
 use clap::{App, Arg, ArgMatches};
-use std::cell::Ref;
+use std::collections::BTreeMap;
 use std::ffi::OsString;
-use std::path::PathBuf;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 
+use crate::attributes::{AttrValue, WorkspaceAttributes};
 use crate::cmd::Context;
 use crate::diff;
-use crate::index::Index;
+use crate::diff::stat::FileStat;
+use crate::object::{Blob, GitObject, Mode, PathEntry};
 use crate::prelude::*;
-use crate::repo::{ChangeType, Status};
+use crate::repo::{diff_entries, ChangeType};
+use crate::util::{self, resolve_range, RevArg};
 
 const NULL_SHA: &str = "0000000000000000000000000000000000000000";
 const NULL_PATH: &str = "/dev/null";
 
 #[derive(Debug)]
 struct DiffCmd<'r> {
-  repo:   &'r Repository,
-  status: Status,
-  index:  Ref<'r, Index>,
+  repo:    &'r Repository,
+  attrs:   WorkspaceAttributes,
+  paths:   Vec<PathBuf>, // empty means everything
+  options: DiffOptions,
 }
 
+// One side of a diff. Two trees we can compare directly; otherwise, we flatten
+// both sides into maps of every file in them, and compare those.
 #[derive(Debug)]
-struct DiffTarget {
+enum Side {
+  Tree(Option<Sha>), // None is the empty tree, for an unborn HEAD
+  Index,
+  Workspace, // only the files the index knows about, like git
+}
+
+// How to show the changes: as patches, unless we're asked for something else.
+#[derive(Debug, Default)]
+pub(super) struct DiffOptions {
+  binary:    bool, // a patch git can apply, even to binary files
+  names:     Option<Names>,
+  stat:      Option<usize>, // the width to fit it into
+  numstat:   bool,
+  shortstat: bool,
+  dirstat:   Option<usize>, // in permille, the least worth mentioning
+  summary:   bool,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Names {
+  Only,
+  WithStatus,
+}
+
+#[derive(Debug)]
+pub(super) struct DiffTarget {
   path:    PathBuf,
   sha:     Sha,
   mode:    u32,
-  content: String,
+  content: Vec<u8>,
 }
 
 pub fn command() -> Command {
@@ -39,205 +71,725 @@ pub fn app() -> ClapApp {
       Arg::with_name("cached")
         .long("cached")
         .alias("staged")
-        .help("view staged changes"),
+        .help("view staged changes (against HEAD, or the given commit)"),
+    )
+    .arg(
+      Arg::with_name("binary")
+        .long("binary")
+        .help("output a binary diff that can be applied"),
+    )
+    .arg(
+      Arg::with_name("stat")
+        .long("stat")
+        .takes_value(true)
+        .min_values(0)
+        .require_equals(true)
+        .value_name("width")
+        .help("show a histogram of changes to each file"),
+    )
+    .arg(
+      Arg::with_name("numstat")
+        .long("numstat")
+        .help("show the lines added and deleted in each file, for machines"),
+    )
+    .arg(
+      Arg::with_name("shortstat")
+        .long("shortstat")
+        .help("show only the totals from --stat"),
+    )
+    .arg(
+      Arg::with_name("dirstat")
+        .long("dirstat")
+        .takes_value(true)
+        .min_values(0)
+        .require_equals(true)
+        .value_name("param")
+        .help("show the share of changed lines in each directory (lines,limit)"),
+    )
+    .arg(
+      Arg::with_name("summary")
+        .long("summary")
+        .help("show files that were created or deleted, or changed mode"),
+    )
+    .arg(
+      Arg::with_name("name-only")
+        .long("name-only")
+        .help("show only the names of changed files"),
+    )
+    .arg(
+      Arg::with_name("name-status")
+        .long("name-status")
+        .conflicts_with("name-only")
+        .help("show only the names and status of changed files"),
+    )
+    .arg(
+      Arg::with_name("no-index")
+        .long("no-index")
+        .conflicts_with("cached")
+        .help("compare two files, which needn't be in a repository"),
+    )
+    .arg(
+      Arg::with_name("revisions")
+        .multiple(true)
+        .help("commits or trees to compare (one, two, A..B or A...B)"),
+    )
+    .arg(
+      Arg::with_name("paths")
+        .multiple(true)
+        .last(true)
+        .help("only show changes to these paths"),
     )
 }
 
 fn run(matches: &ArgMatches, ctx: &Context) -> Result<()> {
+  let options = DiffOptions::from_matches(matches)?;
+
+  let revs = matches
+    .values_of("revisions")
+    .map_or(vec![], |revs| revs.collect::<Vec<_>>());
+
+  if matches.is_present("no-index") {
+    return match revs[..] {
+      [a, b] => diff_files(ctx, &options, a, b),
+      _ => Err(PidgitError::Generic(
+        "usage: pidgit diff --no-index <path> <path>".to_string(),
+      )),
+    };
+  }
+
   let repo = ctx.repo()?;
-  let status = repo.status()?;
-  let index = repo.index();
+
+  let paths = matches.values_of("paths").map_or(Ok(vec![]), |paths| {
+    paths.map(|p| ctx.relative_path(p)).collect()
+  })?;
 
   let cmd = DiffCmd {
     repo,
-    status,
-    index,
+    attrs: repo.workspace().attributes()?,
+    paths,
+    options,
+  };
+
+  let cached = matches.is_present("cached");
+
+  let (old, new) = match revs[..] {
+    [] if cached => (cmd.head_tree()?, Side::Index),
+    [] => (Side::Index, Side::Workspace),
+    [rev] => match cmd.range(rev)? {
+      Some(sides) => sides,
+      None if cached => (cmd.tree(rev)?, Side::Index),
+      None => (cmd.tree(rev)?, Side::Workspace),
+    },
+    [a, b] if !cached => (cmd.tree(a)?, cmd.tree(b)?),
+    _ => {
+      return Err(PidgitError::Generic(
+        "usage: pidgit diff [--cached] [<commit> [<commit>]] [-- <path>...]"
+          .to_string(),
+      ))
+    },
   };
 
   ctx.setup_pager()?;
+  cmd.print(ctx, &old, &new)
+}
 
-  if matches.is_present("cached") {
-    cmd.print_index_diff(ctx);
-  } else {
-    cmd.print_workspace_diff(ctx);
+impl DiffOptions {
+  fn from_matches(matches: &ArgMatches) -> Result<Self> {
+    let names = if matches.is_present("name-only") {
+      Some(Names::Only)
+    } else if matches.is_present("name-status") {
+      Some(Names::WithStatus)
+    } else {
+      None
+    };
+
+    let stat = match matches.value_of("stat") {
+      Some(width) => Some(width.parse().map_err(|_| {
+        PidgitError::Generic(format!("--stat expects a width, not {:?}", width))
+      })?),
+      None if matches.is_present("stat") => Some(util::term_columns()),
+      None => None,
+    };
+
+    let dirstat = match matches.value_of("dirstat") {
+      Some(params) => Some(dirstat_limit(params)?),
+      None if matches.is_present("dirstat") => Some(diff::stat::DIRSTAT_PERMILLE),
+      None => None,
+    };
+
+    Ok(DiffOptions {
+      binary: matches.is_present("binary"),
+      names,
+      stat,
+      numstat: matches.is_present("numstat"),
+      shortstat: matches.is_present("shortstat"),
+      dirstat,
+      summary: matches.is_present("summary"),
+    })
   }
 
-  Ok(())
+  fn wants_stats(&self) -> bool {
+    self.stat.is_some()
+      || self.numstat
+      || self.shortstat
+      || self.dirstat.is_some()
+      || self.summary
+  }
 }
 
-impl<'r> DiffCmd<'r> {
-  fn print_workspace_diff(&self, ctx: &Context) {
-    for (path, state) in self.status.workspace_diff().iter() {
-      match state {
-        ChangeType::Modified => {
-          self.print_diff(
-            ctx,
-            self.target_from_index(path),
-            self.target_from_file(path),
-          );
-        },
-        ChangeType::Deleted => {
-          self.print_diff(
-            ctx,
-            self.target_from_index(path),
-            DiffTarget::null(path),
-          );
-        },
-        _ => println!("{:?}, {:?}", path, state),
-      }
+// --dirstat=lines,10: we only count lines (which is what git calls "lines"),
+// and like git, a limit of 10 means 10%, and it can have a decimal place.
+// Anything that isn't a percentage (including NaN) is an unknown parameter.
+fn dirstat_limit(params: &str) -> Result<usize> {
+  let mut permille = diff::stat::DIRSTAT_PERMILLE;
+
+  for param in params.split(',') {
+    match param.parse::<f64>() {
+      Ok(pct) if (0.0..=100.0).contains(&pct) => permille = (pct * 10.0) as usize,
+      Err(_) if param == "lines" => {},
+      _ => {
+        return Err(PidgitError::Generic(format!(
+          "unknown --dirstat parameter {:?}",
+          param
+        )))
+      },
     }
   }
 
-  fn print_index_diff(&self, ctx: &Context) {
-    for (path, state) in self.status.index_diff().iter() {
-      match state {
-        ChangeType::Modified => {
-          self.print_diff(
-            ctx,
-            self.target_from_head(path),
-            self.target_from_index(path),
-          );
-        },
-        ChangeType::Deleted => {
-          self.print_diff(
-            ctx,
-            self.target_from_head(path),
-            DiffTarget::null(path),
-          );
-        },
-        _ => println!("{:?}, {:?}", path, state),
+  Ok(permille)
+}
+
+impl<'r> DiffCmd<'r> {
+  fn head_tree(&self) -> Result<Side> {
+    Ok(Side::Tree(self.repo.head()?.map(|c| c.tree().clone())))
+  }
+
+  fn tree(&self, name: &str) -> Result<Side> {
+    Ok(Side::Tree(Some(self.repo.resolve_tree(name)?)))
+  }
+
+  // A..B compares A and B, and A...B compares B to where it forked from A;
+  // either side defaults to HEAD. Anything else isn't a range.
+  fn range(&self, rev: &str) -> Result<Option<(Side, Side)>> {
+    if util::split_range(rev).is_none() {
+      return Ok(None);
+    }
+
+    let mut old = None;
+    let mut new = None;
+
+    for arg in resolve_range(rev, self.repo)? {
+      match arg {
+        RevArg::Include(sha) => new = Some(sha),
+        RevArg::Exclude(sha) if old.is_none() => old = Some(sha),
+        _ => {},
       }
     }
+
+    match (old, new) {
+      (Some(old), Some(new)) => Ok(Some((
+        self.tree(&old.hexdigest())?,
+        self.tree(&new.hexdigest())?,
+      ))),
+      _ => Err(PidgitError::Generic(format!("{}: no merge base", rev))),
+    }
   }
 
-  fn print_diff(&self, ctx: &Context, mut a: DiffTarget, mut b: DiffTarget) {
-    if a.sha == b.sha && a.mode == b.mode {
-      return;
+  fn print(&self, ctx: &Context, old: &Side, new: &Side) -> Result<()> {
+    let changes = match (old, new) {
+      (Side::Tree(a), Side::Tree(b)) => {
+        self.repo.tree_changes(a.as_ref(), b.as_ref(), true)?
+      },
+      _ => diff_entries(self.entries(old)?, self.entries(new)?),
+    };
+
+    let mut pairs = vec![];
+
+    for change in changes.into_iter().filter(|c| self.wanted(&c.path)) {
+      pairs.push((
+        self.target(old, &change.path, change.old)?,
+        self.target(new, &change.path, change.new)?,
+      ));
     }
 
-    a.path = a.with_prefix("a");
-    b.path = b.with_prefix("b");
+    print_diffs(ctx, &self.options, Some(&self.attrs), pairs)
+  }
 
-    ctx.println_color(
-      format!("diff --git {} {}", a.path.display(), b.path.display()),
-      Style::new().bold(),
-    );
+  // whether a path is one of (or inside one of) the ones we were given
+  fn wanted(&self, path: &Path) -> bool {
+    self.paths.is_empty() || self.paths.iter().any(|p| path.starts_with(p))
+  }
 
-    self.print_diff_mode(ctx, &a, &b);
-    self.print_diff_content(ctx, a, b);
+  // Every file on this side, keyed by its path from the top of the workspace.
+  fn entries(&self, side: &Side) -> Result<BTreeMap<OsString, PathEntry>> {
+    let mut entries = match side {
+      Side::Tree(Some(sha)) => return self.repo.flat_tree(sha),
+      Side::Tree(None) => return Ok(BTreeMap::new()),
+      _ => self
+        .repo
+        .index()
+        .entries()
+        .map(|e| (e.name.clone(), PathEntry::from(e)))
+        .collect::<BTreeMap<_, _>>(),
+    };
+
+    if let Side::Workspace = side {
+      let status = self.repo.status()?;
+
+      for (path, change) in status.workspace_diff() {
+        match change {
+          ChangeType::Deleted => {
+            entries.remove(path);
+          },
+          _ => {
+            let entry = entries.get_mut(path).unwrap();
+            entry.sha = self.repo.workspace().read_blob(path, &self.attrs)?.sha();
+            entry.mode = Mode::from(status.stat_for(path).unwrap());
+          },
+        }
+      }
+    }
+
+    Ok(entries)
   }
 
-  fn print_diff_mode(&self, ctx: &Context, a: &DiffTarget, b: &DiffTarget) {
-    let bold = Style::new().bold();
+  fn target(
+    &self,
+    side: &Side,
+    path: &Path,
+    entry: Option<PathEntry>,
+  ) -> Result<DiffTarget> {
+    let entry = match entry {
+      Some(entry) => entry,
+      None => return Ok(DiffTarget::null(path)),
+    };
 
-    if b.is_null() {
-      ctx.println_color(format!("deleted file mode {:0o}", a.mode), bold);
-    } else if a.mode != b.mode {
-      ctx.println_color(format!("old mode {:0o}", a.mode), bold);
-      ctx.println_color(format!("new mode {:0o}", b.mode), bold);
+    match side {
+      Side::Workspace => Ok(DiffTarget {
+        path:    path.into(),
+        mode:    entry.mode().into(),
+        sha:     entry.sha,
+        content: self
+          .repo
+          .workspace()
+          .read_blob(&path, &self.attrs)?
+          .raw_content(),
+      }),
+      _ => {
+        DiffTarget::from_blob(self.repo, path, &entry.sha, entry.mode().into())
+      },
     }
   }
+}
 
-  fn print_diff_content(&self, ctx: &Context, a: DiffTarget, b: DiffTarget) {
-    if a.sha == b.sha {
-      return;
+// diff --no-index: two files anywhere at all, named relative to where we are.
+fn diff_files(
+  ctx: &Context,
+  options: &DiffOptions,
+  a: &str,
+  b: &str,
+) -> Result<()> {
+  let target = |name: &str| -> Result<DiffTarget> {
+    let path = ctx.pwd.join(name);
+
+    if !path.is_file() {
+      return Err(PidgitError::Generic(format!(
+        "{}: no such file (directories aren't supported)",
+        name
+      )));
     }
 
-    let mode_str = if a.mode == b.mode {
-      format!(" {:0o}", a.mode)
-    } else {
-      "".to_string()
-    };
+    let blob = Blob::from_path(&path)?;
 
-    let bold = Style::new().bold();
+    Ok(DiffTarget {
+      path:    name.trim_start_matches("./").into(),
+      sha:     blob.sha(),
+      mode:    (&Mode::from(&path.metadata()?)).into(),
+      content: blob.raw_content(),
+    })
+  };
 
-    ctx.println_color(
-      format!("index {}..{}{}", a.sha.short(8), b.sha.short(8), mode_str),
-      bold,
-    );
+  ctx.setup_pager()?;
+  print_diffs(ctx, options, None, vec![(target(a)?, target(b)?)])
+}
 
-    ctx.println_color(format!("--- {}", a.diff_path().display()), bold);
-    ctx.println_color(format!("+++ {}", a.diff_path().display()), bold);
+// Show the changes between each pair, in the forms we were asked for (in the
+// same order git uses, if there's more than one). Without attributes (for
+// --no-index, outside of any repository), the content decides what's binary.
+fn print_diffs(
+  ctx: &Context,
+  options: &DiffOptions,
+  attrs: Option<&WorkspaceAttributes>,
+  pairs: Vec<(DiffTarget, DiffTarget)>,
+) -> Result<()> {
+  let pairs = pairs
+    .into_iter()
+    .filter(|(a, b)| a.sha != b.sha || a.mode != b.mode)
+    .collect::<Vec<_>>();
 
-    let hunks = diff::diff_hunks(a.content, b.content);
-    for hunk in hunks {
-      ctx.println_color(hunk.header(), Color::Cyan.normal());
+  if let Some(names) = &options.names {
+    for (a, b) in &pairs {
+      let path = stat_path(a, b);
 
-      for edit in hunk.edits {
-        ctx.println(format!("{}", edit))
+      match names {
+        Names::Only => ctx.println(path),
+        Names::WithStatus => ctx.println(format!("{}\t{}", status(a, b), path)),
       }
     }
+
+    return Ok(());
   }
 
-  fn target_from_index(&self, path: &OsString) -> DiffTarget {
-    let entry = self.index.entry_for(path).expect("missing index entry!");
-    let blob = self
-      .repo
-      .object_for_sha(&entry.sha)
-      .expect("no blob?")
-      .as_blob()
-      .expect("bad blob object?");
+  if !options.wants_stats() {
+    for (a, b) in pairs {
+      print_diff(ctx, options, attrs, a, b)?;
+    }
+
+    return Ok(());
+  }
+
+  let stats = pairs
+    .iter()
+    .map(|(a, b)| file_stat(attrs, a, b))
+    .collect::<Result<Vec<_>>>()?;
 
-    DiffTarget {
-      path:    entry.name.clone().into(),
-      sha:     entry.sha.clone(),
-      mode:    entry.mode(),
-      content: blob.string_content(),
+  let mut lines = vec![];
+
+  if let Some(permille) = options.dirstat {
+    lines.extend(diff::stat::dirstat(&stats, permille));
+  }
+
+  if options.numstat {
+    lines.extend(diff::stat::numstat(&stats));
+  }
+
+  if let Some(width) = options.stat {
+    if !stats.is_empty() {
+      lines.extend(diff::stat::stat(&stats, width));
     }
   }
 
-  fn target_from_head(&self, path: &OsString) -> DiffTarget {
-    let entry = self
-      .status
-      .head_diff()
-      .get(path)
-      .expect("missing index entry!");
+  if options.shortstat && !stats.is_empty() {
+    lines.push(diff::stat::shortstat(&stats));
+  }
 
-    let blob = self
-      .repo
-      .object_for_sha(&entry.sha)
-      .expect("no blob?")
-      .as_blob()
-      .expect("bad blob object?");
+  if options.summary {
+    lines.extend(pairs.iter().filter_map(|(a, b)| summary(a, b)));
+  }
+
+  for line in lines {
+    ctx.println(line);
+  }
+
+  Ok(())
+}
+
+// The path to show for a change, which for --no-index might be two of them.
+fn stat_path(a: &DiffTarget, b: &DiffTarget) -> String {
+  if a.is_null() {
+    b.path.display().to_string()
+  } else if b.is_null() || a.path == b.path {
+    a.path.display().to_string()
+  } else {
+    format!("{} => {}", a.path.display(), b.path.display())
+  }
+}
+
+fn status(a: &DiffTarget, b: &DiffTarget) -> &'static str {
+  if a.is_null() {
+    "A"
+  } else if b.is_null() {
+    "D"
+  } else {
+    "M"
+  }
+}
+
+fn file_stat(
+  attrs: Option<&WorkspaceAttributes>,
+  a: &DiffTarget,
+  b: &DiffTarget,
+) -> Result<FileStat> {
+  let mut stat = FileStat {
+    path:    stat_path(a, b),
+    added:   0,
+    deleted: 0,
+    binary:  false,
+  };
+
+  // just a mode change
+  if a.sha == b.sha {
+    return Ok(stat);
+  }
+
+  let (added, deleted) = match DiffDriver::for_pair(attrs, a, b)? {
+    DiffDriver::Binary => {
+      stat.binary = true;
+      (b.content.len(), a.content.len())
+    },
+    DiffDriver::TextConv(cmd) => {
+      let conv = |t: &DiffTarget| -> Result<String> {
+        let content = if t.is_null() {
+          vec![]
+        } else {
+          textconv(&cmd, &t.content)?
+        };
+        Ok(String::from_utf8_lossy(&content).into_owned())
+      };
+
+      diff::line_changes(conv(a)?, conv(b)?)
+    },
+    DiffDriver::Text => {
+      diff::line_changes(a.content_string(), b.content_string())
+    },
+  };
+
+  stat.added = added;
+  stat.deleted = deleted;
+  Ok(stat)
+}
+
+// " create mode 100644 path", and so on, for changes that aren't just content.
+fn summary(a: &DiffTarget, b: &DiffTarget) -> Option<String> {
+  if a.is_null() {
+    Some(format!(" create mode {:0o} {}", b.mode, b.path.display()))
+  } else if b.is_null() {
+    Some(format!(" delete mode {:0o} {}", a.mode, a.path.display()))
+  } else if a.mode != b.mode {
+    Some(format!(
+      " mode change {:0o} => {:0o} {}",
+      a.mode,
+      b.mode,
+      b.path.display()
+    ))
+  } else {
+    None
+  }
+}
+
+// Print a patch going from a to b, if they're actually different.
+pub(super) fn print_diff(
+  ctx: &Context,
+  options: &DiffOptions,
+  attrs: Option<&WorkspaceAttributes>,
+  mut a: DiffTarget,
+  mut b: DiffTarget,
+) -> Result<()> {
+  if a.sha == b.sha && a.mode == b.mode {
+    return Ok(());
+  }
+
+  let driver = DiffDriver::for_pair(attrs, &a, &b)?;
+
+  a.path = a.with_prefix("a");
+  b.path = b.with_prefix("b");
+
+  ctx.println_color(
+    format!("diff --git {} {}", a.path.display(), b.path.display()),
+    Style::new().bold(),
+  );
+
+  print_diff_mode(ctx, &a, &b);
+  print_diff_content(ctx, options, a, b, driver)
+}
+
+fn print_diff_mode(ctx: &Context, a: &DiffTarget, b: &DiffTarget) {
+  let bold = Style::new().bold();
+
+  if a.is_null() {
+    ctx.println_color(format!("new file mode {:0o}", b.mode), bold);
+  } else if b.is_null() {
+    ctx.println_color(format!("deleted file mode {:0o}", a.mode), bold);
+  } else if a.mode != b.mode {
+    ctx.println_color(format!("old mode {:0o}", a.mode), bold);
+    ctx.println_color(format!("new mode {:0o}", b.mode), bold);
+  }
+}
+
+fn print_diff_content(
+  ctx: &Context,
+  options: &DiffOptions,
+  mut a: DiffTarget,
+  mut b: DiffTarget,
+  driver: DiffDriver,
+) -> Result<()> {
+  if a.sha == b.sha {
+    return Ok(());
+  }
+
+  let mode_str = if a.mode == b.mode {
+    format!(" {:0o}", a.mode)
+  } else {
+    "".to_string()
+  };
+
+  // a patch that's meant to be applied names the blobs in full
+  let (a_sha, b_sha) = if options.binary {
+    (a.sha.hexdigest(), b.sha.hexdigest())
+  } else {
+    (a.sha.short(8), b.sha.short(8))
+  };
+
+  let bold = Style::new().bold();
+
+  ctx.println_color(format!("index {}..{}{}", a_sha, b_sha, mode_str), bold);
+
+  match driver {
+    DiffDriver::Binary if options.binary => {
+      // forwards, then backwards, so it can be applied in reverse too
+      ctx.println("GIT binary patch".to_string());
+      ctx.println(diff::binary_hunk(&a.content, &b.content));
+      ctx.println(diff::binary_hunk(&b.content, &a.content));
+      return Ok(());
+    },
+    DiffDriver::Binary => {
+      ctx.println(format!(
+        "Binary files {} and {} differ",
+        a.diff_path().display(),
+        b.diff_path().display()
+      ));
+      return Ok(());
+    },
+    DiffDriver::TextConv(cmd) => {
+      for target in &mut [&mut a, &mut b] {
+        if !target.is_null() {
+          target.content = textconv(&cmd, &target.content)?;
+        }
+      }
+    },
+    DiffDriver::Text => {},
+  }
+
+  ctx.println_color(format!("--- {}", a.diff_path().display()), bold);
+  ctx.println_color(format!("+++ {}", b.diff_path().display()), bold);
+
+  let hunks = diff::diff_hunks(a.content_string(), b.content_string());
+  for hunk in hunks {
+    ctx.println_color(hunk.header(), Color::Cyan.normal());
 
-    DiffTarget {
-      path:    entry.path.clone(),
-      sha:     entry.sha.clone(),
-      mode:    entry.mode().into(),
-      content: blob.string_content(),
+    for edit in hunk.edits {
+      ctx.println(format!("{}", edit))
     }
   }
 
-  fn target_from_file(&self, path: &OsString) -> DiffTarget {
-    use std::os::unix::fs::PermissionsExt;
+  Ok(())
+}
 
-    let blob = self
-      .repo
-      .workspace()
-      .read_blob(path)
-      .expect("could not create blob");
-    let stat = self.status.stat_for(path).expect("missing stat");
+// How to show a path's content in a patch: the diff attribute can turn it off
+// (-diff, or binary), or name a driver whose diff.<driver>.textconv command
+// turns the content into something worth diffing.
+enum DiffDriver {
+  Text,
+  Binary,
+  TextConv(String),
+}
 
-    // TODO: diff non-strings?
-    DiffTarget {
-      path:    path.into(),
-      sha:     blob.sha(),
-      mode:    stat.permissions().mode(),
-      content: blob.string_content(),
+impl DiffDriver {
+  // The attributes go with the path as it is now, and anything that looks
+  // binary is, unless a driver says otherwise.
+  fn for_pair(
+    attrs: Option<&WorkspaceAttributes>,
+    a: &DiffTarget,
+    b: &DiffTarget,
+  ) -> Result<Self> {
+    let path = if b.is_null() { &a.path } else { &b.path };
+
+    let driver = match attrs {
+      Some(attrs) => Self::for_path(attrs, path)?,
+      None => Self::Text,
+    };
+
+    match driver {
+      Self::Text if a.is_binary() || b.is_binary() => Ok(Self::Binary),
+      driver => Ok(driver),
+    }
+  }
+
+  fn for_path(attrs: &WorkspaceAttributes, path: &Path) -> Result<Self> {
+    let name = match attrs.for_path(path)?.get("diff") {
+      Some(AttrValue::Unset) => return Ok(DiffDriver::Binary),
+      Some(AttrValue::Value(name)) => name.clone(),
+      _ => return Ok(DiffDriver::Text),
+    };
+
+    let config = attrs.config();
+
+    if let Some(cmd) = config.get(&format!("diff.{}.textconv", name)) {
+      Ok(DiffDriver::TextConv(cmd.to_string()))
+    } else if config.get(&format!("diff.{}.binary", name)) == Some("true") {
+      Ok(DiffDriver::Binary)
+    } else {
+      Ok(DiffDriver::Text)
     }
   }
 }
 
+// Run a textconv command, which (like in git) gets the name of a file holding
+// the content, and prints what to diff instead. The file is only ours (it's
+// created afresh, and only we can read it), and goes away however we leave.
+fn textconv(cmd: &str, content: &[u8]) -> Result<Vec<u8>> {
+  let mut tmp = tempfile::Builder::new()
+    .prefix("pidgit-textconv-")
+    .tempfile()?;
+  tmp.write_all(content)?;
+
+  let output = std::process::Command::new("sh")
+    .arg("-c")
+    .arg(format!("{} \"$@\"", cmd))
+    .arg(cmd)
+    .arg(tmp.path())
+    .output()?;
+
+  if !output.status.success() {
+    return Err(PidgitError::Generic(format!(
+      "textconv command {:?} failed",
+      cmd
+    )));
+  }
+
+  Ok(output.stdout)
+}
+
 impl DiffTarget {
-  fn null(path: &OsString) -> Self {
+  // the blob with this sha, found at path
+  pub(super) fn from_blob(
+    repo: &Repository,
+    path: &Path,
+    sha: &Sha,
+    mode: u32,
+  ) -> Result<Self> {
+    // a submodule's commit isn't ours, so git shows this instead
+    let content = if Mode::Gitlink == mode {
+      format!("Subproject commit {}\n", sha).into_bytes()
+    } else {
+      repo.object_for_sha(sha)?.as_blob()?.raw_content()
+    };
+
+    Ok(Self {
+      path: path.to_path_buf(),
+      sha: sha.clone(),
+      mode,
+      content,
+    })
+  }
+
+  pub(super) fn null(path: &Path) -> Self {
     Self {
       path:    path.into(),
       sha:     NULL_SHA.into(),
       mode:    0,
-      content: "".to_string(),
+      content: vec![],
     }
   }
 
+  fn is_binary(&self) -> bool {
+    diff::is_binary(&self.content)
+  }
+
+  fn content_string(&self) -> String {
+    String::from_utf8_lossy(&self.content).into_owned()
+  }
+
   fn with_prefix(&self, prefix: &str) -> PathBuf {
     PathBuf::from(prefix).join(&self.path)
   }
@@ -254,3 +806,234 @@ impl DiffTarget {
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use crate::object::GitObject;
+  use crate::test_prelude::*;
+
+  fn new_with_attributes() -> TestRepo {
+    let tr = new_empty_repo();
+
+    tr.write_file(
+      ".gitattributes",
+      "*.bin -diff\n*.txt text eol=crlf\n*.up diff=upper\n",
+    );
+    tr.run_pidgit(vec!["config", "diff.upper.textconv", "sed s/^/conv:/"])
+      .unwrap();
+
+    tr.write_file("a.bin", "one\n");
+    tr.write_file("a.txt", "one\r\ntwo\r\n");
+    tr.write_file("a.up", "hello\n");
+    tr.commit_all();
+
+    tr
+  }
+
+  #[test]
+  fn line_endings() {
+    let tr = new_with_attributes();
+
+    // stored with LF, but the CRLF file on disk isn't a change
+    let blob = tr
+      .repo
+      .resolve_object("HEAD:a.txt")
+      .unwrap()
+      .as_blob()
+      .unwrap();
+    assert_eq!(blob.raw_content(), b"one\ntwo\n");
+    assert_eq!(tr.run_pidgit(vec!["status", "--porcelain"]).unwrap(), "");
+
+    // and it comes back out with CRLF
+    tr.rm_file("a.txt");
+    tr.run_pidgit(vec!["reset", "--hard"]).unwrap();
+    let path = tr.repo.workspace().canonicalize(&"a.txt");
+    assert_eq!(std::fs::read(path).unwrap(), b"one\r\ntwo\r\n");
+  }
+
+  #[test]
+  fn drivers() {
+    let tr = new_with_attributes();
+
+    tr.write_file("a.bin", "two\n");
+    tr.write_file("a.up", "bye\n");
+
+    let out = tr.run_pidgit(vec!["diff"]).unwrap();
+    let lines = out.lines().collect::<Vec<_>>();
+
+    assert_eq!(lines[0], "diff --git a/a.bin b/a.bin");
+    assert_eq!(lines[2], "Binary files a/a.bin and b/a.bin differ");
+
+    assert_eq!(lines[3], "diff --git a/a.up b/a.up");
+    assert_eq!(&lines[8..], &["-conv:hello", "+conv:bye"]);
+  }
+
+  #[test]
+  fn binary_content() {
+    let tr = new_empty_repo();
+    tr.write_file("a.dat", "x\0y");
+    tr.commit_all();
+    tr.write_file("a.dat", "x\0z");
+
+    let out = tr.run_pidgit(vec!["diff"]).unwrap();
+    assert_eq!(
+      out.lines().last().unwrap(),
+      "Binary files a/a.dat and b/a.dat differ"
+    );
+
+    let out = tr.run_pidgit(vec!["diff", "--binary"]).unwrap();
+    let lines = out.lines().collect::<Vec<_>>();
+
+    assert_eq!(
+      lines[1],
+      "index d5d0b8b4c4c9e936890870f6799cfbb5ba984470..\
+       4a270318359d8c2a960136495bceeae9eee22424 100644"
+    );
+    assert_eq!(
+      &lines[2..],
+      &[
+        "GIT binary patch",
+        "literal 3",
+        "Kcmb<mr~&{1<pA>l",
+        "",
+        "literal 3",
+        "Kcmb<ms0083<N)#j",
+        "",
+      ]
+    );
+  }
+
+  // just the paths with changes, from the diff --git lines
+  fn changed(tr: &TestRepo, args: &[&str]) -> String {
+    let out = tr.run_pidgit([&["diff"], args].concat()).unwrap();
+
+    out
+      .lines()
+      .filter_map(|l| l.strip_prefix("diff --git a/"))
+      .map(|l| l.split(' ').next().unwrap())
+      .collect::<Vec<_>>()
+      .join(" ")
+  }
+
+  #[test]
+  fn revisions_and_paths() {
+    let tr = new_empty_repo();
+
+    tr.write_file("a.txt", "one\n");
+    tr.write_file("b.txt", "b\n");
+    tr.commit_all();
+    tr.run_pidgit(vec!["branch", "side"]).unwrap();
+
+    tr.write_file("a.txt", "two\n");
+    tr.write_file("c/d.txt", "d\n");
+    tr.commit_all();
+
+    tr.run_pidgit(vec!["switch", "side"]).unwrap();
+    tr.write_file("side.txt", "side\n");
+    tr.commit_all();
+    tr.run_pidgit(vec!["switch", "main"]).unwrap();
+
+    tr.write_file("b.txt", "staged\n");
+    tr.run_pidgit(vec!["add", "b.txt"]).unwrap();
+    tr.write_file("a.txt", "three\n");
+
+    assert_eq!(changed(&tr, &[]), "a.txt");
+    assert_eq!(changed(&tr, &["--cached"]), "b.txt");
+    assert_eq!(changed(&tr, &["HEAD"]), "a.txt b.txt");
+    assert_eq!(changed(&tr, &["--cached", "HEAD~1"]), "a.txt b.txt c/d.txt");
+    assert_eq!(changed(&tr, &["HEAD~1", "HEAD"]), "a.txt c/d.txt");
+    assert_eq!(changed(&tr, &["HEAD~1..HEAD"]), "a.txt c/d.txt");
+    assert_eq!(changed(&tr, &["main..side"]), "a.txt c/d.txt side.txt");
+
+    // three dots: only what happened on the right since they forked
+    assert_eq!(changed(&tr, &["side...main"]), "a.txt c/d.txt");
+    assert_eq!(changed(&tr, &["main...side"]), "side.txt");
+
+    assert_eq!(changed(&tr, &["HEAD~1", "--", "c"]), "c/d.txt");
+    assert_eq!(changed(&tr, &["HEAD", "--", "b.txt", "c"]), "b.txt");
+
+    let out = tr.run_pidgit(vec!["diff", "HEAD~1", "HEAD", "--", "a.txt"]);
+    let lines = out.unwrap().lines().map(String::from).collect::<Vec<_>>();
+    assert_eq!(&lines[5..], &["-one", "+two"]);
+  }
+
+  #[test]
+  fn no_index() {
+    let tr = new_empty_repo();
+    tr.write_file("f1", "a\nb\n");
+    tr.write_file("f2", "a\nc\n");
+
+    let out = tr
+      .run_pidgit(vec!["diff", "--no-index", "f1", "f2"])
+      .unwrap();
+    let lines = out.lines().collect::<Vec<_>>();
+
+    assert_eq!(lines[0], "diff --git a/f1 b/f2");
+    assert_eq!(&lines[2..4], &["--- a/f1", "+++ b/f2"]);
+    assert_eq!(&lines[5..], &[" a", "-b", "+c"]);
+
+    assert!(tr
+      .run_pidgit(vec!["diff", "--no-index", "f1", "nope"])
+      .is_err());
+  }
+
+  #[test]
+  fn stats_and_names() {
+    let tr = new_empty_repo();
+
+    tr.write_file("a.txt", "one\ntwo\nthree\n");
+    tr.write_file("b.bin", "x\0y");
+    tr.write_file("gone.txt", "gone\n");
+    tr.write_file("run.sh", "s\n");
+    tr.commit_all();
+
+    tr.write_file("a.txt", "one\n2\nthree\nfour\n");
+    tr.write_file("b.bin", "x\0yz");
+    tr.rm_file("gone.txt");
+    tr.write_file("sub/new.txt", "new\n");
+    tr.chmod("run.sh", 0o755);
+    tr.run_pidgit(vec!["add", "."]).unwrap();
+
+    let diff = |arg| tr.run_pidgit(vec!["diff", "HEAD", arg]).unwrap();
+
+    // these are all exactly what git says
+    assert_eq!(
+      diff("--name-status"),
+      "M\ta.txt\nM\tb.bin\nD\tgone.txt\nM\trun.sh\nA\tsub/new.txt\n"
+    );
+
+    assert_eq!(
+      diff("--numstat"),
+      "2\t1\ta.txt\n-\t-\tb.bin\n0\t1\tgone.txt\n0\t0\trun.sh\n1\t0\tsub/new.txt\n"
+    );
+
+    assert_eq!(
+      diff("--stat=60"),
+      [
+        " a.txt       |   3 ++-",
+        " b.bin       | Bin 3 -> 4 bytes",
+        " gone.txt    |   1 -",
+        " run.sh      |   0",
+        " sub/new.txt |   1 +",
+        " 5 files changed, 3 insertions(+), 2 deletions(-)",
+        "",
+      ]
+      .join("\n")
+    );
+
+    assert_eq!(
+      diff("--summary"),
+      [
+        " delete mode 100644 gone.txt",
+        " mode change 100644 => 100755 run.sh",
+        " create mode 100644 sub/new.txt",
+        "",
+      ]
+      .join("\n")
+    );
+
+    for limit in &["--dirstat=-5", "--dirstat=NaN", "--dirstat=lines,101"] {
+      assert!(tr.run_pidgit(vec!["diff", "HEAD", limit]).is_err());
+    }
+  }
+}
@@ -1,40 +1,304 @@
-use clap::{App, Arg, ArgMatches};
+use clap::{App, Arg, ArgGroup, ArgMatches};
 
+use crate::attributes::WorkspaceAttributes;
+use crate::cmd::diff::{self, DiffOptions, DiffTarget};
+use crate::object::PathEntry;
 use crate::prelude::*;
+use crate::repo::TreeChange;
+
+const NULL_SHA: &str = "0000000000000000000000000000000000000000";
+
+#[derive(Debug, PartialEq, Eq)]
+enum Format {
+  Raw,
+  NameOnly,
+  NameStatus,
+  Patch,
+}
 
 pub fn command() -> Command {
   (app, run)
 }
 
-// this is, for now, a weak imitation of git.
 fn app() -> ClapApp {
   App::new("diff-tree")
     .about("compares the content and mode of blobs found via two tree objects")
     .arg(
-      Arg::with_name("tree1")
-        .takes_value(true)
-        .required(true)
-        .help("first tree"),
+      Arg::with_name("recursive")
+        .short("r")
+        .help("recurse into subtrees"),
     )
     .arg(
-      Arg::with_name("tree2")
+      Arg::with_name("name-only")
+        .long("name-only")
+        .help("show only names of changed files"),
+    )
+    .arg(
+      Arg::with_name("name-status")
+        .long("name-status")
+        .help("show only names and status of changed files"),
+    )
+    .arg(
+      Arg::with_name("patch")
+        .short("p")
+        .long("patch")
+        .help("generate a patch (implies -r)"),
+    )
+    .group(ArgGroup::with_name("format").args(&[
+      "name-only",
+      "name-status",
+      "patch",
+    ]))
+    .arg(
+      Arg::with_name("tree1")
         .takes_value(true)
         .required(true)
-        .help("second tree"),
+        .help("first tree (or commit)"),
     )
+    .arg(Arg::with_name("tree2").takes_value(true).help(
+      "second tree (or commit); if absent, tree1 is compared to its parent",
+    ))
 }
 
 fn run(matches: &ArgMatches, ctx: &Context) -> Result<()> {
   let repo = ctx.repo()?;
 
-  let tree1 = repo.resolve_object(matches.value_of("tree1").unwrap())?;
-  let tree2 = repo.resolve_object(matches.value_of("tree2").unwrap())?;
+  let format = if matches.is_present("name-only") {
+    Format::NameOnly
+  } else if matches.is_present("name-status") {
+    Format::NameStatus
+  } else if matches.is_present("patch") {
+    Format::Patch
+  } else {
+    Format::Raw
+  };
 
-  let tree1 = tree1.as_tree()?;
-  let tree2 = tree2.as_tree()?;
+  let recursive = matches.is_present("recursive") || format == Format::Patch;
+  let attrs = repo.workspace().attributes()?;
 
-  println!("{:?}", tree1);
-  println!("{:?}", tree2);
+  let name1 = matches.value_of("tree1").unwrap();
+
+  let (old, new) = match matches.value_of("tree2") {
+    Some(name2) => (Some(repo.resolve_tree(name1)?), repo.resolve_tree(name2)?),
+    None => {
+      // with one argument, it has to be a commit, and we compare it to its
+      // first parent (and say which commit it was first, like git does)
+      let commit = repo.resolve_object(name1)?;
+      let commit = match repo.peel_tag(&commit.sha()) {
+        Some(sha) => repo.object_for_sha(&sha)?,
+        None => commit,
+      }
+      .as_commit()?;
+
+      ctx.println(commit.sha().hexdigest());
+
+      let parent = commit.parent(repo).map(|p| p.tree().clone());
+      (parent, commit.tree().clone())
+    },
+  };
+
+  for change in repo.tree_changes(old.as_ref(), Some(&new), recursive)? {
+    match format {
+      Format::Raw => ctx.println(raw(&change)),
+      Format::NameOnly => ctx.println(change.path.display().to_string()),
+      Format::NameStatus => {
+        ctx.println(format!("{}\t{}", status(&change), change.path.display()))
+      },
+      Format::Patch => print_patch(ctx, repo, &attrs, &change)?,
+    }
+  }
 
   Ok(())
 }
+
+fn status(change: &TreeChange) -> &'static str {
+  match (&change.old, &change.new) {
+    (None, _) => "A",
+    (_, None) => "D",
+    _ => "M",
+  }
+}
+
+pub(super) fn print_patch(
+  ctx: &Context,
+  repo: &Repository,
+  attrs: &WorkspaceAttributes,
+  change: &TreeChange,
+) -> Result<()> {
+  let path = &change.path;
+  let target = |entry: &Option<PathEntry>| match entry {
+    Some(e) => DiffTarget::from_blob(repo, path, &e.sha, e.mode().into()),
+    None => Ok(DiffTarget::null(path)),
+  };
+
+  diff::print_diff(
+    ctx,
+    &DiffOptions::default(),
+    Some(attrs),
+    target(&change.old)?,
+    target(&change.new)?,
+  )
+}
+
+fn raw(change: &TreeChange) -> String {
+  let mode =
+    |e: &Option<PathEntry>| e.as_ref().map_or(0, |e| u32::from(e.mode()));
+  let sha = |e: &Option<PathEntry>| {
+    e.as_ref()
+      .map_or(NULL_SHA.to_string(), |e| e.sha.hexdigest())
+  };
+
+  format!(
+    ":{:06o} {:06o} {} {} {}\t{}",
+    mode(&change.old),
+    mode(&change.new),
+    sha(&change.old),
+    sha(&change.new),
+    status(change),
+    change.path.display()
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::object::{Commit, GitObject};
+  use crate::test_prelude::*;
+  use std::path::Path;
+
+  fn new_with_history() -> TestRepo {
+    let tr = new_empty_repo();
+
+    tr.write_file("a.txt", "one\n");
+    tr.write_file("dir/b.txt", "two\n");
+    tr.write_file("dir/c.txt", "three\n");
+    tr.commit_all();
+    tr.run_pidgit(vec!["tag", "first"]).unwrap();
+
+    tr.write_file("a.txt", "ONE\n");
+    tr.write_file("dir/b.txt", "TWO\n");
+    tr.write_file("new.txt", "new\n");
+    tr.chmod("dir/c.txt", 0o755);
+    tr.commit_all();
+
+    tr
+  }
+
+  fn commit_for(tr: &TestRepo, rev: &str) -> Commit {
+    tr.repo.resolve_object(rev).unwrap().as_commit().unwrap()
+  }
+
+  // name is a top-level entry, tree or not
+  fn sha_for(tr: &TestRepo, rev: &str, name: &str) -> String {
+    let tree = commit_for(tr, rev).tree().clone();
+    let changes = tr.repo.tree_changes(None, Some(&tree), false).unwrap();
+
+    let change = changes.iter().find(|c| c.path == Path::new(name)).unwrap();
+    change.new.as_ref().unwrap().sha.hexdigest()
+  }
+
+  #[test]
+  fn raw_output() {
+    let tr = new_with_history();
+
+    let old_a = sha_for(&tr, "first", "a.txt");
+    let new_a = sha_for(&tr, "HEAD", "a.txt");
+    let old_dir = sha_for(&tr, "first", "dir");
+    let new_dir = sha_for(&tr, "HEAD", "dir");
+    let new_new = sha_for(&tr, "HEAD", "new.txt");
+
+    let out = tr.run_pidgit(vec!["diff-tree", "first", "HEAD"]).unwrap();
+    assert_eq!(
+      out,
+      format!(
+        ":100644 100644 {} {} M\ta.txt\n\
+         :040000 040000 {} {} M\tdir\n\
+         :000000 100644 {} {} A\tnew.txt\n",
+        old_a,
+        new_a,
+        old_dir,
+        new_dir,
+        "0".repeat(40),
+        new_new
+      )
+    );
+  }
+
+  #[test]
+  fn name_status() {
+    let tr = new_with_history();
+
+    let out = tr
+      .run_pidgit(vec!["diff-tree", "-r", "--name-status", "first", "HEAD"])
+      .unwrap();
+    assert_eq!(out, "M\ta.txt\nM\tdir/b.txt\nM\tdir/c.txt\nA\tnew.txt\n");
+
+    // reversed, with trees instead of commits
+    let first = commit_for(&tr, "first").tree().hexdigest();
+    let out = tr
+      .run_pidgit(vec!["diff-tree", "-r", "--name-only", "HEAD", &first])
+      .unwrap();
+    assert_eq!(out, "a.txt\ndir/b.txt\ndir/c.txt\nnew.txt\n");
+  }
+
+  #[test]
+  fn patch() {
+    let tr = new_with_history();
+
+    let out = tr
+      .run_pidgit(vec!["diff-tree", "-p", "first", "HEAD"])
+      .unwrap();
+
+    assert!(out.starts_with("diff --git a/a.txt b/a.txt\n"));
+    assert!(
+      out.contains("--- a/a.txt\n+++ b/a.txt\n@@ -1,1 +1,1 @@\n-one\n+ONE\n")
+    );
+    assert!(out.contains(
+      "diff --git a/dir/c.txt b/dir/c.txt\nold mode 100644\nnew mode 100755\n"
+    ));
+    assert!(
+      out.contains("diff --git a/new.txt b/new.txt\nnew file mode 100644\n")
+    );
+    assert!(out.contains("--- /dev/null\n+++ b/new.txt\n"));
+  }
+
+  #[test]
+  fn single_commit() {
+    let tr = new_with_history();
+    let head = commit_for(&tr, "HEAD").sha().hexdigest();
+
+    let out = tr
+      .run_pidgit(vec!["diff-tree", "--name-only", "HEAD"])
+      .unwrap();
+    assert_eq!(out, format!("{}\na.txt\ndir\nnew.txt\n", head));
+  }
+
+  #[test]
+  fn symlinks_and_submodules() {
+    let tr = new_empty_repo();
+
+    let target = tr.write_raw_object("blob", b"a.txt");
+    let commit = "1234567890123456789012345678901234567890";
+
+    let mut content = b"120000 link\0".to_vec();
+    content.extend(target.bytes());
+    content.extend(b"160000 sub\0");
+    content.extend(hex::decode(commit).unwrap());
+
+    let tree = tr.write_raw_object("tree", &content).hexdigest();
+    let empty = tr.write_raw_object("tree", b"").hexdigest();
+
+    let out = tr.run_pidgit(vec!["diff-tree", &empty, &tree]).unwrap();
+    assert_eq!(
+      out,
+      format!(
+        ":000000 120000 {0} {1} A\tlink\n:000000 160000 {0} {2} A\tsub\n",
+        "0".repeat(40),
+        target,
+        commit
+      )
+    );
+
+    let out = tr.run_pidgit(vec!["cat-file", "-p", &tree]).unwrap();
+    assert!(out.contains(&format!("160000 commit {}", commit)));
+  }
+}
@@ -1,5 +1,5 @@
 use clap::{App, Arg, ArgMatches};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use crate::object::TreeItem;
 use crate::prelude::*;
@@ -30,12 +30,12 @@ fn run(matches: &ArgMatches, ctx: &Context) -> Result<()> {
     .resolve_object(&commit.tree().hexdigest())?
     .into_inner();
 
-  print_tree(&repo, &tree.sha(), &PathBuf::from(""))?;
+  print_tree(repo, &tree.sha(), &PathBuf::from(""))?;
 
   Ok(())
 }
 
-fn print_tree(repo: &Repository, sha: &Sha, prefix: &PathBuf) -> Result<()> {
+fn print_tree(repo: &Repository, sha: &Sha, prefix: &Path) -> Result<()> {
   let tree = repo.resolve_object(&sha.hexdigest())?.as_tree()?;
 
   for (path, entry) in tree.entries() {
@@ -0,0 +1,147 @@
+use clap::{App, Arg, ArgMatches};
+
+use crate::prelude::*;
+
+pub fn command() -> Command {
+  (app, run)
+}
+
+fn app() -> ClapApp {
+  App::new("fsck")
+    .about("verify the connectivity and validity of the objects in the database")
+    .arg(
+      Arg::with_name("unreachable")
+        .long("unreachable")
+        .help("show all unreachable objects, not just dangling ones"),
+    )
+    .arg(
+      Arg::with_name("no-dangling")
+        .long("no-dangling")
+        .help("don't show dangling objects"),
+    )
+}
+
+fn run(matches: &ArgMatches, ctx: &Context) -> Result<()> {
+  let repo = ctx.repo()?;
+  let report = repo.fsck()?;
+
+  for err in &report.errors {
+    ctx.println(format!("error: {}", err));
+  }
+
+  let show_dangling = !matches.is_present("no-dangling");
+
+  for obj in &report.unreachable {
+    if matches.is_present("unreachable") {
+      ctx.println(format!("unreachable {} {}", obj.kind, obj.sha));
+    } else if obj.dangling && show_dangling {
+      ctx.println(format!("dangling {} {}", obj.kind, obj.sha));
+    }
+  }
+
+  match report.errors.len() {
+    0 => Ok(()),
+    n => Err(PidgitError::Generic(format!(
+      "fsck found {} problem{}",
+      n,
+      if n == 1 { "" } else { "s" }
+    ))),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::object::{Blob, GitObject};
+  use crate::test_prelude::*;
+
+  fn new_with_commits() -> TestRepo {
+    let tr = new_empty_repo();
+
+    tr.write_file("a.txt", "one\n");
+    tr.run_pidgit(vec!["add", "."]).unwrap();
+    tr.commit("first").unwrap();
+
+    tr.write_file("a.txt", "two\n");
+    tr.run_pidgit(vec!["add", "."]).unwrap();
+    tr.commit("second").unwrap();
+
+    tr
+  }
+
+  #[test]
+  fn clean_repo() {
+    let tr = new_with_commits();
+    assert_eq!(tr.run_pidgit(vec!["fsck"]).unwrap(), "");
+
+    // after a gc, everything's in a pack
+    tr.run_pidgit(vec!["gc"]).unwrap();
+    assert_eq!(tr.run_pidgit(vec!["fsck"]).unwrap(), "");
+  }
+
+  #[test]
+  fn dangling_and_unreachable() {
+    let tr = new_with_commits();
+
+    // nothing refers to this, not even the index
+    let blob = Blob::from_content(b"nobody loves me\n".to_vec());
+    tr.repo.write_object(&blob).unwrap();
+
+    let out = tr.run_pidgit(vec!["fsck"]).unwrap();
+    assert_eq!(out, format!("dangling blob {}\n", blob.sha()));
+
+    let out = tr.run_pidgit(vec!["fsck", "--no-dangling"]).unwrap();
+    assert_eq!(out, "");
+
+    let out = tr.run_pidgit(vec!["fsck", "--unreachable"]).unwrap();
+    assert_eq!(out, format!("unreachable blob {}\n", blob.sha()));
+  }
+
+  #[test]
+  fn corruption() {
+    let tr = new_with_commits();
+
+    // a blob whose content doesn't match its name
+    let fake = tr.write_raw_object("blob", b"fake\n");
+    let real = Blob::from_content(b"real\n".to_vec()).sha();
+    let path = tr.repo.path_for_sha(&real);
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::rename(tr.repo.path_for_sha(&fake), path).unwrap();
+
+    // a commit that isn't really a commit
+    let sha = tr.write_raw_object("commit", b"nope\n");
+
+    let err = tr.run_pidgit(vec!["fsck"]).unwrap_err();
+    assert_eq!(err.to_string(), "fsck found 2 problems");
+
+    let report = tr.repo.fsck().unwrap();
+    assert_eq!(report.errors.len(), 2);
+    assert!(report.errors.iter().any(|e| e.contains("hash mismatch")));
+    assert!(report
+      .errors
+      .contains(&format!("{}: invalid commit: missing tree", sha)));
+  }
+
+  #[test]
+  fn broken_links_and_refs() {
+    let tr = new_with_commits();
+
+    let head = tr.repo.resolve_object("HEAD").unwrap().as_commit().unwrap();
+    let tree = head.tree().clone();
+    std::fs::remove_file(tr.repo.path_for_sha(&tree)).unwrap();
+
+    let blob = tr.repo.resolve_object("HEAD~1:a.txt").unwrap().sha();
+    tr.repo
+      .grefs()
+      .create_ref("refs/heads/blob", &blob)
+      .unwrap();
+
+    let report = tr.repo.fsck().unwrap();
+    assert_eq!(
+      report.errors,
+      vec![
+        format!("broken link from commit {} to tree {}", head.sha(), tree),
+        "refs/heads/blob: not a commit".to_string(),
+      ]
+    );
+  }
+}
@@ -0,0 +1,189 @@
+use clap::{App, Arg, ArgMatches};
+use std::time::{Duration, SystemTime};
+
+use crate::prelude::*;
+
+const DEFAULT_EXPIRY: &str = "2.weeks.ago";
+
+pub fn command() -> Command {
+  (app, run)
+}
+
+fn app() -> ClapApp {
+  App::new("gc")
+    .about("cleanup unnecessary files and optimize the local repository")
+    .arg(
+      Arg::with_name("prune")
+        .long("prune")
+        .takes_value(true)
+        .value_name("date")
+        .default_value(DEFAULT_EXPIRY)
+        .help("prune unreachable loose objects older than date"),
+    )
+    .arg(
+      Arg::with_name("no-prune")
+        .long("no-prune")
+        .help("do not prune any unreachable loose objects"),
+    )
+}
+
+fn run(matches: &ArgMatches, ctx: &Context) -> Result<()> {
+  let repo = ctx.repo()?;
+
+  // parse this first, so we don't do a bunch of work and then die
+  let expire = if matches.is_present("no-prune") {
+    None
+  } else {
+    parse_expiry(matches.value_of("prune").unwrap())?
+  };
+
+  if let Some(stats) = repo.repack(false)? {
+    ctx.println(format!(
+      "packed {} objects ({} deltas) into {}",
+      stats.objects,
+      stats.deltas,
+      stats.path.file_name().unwrap().to_string_lossy(),
+    ));
+  }
+
+  let packed = repo.prune_packed()?;
+  if packed > 0 {
+    ctx.println(format!("removed {} packed loose objects", packed));
+  }
+
+  if let Some(expire) = expire {
+    let pruned = repo.prune_loose(expire)?;
+    if pruned > 0 {
+      ctx.println(format!("pruned {} unreachable objects", pruned));
+    }
+  }
+
+  Ok(())
+}
+
+// Git understands a truly bewildering variety of dates here. We understand
+// "now", "never", and things of the form "3.days.ago".
+fn parse_expiry(s: &str) -> Result<Option<SystemTime>> {
+  let bad = || PidgitError::Generic(format!("unrecognized expiry date: {}", s));
+
+  match s {
+    "now" => return Ok(Some(SystemTime::now())),
+    "never" => return Ok(None),
+    _ => {},
+  };
+
+  let hunks = s.split('.').collect::<Vec<_>>();
+  if hunks.len() != 3 || hunks[2] != "ago" {
+    return Err(bad());
+  }
+
+  let n = hunks[0].parse::<u64>().map_err(|_| bad())?;
+
+  let unit = match hunks[1].trim_end_matches('s') {
+    "second" => 1,
+    "minute" => 60,
+    "hour" => 60 * 60,
+    "day" => 24 * 60 * 60,
+    "week" => 7 * 24 * 60 * 60,
+    _ => return Err(bad()),
+  };
+
+  let ago = Duration::from_secs(n * unit);
+  Ok(Some(
+    SystemTime::now()
+      .checked_sub(ago)
+      .unwrap_or(SystemTime::UNIX_EPOCH),
+  ))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::parse_expiry;
+  use crate::object::{Blob, GitObject};
+  use crate::test_prelude::*;
+
+  fn new_with_history() -> TestRepo {
+    let tr = new_empty_repo();
+
+    let mut content = String::new();
+    for n in 0..10 {
+      for line in 0..20 {
+        content.push_str(&format!("commit {}, line {}\n", n, line));
+      }
+
+      tr.write_file("file.txt", &content);
+      tr.write_file(&format!("dir/{}.txt", n), "some file\n");
+      tr.commit_all();
+    }
+
+    tr
+  }
+
+  #[test]
+  fn expiry() {
+    assert!(parse_expiry("now").unwrap().is_some());
+    assert!(parse_expiry("never").unwrap().is_none());
+    assert!(parse_expiry("2.weeks.ago").unwrap().is_some());
+    assert!(parse_expiry("1.day.ago").unwrap().is_some());
+    assert!(parse_expiry("yesterday").is_err());
+    assert!(parse_expiry("3.fortnights.ago").is_err());
+  }
+
+  #[test]
+  fn gc_packs_reachable_objects() {
+    let tr = new_with_history();
+    let before = tr.run_pidgit(vec!["log"]).unwrap();
+    let head = tr.run_pidgit(vec!["cat-file", "-p", "HEAD"]).unwrap();
+
+    let stdout = tr.run_pidgit(vec!["gc"]).unwrap();
+    assert!(stdout.contains("deltas) into pack-"), "output: {}", stdout);
+
+    assert!(tr.repo.loose_objects().unwrap().is_empty());
+    assert_eq!(tr.run_pidgit(vec!["log"]).unwrap(), before);
+    assert_eq!(tr.run_pidgit(vec!["cat-file", "-p", "HEAD"]).unwrap(), head);
+    assert_eq!(tr.run_pidgit(vec!["status", "-s"]).unwrap(), "");
+
+    // nothing left to do the second time around
+    assert_eq!(tr.run_pidgit(vec!["gc"]).unwrap(), "");
+  }
+
+  #[test]
+  fn gc_keeps_recent_unreachable_objects() {
+    let tr = new_with_history();
+
+    let orphan = Blob::from_content(b"nobody loves me\n".to_vec());
+    tr.repo.write_object(&orphan).unwrap();
+
+    tr.run_pidgit(vec!["gc"]).unwrap();
+    assert!(tr.repo.path_for_sha(&orphan.sha()).is_file());
+
+    let stdout = tr.run_pidgit(vec!["gc", "--prune=now"]).unwrap();
+    assert_eq!(stdout, "pruned 1 unreachable objects\n");
+    assert!(!tr.repo.has_object(&orphan.sha()));
+  }
+
+  #[test]
+  fn repack_all() {
+    let tr = new_with_history();
+
+    tr.run_pidgit(vec!["repack", "-d"]).unwrap();
+    tr.write_file("new.txt", "new file\n");
+    tr.commit_all();
+    tr.run_pidgit(vec!["repack", "-d"]).unwrap();
+
+    let pack_dir = tr.repo.git_dir().join("objects/pack");
+    let count_packs = || {
+      std::fs::read_dir(&pack_dir)
+        .unwrap()
+        .filter(|e| e.as_ref().unwrap().path().extension().unwrap() == "pack")
+        .count()
+    };
+
+    assert_eq!(count_packs(), 2);
+
+    tr.run_pidgit(vec!["repack", "-a", "-d"]).unwrap();
+    assert_eq!(count_packs(), 1);
+    assert!(tr.repo.loose_objects().unwrap().is_empty());
+    assert_eq!(tr.run_pidgit(vec!["status", "-s"]).unwrap(), "");
+  }
+}
@@ -4,9 +4,6 @@ use std::path::PathBuf;
 use crate::object::Blob;
 use crate::prelude::*;
 
-#[derive(Debug)]
-struct HashObject;
-
 pub fn command() -> Command {
   (app, run)
 }
@@ -59,7 +56,7 @@ fn run(matches: &ArgMatches, ctx: &Context) -> Result<()> {
     repo.unwrap().write_object(&blob)?;
   }
 
-  ctx.println(format!("{}", blob.sha().hexdigest()));
+  ctx.println(blob.sha().hexdigest().to_string());
 
   Ok(())
 }
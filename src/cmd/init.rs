@@ -2,9 +2,6 @@ use clap::{App, ArgMatches};
 
 use crate::prelude::*;
 
-#[derive(Debug)]
-struct Init;
-
 pub fn command() -> Command {
   (app, run)
 }
@@ -70,13 +67,13 @@ mod tests {
     use std::io::Cursor;
 
     let dir = tempdir();
-    std::env::set_current_dir(&dir.path())?;
+    std::env::set_current_dir(dir.path())?;
 
     // this is silly, but this test is also like, the only place we need to run
     // outside of a repository
     let mut app = pidgit::new();
     let mut stdout = Cursor::new(vec![]);
-    let matches = app.clap_app().get_matches_from_safe(&["pidgit", "init"])?;
+    let matches = app.clap_app().get_matches_from_safe(["pidgit", "init"])?;
 
     app.dispatch(&matches, None, &mut stdout, std::env::current_dir()?)?;
 
@@ -1,46 +1,487 @@
 use clap::{App, Arg, ArgMatches};
 
-use crate::object::Object;
+use crate::graph::Graph;
+use crate::object::Commit;
 use crate::prelude::*;
+use crate::pretty::{DateFormat, Format, Pretty};
+use crate::repo::{RevList, SortOrder};
 
 pub fn command() -> Command {
   (app, run)
 }
 
 pub fn app() -> ClapApp {
-  App::new("log").about("show commit logs").arg(
-    Arg::with_name("ref")
-      .default_value("HEAD")
-      .help("ref to start"),
-  )
+  App::new("log")
+    .about("show commit logs")
+    .arg(
+      Arg::with_name("max-count")
+        .short("n")
+        .long("max-count")
+        .takes_value(true)
+        .value_name("number")
+        .help("limit the number of commits to output"),
+    )
+    .arg(
+      Arg::with_name("topo-order")
+        .long("topo-order")
+        .help("show no parents before all of their children are shown"),
+    )
+    .arg(
+      Arg::with_name("date-order")
+        .long("date-order")
+        .overrides_with("topo-order")
+        .help("show commits in commit timestamp order (default)"),
+    )
+    .arg(
+      Arg::with_name("reverse")
+        .long("reverse")
+        .help("output the selected commits in reverse order"),
+    )
+    .arg(
+      Arg::with_name("graph")
+        .long("graph")
+        .conflicts_with("reverse")
+        .help("draw the commit history as a graph (implies --topo-order)"),
+    )
+    .arg(
+      Arg::with_name("pretty")
+        .long("pretty")
+        .alias("format")
+        .takes_value(true)
+        .value_name("format")
+        .default_value("medium")
+        .help(
+          "pretty-print commits in this format: oneline, short, medium, full, \
+           fuller, raw, or format:<string>",
+        ),
+    )
+    .arg(
+      Arg::with_name("oneline")
+        .long("oneline")
+        .help("shorthand for --pretty=oneline --abbrev-commit"),
+    )
+    .arg(
+      Arg::with_name("date")
+        .long("date")
+        .takes_value(true)
+        .value_name("format")
+        .possible_values(DateFormat::NAMES)
+        .default_value("default")
+        .help("format dates this way"),
+    )
+    .arg(
+      Arg::with_name("revisions")
+        .multiple(true)
+        .default_value("HEAD")
+        .allow_hyphen_values(true)
+        .help("commits to start from (^rev to exclude, or a..b)"),
+    )
+    .arg(
+      Arg::with_name("follow")
+        .long("follow")
+        .help("continue listing the history of a file beyond renames"),
+    )
+    .arg(
+      Arg::with_name("paths")
+        .multiple(true)
+        .last(true)
+        .help("only show commits that change these paths"),
+    )
 }
 
 fn run(matches: &ArgMatches, ctx: &Context) -> Result<()> {
   let repo = ctx.repo()?;
 
-  let to_find = matches.value_of("ref").unwrap();
-  let object = repo.resolve_object(to_find)?;
-
-  let mut c = match object {
-    Object::Commit(commit) => commit,
-    _ => {
-      return Err(PidgitError::Generic(format!(
-        "ref {} is not a commit!",
-        to_find,
-      )))
-    },
+  let mut revs = RevList::new(repo);
+
+  for rev in matches.values_of("revisions").unwrap() {
+    revs.add_rev(rev)?;
+  }
+
+  if let Some(paths) = matches.values_of("paths") {
+    let paths = paths
+      .map(|p| ctx.relative_path(p))
+      .collect::<Result<Vec<_>>>()?;
+
+    if matches.is_present("follow") && paths.len() != 1 {
+      return Err(PidgitError::Generic(
+        "--follow requires exactly one pathspec".to_string(),
+      ));
+    }
+
+    revs.set_paths(paths);
+    revs.set_follow(matches.is_present("follow"));
+  }
+
+  // the graph only makes sense if parents come after their children, and
+  // every parent it draws a line to actually shows up
+  if matches.is_present("topo-order") || matches.is_present("graph") {
+    revs.set_order(SortOrder::Topo);
+  }
+
+  if matches.is_present("graph") {
+    revs.rewrite_parents();
+  }
+
+  let mut graph = if matches.is_present("graph") {
+    Some(Graph::new())
+  } else {
+    None
+  };
+
+  let max = match matches.value_of("max-count") {
+    Some(n) => n
+      .parse::<usize>()
+      .map_err(|_| PidgitError::Generic(format!("invalid max count: {}", n)))?,
+    None => usize::MAX,
+  };
+
+  let oneline = matches.is_present("oneline");
+  let format = if oneline {
+    Format::Oneline
+  } else {
+    Format::parse(matches.value_of("pretty").unwrap())?
   };
 
-  // this is terrible, but expedient
-  loop {
-    ctx.println(format!("{} {}", &c.sha().hexdigest()[0..8], c.title()));
-    let mut parents = c.parents(&repo);
-    if let Some(parent) = parents.pop() {
-      c = parent;
+  let dates = DateFormat::parse(matches.value_of("date").unwrap())?;
+  let mut pretty = Pretty::new(repo, format, dates)?;
+  if oneline {
+    pretty = pretty.abbrev_commit();
+  }
+  let spaced = pretty.format().spaced();
+
+  // we can't reverse without having the whole list
+  let commits: Box<dyn Iterator<Item = Result<Commit>>> =
+    if matches.is_present("reverse") {
+      Box::new(revs.take(max).collect::<Vec<_>>().into_iter().rev())
     } else {
-      break;
+      Box::new(revs.take(max))
+    };
+
+  ctx.setup_pager()?;
+
+  for (i, commit) in commits.enumerate() {
+    let commit = commit?;
+    let text = pretty.show(&commit);
+
+    let graph = match graph.as_mut() {
+      Some(graph) => graph,
+      None => {
+        if i > 0 && spaced {
+          ctx.println("".to_string());
+        }
+
+        ctx.println(text);
+        continue;
+      },
+    };
+
+    let rows = graph.next_commit(&commit.sha(), &commit.parent_shas);
+
+    if i > 0 && spaced {
+      ctx.println(graph.separator().to_string());
+    }
+
+    let lines = text.lines().collect::<Vec<_>>();
+
+    for n in 0..rows.len().max(lines.len()) {
+      let prefix = rows.get(n).cloned().unwrap_or_else(|| graph.padding());
+      ctx.println(format!("{}{}", prefix, lines.get(n).unwrap_or(&"")));
     }
   }
 
   Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+  use crate::object::GitObject;
+  use crate::test_prelude::*;
+
+  fn titles(tr: &TestRepo, args: &[&str]) -> String {
+    let mut full = vec!["log", "--oneline"];
+    full.extend(args);
+
+    tr.run_pidgit(full)
+      .unwrap()
+      .lines()
+      .map(|l| l.split(' ').nth(1).unwrap())
+      .collect::<Vec<_>>()
+      .join(" ")
+  }
+
+  #[test]
+  fn walks_merges() {
+    let tr = new_with_merge();
+
+    assert_eq!(titles(&tr, &[]), "F C E D B A");
+    assert_eq!(titles(&tr, &["--topo-order"]), "F E D C B A");
+    assert_eq!(titles(&tr, &["--reverse"]), "A B D E C F");
+    assert_eq!(titles(&tr, &["-n", "2"]), "F C");
+    assert_eq!(titles(&tr, &["-n", "2", "--reverse"]), "C F");
+  }
+
+  #[test]
+  fn exclusions() {
+    let tr = new_with_merge();
+
+    assert_eq!(titles(&tr, &["topic..main"]), "F C");
+    assert_eq!(titles(&tr, &["main", "^topic"]), "F C");
+    assert_eq!(titles(&tr, &["main..topic"]), "");
+    assert_eq!(titles(&tr, &["HEAD~1..topic"]), "E D");
+    assert_eq!(titles(&tr, &["topic", "HEAD~1", "^HEAD~2"]), "C E D");
+  }
+
+  #[test]
+  fn graph() {
+    let tr = new_with_merge();
+
+    // keep the graph and the titles, but not the shas
+    let out = tr
+      .run_pidgit(vec!["log", "--graph", "--oneline"])
+      .unwrap()
+      .lines()
+      .map(|l| match l.find(|c: char| c.is_ascii_hexdigit()) {
+        Some(n) => format!("{}{}", &l[..n], &l[n + 9..]),
+        None => l.to_string(),
+      })
+      .collect::<Vec<_>>()
+      .join("\n");
+
+    assert_eq!(
+      out,
+      "*   F\n\
+       |\\  \n\
+       | * E\n\
+       | * D\n\
+       * | C\n\
+       |/  \n\
+       * B\n\
+       * A"
+    );
+  }
+
+  #[test]
+  fn medium_format() {
+    let tr = new_with_merge();
+    let head = tr.repo.head().unwrap().unwrap();
+    let parents = head
+      .parent_shas
+      .iter()
+      .map(|p| p.short(8))
+      .collect::<Vec<_>>();
+
+    let out = tr.run_pidgit(vec!["log", "-n", "1"]).unwrap();
+
+    assert_eq!(
+      out,
+      format!(
+        "commit {}\nMerge: {}\nAuthor: {}\nDate:   {}\n\n    F\n",
+        head.sha(),
+        parents.join(" "),
+        head.author,
+        head.author.date.format("%a %b %-d %H:%M:%S %Y %z")
+      )
+    );
+  }
+
+  #[test]
+  fn user_format() {
+    let tr = new_with_merge();
+    tr.run_pidgit(vec!["tag", "v1", "topic~1"]).unwrap();
+
+    let out = tr
+      .run_pidgit(vec!["log", "-n", "3", "--format=%s%d"])
+      .unwrap();
+    assert_eq!(out, "F (HEAD -> main)\nC\nE (topic)\n");
+
+    let out = tr
+      .run_pidgit(vec![
+        "log",
+        "--date=iso",
+        "--pretty=format:%s%d %cd %an <%ae>",
+        "topic",
+      ])
+      .unwrap();
+    assert_eq!(
+      out,
+      "E (topic) 2020-09-13 12:29:40 +0000 Pidgit <pidgit@example.com>\n\
+       D (tag: v1) 2020-09-13 12:28:40 +0000 Pidgit <pidgit@example.com>\n\
+       B 2020-09-13 12:27:40 +0000 Pidgit <pidgit@example.com>\n\
+       A 2020-09-13 12:26:40 +0000 Pidgit <pidgit@example.com>\n"
+    );
+
+    // shas, and things we don't know about are left alone
+    let head = tr.repo.head().unwrap().unwrap();
+    let out = tr
+      .run_pidgit(vec!["log", "-n", "1", "--pretty=%h %t %p%n%%%x"])
+      .unwrap();
+    let parents = head
+      .parent_shas
+      .iter()
+      .map(|p| p.short(8))
+      .collect::<Vec<_>>();
+
+    assert_eq!(
+      out,
+      format!(
+        "{} {} {}\n%%x\n",
+        head.sha().short(8),
+        head.tree().short(8),
+        parents.join(" ")
+      )
+    );
+  }
+
+  #[test]
+  fn presets() {
+    let tr = new_with_merge();
+
+    let out = tr
+      .run_pidgit(vec![
+        "log",
+        "-n",
+        "1",
+        "--pretty=fuller",
+        "--date=unix",
+        "HEAD~1",
+      ])
+      .unwrap();
+    let sha = tr.repo.resolve_object("HEAD~1").unwrap().sha();
+
+    assert_eq!(
+      out,
+      format!(
+        "commit {}\n\
+         Author:     Pidgit <pidgit@example.com>\n\
+         AuthorDate: 1600000240\n\
+         Commit:     Pidgit <pidgit@example.com>\n\
+         CommitDate: 1600000240\n\
+         \n    C\n",
+        sha
+      )
+    );
+
+    let out = tr
+      .run_pidgit(vec!["log", "-n", "1", "--pretty=short", "HEAD~1"])
+      .unwrap();
+    assert_eq!(
+      out,
+      format!(
+        "commit {}\nAuthor: Pidgit <pidgit@example.com>\n\n    C\n",
+        sha
+      )
+    );
+
+    // --oneline abbreviates, but --pretty=oneline doesn't
+    let out = tr.run_pidgit(vec!["log", "-n", "1", "--oneline", "HEAD~1"]);
+    assert_eq!(out.unwrap(), format!("{} C\n", sha.short(8)));
+
+    let out = tr
+      .run_pidgit(vec!["log", "-n", "1", "--pretty=oneline", "HEAD~1"])
+      .unwrap();
+    assert_eq!(out, format!("{} C\n", sha));
+
+    assert!(tr.run_pidgit(vec!["log", "--pretty=nope"]).is_err());
+  }
+
+  #[test]
+  fn limited_to_paths() {
+    let tr = new_with_merge();
+
+    assert_eq!(titles(&tr, &["--", "D.txt"]), "D");
+    assert_eq!(titles(&tr, &["--", "E.txt", "A.txt"]), "E A");
+    assert_eq!(titles(&tr, &["--", "nope.txt"]), "");
+
+    // the merge doesn't change C.txt relative to C, so we only follow main
+    assert_eq!(titles(&tr, &["--", "C.txt", "B.txt"]), "C B");
+
+    // but it changes something relative to both parents here
+    assert_eq!(titles(&tr, &["--", "C.txt", "D.txt"]), "F C D");
+  }
+
+  #[test]
+  fn graph_limited_to_paths() {
+    let tr = new_with_merge();
+
+    let out = tr
+      .run_pidgit(vec![
+        "log",
+        "--graph",
+        "--format=%s",
+        "--",
+        "C.txt",
+        "D.txt",
+      ])
+      .unwrap();
+
+    assert_eq!(out, "*   F\n|\\  \n| * D\n* C\n");
+  }
+
+  #[test]
+  fn follow_renames() {
+    let tr = new_empty_repo();
+    let lines = (1..=10).map(|n| format!("{}\n", n)).collect::<String>();
+
+    tr.write_file("old.txt", &lines);
+    tr.commit_all();
+
+    // renamed, with a change
+    tr.rm_file("old.txt");
+    tr.repo.index_mut().remove("old.txt".as_ref());
+    tr.write_file("new.txt", &(lines + "11\n"));
+    tr.run_pidgit(vec!["add", "."]).unwrap();
+    tr.commit("rename").unwrap();
+
+    tr.write_file("new.txt", "something else entirely\n");
+    tr.run_pidgit(vec!["add", "."]).unwrap();
+    tr.commit("rewrite").unwrap();
+
+    let log = |args: &[&str]| {
+      let mut full = vec!["log", "--format=%s"];
+      full.extend(args);
+      tr.run_pidgit(full).unwrap()
+    };
+
+    assert_eq!(log(&["--", "new.txt"]), "rewrite\nrename\n");
+    assert_eq!(
+      log(&["--follow", "--", "new.txt"]),
+      "rewrite\nrename\nauto commit message\n"
+    );
+
+    assert!(tr
+      .run_pidgit(vec!["log", "--follow", "--", "a", "b"])
+      .is_err());
+  }
+
+  #[test]
+  fn malformed_commit() {
+    let tr = new_empty_repo();
+    tr.write_file("a.txt", "a\n");
+    tr.run_pidgit(vec!["add", "."]).unwrap();
+    tr.commit("first").unwrap();
+
+    let tree = tr.repo.resolve_object("HEAD^{tree}").unwrap().sha();
+    let content = format!(
+      "tree {}\nparent {}\nauthor nobody\ncommitter nobody\n\nbroken\n",
+      tree,
+      tr.repo.resolve_object("HEAD").unwrap().sha(),
+    );
+    let broken = tr.write_raw_object("commit", content.as_bytes());
+    tr.repo
+      .grefs()
+      .create_ref("refs/heads/broken", &broken)
+      .unwrap();
+
+    // the author ident starts 101 bytes in, after the tree and parent lines
+    // and "author "
+    let err = tr.run_pidgit(vec!["log", "broken"]).unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      format!("malformed commit {}: missing email at byte 101", broken)
+    );
+
+    // but the rest of history is fine
+    assert!(tr.run_pidgit(vec!["log", "main"]).is_ok());
+  }
+}
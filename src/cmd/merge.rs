@@ -0,0 +1,392 @@
+use clap::{App, Arg, ArgMatches};
+
+use crate::prelude::*;
+
+pub fn command() -> Command {
+  (app, run)
+}
+
+fn app() -> ClapApp {
+  App::new("merge")
+    .about("join two development histories together")
+    .arg(
+      Arg::with_name("message")
+        .short("m")
+        .long("message")
+        .takes_value(true)
+        .value_name("msg")
+        .help("use this as the message for the merge commit"),
+    )
+    .arg(
+      Arg::with_name("abort")
+        .long("abort")
+        .conflicts_with_all(&["message", "commit"])
+        .help("give up on the current merge, and go back to HEAD"),
+    )
+    .arg(
+      Arg::with_name("commit")
+        .required_unless("abort")
+        .help("commit to merge into the current branch"),
+    )
+}
+
+fn run(matches: &ArgMatches, ctx: &Context) -> Result<()> {
+  let repo = ctx.repo()?;
+
+  if matches.is_present("abort") {
+    return abort(repo);
+  }
+
+  if repo.merge_head().is_some() {
+    return Err(PidgitError::Generic(
+      "You have not concluded your merge (MERGE_HEAD exists).\n\
+       Please, commit your changes before you merge."
+        .into(),
+    ));
+  }
+
+  if repo.index().has_unmerged() {
+    return Err(PidgitError::Generic(
+      "Merging is not possible because you have unmerged files.".into(),
+    ));
+  }
+
+  let rev = matches.value_of("commit").unwrap();
+  let theirs = repo.resolve_commit(rev)?;
+  let ours = repo.head()?.ok_or_else(|| {
+    PidgitError::Generic("cannot merge into a branch with no commits".into())
+  })?;
+
+  let (our_sha, their_sha) = (ours.sha(), theirs.sha());
+
+  if repo.is_ancestor(&their_sha, &our_sha)? {
+    ctx.println("Already up to date.".to_string());
+    return Ok(());
+  }
+
+  let who = repo.whoami()?;
+
+  if repo.is_ancestor(&our_sha, &their_sha)? {
+    repo.migrate_to(&theirs)?;
+    repo.grefs().update_head(
+      &their_sha,
+      &who,
+      &format!("merge {}: Fast-forward", rev),
+    )?;
+
+    ctx.println(format!(
+      "Updating {}..{}\nFast-forward",
+      our_sha.short(8),
+      their_sha.short(8)
+    ));
+    return Ok(());
+  }
+
+  if repo.status()?.has_index_changes() {
+    return Err(PidgitError::Generic(
+      "Your index contains uncommitted changes.\n\
+       Please commit your changes before you merge."
+        .into(),
+    ));
+  }
+
+  let message = match matches.value_of("message") {
+    Some(msg) => msg.to_string(),
+    None => default_message(repo, rev),
+  };
+
+  let base = repo.merge_base(&our_sha, &their_sha)?;
+  let conflicts = repo.merge_trees(base.as_ref(), &ours, &theirs, rev)?;
+
+  repo.start_merge(&their_sha, &message)?;
+
+  if !conflicts.is_empty() {
+    for conflict in conflicts {
+      ctx.println(conflict.describe("HEAD", rev));
+    }
+
+    return Err(PidgitError::Generic(
+      "Automatic merge failed; fix conflicts and then commit the result.".into(),
+    ));
+  }
+
+  let commit = repo.commit(&message, repo.author()?, who)?;
+
+  ctx.println(format!("[{}] {}", commit.sha().short(8), commit.title()));
+  Ok(())
+}
+
+// Put everything back the way it was at HEAD.
+fn abort(repo: &Repository) -> Result<()> {
+  if repo.merge_head().is_none() {
+    return Err(PidgitError::Generic(
+      "There is no merge to abort (MERGE_HEAD missing).".into(),
+    ));
+  }
+
+  let head = repo.head()?.ok_or_else(|| {
+    PidgitError::Generic("cannot abort a merge with no HEAD".into())
+  })?;
+
+  let previous = repo.tracked_paths()?;
+
+  repo.reset_index(head.tree(), &[])?;
+  repo.reset_workspace(&previous)?;
+  repo.clear_merge_state()
+}
+
+fn default_message(repo: &Repository, rev: &str) -> String {
+  let is_branch = repo
+    .grefs()
+    .path_for_name(rev)
+    .is_some_and(|name| name.starts_with("refs/heads/"));
+
+  if is_branch {
+    format!("Merge branch '{}'", rev)
+  } else {
+    format!("Merge commit '{}'", rev)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::object::GitObject;
+  use crate::test_prelude::*;
+
+  // main and topic both branch off a commit with two files
+  fn new_with_branches() -> TestRepo {
+    let tr = new_empty_repo();
+
+    tr.write_file("shared.txt", "one\ntwo\nthree\nfour\nfive\n");
+    tr.write_file("other.txt", "other\n");
+    tr.commit_all();
+
+    tr.run_pidgit(vec!["branch", "topic"]).unwrap();
+    tr
+  }
+
+  fn read(tr: &TestRepo, path: &str) -> String {
+    std::fs::read_to_string(tr.repo.workspace().canonicalize(&path)).unwrap()
+  }
+
+  fn exists(tr: &TestRepo, path: &str) -> bool {
+    tr.repo.workspace().root().join(path).exists()
+  }
+
+  // add doesn't stage deletions, so we have to do it by hand
+  fn delete(tr: &TestRepo, path: &str) {
+    tr.rm_file(path);
+    tr.repo.index_mut().remove(path.as_ref());
+    tr.repo.write_index().unwrap();
+  }
+
+  fn head_parents(tr: &TestRepo) -> usize {
+    tr.repo.head().unwrap().unwrap().parent_shas.len()
+  }
+
+  #[test]
+  fn fast_forward() {
+    let tr = new_with_branches();
+    let main = tr.repo.head().unwrap().unwrap().sha();
+
+    tr.run_pidgit(vec!["switch", "topic"]).unwrap();
+    tr.write_file("new.txt", "new\n");
+    tr.commit_all();
+    let topic = tr.repo.head().unwrap().unwrap().sha();
+
+    tr.run_pidgit(vec!["switch", "main"]).unwrap();
+
+    let out = tr.run_pidgit(vec!["merge", "topic"]).unwrap();
+    assert_eq!(
+      out,
+      format!(
+        "Updating {}..{}\nFast-forward\n",
+        main.short(8),
+        topic.short(8)
+      )
+    );
+
+    assert_eq!(tr.repo.head().unwrap().unwrap().sha(), topic);
+    assert!(exists(&tr, "new.txt"));
+
+    let out = tr.run_pidgit(vec!["merge", "topic"]).unwrap();
+    assert_eq!(out, "Already up to date.\n");
+  }
+
+  #[test]
+  fn clean_merge() {
+    let tr = new_with_branches();
+
+    tr.write_file("shared.txt", "ONE\ntwo\nthree\nfour\nfive\n");
+    tr.commit_all();
+
+    tr.run_pidgit(vec!["switch", "topic"]).unwrap();
+    tr.write_file("shared.txt", "one\ntwo\nthree\nfour\nFIVE\n");
+    tr.write_file("topic.txt", "topic\n");
+    delete(&tr, "other.txt");
+    tr.commit_all();
+
+    tr.run_pidgit(vec!["switch", "main"]).unwrap();
+    let out = tr.run_pidgit(vec!["merge", "topic"]).unwrap();
+    assert!(out.ends_with("Merge branch 'topic'\n"));
+
+    assert_eq!(read(&tr, "shared.txt"), "ONE\ntwo\nthree\nfour\nFIVE\n");
+    assert_eq!(read(&tr, "topic.txt"), "topic\n");
+    assert!(!exists(&tr, "other.txt"));
+
+    assert_eq!(head_parents(&tr), 2);
+    assert!(tr.repo.merge_head().is_none());
+
+    let status = tr.run_pidgit(vec!["status", "-s"]).unwrap();
+    assert_eq!(status, "");
+
+    let reflog = tr.run_pidgit(vec!["reflog"]).unwrap();
+    assert!(reflog
+      .lines()
+      .next()
+      .unwrap()
+      .ends_with("commit (merge): Merge branch 'topic'"));
+  }
+
+  #[test]
+  fn conflicted_merge() {
+    let tr = new_with_branches();
+
+    tr.write_file("shared.txt", "one\nTWO\nthree\nfour\nfive\n");
+    tr.commit_all();
+
+    tr.run_pidgit(vec!["switch", "topic"]).unwrap();
+    tr.write_file("shared.txt", "one\ndeux\nthree\nfour\nfive\n");
+    tr.write_file("other.txt", "changed on topic\n");
+    tr.commit_all();
+
+    tr.run_pidgit(vec!["switch", "main"]).unwrap();
+    let err = tr.run_pidgit(vec!["merge", "topic"]).unwrap_err();
+    assert!(err.to_string().starts_with("Automatic merge failed"));
+
+    assert_eq!(
+      read(&tr, "shared.txt"),
+      "one\n<<<<<<< HEAD\nTWO\n=======\ndeux\n>>>>>>> topic\nthree\nfour\nfive\n"
+    );
+    assert_eq!(read(&tr, "other.txt"), "changed on topic\n");
+
+    let stages = tr
+      .repo
+      .index()
+      .unmerged()
+      .map(|(_, entries)| entries.iter().map(|e| e.stage()).collect())
+      .collect::<Vec<Vec<_>>>();
+    assert_eq!(stages, vec![vec![1, 2, 3]]);
+
+    let status = tr.run_pidgit(vec!["status", "-s"]).unwrap();
+    assert_eq!(status, "M  other.txt\nUU shared.txt\n");
+
+    // can't commit until it's resolved
+    assert!(tr.commit("too soon").is_err());
+
+    tr.write_file("shared.txt", "one\nzwei\nthree\nfour\nfive\n");
+    tr.run_pidgit(vec!["add", "shared.txt"]).unwrap();
+    tr.run_pidgit(vec!["commit"]).unwrap();
+
+    let head = tr.repo.head().unwrap().unwrap();
+    assert_eq!(head.parent_shas.len(), 2);
+    assert_eq!(head.title(), "Merge branch 'topic'");
+    assert!(tr.repo.merge_head().is_none());
+  }
+
+  #[test]
+  fn modify_delete_conflict() {
+    let tr = new_with_branches();
+
+    delete(&tr, "other.txt");
+    tr.commit_all();
+
+    tr.run_pidgit(vec!["switch", "topic"]).unwrap();
+    tr.write_file("other.txt", "changed on topic\n");
+    tr.commit_all();
+
+    tr.run_pidgit(vec!["switch", "main"]).unwrap();
+    let out = tr.run_pidgit(vec!["merge", "topic"]);
+    assert!(out.is_err());
+
+    assert_eq!(read(&tr, "other.txt"), "changed on topic\n");
+
+    let status = tr.run_pidgit(vec!["status", "-s"]).unwrap();
+    assert_eq!(status, "DU other.txt\n");
+  }
+
+  #[test]
+  fn abort() {
+    let tr = new_with_branches();
+
+    tr.write_file("shared.txt", "one\nTWO\nthree\nfour\nfive\n");
+    tr.commit_all();
+    let head = tr.repo.head().unwrap().unwrap().sha();
+
+    tr.run_pidgit(vec!["switch", "topic"]).unwrap();
+    tr.write_file("shared.txt", "one\ndeux\nthree\nfour\nfive\n");
+    tr.write_file("topic.txt", "topic\n");
+    tr.commit_all();
+
+    tr.run_pidgit(vec!["switch", "main"]).unwrap();
+    assert!(tr.run_pidgit(vec!["merge", "topic"]).is_err());
+    assert!(tr.run_pidgit(vec!["merge", "topic"]).is_err());
+
+    tr.run_pidgit(vec!["merge", "--abort"]).unwrap();
+
+    assert_eq!(tr.repo.head().unwrap().unwrap().sha(), head);
+    assert!(tr.repo.merge_head().is_none());
+    assert!(!tr.repo.index().has_unmerged());
+    assert_eq!(read(&tr, "shared.txt"), "one\nTWO\nthree\nfour\nfive\n");
+    assert!(!exists(&tr, "topic.txt"));
+
+    let status = tr.run_pidgit(vec!["status", "-s"]).unwrap();
+    assert_eq!(status, "");
+  }
+
+  #[test]
+  fn refuses_to_clobber_local_changes() {
+    let tr = new_with_branches();
+
+    tr.write_file("shared.txt", "ONE\ntwo\nthree\nfour\nfive\n");
+    tr.commit_all();
+
+    tr.run_pidgit(vec!["switch", "topic"]).unwrap();
+    tr.write_file("other.txt", "topic\n");
+    tr.commit_all();
+
+    tr.run_pidgit(vec!["switch", "main"]).unwrap();
+    tr.write_file("other.txt", "local\n");
+
+    let err = tr.run_pidgit(vec!["merge", "topic"]).unwrap_err();
+    assert!(err.to_string().contains("would be overwritten by merge"));
+    assert!(tr.repo.merge_head().is_none());
+    assert_eq!(read(&tr, "other.txt"), "local\n");
+  }
+
+  #[test]
+  fn merge_bases() {
+    let tr = new_with_branches();
+    let base = tr.repo.head().unwrap().unwrap().sha();
+
+    tr.write_file("main.txt", "main\n");
+    tr.commit_all();
+    let main = tr.repo.head().unwrap().unwrap().sha();
+
+    tr.run_pidgit(vec!["switch", "topic"]).unwrap();
+    tr.write_file("topic.txt", "topic\n");
+    tr.commit_all();
+    let topic = tr.repo.head().unwrap().unwrap().sha();
+
+    assert_eq!(
+      tr.repo.merge_bases(&main, &topic).unwrap(),
+      vec![base.clone()]
+    );
+    assert_eq!(
+      tr.repo.merge_bases(&topic, &base).unwrap(),
+      vec![base.clone()]
+    );
+    assert!(tr.repo.is_ancestor(&base, &main).unwrap());
+    assert!(!tr.repo.is_ancestor(&main, &topic).unwrap());
+  }
+}
@@ -0,0 +1,181 @@
+use clap::{App, Arg, ArgMatches};
+
+use crate::prelude::*;
+
+pub fn command() -> Command {
+  (app, run)
+}
+
+fn app() -> ClapApp {
+  App::new("merge-base")
+    .about("find as good common ancestors as possible for a merge")
+    .arg(
+      Arg::with_name("all")
+        .short("a")
+        .long("all")
+        .help("output all merge bases, not just one"),
+    )
+    .arg(
+      Arg::with_name("is-ancestor")
+        .long("is-ancestor")
+        .conflicts_with_all(&["all", "octopus"])
+        .help("check if the first commit is an ancestor of the second"),
+    )
+    .arg(
+      Arg::with_name("octopus")
+        .long("octopus")
+        .help("find the best common ancestors of all commits"),
+    )
+    .arg(
+      Arg::with_name("commits")
+        .multiple(true)
+        .required(true)
+        .help("commits to find the common ancestors of"),
+    )
+}
+
+fn run(matches: &ArgMatches, ctx: &Context) -> Result<()> {
+  let repo = ctx.repo()?;
+
+  let commits = matches
+    .values_of("commits")
+    .unwrap()
+    .map(|name| repo.resolve_commit(name).map(|c| c.sha()))
+    .collect::<Result<Vec<_>>>()?;
+
+  if matches.is_present("is-ancestor") {
+    if commits.len() != 2 {
+      return Err(PidgitError::Generic(
+        "--is-ancestor takes exactly two commits".to_string(),
+      ));
+    }
+
+    if !repo.is_ancestor(&commits[0], &commits[1])? {
+      std::process::exit(1); // like git, the answer is the exit status
+    }
+
+    return Ok(());
+  }
+
+  let bases = if matches.is_present("octopus") {
+    repo.merge_bases_octopus(&commits)?
+  } else if commits.len() < 2 {
+    return Err(PidgitError::Generic(
+      "merge-base needs at least two commits".to_string(),
+    ));
+  } else {
+    repo.merge_bases_many(&commits[0], &commits[1..])?
+  };
+
+  if bases.is_empty() {
+    std::process::exit(1);
+  }
+
+  let count = if matches.is_present("all") {
+    bases.len()
+  } else {
+    1
+  };
+
+  for sha in bases.iter().take(count) {
+    ctx.println(sha.to_string());
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::test_prelude::*;
+
+  fn merge(tr: &TestRepo, branch: &str) {
+    tr.run_pidgit(vec!["merge", branch, "-m", "merge"]).unwrap();
+  }
+
+  //   A - B - M1   (main)
+  //    \   \ /
+  //     \   X
+  //      \ / \
+  //       C - M2   (topic)
+  #[test]
+  fn criss_cross() {
+    let tr = new_empty_repo();
+
+    tr.commit_file("A");
+    tr.run_pidgit(vec!["branch", "topic"]).unwrap();
+    let b = tr.commit_file("B");
+    tr.run_pidgit(vec!["switch", "topic"]).unwrap();
+    let c = tr.commit_file("C");
+    tr.run_pidgit(vec!["switch", "main"]).unwrap();
+    tr.run_pidgit(vec!["branch", "main-before"]).unwrap();
+    merge(&tr, "topic");
+    tr.run_pidgit(vec!["switch", "topic"]).unwrap();
+    merge(&tr, "main-before");
+
+    let mut all = tr
+      .run_pidgit(vec!["merge-base", "--all", "main", "topic"])
+      .unwrap()
+      .lines()
+      .map(String::from)
+      .collect::<Vec<_>>();
+    all.sort();
+
+    let mut want = vec![b.to_string(), c.to_string()];
+    want.sort();
+    assert_eq!(all, want);
+
+    let one = tr.run_pidgit(vec!["merge-base", "main", "topic"]).unwrap();
+    assert_eq!(one.lines().count(), 1);
+    assert!(want.contains(&one.trim().to_string()));
+
+    tr.run_pidgit(vec!["merge-base", "--is-ancestor", "main~1", "main"])
+      .unwrap();
+  }
+
+  //       B      (one)
+  //      /
+  // A - C - D    (two)
+  //          \
+  //           E  (three)
+  #[test]
+  fn many_commits() {
+    let tr = new_empty_repo();
+
+    let a = tr.commit_file("A");
+    tr.run_pidgit(vec!["branch", "one"]).unwrap();
+    let c = tr.commit_file("C");
+    let d = tr.commit_file("D");
+    tr.run_pidgit(vec!["switch", "-c", "three"]).unwrap();
+    let e = tr.commit_file("E");
+    tr.run_pidgit(vec!["switch", "one"]).unwrap();
+    let b = tr.commit_file("B");
+
+    let repo = &tr.repo;
+    assert_eq!(repo.merge_bases(&e, &b).unwrap(), vec![a.clone()]);
+    assert_eq!(
+      repo.merge_bases_many(&e, &[b.clone(), d.clone()]).unwrap(),
+      vec![d.clone()]
+    );
+    assert_eq!(
+      repo
+        .merge_bases_octopus(&[e.clone(), b.clone(), d.clone()])
+        .unwrap(),
+      vec![a.clone()]
+    );
+    assert_eq!(
+      repo.merge_bases_octopus(&[e.clone(), d.clone()]).unwrap(),
+      vec![d.clone()]
+    );
+    assert!(!repo.is_ancestor(&c, &b).unwrap());
+
+    let out = tr
+      .run_pidgit(vec!["merge-base", "three", "one", "main"])
+      .unwrap();
+    assert_eq!(out, format!("{}\n", d));
+
+    let out = tr
+      .run_pidgit(vec!["merge-base", "--octopus", "three", "one", "main"])
+      .unwrap();
+    assert_eq!(out, format!("{}\n", a));
+  }
+}
@@ -0,0 +1,140 @@
+use clap::{App, Arg, ArgMatches};
+
+use crate::prelude::*;
+
+pub fn command() -> Command {
+  (app, run)
+}
+
+fn app() -> ClapApp {
+  App::new("pack-refs")
+    .about("pack heads and tags for efficient repository access")
+    .arg(
+      Arg::with_name("all")
+        .long("all")
+        .help("pack all refs, not just tags and already-packed refs"),
+    )
+    .arg(
+      Arg::with_name("no-prune")
+        .long("no-prune")
+        .help("do not remove loose refs after packing them"),
+    )
+}
+
+fn run(matches: &ArgMatches, ctx: &Context) -> Result<()> {
+  let repo = ctx.repo()?;
+
+  repo.grefs().pack_refs(
+    matches.is_present("all"),
+    !matches.is_present("no-prune"),
+    |sha| repo.peel_tag(sha),
+  )?;
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::test_prelude::*;
+
+  fn new_with_branches() -> TestRepo {
+    let tr = new_empty_repo();
+    tr.write_file("file.txt", "hello\n");
+    tr.commit_all();
+    tr.run_pidgit(vec!["branch", "topic"]).unwrap();
+    tr
+  }
+
+  #[test]
+  fn pack_all_refs() {
+    let tr = new_with_branches();
+    let git_dir = tr.repo.git_dir().clone();
+    let head = tr.run_pidgit(vec!["rev-parse", "HEAD"]).unwrap();
+
+    tr.run_pidgit(vec!["pack-refs", "--all"]).unwrap();
+
+    assert!(!git_dir.join("refs/heads/main").exists());
+    assert!(!git_dir.join("refs/heads/topic").exists());
+    assert!(git_dir.join("refs/heads").is_dir());
+
+    let packed = std::fs::read_to_string(git_dir.join("packed-refs")).unwrap();
+    assert!(packed.starts_with("# pack-refs with:"));
+    assert!(packed.contains(&format!("{} refs/heads/topic", head.trim())));
+
+    assert_eq!(tr.run_pidgit(vec!["rev-parse", "HEAD"]).unwrap(), head);
+    assert_eq!(tr.run_pidgit(vec!["rev-parse", "topic"]).unwrap(), head);
+
+    let names = tr.repo.grefs().all_refs().unwrap();
+    assert_eq!(names.len(), 2);
+  }
+
+  #[test]
+  fn loose_refs_win() {
+    let tr = new_with_branches();
+    tr.run_pidgit(vec!["pack-refs", "--all", "--no-prune"])
+      .unwrap();
+    let old = tr.run_pidgit(vec!["rev-parse", "topic"]).unwrap();
+
+    tr.write_file("file.txt", "goodbye\n");
+    tr.commit_all();
+
+    // main has moved (loose), but its packed copy is stale
+    let new = tr.run_pidgit(vec!["rev-parse", "main"]).unwrap();
+    assert_ne!(old, new);
+    assert_eq!(tr.run_pidgit(vec!["rev-parse", "HEAD"]).unwrap(), new);
+
+    let packed = tr.repo.grefs().packed_refs().unwrap();
+    assert_eq!(packed["refs/heads/main"].sha, old.trim().into());
+  }
+
+  #[test]
+  fn commit_on_packed_nested_branch() {
+    let tr = new_with_branches();
+    tr.run_pidgit(vec!["branch", "a/b"]).unwrap();
+    tr.run_pidgit(vec!["pack-refs", "--all"]).unwrap();
+    assert!(!tr.repo.git_dir().join("refs/heads/a").exists());
+
+    tr.run_pidgit(vec!["switch", "a/b"]).unwrap();
+    tr.write_file("file.txt", "goodbye\n");
+    tr.commit_all();
+
+    let head = tr.run_pidgit(vec!["rev-parse", "HEAD"]).unwrap();
+    assert_eq!(tr.run_pidgit(vec!["rev-parse", "a/b"]).unwrap(), head);
+    assert!(tr.repo.git_dir().join("refs/heads/a/b").is_file());
+  }
+
+  #[test]
+  fn default_only_packs_tags() {
+    let tr = new_with_branches();
+    tr.run_pidgit(vec!["pack-refs"]).unwrap();
+
+    assert!(tr.repo.git_dir().join("refs/heads/topic").is_file());
+    assert!(tr.repo.grefs().packed_refs().unwrap().is_empty());
+  }
+
+  #[test]
+  fn parse_peeled() {
+    let tr = new_empty_repo();
+    let (a, b) = ("a".repeat(40), "b".repeat(40));
+    tr.write_file(
+      ".pidgit/packed-refs",
+      &format!(
+        "# pack-refs with: peeled fully-peeled sorted \n\
+         {a} refs/heads/main\n{b} refs/tags/v1.0\n^{a}\n",
+        a = a,
+        b = b
+      ),
+    );
+
+    let packed = tr.repo.grefs().packed_refs().unwrap();
+    assert_eq!(packed["refs/heads/main"].peeled, None);
+    assert_eq!(packed["refs/tags/v1.0"].sha, b.as_str().into());
+    assert_eq!(packed["refs/tags/v1.0"].peeled, Some(a.as_str().into()));
+
+    assert_eq!(tr.repo.grefs().resolve("v1.0").unwrap(), b.into());
+    assert_eq!(
+      tr.repo.grefs().path_for_name("main"),
+      Some("refs/heads/main".to_string())
+    );
+  }
+}
@@ -0,0 +1,78 @@
+use clap::{App, Arg, ArgMatches};
+
+use crate::prelude::*;
+
+pub fn command() -> Command {
+  (app, run)
+}
+
+fn app() -> ClapApp {
+  App::new("reflog").about("show the reference log").arg(
+    Arg::with_name("ref")
+      .default_value("HEAD")
+      .help("ref whose log to show"),
+  )
+}
+
+fn run(matches: &ArgMatches, ctx: &Context) -> Result<()> {
+  let repo = ctx.repo()?;
+  let name = matches.value_of("ref").unwrap();
+
+  let full_name = repo
+    .grefs()
+    .path_for_name(name)
+    .ok_or_else(|| PidgitError::RefNotFound(name.to_string()))?;
+
+  let entries = repo.grefs().reflog(&full_name)?;
+
+  for (n, entry) in entries.iter().rev().enumerate() {
+    ctx.println(format!(
+      "{} {}@{{{}}}: {}",
+      entry.new.short(8),
+      name,
+      n,
+      entry.message
+    ));
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::test_prelude::*;
+
+  #[test]
+  fn reflog_for_commits_and_branches() {
+    let tr = new_empty_repo();
+
+    tr.write_file("file.txt", "one\n");
+    tr.commit("first").unwrap();
+    tr.write_file("file.txt", "two\n");
+    tr.commit("second").unwrap();
+    tr.run_pidgit(vec!["branch", "topic", "HEAD^"]).unwrap();
+
+    let head = tr.run_pidgit(vec!["reflog"]).unwrap();
+    let lines = head.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].ends_with(" HEAD@{0}: commit: second"));
+    assert!(lines[1].ends_with(" HEAD@{1}: commit (initial): first"));
+
+    let main = tr.run_pidgit(vec!["reflog", "main"]).unwrap();
+    assert!(main.starts_with(&lines[0][0..8]));
+    assert!(main.contains(" main@{1}: commit (initial): first\n"));
+
+    let topic = tr.run_pidgit(vec!["reflog", "topic"]).unwrap();
+    assert!(topic.starts_with(&lines[1][0..8]));
+    assert!(topic.contains(" topic@{0}: branch: Created from HEAD^\n"));
+
+    let first = tr.run_pidgit(vec!["rev-parse", "topic"]).unwrap();
+    assert_eq!(tr.run_pidgit(vec!["rev-parse", "HEAD@{1}"]).unwrap(), first);
+    assert_eq!(tr.run_pidgit(vec!["rev-parse", "@{1}"]).unwrap(), first);
+    assert_eq!(
+      tr.run_pidgit(vec!["rev-parse", "main@{0}^"]).unwrap(),
+      first
+    );
+    assert!(tr.run_pidgit(vec!["rev-parse", "HEAD@{2}"]).is_err());
+  }
+}
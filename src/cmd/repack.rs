@@ -0,0 +1,51 @@
+use clap::{App, Arg, ArgMatches};
+
+use crate::prelude::*;
+
+pub fn command() -> Command {
+  (app, run)
+}
+
+fn app() -> ClapApp {
+  App::new("repack")
+    .about("pack unpacked objects in a repository")
+    .arg(
+      Arg::with_name("all")
+        .short("a")
+        .help("pack all reachable objects into a single pack"),
+    )
+    .arg(
+      Arg::with_name("delete")
+        .short("d")
+        .help("remove redundant packs and loose objects after packing"),
+    )
+}
+
+fn run(matches: &ArgMatches, ctx: &Context) -> Result<()> {
+  let repo = ctx.repo()?;
+  let all = matches.is_present("all");
+
+  let stats = repo.repack(all)?;
+
+  match &stats {
+    Some(stats) => ctx.println(format!(
+      "packed {} objects ({} deltas) into {}",
+      stats.objects,
+      stats.deltas,
+      stats.path.file_name().unwrap().to_string_lossy(),
+    )),
+    None => ctx.println("nothing new to pack".to_string()),
+  };
+
+  if matches.is_present("delete") {
+    // If we packed everything, anything in the old packs that isn't in the
+    // new one is unreachable, which is what git does too.
+    if let (true, Some(stats)) = (all, &stats) {
+      repo.remove_packs_except(&stats.path)?;
+    }
+
+    repo.prune_packed()?;
+  }
+
+  Ok(())
+}
@@ -0,0 +1,208 @@
+use clap::{App, Arg, ArgGroup, ArgMatches};
+use std::path::PathBuf;
+
+use crate::prelude::*;
+
+pub fn command() -> Command {
+  (app, run)
+}
+
+fn app() -> ClapApp {
+  App::new("reset")
+    .about("reset current HEAD to the specified state")
+    .arg(
+      Arg::with_name("soft")
+        .long("soft")
+        .help("only move HEAD, leaving the index and working tree alone"),
+    )
+    .arg(
+      Arg::with_name("mixed").long("mixed").help(
+        "move HEAD and reset the index, but not the working tree (default)",
+      ),
+    )
+    .arg(
+      Arg::with_name("hard")
+        .long("hard")
+        .help("move HEAD and reset the index and working tree"),
+    )
+    .group(ArgGroup::with_name("mode").args(&["soft", "mixed", "hard"]))
+    .arg(
+      Arg::with_name("commit")
+        .default_value("HEAD")
+        .help("commit to reset to"),
+    )
+    .arg(
+      Arg::with_name("paths")
+        .multiple(true)
+        .last(true)
+        .conflicts_with("mode")
+        .help("reset only these paths in the index"),
+    )
+}
+
+fn run(matches: &ArgMatches, ctx: &Context) -> Result<()> {
+  let repo = ctx.repo()?;
+
+  let rev = matches.value_of("commit").unwrap();
+  let sha = repo.resolve_object(rev)?.sha();
+  let commit = repo
+    .object_for_sha(&repo.peel_tag(&sha).unwrap_or(sha))?
+    .as_commit()?;
+
+  if let Some(raw_paths) = matches.values_of("paths") {
+    let paths = raw_paths
+      .map(|p| ctx.relative_path(p))
+      .collect::<Result<Vec<_>>>()?;
+
+    repo.reset_index(commit.tree(), &paths)?;
+    return print_unstaged(ctx, repo);
+  }
+
+  let previous = repo.tracked_paths()?;
+
+  repo.grefs().update_head(
+    &commit.sha(),
+    &repo.whoami()?,
+    &format!("reset: moving to {}", rev),
+  )?;
+
+  if matches.is_present("soft") {
+    return Ok(());
+  }
+
+  repo.reset_index(commit.tree(), &[])?;
+
+  if !matches.is_present("hard") {
+    return print_unstaged(ctx, repo);
+  }
+
+  repo.reset_workspace(&previous)?;
+
+  ctx.println(format!(
+    "HEAD is now at {} {}",
+    commit.sha().short(8),
+    commit.title()
+  ));
+
+  Ok(())
+}
+
+fn print_unstaged(ctx: &Context, repo: &Repository) -> Result<()> {
+  let status = repo.status()?;
+
+  if !status.has_workspace_changes() {
+    return Ok(());
+  }
+
+  ctx.println("Unstaged changes after reset:".to_string());
+
+  for (path, change) in status.workspace_diff() {
+    ctx.println(format!(
+      "{}\t{}",
+      change.display(),
+      PathBuf::from(path).display()
+    ));
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::test_prelude::*;
+
+  fn new_with_history() -> TestRepo {
+    let tr = new_empty_repo();
+
+    tr.write_file("a.txt", "a1\n");
+    tr.write_file("b.txt", "b1\n");
+    tr.commit_all();
+
+    tr.write_file("a.txt", "a2\n");
+    tr.write_file("dir/c.txt", "c2\n");
+    tr.commit_all();
+
+    tr
+  }
+
+  fn read(tr: &TestRepo, path: &str) -> String {
+    std::fs::read_to_string(tr.repo.workspace().canonicalize(&path)).unwrap()
+  }
+
+  fn rev_parse(tr: &TestRepo, rev: &str) -> String {
+    tr.run_pidgit(vec!["rev-parse", rev]).unwrap()
+  }
+
+  #[test]
+  fn soft() {
+    let tr = new_with_history();
+    let first = rev_parse(&tr, "HEAD^");
+
+    assert_eq!(tr.run_pidgit(vec!["reset", "--soft", "HEAD^"]).unwrap(), "");
+    assert_eq!(rev_parse(&tr, "HEAD"), first);
+    assert_eq!(rev_parse(&tr, "main"), first);
+
+    let status = tr.run_pidgit(vec!["status", "-s"]).unwrap();
+    assert_eq!(status, "M  a.txt\nA  dir/c.txt\n");
+  }
+
+  #[test]
+  fn mixed() {
+    let tr = new_with_history();
+
+    let out = tr.run_pidgit(vec!["reset", "HEAD^"]).unwrap();
+    assert_eq!(out, "Unstaged changes after reset:\nM\ta.txt\n");
+    assert_eq!(read(&tr, "a.txt"), "a2\n");
+
+    let status = tr.run_pidgit(vec!["status", "-s"]).unwrap();
+    assert_eq!(status, " M a.txt\n?? dir/\n");
+  }
+
+  #[test]
+  fn hard() {
+    let tr = new_with_history();
+    let first = rev_parse(&tr, "HEAD^");
+
+    tr.write_file("b.txt", "local change\n");
+    tr.write_file("untracked.txt", "leave me be\n");
+
+    let out = tr.run_pidgit(vec!["reset", "--hard", "HEAD^"]).unwrap();
+    assert!(out.starts_with(&format!("HEAD is now at {}", &first[0..8])));
+
+    assert_eq!(read(&tr, "a.txt"), "a1\n");
+    assert_eq!(read(&tr, "b.txt"), "b1\n");
+    assert_eq!(read(&tr, "untracked.txt"), "leave me be\n");
+    assert!(!tr.repo.workspace().root().join("dir").exists());
+
+    let status = tr.run_pidgit(vec!["status", "-s"]).unwrap();
+    assert_eq!(status, "?? untracked.txt\n");
+
+    let reflog = tr.run_pidgit(vec!["reflog"]).unwrap();
+    assert!(reflog.contains("HEAD@{0}: reset: moving to HEAD^\n"));
+  }
+
+  #[test]
+  fn paths() {
+    let tr = new_with_history();
+
+    tr.write_file("a.txt", "a3\n");
+    tr.write_file("dir/c.txt", "c3\n");
+    tr.write_file("dir/d.txt", "d3\n");
+    tr.run_pidgit(vec!["add", "."]).unwrap();
+
+    let out = tr.run_pidgit(vec!["reset", "--", "dir"]).unwrap();
+    assert_eq!(out, "Unstaged changes after reset:\nM\tdir/c.txt\n");
+
+    let status = tr.run_pidgit(vec!["status", "-s"]).unwrap();
+    assert_eq!(status, "M  a.txt\n M dir/c.txt\n?? dir/d.txt\n");
+
+    tr.run_pidgit(vec!["reset", "HEAD^", "--", "a.txt"])
+      .unwrap();
+    let status = tr.run_pidgit(vec!["status", "-s"]).unwrap();
+    assert!(status.starts_with("MM a.txt\n"), "status: {}", status);
+
+    assert!(tr
+      .run_pidgit(vec!["reset", "--hard", "--", "a.txt"])
+      .is_err());
+  }
+}
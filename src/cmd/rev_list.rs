@@ -0,0 +1,221 @@
+use clap::{App, Arg, ArgMatches};
+
+use crate::prelude::*;
+use crate::repo::RevList;
+
+pub fn command() -> Command {
+  (app, run)
+}
+
+fn app() -> ClapApp {
+  App::new("rev-list")
+    .about("lists commit objects in reverse chronological order")
+    .arg(
+      Arg::with_name("max-count")
+        .short("n")
+        .long("max-count")
+        .takes_value(true)
+        .value_name("number")
+        .help("limit the number of commits to output"),
+    )
+    .arg(
+      Arg::with_name("count")
+        .long("count")
+        .help("print how many commits would have been listed, and nothing else"),
+    )
+    .arg(
+      Arg::with_name("left-right")
+        .long("left-right")
+        .help("mark which side of a symmetric difference each commit is from"),
+    )
+    .arg(
+      Arg::with_name("objects")
+        .long("objects")
+        .help("also list the trees and blobs the commits use"),
+    )
+    .arg(
+      Arg::with_name("not")
+        .long("not")
+        .multiple(true)
+        .help("reverse the meaning of the ^ prefix for the revisions after this"),
+    )
+    .arg(
+      Arg::with_name("revisions")
+        .multiple(true)
+        .required(true)
+        .allow_hyphen_values(true)
+        .help(
+          "commits to start from (^rev to exclude, a..b, a...b, rev^@, rev^!)",
+        ),
+    )
+}
+
+fn run(matches: &ArgMatches, ctx: &Context) -> Result<()> {
+  let repo = ctx.repo()?;
+
+  let mut revs = RevList::new(repo);
+
+  // --not applies to everything after it. Clap only sees it as a flag if it
+  // comes before any revisions; after that, it shows up as a revision itself.
+  let nots = matches.indices_of("not").map_or(vec![], |i| i.collect());
+  let values = matches.values_of("revisions").unwrap();
+  let indices = matches.indices_of("revisions").unwrap();
+
+  let mut negated = false;
+  for (rev, idx) in values.zip(indices) {
+    if rev == "--not" {
+      negated = !negated;
+      continue;
+    }
+
+    let flip = negated ^ (nots.iter().filter(|&&n| n < idx).count() % 2 == 1);
+
+    for arg in util::resolve_range(rev, repo)? {
+      revs.add_arg(&if flip { arg.negate() } else { arg })?;
+    }
+  }
+
+  let max = match matches.value_of("max-count") {
+    Some(n) => n
+      .parse::<usize>()
+      .map_err(|_| PidgitError::Generic(format!("invalid max count: {}", n)))?,
+    None => usize::MAX,
+  };
+
+  let commits = revs.by_ref().take(max).collect::<Result<Vec<_>>>()?;
+
+  let left_right = matches.is_present("left-right");
+
+  if matches.is_present("count") {
+    let left = commits.iter().filter(|c| revs.is_left(&c.sha())).count();
+
+    if left_right {
+      ctx.println(format!("{}\t{}", left, commits.len() - left));
+    } else {
+      ctx.println(commits.len().to_string());
+    }
+
+    return Ok(());
+  }
+
+  for commit in &commits {
+    let sha = commit.sha();
+
+    let mark = match (left_right, revs.is_left(&sha)) {
+      (false, _) => "",
+      (true, true) => "<",
+      (true, false) => ">",
+    };
+
+    ctx.println(format!("{}{}", mark, sha));
+  }
+
+  if matches.is_present("objects") {
+    for (sha, path) in revs.objects(&commits)? {
+      ctx.println(format!("{} {}", sha, path.display()));
+    }
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::object::GitObject;
+  use crate::test_prelude::*;
+
+  // run rev-list, and turn the shas back into commit messages
+  fn names(tr: &TestRepo, args: &[&str]) -> String {
+    let mut full = vec!["rev-list"];
+    full.extend(args);
+
+    tr.run_pidgit(full)
+      .unwrap()
+      .lines()
+      .map(|line| {
+        let (mark, sha) = line.split_at(line.len() - 40);
+        let obj = tr.repo.resolve_object(sha).unwrap();
+        format!("{}{}", mark, obj.as_commit().unwrap().message.trim())
+      })
+      .collect::<Vec<_>>()
+      .join(" ")
+  }
+
+  #[test]
+  fn sets() {
+    let tr = new_with_topic_history();
+
+    assert_eq!(names(&tr, &["topic"]), "E D B A");
+    assert_eq!(names(&tr, &["main..topic"]), "E D");
+    assert_eq!(names(&tr, &["topic", "^main"]), "E D");
+    assert_eq!(names(&tr, &["topic", "--not", "main"]), "E D");
+    assert_eq!(names(&tr, &["--not", "main", "--not", "topic"]), "E D");
+    assert_eq!(names(&tr, &["topic^@"]), "D B A");
+    assert_eq!(names(&tr, &["topic^!"]), "E");
+    assert_eq!(names(&tr, &["-n", "1", "topic"]), "E");
+
+    let both = names(&tr, &["--left-right", "main...topic"]);
+    let mut both = both.split(' ').collect::<Vec<_>>();
+    both.sort();
+    assert_eq!(both, vec!["<C", ">D", ">E"]);
+  }
+
+  // Z - K - I          (main)
+  //      \
+  //       U2 - U1 - U  (other)
+  //
+  // all made at the same time, so U's side can't be walked by date ahead of
+  // main's; we still have to find out that it excludes K and Z
+  #[test]
+  fn same_dates() {
+    let tr = new_empty_repo();
+
+    tr.commit_file_at("Z", 0);
+    tr.commit_file_at("K", 0);
+    tr.run_pidgit(vec!["switch", "-c", "other"]).unwrap();
+    tr.commit_file_at("U2", 0);
+    tr.commit_file_at("U1", 0);
+    tr.commit_file_at("U", 0);
+    tr.run_pidgit(vec!["switch", "main"]).unwrap();
+    tr.commit_file_at("I", 0);
+
+    assert_eq!(names(&tr, &["main", "^other"]), "I");
+    assert_eq!(names(&tr, &["other..main"]), "I");
+    assert_eq!(names(&tr, &["main..other"]), "U U1 U2");
+  }
+
+  #[test]
+  fn count() {
+    let tr = new_with_topic_history();
+
+    let count = |args: &[&str]| {
+      let mut full = vec!["rev-list", "--count"];
+      full.extend(args);
+      tr.run_pidgit(full).unwrap()
+    };
+
+    assert_eq!(count(&["main"]), "3\n");
+    assert_eq!(count(&["main...topic"]), "3\n");
+    assert_eq!(count(&["--left-right", "main...topic"]), "1\t2\n");
+  }
+
+  #[test]
+  fn objects() {
+    let tr = new_with_topic_history();
+    let e = tr
+      .repo
+      .resolve_object("topic")
+      .unwrap()
+      .as_commit()
+      .unwrap();
+    let tree = e.tree();
+    let blob = tr.repo.resolve_object("topic:E.txt").unwrap().sha();
+
+    // only E.txt is new in E; everything else is in D's tree already
+    let out = tr
+      .run_pidgit(vec!["rev-list", "--objects", "topic~1..topic"])
+      .unwrap();
+
+    assert_eq!(out, format!("{}\n{} \n{} E.txt\n", e.sha(), tree, blob));
+  }
+}
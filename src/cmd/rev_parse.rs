@@ -1,6 +1,7 @@
 use clap::{App, Arg, ArgMatches};
 
 use crate::prelude::*;
+use crate::util::RevArg;
 
 pub fn command() -> Command {
   (app, run)
@@ -10,18 +11,85 @@ pub fn app() -> ClapApp {
   App::new("rev-parse")
     .about("pick out and massage parameters")
     .arg(
-      Arg::with_name("object")
+      Arg::with_name("revisions")
         .required(true)
-        .help("object to view"),
+        .multiple(true)
+        .help("revisions to parse (including ranges like a..b)"),
     )
 }
 
 fn run(matches: &ArgMatches, ctx: &Context) -> Result<()> {
   let repo = ctx.repo()?;
 
-  let object = repo.resolve_object(matches.value_of("object").unwrap())?;
-
-  ctx.println(format!("{}", object.get_ref().sha().hexdigest()));
+  // ranges come out as the commits to include, then the ones to exclude
+  // (prefixed with ^), just like git prints them
+  for rev in matches.values_of("revisions").unwrap() {
+    for arg in util::resolve_range(rev, repo)? {
+      match arg {
+        RevArg::Exclude(sha) => ctx.println(format!("^{}", sha)),
+        _ => ctx.println(arg.sha().hexdigest()),
+      }
+    }
+  }
 
   Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+  use crate::test_prelude::*;
+
+  #[test]
+  fn ranges() {
+    let tr = new_empty_repo();
+    tr.write_file("a.txt", "a\n");
+    tr.commit_all();
+    tr.write_file("a.txt", "b\n");
+    tr.commit_all();
+
+    let sha = |rev: &str| tr.repo.resolve_object(rev).unwrap().sha();
+    let (head, parent) = (sha("HEAD"), sha("HEAD~1"));
+
+    let out = tr
+      .run_pidgit(vec!["rev-parse", "HEAD", "HEAD~1..HEAD"])
+      .unwrap();
+    assert_eq!(out, format!("{}\n{}\n^{}\n", head, head, parent));
+
+    let out = tr
+      .run_pidgit(vec!["rev-parse", "HEAD^!", "HEAD^@"])
+      .unwrap();
+    assert_eq!(out, format!("{}\n^{}\n{}\n", head, parent, parent));
+
+    // the merge base of a commit and its parent is the parent
+    let out = tr.run_pidgit(vec!["rev-parse", "HEAD~1...HEAD"]).unwrap();
+    assert_eq!(out, format!("{}\n{}\n^{}\n", head, parent, parent));
+  }
+
+  #[test]
+  fn malformed_objects() {
+    let tr = new_empty_repo();
+
+    // a tree entry with no sha after it
+    let tree = tr.write_raw_object("tree", b"100644 a.txt\0");
+    let content = format!(
+      "tree {}\nauthor A <a@x> 1600000000 +0000\n\
+       committer A <a@x> 1600000000 +0000\n\nbroken\n",
+      tree
+    );
+    let commit = tr.write_raw_object("commit", content.as_bytes());
+    tr.repo
+      .grefs()
+      .create_ref("refs/heads/broken", &commit)
+      .unwrap();
+
+    // a tree we can't read isn't the same as no tree at all
+    let want = format!("malformed tree {}: truncated sha at byte 0", tree);
+    for rev in &["broken^{tree}", "broken:a.txt"] {
+      let err = tr.run_pidgit(vec!["rev-parse", rev]).unwrap_err();
+      assert_eq!(err.to_string(), want);
+    }
+
+    let err = tr.run_pidgit(vec!["rev-parse", "nope^{tree}"]).unwrap_err();
+    assert_eq!(err.to_string(), "object not found: nope^{tree}");
+  }
+}
@@ -0,0 +1,138 @@
+use clap::{App, Arg, ArgMatches};
+
+use crate::cmd::diff_tree;
+use crate::object::Object;
+use crate::prelude::*;
+use crate::pretty::{DateFormat, Format, Pretty};
+
+pub fn command() -> Command {
+  (app, run)
+}
+
+fn app() -> ClapApp {
+  App::new("show")
+    .about("show commits, with their changes")
+    .arg(
+      Arg::with_name("pretty")
+        .long("pretty")
+        .alias("format")
+        .takes_value(true)
+        .value_name("format")
+        .default_value("medium")
+        .help(
+          "pretty-print commits in this format: oneline, short, medium, full, \
+           fuller, raw, or format:<string>",
+        ),
+    )
+    .arg(
+      Arg::with_name("oneline")
+        .long("oneline")
+        .help("shorthand for --pretty=oneline --abbrev-commit"),
+    )
+    .arg(
+      Arg::with_name("date")
+        .long("date")
+        .takes_value(true)
+        .value_name("format")
+        .possible_values(DateFormat::NAMES)
+        .default_value("default")
+        .help("format dates this way"),
+    )
+    .arg(
+      Arg::with_name("no-patch")
+        .short("s")
+        .long("no-patch")
+        .help("don't show the changes"),
+    )
+    .arg(
+      Arg::with_name("objects")
+        .multiple(true)
+        .default_value("HEAD")
+        .help("commits to show"),
+    )
+}
+
+fn run(matches: &ArgMatches, ctx: &Context) -> Result<()> {
+  let repo = ctx.repo()?;
+
+  let oneline = matches.is_present("oneline");
+  let format = if oneline {
+    Format::Oneline
+  } else {
+    Format::parse(matches.value_of("pretty").unwrap())?
+  };
+
+  let dates = DateFormat::parse(matches.value_of("date").unwrap())?;
+  let mut pretty = Pretty::new(repo, format, dates)?;
+  if oneline {
+    pretty = pretty.abbrev_commit();
+  }
+  let attrs = repo.workspace().attributes()?;
+
+  ctx.setup_pager()?;
+
+  for (i, name) in matches.values_of("objects").unwrap().enumerate() {
+    let obj = repo.resolve_object(name)?;
+    let commit = match repo.peel_tag(&obj.sha()) {
+      Some(sha) => repo.object_for_sha(&sha)?,
+      None => obj,
+    };
+
+    let commit = match commit {
+      Object::Commit(commit) => commit,
+      _ => return Err(PidgitError::Generic(format!("{} is not a commit", name))),
+    };
+
+    if i > 0 && pretty.format().spaced() {
+      ctx.println("".to_string());
+    }
+
+    ctx.println(pretty.show(&commit));
+
+    // like git without -m, we don't show anything for merges
+    if matches.is_present("no-patch") || commit.parent_shas.len() > 1 {
+      continue;
+    }
+
+    let parent = commit.parent(repo).map(|p| p.tree().clone());
+    let changes =
+      repo.tree_changes(parent.as_ref(), Some(commit.tree()), true)?;
+
+    if !changes.is_empty() && *pretty.format() != Format::Oneline {
+      ctx.println("".to_string());
+    }
+
+    for change in changes {
+      diff_tree::print_patch(ctx, repo, &attrs, &change)?;
+    }
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::test_prelude::*;
+
+  #[test]
+  fn show_commit() {
+    let tr = new_empty_repo();
+
+    tr.write_file("a.txt", "one\n");
+    tr.run_pidgit(vec!["add", "."]).unwrap();
+    tr.commit("first").unwrap();
+
+    tr.write_file("a.txt", "one\ntwo\n");
+    tr.run_pidgit(vec!["add", "."]).unwrap();
+    tr.commit("second").unwrap();
+
+    let out = tr.run_pidgit(vec!["show", "--format=%s"]).unwrap();
+    assert!(out.starts_with("second\n\ndiff --git a/a.txt b/a.txt\n"));
+    assert!(out.ends_with(" one\n+two\n"));
+
+    let out = tr
+      .run_pidgit(vec!["show", "-s", "--format=%s", "HEAD", "HEAD~1"])
+      .unwrap();
+    assert_eq!(out, "second\nfirst\n");
+  }
+}
@@ -52,6 +52,16 @@ fn run(matches: &ArgMatches, ctx: &Context) -> Result<()> {
 
 impl StatusCmd {
   fn status_for(&self, path: &OsString, use_color: bool) -> String {
+    if let Some(conflict) = self.status.conflicts().get(path) {
+      let code = conflict.display();
+
+      return if use_color {
+        util::colored(code, Color::Red.normal()).to_string()
+      } else {
+        code.to_string()
+      };
+    }
+
     let left = match self.status.index_diff().get(path) {
       Some(ct) => ct.display(),
       None => " ",
@@ -81,13 +91,14 @@ impl StatusCmd {
         .status
         .index_diff()
         .keys()
-        .chain(self.status.workspace_diff().keys()),
+        .chain(self.status.workspace_diff().keys())
+        .chain(self.status.conflicts().keys()),
     );
 
     for path in paths {
       ctx.println(format!(
         "{} {}",
-        self.status_for(&path, use_color),
+        self.status_for(path, use_color),
         PathBuf::from(path).display()
       ));
     }
@@ -104,6 +115,7 @@ impl StatusCmd {
       self.status.index_diff(),
       Color::Green,
     );
+    self.print_conflicts(ctx);
     self.print_changes(
       ctx,
       "Changes not staged for commit",
@@ -126,7 +138,7 @@ impl StatusCmd {
     changeset: &BTreeMap<OsString, ChangeType>,
     color: Color,
   ) {
-    if changeset.len() == 0 {
+    if changeset.is_empty() {
       return;
     }
 
@@ -148,12 +160,33 @@ impl StatusCmd {
     ctx.println("".to_string());
   }
 
+  fn print_conflicts(&self, ctx: &Context) {
+    if !self.status.has_conflicts() {
+      return;
+    }
+
+    ctx.println("Unmerged paths:".to_string());
+
+    for (path, kind) in self.status.conflicts() {
+      ctx.println_color(
+        format!(
+          "\t{:<17}{}",
+          kind.long_display().to_string() + ":",
+          PathBuf::from(path).display()
+        ),
+        Color::Red.normal(),
+      );
+    }
+
+    ctx.println("".to_string());
+  }
+
   fn print_commit_status(&self, ctx: &Context) {
     if self.status.has_index_changes() {
       return;
     }
 
-    if self.status.has_workspace_changes() {
+    if self.status.has_workspace_changes() || self.status.has_conflicts() {
       ctx.println("no changes added to commit".into())
     } else if self.status.has_workspace_changes() {
       ctx.println("nothing added to commit but untracked files present".into())
@@ -281,6 +314,34 @@ mod tests {
     assert_status(stdout, " D file.txt");
   }
 
+  #[test]
+  fn both_deleted() {
+    use crate::index::IndexEntry;
+    use crate::object::{Blob, GitObject, Mode};
+
+    let tr = new_empty_repo();
+    tr.write_file("keep.txt", "keep\n");
+    tr.commit_all();
+
+    // just a base version, which is what git leaves when both sides of a
+    // merge deleted a file that was renamed differently on each
+    let blob = Blob::from_content(b"gone\n".to_vec());
+    tr.repo.write_object(&blob).unwrap();
+    tr.repo
+      .index_mut()
+      .add_unmerged(vec![IndexEntry::new_from_tree(
+        "gone.txt".into(),
+        blob.sha(),
+        &Mode::Normal,
+        5,
+      )
+      .with_stage(1)]);
+    tr.repo.index().write().unwrap();
+
+    let stdout = tr.run_pidgit(vec!["status", "-s"]).unwrap();
+    assert_status(stdout, "DD gone.txt");
+  }
+
   // tests below deal with diffing HEAD and the index, so we'll generate a
   // not-empty repo for testing
   fn new_with_commit() -> TestRepo {
@@ -361,4 +422,28 @@ mod tests {
     let stdout = tr.run_pidgit(vec!["status", "-s"]).unwrap();
     assert_status(stdout, "D  a/2.txt\nD  a/b/3.txt");
   }
+
+  #[test]
+  fn malformed_head_tree() {
+    let tr = new_empty_repo();
+
+    // a tree entry with no sha after it
+    let tree = tr.write_raw_object("tree", b"100644 a.txt\0");
+    let content = format!(
+      "tree {}\nauthor A <a@x> 1600000000 +0000\n\
+       committer A <a@x> 1600000000 +0000\n\nbroken\n",
+      tree
+    );
+    let commit = tr.write_raw_object("commit", content.as_bytes());
+    tr.repo
+      .grefs()
+      .create_ref("refs/heads/main", &commit)
+      .unwrap();
+
+    let err = tr.run_pidgit(vec!["status"]).unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      format!("malformed tree {}: truncated sha at byte 0", tree)
+    );
+  }
 }
@@ -0,0 +1,56 @@
+use clap::{App, Arg, ArgMatches};
+
+use super::checkout;
+use crate::prelude::*;
+
+pub fn command() -> Command {
+  (app, run)
+}
+
+fn app() -> ClapApp {
+  App::new("switch")
+    .about("switch branches")
+    .arg(
+      Arg::with_name("create")
+        .short("c")
+        .long("create")
+        .takes_value(true)
+        .value_name("new-branch")
+        .conflicts_with("detach")
+        .help("create a new branch and switch to it"),
+    )
+    .arg(
+      Arg::with_name("detach")
+        .long("detach")
+        .help("switch to a commit for inspection, detaching HEAD"),
+    )
+    .arg(
+      Arg::with_name("target")
+        .required_unless("create")
+        .help("branch to switch to (or start point, with -c)"),
+    )
+}
+
+fn run(matches: &ArgMatches, ctx: &Context) -> Result<()> {
+  let target = matches.value_of("target").unwrap_or("HEAD");
+
+  if let Some(name) = matches.value_of("create") {
+    return checkout::checkout_new_branch(ctx, name, target);
+  }
+
+  let detach = matches.is_present("detach");
+  let is_branch = ctx
+    .repo()?
+    .grefs()
+    .path_for_name(target)
+    .is_some_and(|name| name.starts_with("refs/heads/"));
+
+  if !detach && !is_branch {
+    return Err(PidgitError::Generic(format!(
+      "a branch is expected, got '{}' (use --detach to check out a commit)",
+      target
+    )));
+  }
+
+  checkout::checkout(ctx, target, detach)
+}
@@ -0,0 +1,182 @@
+use clap::{App, Arg, ArgMatches};
+
+use crate::object::Tag;
+use crate::prelude::*;
+
+pub fn command() -> Command {
+  (app, run)
+}
+
+fn app() -> ClapApp {
+  App::new("tag")
+    .about("create, list, or delete tags")
+    .arg(
+      Arg::with_name("annotate")
+        .short("a")
+        .long("annotate")
+        .requires("tagname")
+        .help("make an annotated tag object"),
+    )
+    .arg(
+      Arg::with_name("message")
+        .short("m")
+        .long("message")
+        .takes_value(true)
+        .value_name("msg")
+        .requires("tagname")
+        .help("use this as the tag message (implies -a)"),
+    )
+    .arg(
+      Arg::with_name("delete")
+        .short("d")
+        .long("delete")
+        .requires("tagname")
+        .conflicts_with_all(&["annotate", "message", "object"])
+        .help("delete the named tag"),
+    )
+    .arg(Arg::with_name("tagname").help("name of the tag"))
+    .arg(
+      Arg::with_name("object")
+        .requires("tagname")
+        .help("object the new tag will refer to (default: HEAD)"),
+    )
+}
+
+fn run(matches: &ArgMatches, ctx: &Context) -> Result<()> {
+  let repo = ctx.repo()?;
+
+  let name = match matches.value_of("tagname") {
+    Some(name) => name,
+    None => {
+      for (refname, _) in repo.grefs().all_refs()? {
+        if let Some(name) = refname.strip_prefix("refs/tags/") {
+          ctx.println(name.to_string());
+        }
+      }
+
+      return Ok(());
+    },
+  };
+
+  let refname = format!("refs/tags/{}", name);
+
+  if matches.is_present("delete") {
+    let sha = repo
+      .grefs()
+      .delete_ref(&refname)
+      .map_err(|_| PidgitError::Generic(format!("tag '{}' not found", name)))?;
+
+    ctx.println(format!("Deleted tag '{}' (was {})", name, sha.short(8)));
+    return Ok(());
+  }
+
+  let target =
+    repo.resolve_object(matches.value_of("object").unwrap_or("HEAD"))?;
+
+  let annotated = matches.is_present("annotate") || matches.is_present("message");
+
+  if !annotated {
+    return repo.grefs().create_ref(&refname, &target.sha());
+  }
+
+  let mut message = matches
+    .value_of("message")
+    .ok_or_else(|| {
+      PidgitError::Generic("annotated tags need a message (use -m)".to_string())
+    })?
+    .to_string();
+
+  if !message.ends_with('\n') {
+    message.push('\n');
+  }
+
+  let tag = Tag {
+    object: target.sha(),
+    kind: target.get_ref().type_str().to_string(),
+    name: name.to_string(),
+    tagger: Some(repo.whoami()?),
+    message,
+    content: None,
+  };
+
+  // check this before we write an object that nobody will point to
+  if repo.grefs().resolve(&refname).is_ok() {
+    return Err(PidgitError::Generic(format!(
+      "A tag named '{}' already exists",
+      name
+    )));
+  }
+
+  repo.write_object(&tag)?;
+  repo.grefs().create_ref(&refname, &tag.sha())
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::object::{GitObject, Object};
+  use crate::test_prelude::*;
+
+  fn new_with_commit() -> TestRepo {
+    let tr = new_empty_repo();
+    tr.write_file("file.txt", "hello\n");
+    tr.commit_all();
+    tr
+  }
+
+  #[test]
+  fn lightweight_tag() {
+    let tr = new_with_commit();
+    let head = tr.run_pidgit(vec!["rev-parse", "HEAD"]).unwrap();
+
+    tr.run_pidgit(vec!["tag", "v1.0"]).unwrap();
+    assert_eq!(tr.run_pidgit(vec!["rev-parse", "v1.0"]).unwrap(), head);
+    assert_eq!(tr.run_pidgit(vec!["tag"]).unwrap(), "v1.0\n");
+
+    assert!(tr.run_pidgit(vec!["tag", "v1.0"]).is_err());
+    assert!(tr.run_pidgit(vec!["tag", "bad..name"]).is_err());
+  }
+
+  #[test]
+  fn annotated_tag() {
+    let tr = new_with_commit();
+    let head = tr.repo.resolve_object("HEAD").unwrap().sha();
+
+    tr.run_pidgit(vec!["tag", "-m", "first release", "v1.0"])
+      .unwrap();
+
+    let tag = match tr.repo.resolve_object("v1.0").unwrap() {
+      Object::Tag(tag) => tag,
+      other => panic!("expected a tag, got {:?}", other),
+    };
+
+    assert_eq!(tag.object, head);
+    assert_eq!(tag.kind, "commit");
+    assert_eq!(tag.name, "v1.0");
+    assert_eq!(tag.message, "first release\n");
+    assert!(tag.tagger.is_some());
+
+    let pretty = tr.run_pidgit(vec!["cat-file", "-p", "v1.0"]).unwrap();
+    assert!(pretty.starts_with(&format!("object {}\ntype commit\n", head)));
+    assert!(pretty.ends_with("\n\nfirst release\n"));
+
+    assert_eq!(tr.repo.peel_tag(&tag.sha()), Some(head));
+  }
+
+  #[test]
+  fn delete_tag() {
+    let tr = new_with_commit();
+
+    tr.run_pidgit(vec!["tag", "packed"]).unwrap();
+    tr.run_pidgit(vec!["pack-refs"]).unwrap();
+    tr.run_pidgit(vec!["tag", "-a", "-m", "hi", "loose"])
+      .unwrap();
+    assert_eq!(tr.run_pidgit(vec!["tag"]).unwrap(), "loose\npacked\n");
+
+    let out = tr.run_pidgit(vec!["tag", "-d", "packed"]).unwrap();
+    assert!(out.starts_with("Deleted tag 'packed' (was "));
+
+    tr.run_pidgit(vec!["tag", "-d", "loose"]).unwrap();
+    assert_eq!(tr.run_pidgit(vec!["tag"]).unwrap(), "");
+    assert!(tr.run_pidgit(vec!["tag", "-d", "loose"]).is_err());
+  }
+}
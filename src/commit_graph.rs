@@ -0,0 +1,443 @@
+use sha1::Sha1;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+
+use crate::object::Commit;
+use crate::prelude::*;
+use crate::Lockfile;
+
+const SIGNATURE: &[u8; 4] = b"CGPH";
+const VERSION: u8 = 1;
+const HASH_VERSION: u8 = 1; // sha-1
+const HEADER_SIZE: usize = 8;
+const CHUNK_ENTRY_SIZE: usize = 12;
+const FANOUT_SIZE: usize = 256;
+const HASH_SIZE: usize = sha1::DIGEST_LENGTH;
+const DATA_SIZE: usize = HASH_SIZE + 16;
+
+const CHUNK_FANOUT: &[u8; 4] = b"OIDF";
+const CHUNK_OIDS: &[u8; 4] = b"OIDL";
+const CHUNK_DATA: &[u8; 4] = b"CDAT";
+const CHUNK_EDGES: &[u8; 4] = b"EDGE";
+
+// Parent positions in the commit data. If a commit has more than two parents,
+// the second one is instead an index into the extra edge list, where the last
+// parent is marked with the high bit.
+const PARENT_NONE: u32 = 0x7000_0000;
+const PARENT_EXTRA: u32 = 0x8000_0000;
+const POSITION_MASK: u32 = 0x7fff_ffff;
+
+pub const GENERATION_MAX: u32 = 0x3fff_ffff;
+const DATE_MASK: u64 = 0x3_ffff_ffff;
+
+// the generation of a commit we don't have in the graph
+pub const GENERATION_INFINITY: u32 = u32::MAX;
+
+// What a history walk needs to know about a commit: we can get all this from
+// the commit-graph without inflating and parsing the commit itself. The date
+// is the committer date, and the generation is one more than the largest
+// generation of the commit's parents (so roots are 1).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommitNode {
+  pub tree:       Sha,
+  pub parents:    Vec<Sha>,
+  pub date:       i64,
+  pub generation: u32,
+}
+
+impl From<&Commit> for CommitNode {
+  fn from(commit: &Commit) -> Self {
+    Self {
+      tree:       commit.tree().clone(),
+      parents:    commit.parent_shas.clone(),
+      date:       commit.committer.date.timestamp(),
+      generation: GENERATION_INFINITY,
+    }
+  }
+}
+
+// An in-memory commit-graph file, which lives at objects/info/commit-graph.
+// Like pack indexes, they're small enough that we read the whole thing.
+#[derive(Debug)]
+pub struct CommitGraph {
+  raw:    Vec<u8>,
+  fanout: [u32; FANOUT_SIZE],
+  oids:   usize, // offsets of the chunks in raw
+  data:   usize,
+  edges:  Option<usize>,
+}
+
+fn graph_error<T>(s: &str) -> Result<T> {
+  Err(PidgitError::CommitGraph(s.to_string()))
+}
+
+fn read_u32(buf: &[u8], pos: usize) -> Result<u32> {
+  match buf.get(pos..pos + 4) {
+    Some(bytes) => {
+      let mut word = [0u8; 4];
+      word.copy_from_slice(bytes);
+      Ok(u32::from_be_bytes(word))
+    },
+    None => graph_error("unexpected end of file"),
+  }
+}
+
+impl CommitGraph {
+  // parse this, based on
+  // https://github.com/git/git/blob/master/Documentation/gitformat-commit-graph.txt
+  // We only read the chunks we know about, and ignore the rest (like the
+  // corrected commit dates and bloom filters newer versions of git write).
+  pub fn load(path: &Path) -> Result<Self> {
+    let mut raw = vec![];
+    File::open(path)?.read_to_end(&mut raw)?;
+
+    // - A 4-byte signature, then 1-byte version, hash version, number of
+    //   chunks, and number of base graphs (which we don't support).
+    if raw.len() < HEADER_SIZE + HASH_SIZE || &raw[0..4] != SIGNATURE {
+      return graph_error("missing signature");
+    }
+
+    if raw[4] != VERSION {
+      return graph_error(&format!("unsupported version {}", raw[4]));
+    }
+
+    if raw[5] != HASH_VERSION {
+      return graph_error(&format!("unsupported hash version {}", raw[5]));
+    }
+
+    if raw[7] != 0 {
+      return graph_error("split commit-graphs are unsupported");
+    }
+
+    // - A table of chunk ids and 8-byte offsets, ending with a zero id.
+    let mut chunks = HashMap::new();
+    let mut pos = HEADER_SIZE;
+
+    for _ in 0..raw[6] {
+      let id = raw
+        .get(pos..pos + 4)
+        .ok_or_else(|| PidgitError::CommitGraph("truncated chunk table".into()))?
+        .to_vec();
+      let hi = read_u32(&raw, pos + 4)? as u64;
+      let lo = read_u32(&raw, pos + 8)? as u64;
+      chunks.insert(id, (hi << 32 | lo) as usize);
+      pos += CHUNK_ENTRY_SIZE;
+    }
+
+    let chunk = |id: &[u8; 4]| chunks.get(&id[..]).copied();
+
+    let (fanout_at, oids, data) =
+      match (chunk(CHUNK_FANOUT), chunk(CHUNK_OIDS), chunk(CHUNK_DATA)) {
+        (Some(f), Some(o), Some(d)) => (f, o, d),
+        _ => return graph_error("missing a required chunk"),
+      };
+
+    // - A 256-entry fan-out table, like a pack index has.
+    let mut fanout = [0u32; FANOUT_SIZE];
+    for n in 0..FANOUT_SIZE {
+      fanout[n] = read_u32(&raw, fanout_at + 4 * n)?;
+
+      if n > 0 && fanout[n] < fanout[n - 1] {
+        return graph_error("fanout table is not in order");
+      }
+    }
+
+    let count = fanout[FANOUT_SIZE - 1] as usize;
+
+    // - The sorted commit ids, and the data for each (in the same order).
+    let end = raw.len() - HASH_SIZE;
+    if oids + count * HASH_SIZE > end || data + count * DATA_SIZE > end {
+      return graph_error("file is truncated");
+    }
+
+    Ok(Self {
+      raw,
+      fanout,
+      oids,
+      data,
+      edges: chunk(CHUNK_EDGES),
+    })
+  }
+
+  pub fn count(&self) -> usize {
+    self.fanout[FANOUT_SIZE - 1] as usize
+  }
+
+  fn sha_bytes_at(&self, n: usize) -> &[u8] {
+    let start = self.oids + n * HASH_SIZE;
+    &self.raw[start..start + HASH_SIZE]
+  }
+
+  pub fn sha_at(&self, n: usize) -> Sha {
+    Sha::from(hex::encode(self.sha_bytes_at(n)))
+  }
+
+  fn position_of(&self, sha: &Sha) -> Option<usize> {
+    let bytes = hex::decode(sha.hexdigest()).ok()?;
+    if bytes.len() != HASH_SIZE {
+      return None;
+    }
+
+    let first = bytes[0] as usize;
+    let hi = self.fanout[first] as usize;
+    let lo = if first == 0 {
+      0
+    } else {
+      self.fanout[first - 1] as usize
+    };
+
+    let (mut lo, mut hi) = (lo, hi);
+    while lo < hi {
+      let mid = (lo + hi) / 2;
+      match self.sha_bytes_at(mid).cmp(&bytes[..]) {
+        std::cmp::Ordering::Less => lo = mid + 1,
+        std::cmp::Ordering::Greater => hi = mid,
+        std::cmp::Ordering::Equal => return Some(mid),
+      }
+    }
+
+    None
+  }
+
+  pub fn contains(&self, sha: &Sha) -> bool {
+    self.position_of(sha).is_some()
+  }
+
+  pub fn lookup(&self, sha: &Sha) -> Result<Option<CommitNode>> {
+    match self.position_of(sha) {
+      Some(n) => self.node_at(n).map(Some),
+      None => Ok(None),
+    }
+  }
+
+  // Each entry is the tree sha, two parent positions, and 8 bytes holding a
+  // 30-bit generation number and a 34-bit commit date.
+  pub fn node_at(&self, n: usize) -> Result<CommitNode> {
+    let start = self.data + n * DATA_SIZE;
+    let tree = Sha::from(hex::encode(&self.raw[start..start + HASH_SIZE]));
+
+    let pos = start + HASH_SIZE;
+    let (one, two) = (read_u32(&self.raw, pos)?, read_u32(&self.raw, pos + 4)?);
+    let (hi, lo) = (
+      read_u32(&self.raw, pos + 8)?,
+      read_u32(&self.raw, pos + 12)?,
+    );
+
+    let mut positions = vec![];
+
+    if one != PARENT_NONE {
+      positions.push(one);
+    }
+
+    if two & PARENT_EXTRA != 0 {
+      let edges = match self.edges {
+        Some(edges) => edges,
+        None => return graph_error("missing extra edge list"),
+      };
+
+      let mut at = edges + 4 * (two & POSITION_MASK) as usize;
+      loop {
+        let edge = read_u32(&self.raw, at)?;
+        positions.push(edge & POSITION_MASK);
+
+        if edge & PARENT_EXTRA != 0 {
+          break;
+        }
+
+        at += 4;
+      }
+    } else if two != PARENT_NONE {
+      positions.push(two);
+    }
+
+    let mut parents = Vec::with_capacity(positions.len());
+    for p in positions {
+      if p as usize >= self.count() {
+        return graph_error(&format!("bad parent position {}", p));
+      }
+
+      parents.push(self.sha_at(p as usize));
+    }
+
+    Ok(CommitNode {
+      tree,
+      parents,
+      date: (((hi & 0b11) as u64) << 32 | lo as u64) as i64,
+      generation: hi >> 2,
+    })
+  }
+
+  // Does the trailing checksum match what's in the file?
+  pub fn checksum_matches(&self) -> bool {
+    let (content, checksum) = self.raw.split_at(self.raw.len() - HASH_SIZE);
+    Sha1::from(content).digest().bytes() == checksum
+  }
+
+  // Are the shas in order, and in the right fanout buckets?
+  pub fn shas_in_order(&self) -> bool {
+    (0..self.count()).all(|n| {
+      let sha = self.sha_bytes_at(n);
+      let bucket = sha[0] as usize;
+      let lo = if bucket == 0 {
+        0
+      } else {
+        self.fanout[bucket - 1]
+      };
+
+      (lo as usize..self.fanout[bucket] as usize).contains(&n)
+        && (n == 0 || self.sha_bytes_at(n - 1) < sha)
+    })
+  }
+}
+
+// Write a commit-graph of these commits to path, filling in their generation
+// numbers. Every parent has to be in there, too.
+pub fn write_graph(path: &Path, nodes: HashMap<Sha, CommitNode>) -> Result<()> {
+  let mut shas = nodes.keys().map(|sha| sha.bytes()).collect::<Vec<_>>();
+  shas.sort();
+
+  let positions = shas
+    .iter()
+    .enumerate()
+    .map(|(n, bytes)| (Sha::from(hex::encode(bytes)), n as u32))
+    .collect::<HashMap<_, _>>();
+
+  let position = |sha: &Sha| match positions.get(sha) {
+    Some(n) => Ok(*n),
+    None => graph_error(&format!("missing parent {}", sha)),
+  };
+
+  let order = shas
+    .iter()
+    .map(|bytes| &nodes[&Sha::from(hex::encode(bytes))])
+    .collect::<Vec<_>>();
+
+  let generations = generations(&order, &position)?;
+
+  let mut fanout = vec![0u32; FANOUT_SIZE];
+  for bytes in &shas {
+    fanout[bytes[0] as usize] += 1;
+  }
+
+  let mut fanout_chunk = vec![];
+  let mut total = 0;
+  for n in fanout {
+    total += n;
+    fanout_chunk.extend(&total.to_be_bytes());
+  }
+
+  let oid_chunk = shas.concat();
+
+  let mut data_chunk = vec![];
+  let mut edge_chunk = vec![];
+
+  for (node, generation) in order.iter().zip(&generations) {
+    data_chunk.extend(node.tree.bytes());
+
+    let parents = node
+      .parents
+      .iter()
+      .map(position)
+      .collect::<Result<Vec<_>>>()?;
+
+    let one = parents.first().copied().unwrap_or(PARENT_NONE);
+    let two = match parents.len() {
+      0 | 1 => PARENT_NONE,
+      2 => parents[1],
+      _ => {
+        let start = (edge_chunk.len() / 4) as u32 | PARENT_EXTRA;
+        for (n, p) in parents[1..].iter().enumerate() {
+          let last = n == parents.len() - 2;
+          let edge = if last { p | PARENT_EXTRA } else { *p };
+          edge_chunk.extend(&edge.to_be_bytes());
+        }
+        start
+      },
+    };
+
+    let date = node.date.max(0) as u64 & DATE_MASK;
+
+    data_chunk.extend(&one.to_be_bytes());
+    data_chunk.extend(&two.to_be_bytes());
+    data_chunk.extend(&(generation << 2 | (date >> 32) as u32).to_be_bytes());
+    data_chunk.extend(&(date as u32).to_be_bytes());
+  }
+
+  let mut chunks = vec![
+    (CHUNK_FANOUT, fanout_chunk),
+    (CHUNK_OIDS, oid_chunk),
+    (CHUNK_DATA, data_chunk),
+  ];
+
+  if !edge_chunk.is_empty() {
+    chunks.push((CHUNK_EDGES, edge_chunk));
+  }
+
+  let mut out = SIGNATURE.to_vec();
+  out.extend(&[VERSION, HASH_VERSION, chunks.len() as u8, 0]);
+
+  let mut offset = (HEADER_SIZE + (chunks.len() + 1) * CHUNK_ENTRY_SIZE) as u64;
+  for (id, chunk) in &chunks {
+    out.extend(&id[..]);
+    out.extend(&offset.to_be_bytes());
+    offset += chunk.len() as u64;
+  }
+
+  out.extend(&[0; 4]);
+  out.extend(&offset.to_be_bytes());
+
+  for (_, chunk) in chunks {
+    out.extend(chunk);
+  }
+
+  let checksum = Sha1::from(&out).digest().bytes();
+  out.extend(&checksum);
+
+  let lockfile = Lockfile::new(path.to_path_buf());
+  let mut lock = lockfile.lock()?;
+  lock.write_all(&out)?;
+  lock.commit()
+}
+
+// Generation numbers for these nodes (in position order). We have to do the
+// parents first, so we use a stack rather than recursing, since history can
+// be very deep.
+fn generations<F>(nodes: &[&CommitNode], position: &F) -> Result<Vec<u32>>
+where
+  F: Fn(&Sha) -> Result<u32>,
+{
+  let mut generations = vec![0u32; nodes.len()];
+
+  for start in 0..nodes.len() {
+    let mut stack = vec![start];
+
+    while let Some(&n) = stack.last() {
+      if generations[n] != 0 {
+        stack.pop();
+        continue;
+      }
+
+      let mut pending = vec![];
+      let mut max = 0;
+
+      for parent in &nodes[n].parents {
+        let p = position(parent)? as usize;
+        match generations[p] {
+          0 => pending.push(p),
+          g => max = max.max(g),
+        }
+      }
+
+      if pending.is_empty() {
+        generations[n] = (max + 1).min(GENERATION_MAX);
+        stack.pop();
+      } else {
+        stack.extend(pending);
+      }
+    }
+  }
+
+  Ok(generations)
+}
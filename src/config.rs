@@ -0,0 +1,716 @@
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+use crate::errors::{PidgitError, Result};
+use crate::Lockfile;
+
+// git gives up on include loops at about this depth, so we do too
+const MAX_INCLUDE_DEPTH: usize = 10;
+
+// Where a config value came from. Later scopes override earlier ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+  System,
+  Global,
+  Local,
+}
+
+// One value, with its key in canonical form: section and name lowercased,
+// subsection left alone (so "Branch.Main.Remote" is "branch.Main.remote").
+// A key with no "=" at all has no value, which means true.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+  pub key:   String,
+  pub value: Option<String>,
+  pub scope: Scope,
+}
+
+// Everything from every config file we read, includes and all, in the order
+// we read it. Lookups take the last value, so that local beats global beats
+// system.
+#[derive(Debug, Default)]
+pub struct Config {
+  entries: Vec<Entry>,
+}
+
+// A key as given on the command line: "section.name" or
+// "section.sub.section.name".
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Key {
+  section:    String,
+  subsection: Option<String>,
+  name:       String,
+}
+
+// A single config file, kept line by line so that we can edit it without
+// disturbing the comments and formatting around whatever we change.
+#[derive(Debug)]
+pub struct ConfigFile {
+  path:  PathBuf,
+  lines: Vec<Line>,
+}
+
+#[derive(Debug)]
+struct Line {
+  raw:  String, // might be several physical lines, if a value was continued
+  kind: LineKind,
+}
+
+#[derive(Debug)]
+enum LineKind {
+  Section(String, Option<String>),
+  Entry(String, Option<String>),
+  Other, // blank or comment
+}
+
+impl Scope {
+  // The file we'd write to for this scope (reading can involve several).
+  pub fn path(self, git_dir: Option<&Path>) -> Option<PathBuf> {
+    match self {
+      Scope::System => Some(system_path()),
+      Scope::Global => std::env::var_os("GIT_CONFIG_GLOBAL")
+        .map(PathBuf::from)
+        .or_else(|| home_dir().map(|h| h.join(".gitconfig"))),
+      Scope::Local => git_dir.map(|d| d.join("config")),
+    }
+  }
+}
+
+fn home_dir() -> Option<PathBuf> {
+  std::env::var_os("HOME").map(PathBuf::from)
+}
+
+// Paths in config files can start with ~/, meaning $HOME.
+pub fn expand_path(path: &str) -> Option<PathBuf> {
+  match path.strip_prefix("~/") {
+    Some(rest) => home_dir().map(|home| home.join(rest)),
+    None => Some(PathBuf::from(path)),
+  }
+}
+
+fn system_path() -> PathBuf {
+  std::env::var_os("GIT_CONFIG_SYSTEM")
+    .map(PathBuf::from)
+    .unwrap_or_else(|| PathBuf::from("/etc/gitconfig"))
+}
+
+// All the files we read, in order, for a repo with this git dir (or for no repo
+// at all). Like git, we read both the XDG file and ~/.gitconfig, unless
+// $GIT_CONFIG_GLOBAL says otherwise.
+pub fn files(git_dir: Option<&Path>) -> Vec<(Scope, PathBuf)> {
+  let mut files = vec![];
+
+  if std::env::var_os("GIT_CONFIG_NOSYSTEM").is_none() {
+    files.push((Scope::System, system_path()));
+  }
+
+  if let Some(path) = std::env::var_os("GIT_CONFIG_GLOBAL") {
+    files.push((Scope::Global, PathBuf::from(path)));
+  } else {
+    let xdg = std::env::var_os("XDG_CONFIG_HOME")
+      .map(PathBuf::from)
+      .or_else(|| home_dir().map(|h| h.join(".config")));
+
+    if let Some(xdg) = xdg {
+      files.push((Scope::Global, xdg.join("git/config")));
+    }
+
+    if let Some(home) = home_dir() {
+      files.push((Scope::Global, home.join(".gitconfig")));
+    }
+  }
+
+  if let Some(path) = Scope::Local.path(git_dir) {
+    files.push((Scope::Local, path));
+  }
+
+  files
+}
+
+impl Config {
+  // Read all of these files, in order, following include.path as we go.
+  // Missing files are fine; broken ones aren't.
+  pub fn load(files: &[(Scope, PathBuf)]) -> Result<Self> {
+    let mut config = Config::default();
+
+    for (scope, path) in files {
+      config.read(*scope, path, 0)?;
+    }
+
+    Ok(config)
+  }
+
+  fn read(&mut self, scope: Scope, path: &Path, depth: usize) -> Result<()> {
+    if depth > MAX_INCLUDE_DEPTH {
+      return Err(PidgitError::Generic(format!(
+        "exceeded maximum include depth ({}) while including {}",
+        MAX_INCLUDE_DEPTH,
+        path.display()
+      )));
+    }
+
+    let file = ConfigFile::open(path)?;
+
+    for (key, value) in file.entries() {
+      self.entries.push(Entry {
+        key: key.to_string(),
+        value: value.map(String::from),
+        scope,
+      });
+
+      // included files go exactly where they were included from
+      if let ("include.path", Some(include)) = (key, value) {
+        if let Some(included) = expand_path(include) {
+          let included = path.parent().unwrap_or(path).join(included);
+          self.read(scope, &included, depth + 1)?;
+        }
+      }
+    }
+
+    Ok(())
+  }
+
+  pub fn entries(&self) -> &[Entry] {
+    &self.entries
+  }
+
+  // The last value for this key, if there is one. A key with no value at all
+  // comes back as "".
+  pub fn get(&self, key: &str) -> Option<&str> {
+    self.get_all(key).pop()
+  }
+
+  pub fn get_all(&self, key: &str) -> Vec<&str> {
+    let key = match Key::parse(key) {
+      Ok(key) => key.canonical(),
+      Err(_) => return vec![],
+    };
+
+    self
+      .entries
+      .iter()
+      .filter(|e| e.key == key)
+      .map(|e| e.value.as_deref().unwrap_or(""))
+      .collect()
+  }
+}
+
+impl Key {
+  pub fn parse(key: &str) -> Result<Self> {
+    let (section, rest) = key.split_once('.').ok_or_else(|| {
+      PidgitError::Generic(format!("key does not contain a section: {}", key))
+    })?;
+
+    let (subsection, name) = match rest.rsplit_once('.') {
+      Some((sub, name)) => (Some(sub.to_string()), name),
+      None => (None, rest),
+    };
+
+    if !is_valid_section(section) || !is_valid_name(name) {
+      return Err(PidgitError::Generic(format!("invalid key: {}", key)));
+    }
+
+    Ok(Key {
+      section: section.to_string(),
+      subsection,
+      name: name.to_string(),
+    })
+  }
+
+  pub fn canonical(&self) -> String {
+    canonical_key(&self.section, self.subsection.as_deref(), &self.name)
+  }
+
+  fn in_section(&self, section: &str, subsection: Option<&str>) -> bool {
+    self.section.eq_ignore_ascii_case(section)
+      && self.subsection.as_deref() == subsection
+  }
+}
+
+fn canonical_key(section: &str, subsection: Option<&str>, name: &str) -> String {
+  match subsection {
+    Some(sub) => format!(
+      "{}.{}.{}",
+      section.to_ascii_lowercase(),
+      sub,
+      name.to_ascii_lowercase()
+    ),
+    None => format!(
+      "{}.{}",
+      section.to_ascii_lowercase(),
+      name.to_ascii_lowercase()
+    ),
+  }
+}
+
+fn is_valid_section(s: &str) -> bool {
+  !s.is_empty()
+    && s
+      .chars()
+      .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+}
+
+fn is_valid_name(s: &str) -> bool {
+  s.starts_with(|c: char| c.is_ascii_alphabetic())
+    && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+impl ConfigFile {
+  // A file that doesn't exist is just an empty one.
+  pub fn open(path: &Path) -> Result<Self> {
+    let text = match std::fs::read(path) {
+      Ok(bytes) => String::from_utf8(bytes)?,
+      Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+      Err(e) => return Err(e.into()),
+    };
+
+    let lines = parse(&text).map_err(|n| {
+      PidgitError::Generic(format!(
+        "bad config line {} in file {}",
+        n,
+        path.display()
+      ))
+    })?;
+
+    Ok(ConfigFile {
+      path: path.to_path_buf(),
+      lines,
+    })
+  }
+
+  // (canonical key, value) pairs, in order
+  pub fn entries(&self) -> impl Iterator<Item = (&str, Option<&str>)> {
+    self.lines.iter().filter_map(|line| match &line.kind {
+      LineKind::Entry(key, value) => Some((key.as_str(), value.as_deref())),
+      _ => None,
+    })
+  }
+
+  fn find(&self, key: &Key) -> Vec<usize> {
+    let canonical = key.canonical();
+
+    (0..self.lines.len())
+      .filter(|&i| {
+        matches!(&self.lines[i].kind, LineKind::Entry(k, _) if *k == canonical)
+      })
+      .collect()
+  }
+
+  // Replace the value for this key, or add it if there isn't one. We can't
+  // know which of several values to replace, so that's an error.
+  pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+    let key = Key::parse(key)?;
+
+    match self.find(&key).as_slice() {
+      [] => self.add(&key, value),
+      [i] => self.lines[*i] = entry_line(&key, value),
+      _ => {
+        return Err(PidgitError::Generic(format!(
+          "cannot overwrite multiple values with a single value for {}",
+          key.canonical()
+        )))
+      },
+    }
+
+    Ok(())
+  }
+
+  // Add another value for this key, at the end of the last section it could
+  // go in (making a new section if there isn't one).
+  pub fn add_value(&mut self, key: &str, value: &str) -> Result<()> {
+    let key = Key::parse(key)?;
+    self.add(&key, value);
+    Ok(())
+  }
+
+  fn add(&mut self, key: &Key, value: &str) {
+    let mut insert_at = None;
+    let mut in_section = false;
+
+    for (i, line) in self.lines.iter().enumerate() {
+      match &line.kind {
+        LineKind::Section(section, sub) => {
+          in_section = key.in_section(section, sub.as_deref());
+          if in_section {
+            insert_at = Some(i + 1);
+          }
+        },
+        LineKind::Entry(..) if in_section => insert_at = Some(i + 1),
+        _ => {},
+      }
+    }
+
+    let at = match insert_at {
+      Some(at) => at,
+      None => {
+        self.lines.push(section_line(key));
+        self.lines.len()
+      },
+    };
+
+    self.lines.insert(at, entry_line(key, value));
+  }
+
+  // Remove the value for this key, returning whether there was one. As with
+  // set, we refuse to guess which of several to remove.
+  pub fn unset(&mut self, key: &str) -> Result<bool> {
+    let key = Key::parse(key)?;
+
+    match self.find(&key).as_slice() {
+      [] => Ok(false),
+      [i] => {
+        self.lines.remove(*i);
+        Ok(true)
+      },
+      _ => Err(PidgitError::Generic(format!(
+        "{} has multiple values",
+        key.canonical()
+      ))),
+    }
+  }
+
+  pub fn save(&self) -> Result<()> {
+    let lockfile = Lockfile::new(self.path.clone());
+    let mut lock = lockfile.lock()?;
+
+    for line in &self.lines {
+      writeln!(lock, "{}", line.raw)?;
+    }
+
+    lock.commit()
+  }
+}
+
+fn section_line(key: &Key) -> Line {
+  let section = key.section.to_ascii_lowercase();
+
+  let raw = match &key.subsection {
+    Some(sub) => {
+      let sub = sub.replace('\\', "\\\\").replace('"', "\\\"");
+      format!("[{} \"{}\"]", section, sub)
+    },
+    None => format!("[{}]", section),
+  };
+
+  Line {
+    raw,
+    kind: LineKind::Section(section, key.subsection.clone()),
+  }
+}
+
+fn entry_line(key: &Key, value: &str) -> Line {
+  Line {
+    raw:  format!("\t{} = {}", key.name, quote_value(value)),
+    kind: LineKind::Entry(key.canonical(), Some(value.to_string())),
+  }
+}
+
+// Quote a value only if reading it back would otherwise change it.
+fn quote_value(value: &str) -> String {
+  let escaped = value
+    .replace('\\', "\\\\")
+    .replace('"', "\\\"")
+    .replace('\n', "\\n")
+    .replace('\t', "\\t");
+
+  let needs_quotes = value.starts_with(char::is_whitespace)
+    || value.ends_with(char::is_whitespace)
+    || value.contains([';', '#']);
+
+  if needs_quotes {
+    format!("\"{}\"", escaped)
+  } else {
+    escaped
+  }
+}
+
+// On failure, returns the (1-based) line number that was bad.
+fn parse(text: &str) -> std::result::Result<Vec<Line>, usize> {
+  let mut lines = vec![];
+  let mut section: Option<(String, Option<String>)> = None;
+  let mut physical = text.lines().enumerate();
+
+  while let Some((n, line)) = physical.next() {
+    let trimmed = line.trim_start();
+
+    if trimmed.is_empty() || trimmed.starts_with(['#', ';']) {
+      lines.push(Line {
+        raw:  line.to_string(),
+        kind: LineKind::Other,
+      });
+      continue;
+    }
+
+    if trimmed.starts_with('[') {
+      let (name, sub) = parse_section(trimmed).ok_or(n + 1)?;
+      section = Some((name.clone(), sub.clone()));
+      lines.push(Line {
+        raw:  line.to_string(),
+        kind: LineKind::Section(name, sub),
+      });
+      continue;
+    }
+
+    // a value can carry on over several lines, with a backslash at the end
+    // (but not an escaped one)
+    let mut raw = line.to_string();
+    while raw.chars().rev().take_while(|&c| c == '\\').count() % 2 == 1 {
+      match physical.next() {
+        Some((_, next)) => {
+          raw.push('\n');
+          raw.push_str(next);
+        },
+        None => break,
+      }
+    }
+
+    let (name, value) = parse_entry(&raw).ok_or(n + 1)?;
+    let (sec, sub) = section.as_ref().ok_or(n + 1)?;
+
+    lines.push(Line {
+      kind: LineKind::Entry(canonical_key(sec, sub.as_deref(), &name), value),
+      raw,
+    });
+  }
+
+  Ok(lines)
+}
+
+// [section], [section "subsection"], or the old-fashioned [section.subsection]
+// (where the subsection isn't case-sensitive). Returns the section lowercased.
+fn parse_section(line: &str) -> Option<(String, Option<String>)> {
+  let inner = line.strip_prefix('[')?;
+
+  let (name, sub, rest) = match inner.find(['"', ']'])? {
+    close if inner[close..].starts_with(']') => {
+      let name = &inner[..close];
+      let (name, sub) = match name.split_once('.') {
+        Some((name, sub)) => (name, Some(sub.to_ascii_lowercase())),
+        None => (name, None),
+      };
+      (name, sub, &inner[close + 1..])
+    },
+    quote => {
+      let name = inner[..quote].trim_end();
+      let mut sub = String::new();
+      let mut chars = inner[quote + 1..].char_indices();
+
+      let end = loop {
+        match chars.next()? {
+          (_, '\\') => sub.push(chars.next()?.1),
+          (i, '"') => break quote + 1 + i + 1,
+          (_, c) => sub.push(c),
+        }
+      };
+
+      let rest = inner[end..].strip_prefix(']')?;
+      (name, Some(sub), rest)
+    },
+  };
+
+  let rest = rest.trim_start();
+  let rest_ok = rest.is_empty() || rest.starts_with(['#', ';']);
+
+  if !is_valid_section(name) || !rest_ok {
+    return None;
+  }
+
+  Some((name.to_ascii_lowercase(), sub))
+}
+
+// "name = value # comment", or just "name" for a boolean
+fn parse_entry(line: &str) -> Option<(String, Option<String>)> {
+  let line = line.trim_start();
+  let end = line
+    .find(|c: char| !c.is_ascii_alphanumeric() && c != '-')
+    .unwrap_or(line.len());
+
+  let name = &line[..end];
+  if !is_valid_name(name) {
+    return None;
+  }
+
+  let rest = line[end..].trim_start();
+
+  if rest.is_empty() || rest.starts_with(['#', ';']) {
+    return Some((name.to_ascii_lowercase(), None));
+  }
+
+  let value = parse_value(rest.strip_prefix('=')?)?;
+  Some((name.to_ascii_lowercase(), Some(value)))
+}
+
+// Strip comments and surrounding whitespace, handle quotes and escapes, and
+// join continued lines. Whitespace inside a value is kept as is.
+fn parse_value(raw: &str) -> Option<String> {
+  let mut value = String::new();
+  let mut pending_space = String::new();
+  let mut quoted = false;
+  let mut chars = raw.chars();
+
+  while let Some(c) = chars.next() {
+    let c = match c {
+      '"' => {
+        quoted = !quoted;
+        continue;
+      },
+      ';' | '#' if !quoted => break,
+      c if c.is_whitespace() && !quoted => {
+        if !value.is_empty() {
+          pending_space.push(c);
+        }
+        continue;
+      },
+      '\\' => match chars.next() {
+        Some('\n') => continue,
+        Some('n') => '\n',
+        Some('t') => '\t',
+        Some('b') => '\u{8}',
+        Some(c @ '\\') | Some(c @ '"') => c,
+        _ => return None,
+      },
+      c => c,
+    };
+
+    value.push_str(&pending_space);
+    pending_space.clear();
+    value.push(c);
+  }
+
+  if quoted {
+    None
+  } else {
+    Some(value)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_prelude::*;
+
+  const SAMPLE: &str = "\
+# a comment
+[core]
+\tbare = false ; another comment
+\tflag
+[Branch \"Main\"]
+\tremote = origin
+\tmerge = refs/heads/main
+[url.Example]
+\tvalue = \"  padded; really \" # but not this
+\tescaped = a\\tb\\\"c\\\\
+\tcontinued = one \\
+two
+\tmulti = 1
+\tmulti = 2
+";
+
+  fn sample(dir: &TempDir) -> PathBuf {
+    let child = dir.child("config");
+    child.write_str(SAMPLE).unwrap();
+    child.path().to_path_buf()
+  }
+
+  #[test]
+  fn parse_values() {
+    let dir = tempdir();
+    let config = Config::load(&[(Scope::Local, sample(&dir))]).unwrap();
+
+    assert_eq!(config.get("core.bare"), Some("false"));
+    assert_eq!(config.get("CORE.Flag"), Some(""));
+    assert_eq!(config.get("branch.Main.remote"), Some("origin"));
+    assert_eq!(config.get("branch.main.remote"), None);
+    assert_eq!(config.get("url.example.value"), Some("  padded; really "));
+    assert_eq!(config.get("url.example.escaped"), Some("a\tb\"c\\"));
+    assert_eq!(config.get("url.example.continued"), Some("one two"));
+    assert_eq!(config.get("url.example.multi"), Some("2"));
+    assert_eq!(config.get_all("url.example.multi"), vec!["1", "2"]);
+    assert_eq!(config.get("nope.nope"), None);
+  }
+
+  #[test]
+  fn bad_lines() {
+    let dir = tempdir();
+    let path = dir.child("config");
+
+    for bad in &["[core\n", "[core]\nvalue = \"open\n", "orphan = 1\n"] {
+      path.write_str(bad).unwrap();
+      let err = ConfigFile::open(path.path()).unwrap_err();
+      assert!(err.to_string().starts_with("bad config line"), "{}", bad);
+    }
+  }
+
+  #[test]
+  fn precedence_and_includes() {
+    let dir = tempdir();
+    dir
+      .child("global")
+      .write_str("[user]\n\tname = Global\n\temail = g@example.com\n")
+      .unwrap();
+    dir
+      .child("local")
+      .write_str("[user]\n\tname = Local\n[include]\n\tpath = extra\n")
+      .unwrap();
+    dir
+      .child("extra")
+      .write_str("[user]\n\temail = e@example.com\n")
+      .unwrap();
+
+    let config = Config::load(&[
+      (Scope::Global, dir.child("global").path().to_path_buf()),
+      (Scope::Local, dir.child("local").path().to_path_buf()),
+      (Scope::Local, dir.child("missing").path().to_path_buf()),
+    ])
+    .unwrap();
+
+    assert_eq!(config.get("user.name"), Some("Local"));
+    assert_eq!(config.get("user.email"), Some("e@example.com"));
+
+    let last = config.entries().last().unwrap();
+    assert_eq!(last.key, "user.email");
+    assert_eq!(last.scope, Scope::Local);
+
+    // an include loop is an error, not a hang
+    dir
+      .child("extra")
+      .write_str("[include]\n\tpath = extra\n")
+      .unwrap();
+    let files = [(Scope::Local, dir.child("extra").path().to_path_buf())];
+    assert!(Config::load(&files).is_err());
+  }
+
+  #[test]
+  fn edit_in_place() {
+    let dir = tempdir();
+    let path = sample(&dir);
+
+    let mut file = ConfigFile::open(&path).unwrap();
+    file.set("core.bare", "true").unwrap();
+    file.set("branch.Main.rebase", "true").unwrap();
+    file.set("new.sub.key", " spaced").unwrap();
+    file.add_value("url.example.multi", "3").unwrap();
+    assert!(file.set("url.example.multi", "4").is_err());
+    assert!(file.unset("core.flag").unwrap());
+    assert!(!file.unset("core.flag").unwrap());
+    file.save().unwrap();
+
+    let text = std::fs::read_to_string(&path).unwrap();
+    let expected = SAMPLE
+      .replace(
+        "\tbare = false ; another comment\n\tflag\n",
+        "\tbare = true\n",
+      )
+      .replace(
+        "merge = refs/heads/main\n",
+        "merge = refs/heads/main\n\trebase = true\n",
+      )
+      + "\tmulti = 3\n[new \"sub\"]\n\tkey = \" spaced\"\n";
+    assert_eq!(text, expected);
+
+    let files = [(Scope::Local, path)];
+    let config = Config::load(&files).unwrap();
+    assert_eq!(config.get("new.sub.key"), Some(" spaced"));
+    assert_eq!(config.get_all("url.example.multi"), vec!["1", "2", "3"]);
+  }
+}
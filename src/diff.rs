@@ -1,4 +1,10 @@
+mod binary;
+mod diff3;
 mod myers;
+pub mod stat;
+
+pub use binary::binary_hunk;
+pub use diff3::merge;
 
 use crate::util::colored;
 use std::default::Default;
@@ -35,11 +41,43 @@ impl Default for Line {
   }
 }
 
+// Like git, we call it binary if there's a NUL anywhere near the start.
+pub fn is_binary(content: &[u8]) -> bool {
+  content.iter().take(8000).any(|&b| b == 0)
+}
+
 pub fn diff_hunks(a: String, b: String) -> Vec<DiffHunk> {
   let differ = myers::Myers::new(a, b);
   DiffHunk::filter(differ.diff())
 }
 
+// How many lines going from a to b adds and deletes, for diffstats.
+pub fn line_changes(a: String, b: String) -> (usize, usize) {
+  let edits = myers::Myers::new(a, b).diff();
+  let count = |kind| edits.iter().filter(|e| e.kind == kind).count();
+
+  (count(DiffType::Ins), count(DiffType::Del))
+}
+
+// How alike two texts are, as a percentage: the number of lines they have in
+// common, out of the length of the longer one. Git's rename detection does
+// something similar, but by bytes.
+pub fn similarity(a: String, b: String) -> usize {
+  let longest = a.lines().count().max(b.lines().count());
+
+  if longest == 0 {
+    return 100;
+  }
+
+  let common = myers::Myers::new(a, b)
+    .diff()
+    .iter()
+    .filter(|e| e.kind == DiffType::Eql)
+    .count();
+
+  common * 100 / longest
+}
+
 impl std::fmt::Display for DiffType {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
     let c = match self {
@@ -125,7 +163,7 @@ impl DiffHunk {
     }
   }
 
-  fn build(&mut self, diff: &Vec<Edit>, mut offset: isize) -> isize {
+  fn build(&mut self, diff: &[Edit], mut offset: isize) -> isize {
     let mut counter = -1;
 
     while counter != 0 {
@@ -168,7 +206,7 @@ impl DiffHunk {
     F: FnMut(&Edit) -> Option<&Line>,
   {
     let lines = self.edits.iter().filter_map(getter).collect::<Vec<_>>();
-    let start = if lines.len() > 0 {
+    let start = if !lines.is_empty() {
       lines[0].number()
     } else {
       default
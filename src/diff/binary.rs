@@ -0,0 +1,108 @@
+use flate2::{write::ZlibEncoder, Compression};
+use std::io::prelude::*;
+
+use crate::pack::delta;
+
+const BASE85: &[u8; 85] = b"0123456789\
+  ABCDEFGHIJKLMNOPQRSTUVWXYZ\
+  abcdefghijklmnopqrstuvwxyz\
+  !#$%&()*+-;<=>?@^_`{|}~";
+
+// Bytes of (compressed) data per encoded line.
+const LINE_BYTES: usize = 52;
+
+// One half of a "GIT binary patch": enough to get from `from` to `to`, as
+// either the whole of `to` or a delta against `from`, whichever is smaller.
+// Like git, we only bother with a delta when there's something on both sides.
+pub fn binary_hunk(from: &[u8], to: &[u8]) -> String {
+  let literal = deflate(to);
+
+  let (kind, size, data) = if from.is_empty() || to.is_empty() {
+    ("literal", to.len(), literal)
+  } else {
+    let delta = delta::compute(from, to);
+    let deflated = deflate(&delta);
+
+    if deflated.len() < literal.len() {
+      ("delta", delta.len(), deflated)
+    } else {
+      ("literal", to.len(), literal)
+    }
+  };
+
+  let mut out = format!("{} {}\n", kind, size);
+
+  for chunk in data.chunks(LINE_BYTES) {
+    out.push(line_length_char(chunk.len()));
+    out.push_str(&encode_base85(chunk));
+    out.push('\n');
+  }
+
+  out
+}
+
+// git uses its default core.compression here, which is the fastest level
+fn deflate(data: &[u8]) -> Vec<u8> {
+  let mut z = ZlibEncoder::new(vec![], Compression::fast());
+  z.write_all(data).expect("writing to a vec can't fail");
+  z.finish().expect("writing to a vec can't fail")
+}
+
+// Each line starts with how many bytes it holds: A-Z for 1-26, a-z for 27-52.
+fn line_length_char(len: usize) -> char {
+  let len = len as u8;
+
+  if len <= 26 {
+    (b'A' + len - 1) as char
+  } else {
+    (b'a' + len - 27) as char
+  }
+}
+
+// Every four bytes (big-endian, zero padded at the end) become five chars.
+fn encode_base85(data: &[u8]) -> String {
+  let mut out = String::with_capacity(data.len().div_ceil(4) * 5);
+
+  for group in data.chunks(4) {
+    let mut word = [0u8; 4];
+    word[..group.len()].copy_from_slice(group);
+    let mut n = u32::from_be_bytes(word);
+
+    let mut chars = [0u8; 5];
+    for c in chars.iter_mut().rev() {
+      *c = BASE85[(n % 85) as usize];
+      n /= 85;
+    }
+
+    out.extend(chars.iter().map(|&c| c as char));
+  }
+
+  out
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn base85() {
+    assert_eq!(encode_base85(&[0, 0, 0, 0]), "00000");
+    assert_eq!(encode_base85(&[0xff, 0xff, 0xff, 0xff]), "|NsC0");
+    assert_eq!(encode_base85(&[0, 0, 0, 1, 0]), "0000100000");
+
+    assert_eq!(line_length_char(1), 'A');
+    assert_eq!(line_length_char(26), 'Z');
+    assert_eq!(line_length_char(27), 'a');
+    assert_eq!(line_length_char(52), 'z');
+  }
+
+  #[test]
+  fn hunks() {
+    // these are exactly what git produces
+    assert_eq!(binary_hunk(b"abc", b""), "literal 0\nHcmV?d00001\n");
+    assert_eq!(
+      binary_hunk(b"x\0y", b"x\0z"),
+      "literal 3\nKcmb<mr~&{1<pA>l\n"
+    );
+  }
+}
@@ -0,0 +1,262 @@
+use std::collections::HashMap;
+
+use crate::diff::myers::Myers;
+use crate::diff::DiffType;
+
+// The result of merging two descendants (a and b) of a common ancestor (o).
+// Runs of lines either merged cleanly, or they didn't, in which case we keep
+// all three versions.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Chunk {
+  Clean(Vec<String>),
+  Conflict {
+    o: Vec<String>,
+    a: Vec<String>,
+    b: Vec<String>,
+  },
+}
+
+#[derive(Debug)]
+pub struct Merge {
+  chunks: Vec<Chunk>,
+}
+
+// This is the diff3 algorithm from Khanna, Kuber, and Pierce's "A Formal
+// Investigation of Diff3". We diff o against both a and b; any line in o that
+// matches a line in both of them is a stable point, and we compare the
+// stretches between stable points to see who changed what.
+struct Diff3 {
+  o:       Vec<String>,
+  a:       Vec<String>,
+  b:       Vec<String>,
+  match_a: HashMap<usize, usize>,
+  match_b: HashMap<usize, usize>,
+  line_o:  usize,
+  line_a:  usize,
+  line_b:  usize,
+  chunks:  Vec<Chunk>,
+}
+
+pub fn merge(o: &str, a: &str, b: &str) -> Merge {
+  // keep the newlines, so that we can put everything back together exactly
+  let lines = |s: &str| s.split_inclusive('\n').map(String::from).collect();
+  Diff3::new(lines(o), lines(a), lines(b)).merge()
+}
+
+// Line numbers in o that match line numbers in other; both are 1-based.
+fn matches(o: &[String], other: &[String]) -> HashMap<usize, usize> {
+  Myers::from_lines(o.to_vec(), other.to_vec())
+    .diff()
+    .into_iter()
+    .filter(|edit| edit.kind == DiffType::Eql)
+    .map(|edit| (edit.a.unwrap().number(), edit.b.unwrap().number()))
+    .collect()
+}
+
+impl Diff3 {
+  fn new(o: Vec<String>, a: Vec<String>, b: Vec<String>) -> Self {
+    Self {
+      match_a: matches(&o, &a),
+      match_b: matches(&o, &b),
+      o,
+      a,
+      b,
+      line_o: 0,
+      line_a: 0,
+      line_b: 0,
+      chunks: vec![],
+    }
+  }
+
+  fn merge(mut self) -> Merge {
+    loop {
+      match self.find_next_mismatch() {
+        Some(1) => match self.find_next_match() {
+          Some((o, a, b)) => self.emit_chunk(o, a, b),
+          None => break,
+        },
+        Some(i) => {
+          self.emit_chunk(self.line_o + i, self.line_a + i, self.line_b + i)
+        },
+        None => break,
+      }
+    }
+
+    self.emit_final_chunk();
+
+    Merge {
+      chunks: self.chunks,
+    }
+  }
+
+  fn in_bounds(&self, i: usize) -> bool {
+    self.line_o + i <= self.o.len()
+      || self.line_a + i <= self.a.len()
+      || self.line_b + i <= self.b.len()
+  }
+
+  fn is_match(
+    &self,
+    matches: &HashMap<usize, usize>,
+    offset: usize,
+    i: usize,
+  ) -> bool {
+    matches.get(&(self.line_o + i)) == Some(&(offset + i))
+  }
+
+  // how far we can go from where we are before the three files disagree
+  fn find_next_mismatch(&self) -> Option<usize> {
+    let mut i = 1;
+
+    while self.in_bounds(i)
+      && self.is_match(&self.match_a, self.line_a, i)
+      && self.is_match(&self.match_b, self.line_b, i)
+    {
+      i += 1;
+    }
+
+    if self.in_bounds(i) {
+      Some(i)
+    } else {
+      None
+    }
+  }
+
+  // the next line in o that's in both a and b, if there is one
+  fn find_next_match(&self) -> Option<(usize, usize, usize)> {
+    ((self.line_o + 1)..=self.o.len()).find_map(|o| {
+      match (self.match_a.get(&o), self.match_b.get(&o)) {
+        (Some(&a), Some(&b)) => Some((o, a, b)),
+        _ => None,
+      }
+    })
+  }
+
+  // emit everything up to (but not including) these lines
+  fn emit_chunk(&mut self, o: usize, a: usize, b: usize) {
+    let chunk = self.make_chunk(
+      self.o[self.line_o..o - 1].to_vec(),
+      self.a[self.line_a..a - 1].to_vec(),
+      self.b[self.line_b..b - 1].to_vec(),
+    );
+
+    self.chunks.push(chunk);
+
+    self.line_o = o - 1;
+    self.line_a = a - 1;
+    self.line_b = b - 1;
+  }
+
+  fn emit_final_chunk(&mut self) {
+    let chunk = self.make_chunk(
+      self.o[self.line_o..].to_vec(),
+      self.a[self.line_a..].to_vec(),
+      self.b[self.line_b..].to_vec(),
+    );
+
+    self.chunks.push(chunk);
+  }
+
+  fn make_chunk(&self, o: Vec<String>, a: Vec<String>, b: Vec<String>) -> Chunk {
+    if a == o || a == b {
+      Chunk::Clean(b)
+    } else if b == o {
+      Chunk::Clean(a)
+    } else {
+      Chunk::Conflict { o, a, b }
+    }
+  }
+}
+
+impl Merge {
+  pub fn is_clean(&self) -> bool {
+    self
+      .chunks
+      .iter()
+      .all(|chunk| matches!(chunk, Chunk::Clean(_)))
+  }
+
+  // The merged text, with conflict markers (labeled with a_name and b_name)
+  // around anything that didn't merge cleanly.
+  pub fn to_string(&self, a_name: &str, b_name: &str) -> String {
+    let mut out = String::new();
+
+    let push_lines = |out: &mut String, lines: &[String]| {
+      for line in lines {
+        out.push_str(line);
+      }
+
+      // the markers have to start on their own line
+      if !out.is_empty() && !out.ends_with('\n') {
+        out.push('\n');
+      }
+    };
+
+    for chunk in &self.chunks {
+      match chunk {
+        Chunk::Clean(lines) => lines.iter().for_each(|l| out.push_str(l)),
+        Chunk::Conflict { a, b, .. } => {
+          if !out.is_empty() && !out.ends_with('\n') {
+            out.push('\n');
+          }
+
+          out.push_str(&format!("<<<<<<< {}\n", a_name));
+          push_lines(&mut out, a);
+          out.push_str("=======\n");
+          push_lines(&mut out, b);
+          out.push_str(&format!(">>>>>>> {}\n", b_name));
+        },
+      }
+    }
+
+    out
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn clean_merges() {
+    let o = "celery\ngarlic\nonions\nsalmon\ntomatoes\nwine\n";
+    let a = "celery\nsalmon\ntomatoes\ngarlic\nonions\nwine\n";
+    let b = "celery\ngarlic\nsalmon\ntomatoes\nonions\nwine\n";
+
+    // nobody touched it, or only one side did
+    assert!(merge(o, o, o).is_clean());
+    assert_eq!(merge(o, a, o).to_string("a", "b"), a);
+    assert_eq!(merge(o, o, b).to_string("a", "b"), b);
+    assert_eq!(merge(o, a, a).to_string("a", "b"), a);
+
+    // changes to different parts of the file
+    let o = "one\ntwo\nthree\nfour\nfive\n";
+    let a = "ONE\ntwo\nthree\nfour\nfive\n";
+    let b = "one\ntwo\nthree\nfour\nFIVE\n";
+
+    let merged = merge(o, a, b);
+    assert!(merged.is_clean());
+    assert_eq!(merged.to_string("a", "b"), "ONE\ntwo\nthree\nfour\nFIVE\n");
+  }
+
+  #[test]
+  fn conflicts() {
+    let o = "one\ntwo\nthree\n";
+    let a = "one\nTWO\nthree\n";
+    let b = "one\ndeux\nthree\n";
+
+    let merged = merge(o, a, b);
+    assert!(!merged.is_clean());
+    assert_eq!(
+      merged.to_string("HEAD", "topic"),
+      "one\n<<<<<<< HEAD\nTWO\n=======\ndeux\n>>>>>>> topic\nthree\n"
+    );
+
+    // no trailing newlines and no common ancestor
+    let merged = merge("", "ours", "theirs");
+    assert_eq!(
+      merged.to_string("a", "b"),
+      "<<<<<<< a\nours\n=======\ntheirs\n>>>>>>> b\n"
+    );
+  }
+}
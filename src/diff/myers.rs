@@ -14,19 +14,24 @@ struct Trace(usize, usize, usize, usize);
 impl Myers {
   // strings now owned by wrapping vecs
   pub fn new(a: String, b: String) -> Self {
-    Self {
-      a: a
-        .lines()
-        .enumerate()
-        .map(|(n, s)| Line(n + 1, s.to_string()))
-        .collect::<Vec<_>>()
-        .into(),
-      b: b
-        .lines()
+    let lines = |s: String| s.lines().map(String::from).collect::<Vec<_>>();
+    Self::from_lines(lines(a), lines(b))
+  }
+
+  // for when the caller has already split things up (maybe not on newlines)
+  pub fn from_lines(a: Vec<String>, b: Vec<String>) -> Self {
+    let numbered = |lines: Vec<String>| {
+      lines
+        .into_iter()
         .enumerate()
-        .map(|(n, s)| Line(n + 1, s.to_string()))
+        .map(|(n, s)| Line(n + 1, s))
         .collect::<Vec<_>>()
-        .into(),
+        .into()
+    };
+
+    Self {
+      a: numbered(a),
+      b: numbered(b),
     }
   }
 
@@ -0,0 +1,311 @@
+// The numbers behind --stat and friends, for one changed path.
+#[derive(Debug, Clone)]
+pub struct FileStat {
+  pub path:    String,
+  pub added:   usize, // lines, or for binary files the new size in bytes
+  pub deleted: usize, // likewise, the old size
+  pub binary:  bool,
+}
+
+// git's default for --dirstat: directories with less than 3% of the changes
+// aren't worth mentioning
+pub const DIRSTAT_PERMILLE: usize = 30;
+
+// The --stat block: a line per path with a histogram of what changed in it,
+// scaled to fit into width columns the way git does it, and then the totals.
+pub fn stat(files: &[FileStat], width: usize) -> Vec<String> {
+  let mut max_len = 0;
+  let mut max_change = 0;
+  let mut number_width = 0;
+  let mut bin_width = 0;
+
+  for file in files {
+    max_len = max_len.max(file.path.chars().count());
+
+    if file.binary {
+      // "Bin XXX -> YYY bytes", with the counts lined up with "Bin"
+      let w = 14 + decimal_width(file.added) + decimal_width(file.deleted);
+      bin_width = bin_width.max(w);
+      number_width = 3;
+    } else {
+      max_change = max_change.max(file.added + file.deleted);
+    }
+  }
+
+  let number_width = number_width.max(decimal_width(max_change)) as isize;
+
+  // enough room for at least 6 columns of graph and 10 of filename
+  let width = (width as isize).max(16 + 6 + number_width);
+
+  let mut graph_width = if max_change + 4 > bin_width {
+    max_change as isize
+  } else {
+    bin_width as isize - 4
+  };
+
+  let mut name_width = max_len as isize;
+
+  // " " and " | " around the count, and a blank column at the end
+  if name_width + number_width + 6 + graph_width > width {
+    if graph_width > width * 3 / 8 - number_width - 6 {
+      graph_width = (width * 3 / 8 - number_width - 6).max(6);
+    }
+
+    if name_width > width - number_width - 6 - graph_width {
+      name_width = width - number_width - 6 - graph_width;
+    } else {
+      graph_width = width - number_width - 6 - name_width;
+    }
+  }
+
+  let (name_width, graph_width) = (name_width as usize, graph_width as usize);
+  let number_width = number_width as usize;
+
+  let mut lines = vec![];
+
+  for file in files {
+    let name = scale_name(&file.path, name_width);
+    let prefix = format!(" {:<w$} | ", name, w = name_width);
+
+    if file.binary {
+      let mut line = format!("{}{:>w$}", prefix, "Bin", w = number_width);
+      if file.added + file.deleted > 0 {
+        line += &format!(" {} -> {} bytes", file.deleted, file.added);
+      }
+
+      lines.push(line);
+      continue;
+    }
+
+    let (mut add, mut del) = (file.added, file.deleted);
+
+    if graph_width <= max_change {
+      let mut total = scale_linear(add + del, graph_width, max_change);
+      if total < 2 && add > 0 && del > 0 {
+        total = 2;
+      }
+
+      if add < del {
+        add = scale_linear(add, graph_width, max_change);
+        del = total - add;
+      } else {
+        del = scale_linear(del, graph_width, max_change);
+        add = total - del;
+      }
+    }
+
+    let changed = file.added + file.deleted;
+
+    lines.push(format!(
+      "{}{:>w$}{}{}{}",
+      prefix,
+      changed,
+      if changed > 0 { " " } else { "" },
+      "+".repeat(add),
+      "-".repeat(del),
+      w = number_width
+    ));
+  }
+
+  lines.push(shortstat(files));
+  lines
+}
+
+// Just the totals: " 2 files changed, 3 insertions(+), 1 deletion(-)".
+// Binary files count as files, but they don't have lines to count.
+pub fn shortstat(files: &[FileStat]) -> String {
+  let text = files.iter().filter(|f| !f.binary);
+  let insertions = text.clone().map(|f| f.added).sum::<usize>();
+  let deletions = text.map(|f| f.deleted).sum::<usize>();
+
+  let plural = |n| if n == 1 { "" } else { "s" };
+
+  let mut line = format!(" {} file{} changed", files.len(), plural(files.len()));
+
+  if insertions > 0 || deletions == 0 {
+    line += &format!(", {} insertion{}(+)", insertions, plural(insertions));
+  }
+
+  if deletions > 0 || insertions == 0 {
+    line += &format!(", {} deletion{}(-)", deletions, plural(deletions));
+  }
+
+  line
+}
+
+// "added<tab>deleted<tab>path", with dashes for the counts of binary files.
+pub fn numstat(files: &[FileStat]) -> Vec<String> {
+  files
+    .iter()
+    .map(|f| {
+      if f.binary {
+        format!("-\t-\t{}", f.path)
+      } else {
+        format!("{}\t{}\t{}", f.added, f.deleted, f.path)
+      }
+    })
+    .collect()
+}
+
+// How the changed lines are spread out over directories, like git's
+// --dirstat=lines: each directory with at least permille of them gets a
+// line, and its changes then don't count towards its parents. A directory
+// whose changes all come from one subdirectory isn't interesting, though.
+pub fn dirstat(files: &[FileStat], permille: usize) -> Vec<String> {
+  let mut files = files
+    .iter()
+    .map(|f| {
+      // binary files are in bytes, so call it 64 of them per line
+      let damage = if f.binary {
+        (f.added + f.deleted).div_ceil(64)
+      } else {
+        f.added + f.deleted
+      };
+
+      (f.path.as_str(), damage)
+    })
+    .collect::<Vec<_>>();
+
+  files.sort();
+
+  let total = files.iter().map(|(_, damage)| damage).sum::<usize>();
+  let mut lines = vec![];
+
+  if total > 0 {
+    let mut files = &files[..];
+    gather_dirstat(&mut files, "", total, permille, &mut lines);
+  }
+
+  lines
+}
+
+// Consume the files under base (which is empty, or ends with a slash), and
+// return how many of their changes haven't been reported yet.
+fn gather_dirstat(
+  files: &mut &[(&str, usize)],
+  base: &str,
+  total: usize,
+  permille: usize,
+  lines: &mut Vec<String>,
+) -> usize {
+  let mut sum = 0;
+  let mut sources = 0;
+
+  while let Some(&(name, damage)) = files.first() {
+    let rest = match name.strip_prefix(base) {
+      Some(rest) => rest,
+      None => break,
+    };
+
+    match rest.find('/') {
+      Some(slash) => {
+        let dir = &name[..base.len() + slash + 1];
+        sum += gather_dirstat(files, dir, total, permille, lines);
+        sources += 1;
+      },
+      None => {
+        sum += damage;
+        *files = &files[1..];
+        sources += 2;
+      },
+    }
+  }
+
+  if !base.is_empty() && sources != 1 && sum > 0 {
+    let this = sum * 1000 / total;
+
+    if this >= permille {
+      lines.push(format!("{:4}.{}% {}", this / 10, this % 10, base));
+      return 0;
+    }
+  }
+
+  sum
+}
+
+// Scale so that any change at all gets at least one column.
+fn scale_linear(n: usize, width: usize, max_change: usize) -> usize {
+  if n == 0 {
+    return 0;
+  }
+
+  1 + n * (width - 1) / max_change
+}
+
+// Names too long to fit lose their start, back to a slash if there is one.
+fn scale_name(name: &str, width: usize) -> String {
+  let len = name.chars().count();
+
+  if len <= width {
+    return name.to_string();
+  }
+
+  let keep = width.saturating_sub(3);
+  let tail = name.chars().skip(len - keep).collect::<String>();
+
+  match tail.find('/') {
+    Some(slash) => format!("...{}", &tail[slash..]),
+    None => format!("...{}", tail),
+  }
+}
+
+fn decimal_width(n: usize) -> usize {
+  n.to_string().len()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn file(path: &str, added: usize, deleted: usize) -> FileStat {
+    FileStat {
+      path: path.to_string(),
+      added,
+      deleted,
+      binary: false,
+    }
+  }
+
+  #[test]
+  fn scaled_histogram() {
+    let files = vec![
+      file("a.txt", 100, 50),
+      file("some/much/longer/path/b.txt", 1, 1),
+      FileStat {
+        path:    "c.bin".to_string(),
+        added:   1024,
+        deleted: 10,
+        binary:  true,
+      },
+    ];
+
+    assert_eq!(
+      stat(&files, 60),
+      [
+        " a.txt                       | 150 ++++++++++++++++--------",
+        " some/much/longer/path/b.txt |   2 +-",
+        " c.bin                       | Bin 10 -> 1024 bytes",
+        " 3 files changed, 101 insertions(+), 51 deletions(-)",
+      ]
+    );
+
+    // squeezed, the name loses its start
+    assert_eq!(stat(&files[1..2], 20)[0], " .../b.txt  | 2 +-");
+  }
+
+  #[test]
+  fn dirstat_lines() {
+    let files = vec![
+      file("a/one.txt", 10, 0),
+      file("a/b/two.txt", 10, 0),
+      file("c/d/three.txt", 79, 0),
+      file("top.txt", 1, 0),
+    ];
+
+    // c/ only has changes from c/d/, so it doesn't get a line of its own
+    assert_eq!(
+      dirstat(&files, 30),
+      ["  10.0% a/b/", "  10.0% a/", "  79.0% c/d/"]
+    );
+  }
+}
@@ -6,6 +6,8 @@ use std::path::PathBuf;
 use std::str::Utf8Error;
 use std::string::FromUtf8Error;
 
+use crate::util::Sha;
+
 #[derive(Debug)]
 pub enum PidgitError {
   Generic(String),
@@ -19,6 +21,14 @@ pub enum PidgitError {
   InvalidRefName(String),
   PathspecNotFound(OsString),
   Index(String),
+  Pack(String),
+  CommitGraph(String),
+  MalformedObject {
+    kind:   String,
+    sha:    Box<Sha>, // which can be a whole hasher, so it's big
+    offset: usize,    // where in the content (after the header) things went wrong
+    reason: String,
+  },
   Lock(PathBuf, IoError),
 }
 
@@ -40,6 +50,18 @@ impl fmt::Display for PidgitError {
       PE::InvalidObject(want) => write!(f, "invalid object type: not a {}", want),
       PE::InvalidRefName(name) => write!(f, "invalid ref name: {}", name),
       PE::Index(err) => write!(f, "could not parse index file: {}", err),
+      PE::Pack(err) => write!(f, "could not read packfile: {}", err),
+      PE::CommitGraph(err) => write!(f, "bad commit-graph: {}", err),
+      PE::MalformedObject {
+        kind,
+        sha,
+        offset,
+        reason,
+      } => write!(
+        f,
+        "malformed {} {}: {} at byte {}",
+        kind, sha, reason, offset
+      ),
       PE::PathspecNotFound(spec) => {
         write!(f, "pathspec {:?} did not match any files", spec)
       },
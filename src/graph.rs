@@ -0,0 +1,203 @@
+use ansi_term::Color;
+use std::collections::HashSet;
+
+use crate::util::{self, Sha};
+
+// lanes cycle through these, like git's do
+const COLORS: [Color; 6] = [
+  Color::Red,
+  Color::Green,
+  Color::Yellow,
+  Color::Blue,
+  Color::Purple,
+  Color::Cyan,
+];
+
+// An ASCII-art commit graph, drawn one commit at a time. Commits must come in
+// topological order (no parent before all its children), or the lanes won't
+// make any sense.
+//
+// Each lane is waiting for a commit, and is two characters wide: the lane
+// itself, and a gap that lines between lanes go through.
+#[derive(Debug, Default)]
+pub struct Graph {
+  lanes:      Vec<Lane>,
+  next_color: usize,
+  width:      usize,  // of the rows for the most recent commit
+  separator:  String, // the lanes as they were just before that commit
+}
+
+#[derive(Debug, Clone)]
+struct Lane {
+  sha:   Sha,
+  color: usize,
+}
+
+// a line going from one lane to another (maybe the same one)
+#[derive(Debug)]
+struct Edge {
+  from:  usize,
+  to:    usize,
+  color: usize,
+}
+
+type Cell = (char, Option<usize>);
+
+impl Graph {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  fn new_color(&mut self) -> usize {
+    self.next_color += 1;
+    self.next_color - 1
+  }
+
+  // The rows of graph to print alongside this commit: the first has the commit
+  // itself, and any others connect it to its parents. If the commit's text
+  // needs more lines than this, use padding() for the rest.
+  pub fn next_commit(&mut self, sha: &Sha, parents: &[Sha]) -> Vec<String> {
+    let before = self.lane_cells();
+
+    let col = match self.lanes.iter().position(|l| &l.sha == sha) {
+      Some(col) => col,
+      None => {
+        let color = self.new_color();
+        self.lanes.push(Lane {
+          sha: sha.clone(),
+          color,
+        });
+        self.lanes.len() - 1
+      },
+    };
+
+    let old = std::mem::take(&mut self.lanes);
+
+    // this commit's lane goes to its first parent; any others get new lanes
+    // right next to it
+    let mut next = vec![];
+
+    for (i, lane) in old.iter().enumerate() {
+      if i != col {
+        next.push(lane.clone());
+        continue;
+      }
+
+      for (n, parent) in parents.iter().enumerate() {
+        let color = if n == 0 { lane.color } else { self.new_color() };
+        next.push(Lane {
+          sha: parent.clone(),
+          color,
+        });
+      }
+    }
+
+    // two lanes waiting for the same commit merge into the leftmost one
+    let mut seen = HashSet::new();
+    next.retain(|lane| seen.insert(lane.sha.clone()));
+
+    let position = |sha: &Sha| next.iter().position(|l| &l.sha == sha).unwrap();
+
+    let mut edges = vec![];
+
+    for (i, lane) in old.iter().enumerate() {
+      if i == col {
+        for parent in parents {
+          let to = position(parent);
+          edges.push(Edge {
+            from: i,
+            to,
+            color: next[to].color,
+          });
+        }
+      } else {
+        edges.push(Edge {
+          from:  i,
+          to:    position(&lane.sha),
+          color: lane.color,
+        });
+      }
+    }
+
+    let width = old.len().max(next.len()) * 2;
+
+    let commit_row = old
+      .iter()
+      .enumerate()
+      .flat_map(|(i, lane)| {
+        let c = if i == col {
+          ('*', None)
+        } else {
+          ('|', Some(lane.color))
+        };
+        vec![c, (' ', None)]
+      })
+      .collect::<Vec<_>>();
+
+    self.width = width;
+    self.separator = render(&before, width);
+
+    let mut rows = vec![render(&commit_row, width)];
+
+    // Move every edge one step closer to where it's going, until they're all
+    // straight.
+    while edges.iter().any(|e| e.from != e.to) {
+      let mut cells = vec![(' ', None); width];
+
+      for edge in edges.iter_mut() {
+        if edge.from == edge.to {
+          cells[edge.from * 2] = ('|', Some(edge.color));
+        } else if edge.to > edge.from {
+          cells[edge.from * 2 + 1] = ('\\', Some(edge.color));
+          edge.from += 1;
+        } else {
+          cells[edge.from * 2 - 1] = ('/', Some(edge.color));
+          edge.from -= 1;
+        }
+      }
+
+      rows.push(render(&cells, width));
+    }
+
+    self.lanes = next;
+    rows
+  }
+
+  fn lane_cells(&self) -> Vec<Cell> {
+    self
+      .lanes
+      .iter()
+      .flat_map(|lane| vec![('|', Some(lane.color)), (' ', None)])
+      .collect()
+  }
+
+  // A row with nothing but lanes going straight down, for any lines of the
+  // current commit's text past the rows we gave for it.
+  pub fn padding(&self) -> String {
+    render(&self.lane_cells(), self.width)
+  }
+
+  // Same, but for a line between the previous commit and the current one.
+  pub fn separator(&self) -> &str {
+    &self.separator
+  }
+}
+
+fn render(cells: &[Cell], width: usize) -> String {
+  let mut out = cells
+    .iter()
+    .map(|(c, color)| match color {
+      Some(n) => {
+        let style = COLORS[n % COLORS.len()].normal();
+        util::colored(&c.to_string(), style).to_string()
+      },
+      None => c.to_string(),
+    })
+    .collect::<String>();
+
+  for _ in cells.len()..width {
+    out.push(' ');
+  }
+
+  out
+}
@@ -0,0 +1,442 @@
+use log::debug;
+use regex::Regex;
+use std::path::{Component, Path, PathBuf};
+
+use crate::config::{self, Config};
+use crate::errors::Result;
+
+// One line from an ignore file, ready to match against paths.
+#[derive(Debug, Clone)]
+pub struct Pattern {
+  pub source:  PathBuf, // the file it came from, for check-ignore -v
+  pub line:    usize,
+  pub text:    String, // the line as written
+  pub negated: bool,
+  glob:        Glob,
+}
+
+// A gitignore-style pattern, which .gitattributes uses too.
+#[derive(Debug, Clone)]
+pub struct Glob {
+  base:     String, // the directory the file was in, relative to the top
+  dir_only: bool,
+  anchored: bool,
+  regex:    Regex,
+}
+
+// Read in this order, so a .pidgitignore can override a .gitignore.
+const IGNORE_FILES: &[&str] = &[".gitignore", ".pidgitignore"];
+
+// All the patterns that could apply somewhere, lowest precedence first, so
+// the last one that matches a path is the one that counts. On top of whatever
+// files we start with, it keeps a stack of the directories whose ignore files
+// we've read, so that a walk can read each one just once on the way down.
+#[derive(Debug, Default)]
+pub struct IgnoreList {
+  patterns: Vec<Pattern>,
+  dirs:     Vec<(PathBuf, usize)>, // and where their patterns start
+}
+
+impl IgnoreList {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  // Read the patterns from the file at path (which needn't exist), which
+  // apply to paths under base. source is what to call the file when we report
+  // a match.
+  pub fn add_file(
+    &mut self,
+    path: &Path,
+    source: &Path,
+    base: &Path,
+  ) -> Result<()> {
+    let text = match std::fs::read(path) {
+      Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+      Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+      Err(e) => return Err(e.into()),
+    };
+
+    let base = path_str(base);
+
+    for (i, line) in text.lines().enumerate() {
+      if let Some(pattern) = Pattern::parse(line, source, i + 1, &base) {
+        self.patterns.push(pattern);
+      }
+    }
+
+    Ok(())
+  }
+
+  // Read the ignore files in dir (relative to root, the top of the work tree),
+  // whose patterns apply until we pop it again.
+  pub fn push_dir(&mut self, root: &Path, dir: &Path) -> Result<()> {
+    self.dirs.push((dir.to_path_buf(), self.patterns.len()));
+
+    for name in IGNORE_FILES {
+      let source = dir.join(name);
+      self.add_file(&root.join(&source), &source, dir)?;
+    }
+
+    Ok(())
+  }
+
+  pub fn pop_dir(&mut self) {
+    if let Some((_, start)) = self.dirs.pop() {
+      self.patterns.truncate(start);
+    }
+  }
+
+  // Make the stack hold the ignore files from the top down to dir, popping
+  // the directories that aren't on the way and keeping the ones that are.
+  pub fn enter_dir(&mut self, root: &Path, dir: &Path) -> Result<()> {
+    while self
+      .dirs
+      .last()
+      .is_some_and(|(top, _)| !dir.starts_with(top))
+    {
+      self.pop_dir();
+    }
+
+    let mut current = match self.dirs.last() {
+      Some((top, _)) => top.clone(),
+      None => {
+        self.push_dir(root, Path::new(""))?;
+        PathBuf::new()
+      },
+    };
+
+    for component in dir.strip_prefix(&current).unwrap().components() {
+      current.push(component);
+      self.push_dir(root, &current)?;
+    }
+
+    Ok(())
+  }
+
+  // The pattern that decides whether this path (relative to the top of the
+  // work tree) is ignored, if any does. It might be a negated one, in which
+  // case the path isn't ignored after all.
+  pub fn matching(&self, path: &Path, is_dir: bool) -> Option<&Pattern> {
+    let path = path_str(path);
+    self
+      .patterns
+      .iter()
+      .rev()
+      .find(|p| p.matches(&path, is_dir))
+  }
+
+  pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+    self.matching(path, is_dir).is_some_and(|p| !p.negated)
+  }
+
+  // Like matching, but for a path we haven't found by walking down to it: if
+  // any directory above it is ignored, so is it, and nothing can bring it
+  // back.
+  pub fn matching_with_parents(
+    &self,
+    path: &Path,
+    is_dir: bool,
+  ) -> Option<&Pattern> {
+    let mut dir = PathBuf::new();
+    let mut components = path.components().peekable();
+
+    while let Some(c) = components.next() {
+      if components.peek().is_none() {
+        break;
+      }
+
+      dir.push(c);
+      if let Some(p) = self.matching(&dir, true).filter(|p| !p.negated) {
+        return Some(p);
+      }
+    }
+
+    self.matching(path, is_dir)
+  }
+}
+
+impl Pattern {
+  fn parse(line: &str, source: &Path, lineno: usize, base: &str) -> Option<Self> {
+    let text = trim_trailing_spaces(line);
+
+    if text.is_empty() || text.starts_with('#') {
+      return None;
+    }
+
+    let (negated, pat) = match text.strip_prefix('!') {
+      Some(rest) => (true, rest),
+      None => (false, text),
+    };
+
+    Some(Pattern {
+      source: source.to_path_buf(),
+      line: lineno,
+      text: text.to_string(),
+      negated,
+      glob: Glob::new(pat, base)?,
+    })
+  }
+
+  fn matches(&self, path: &str, is_dir: bool) -> bool {
+    self.glob.matches(path, is_dir)
+  }
+}
+
+impl Glob {
+  // base is the directory the pattern's file lives in.
+  pub fn new(mut pat: &str, base: &str) -> Option<Self> {
+    let dir_only = pat.ends_with('/') && !pat.ends_with("\\/");
+    if dir_only {
+      pat = &pat[..pat.len() - 1];
+    }
+
+    // a slash anywhere but the end ties the pattern to this directory
+    let anchored = pat.contains('/');
+    let pat = pat.strip_prefix('/').unwrap_or(pat);
+
+    if pat.is_empty() {
+      return None;
+    }
+
+    let regex = match Regex::new(&glob_to_regex(pat)) {
+      Ok(re) => re,
+      Err(err) => {
+        debug!("ignoring bad pattern {:?}: {}", pat, err);
+        return None;
+      },
+    };
+
+    Some(Glob {
+      base: base.to_string(),
+      dir_only,
+      anchored,
+      regex,
+    })
+  }
+
+  // path is relative to the top of the work tree, with forward slashes
+  pub fn matches(&self, path: &str, is_dir: bool) -> bool {
+    if self.dir_only && !is_dir {
+      return false;
+    }
+
+    let rel = if self.base.is_empty() {
+      path
+    } else {
+      match path
+        .strip_prefix(&self.base)
+        .and_then(|p| p.strip_prefix('/'))
+      {
+        Some(rel) => rel,
+        None => return false,
+      }
+    };
+
+    if self.anchored {
+      self.regex.is_match(rel)
+    } else {
+      let name = rel.rsplit('/').next().unwrap_or(rel);
+      self.regex.is_match(name)
+    }
+  }
+}
+
+// core.excludesFile, or git's default if that's not set.
+pub fn excludes_file(config: &Config) -> Option<PathBuf> {
+  if let Some(path) = config.get("core.excludesFile") {
+    return config::expand_path(path);
+  }
+
+  let xdg = std::env::var_os("XDG_CONFIG_HOME")
+    .map(PathBuf::from)
+    .or_else(|| std::env::var_os("HOME").map(|h| Path::new(&h).join(".config")));
+
+  xdg.map(|dir| dir.join("git/ignore"))
+}
+
+// Paths here always use forward slashes, whatever the platform.
+pub fn path_str(path: &Path) -> String {
+  path
+    .components()
+    .filter_map(|c| match c {
+      Component::Normal(s) => Some(s.to_string_lossy()),
+      _ => None,
+    })
+    .collect::<Vec<_>>()
+    .join("/")
+}
+
+// Trailing spaces don't count, unless they're escaped with a backslash.
+fn trim_trailing_spaces(line: &str) -> &str {
+  let mut end = line.trim_end_matches('\r').len();
+
+  while line[..end].ends_with(' ') && !line[..end - 1].ends_with('\\') {
+    end -= 1;
+  }
+
+  &line[..end]
+}
+
+// Turn a glob into an anchored regex: * and ? don't cross slashes, ** does
+// (when it's a whole path component), and [...] is a character class.
+fn glob_to_regex(glob: &str) -> String {
+  let chars = glob.chars().collect::<Vec<_>>();
+  let mut re = String::from("^");
+  let mut i = 0;
+
+  while i < chars.len() {
+    match chars[i] {
+      '*' if chars.get(i + 1) == Some(&'*') => {
+        let at_start = i == 0 || chars[i - 1] == '/';
+        let next = chars.get(i + 2);
+
+        if at_start && next == Some(&'/') {
+          re.push_str("(?:.*/)?");
+          i += 3;
+          continue;
+        } else if at_start && next.is_none() {
+          re.push_str(".*");
+        } else {
+          re.push_str("[^/]*");
+        }
+
+        i += 2;
+        continue;
+      },
+      '*' => re.push_str("[^/]*"),
+      '?' => re.push_str("[^/]"),
+      '[' => match class_end(&chars, i) {
+        Some(end) => {
+          re.push('[');
+          let mut j = i + 1;
+
+          if matches!(chars[j], '!' | '^') {
+            re.push('^');
+            j += 1;
+          }
+
+          for &c in &chars[j..end] {
+            match c {
+              '-' => re.push('-'),
+              c => re.push_str(&regex::escape(&c.to_string())),
+            }
+          }
+
+          re.push(']');
+          i = end;
+        },
+        None => re.push_str("\\["),
+      },
+      '\\' if i + 1 < chars.len() => {
+        i += 1;
+        re.push_str(&regex::escape(&chars[i].to_string()));
+      },
+      c => re.push_str(&regex::escape(&c.to_string())),
+    }
+
+    i += 1;
+  }
+
+  re.push('$');
+  re
+}
+
+// Where the character class starting at chars[start] ends, if it does. A ]
+// right at the start (after any negation) is part of the class.
+fn class_end(chars: &[char], start: usize) -> Option<usize> {
+  let mut i = start + 1;
+
+  if matches!(chars.get(i), Some('!') | Some('^')) {
+    i += 1;
+  }
+
+  if chars.get(i) == Some(&']') {
+    i += 1;
+  }
+
+  (i..chars.len()).find(|&j| chars[j] == ']')
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn list(patterns: &[(&str, &str)]) -> IgnoreList {
+    let mut list = IgnoreList::new();
+
+    for (i, (base, pat)) in patterns.iter().enumerate() {
+      let source = Path::new(base).join(".gitignore");
+      if let Some(p) = Pattern::parse(pat, &source, i + 1, base) {
+        list.patterns.push(p);
+      }
+    }
+
+    list
+  }
+
+  #[test]
+  fn globs() {
+    let l = list(&[
+      ("", "*.o"),
+      ("", "!keep.o"),
+      ("", "/top"),
+      ("", "build/"),
+      ("", "doc/**/*.html"),
+      ("", "logs/**"),
+      ("", "**/cache"),
+      ("", "file[0-9].txt"),
+      ("", "\\#hash"),
+      ("", "# just a comment"),
+      ("", "trailing   "),
+    ]);
+
+    let ignored = |path: &str, is_dir| l.is_ignored(Path::new(path), is_dir);
+
+    assert!(ignored("x.o", false));
+    assert!(ignored("deep/down/x.o", false));
+    assert!(!ignored("keep.o", false));
+    assert!(!ignored("deep/keep.o", false));
+
+    assert!(ignored("top", false));
+    assert!(!ignored("sub/top", false));
+
+    assert!(ignored("build", true));
+    assert!(ignored("sub/build", true));
+    assert!(!ignored("build", false));
+
+    assert!(ignored("doc/a.html", false));
+    assert!(ignored("doc/x/y/a.html", false));
+    assert!(!ignored("other/doc/a.html", false));
+
+    assert!(ignored("logs/a/b", false));
+    assert!(!ignored("logs", true));
+
+    assert!(ignored("cache", true));
+    assert!(ignored("a/b/cache", false));
+
+    assert!(ignored("file3.txt", false));
+    assert!(!ignored("filex.txt", false));
+    assert!(ignored("#hash", false));
+    assert!(ignored("trailing", false));
+  }
+
+  #[test]
+  fn nested_and_parents() {
+    let l = list(&[("", "*.log"), ("sub", "!important.log"), ("sub", "/local")]);
+
+    assert!(l.is_ignored(Path::new("a.log"), false));
+    assert!(!l.is_ignored(Path::new("sub/important.log"), false));
+    assert!(l.is_ignored(Path::new("important.log"), false));
+    assert!(l.is_ignored(Path::new("sub/local"), false));
+    assert!(!l.is_ignored(Path::new("local"), false));
+
+    // a file in an ignored directory can't be rescued
+    let l = list(&[("", "out/"), ("", "!out/keep")]);
+    let found = l
+      .matching_with_parents(Path::new("out/keep"), false)
+      .unwrap();
+    assert_eq!(found.text, "out/");
+    assert!(!found.negated);
+  }
+}
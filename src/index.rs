@@ -6,10 +6,11 @@ use std::fs::Metadata;
 use std::io::{prelude::*, BufWriter, Cursor};
 use std::os::unix::ffi::{OsStrExt, OsStringExt};
 use std::os::unix::fs::MetadataExt;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use bit_vec::BitVec;
 
+use crate::object::Mode;
 use crate::prelude::*;
 use crate::Lockfile;
 
@@ -20,6 +21,7 @@ pub struct Index {
   version:  u32,
   changed:  bool,
   entries:  BTreeMap<OsString, IndexEntry>,
+  unmerged: BTreeMap<OsString, Vec<IndexEntry>>,
   parents:  HashMap<OsString, HashSet<OsString>>,
   lockfile: Lockfile,
 }
@@ -35,7 +37,7 @@ pub struct IndexEntry {
 #[derive(Debug)]
 pub struct EntryFlags(BitVec);
 
-#[derive(Eq, PartialEq)]
+#[derive(Eq, PartialEq, Default)]
 pub struct EntryMeta {
   ctime_sec:  u32,
   ctime_nano: u32,
@@ -57,6 +59,7 @@ impl fmt::Debug for Index {
       .field("changed", &self.changed)
       .field("lockfile", &self.lockfile.path())
       .field("entries", &self.entries)
+      .field("unmerged", &self.unmerged)
       .finish()
   }
 }
@@ -99,6 +102,7 @@ impl Index {
       version: INDEX_VERSION,
       changed: false,
       entries: BTreeMap::new(),
+      unmerged: BTreeMap::new(),
       parents: HashMap::new(),
       lockfile,
     }
@@ -106,6 +110,7 @@ impl Index {
 
   pub fn reload(&mut self) -> Result<()> {
     self.entries = BTreeMap::new();
+    self.unmerged = BTreeMap::new();
     self.parents = HashMap::new();
     self.load()
   }
@@ -262,13 +267,19 @@ impl Index {
         size,
       };
 
-      self.add(IndexEntry {
+      let entry = IndexEntry {
         meta,
         sha: sha.into(),
         flags,
         name,
         changed: false,
-      });
+      };
+
+      if entry.stage() == 0 {
+        self.add(entry);
+      } else {
+        self.add_unmerged_entry(entry);
+      }
     }
 
     // we haven't _actually_ changed
@@ -278,7 +289,7 @@ impl Index {
   }
 
   fn has_changed(&self) -> bool {
-    self.changed || self.entries.values().any(|e| e.changed)
+    self.changed || self.entries().any(|e| e.changed)
   }
 
   pub fn write(&self) -> Result<()> {
@@ -303,17 +314,27 @@ impl Index {
     header.extend(self.version.to_be_bytes().iter());
     header.extend(self.num_entries().to_be_bytes().iter());
 
-    writer.write(&header)?;
+    writer.write_all(&header)?;
     sha.update(&header);
 
-    for entry in self.entries.values() {
+    // Entries are sorted by name, then stage. A name is never both merged and
+    // unmerged, so we can just sort the lot.
+    let mut all = self
+      .entries
+      .values()
+      .chain(self.unmerged.values().flatten())
+      .collect::<Vec<_>>();
+
+    all.sort_by(|a, b| (&a.name, a.stage()).cmp(&(&b.name, b.stage())));
+
+    for entry in all {
       let bytes = entry.as_bytes();
-      writer.write(&bytes)?;
+      writer.write_all(&bytes)?;
       sha.update(&bytes);
     }
 
     // last 20 bytes is the sha of this content
-    writer.write(&sha.digest().bytes())?;
+    writer.write_all(&sha.digest().bytes())?;
 
     writer
       .into_inner()
@@ -327,6 +348,40 @@ impl Index {
     self.changed = true;
     self.remove_conflicts(&entry);
 
+    if self.unmerged.contains_key(&entry.name) {
+      self.remove_entry(&entry.name.clone());
+    }
+    self.add_parents(&entry);
+
+    self.entries.insert(entry.name.clone(), entry);
+  }
+
+  // Record a merge conflict: entries are the versions of a single path from
+  // the merge base, ours, and theirs (stages 1, 2, and 3), any of which might
+  // be missing. This replaces any merged (stage 0) entry for the path, and
+  // adding a stage 0 entry later replaces all of these.
+  pub fn add_unmerged(&mut self, entries: Vec<IndexEntry>) {
+    self.changed = true;
+
+    for entry in entries {
+      self.add_unmerged_entry(entry);
+    }
+  }
+
+  fn add_unmerged_entry(&mut self, entry: IndexEntry) {
+    if self.entries.contains_key(&entry.name) {
+      self.remove_entry(&entry.name.clone());
+    }
+
+    self.add_parents(&entry);
+
+    let stages = self.unmerged.entry(entry.name.clone()).or_default();
+    stages.retain(|e| e.stage() != entry.stage());
+    stages.push(entry);
+    stages.sort_by_key(|e| e.stage());
+  }
+
+  fn add_parents(&mut self, entry: &IndexEntry) {
     for parent in entry.parents() {
       let k = key_for_path(&parent);
       let mut set = self.parents.get_mut(&k);
@@ -338,8 +393,18 @@ impl Index {
 
       set.unwrap().insert(entry.name.clone());
     }
+  }
 
-    self.entries.insert(entry.name.clone(), entry);
+  pub fn clear(&mut self) {
+    self.changed = true;
+    self.entries = BTreeMap::new();
+    self.unmerged = BTreeMap::new();
+    self.parents = HashMap::new();
+  }
+
+  pub fn remove(&mut self, key: &OsStr) {
+    self.changed = true;
+    self.remove_entry(key);
   }
 
   fn remove_conflicts(&mut self, entry: &IndexEntry) {
@@ -351,7 +416,10 @@ impl Index {
   }
 
   fn remove_entry(&mut self, key: &OsStr) {
-    let entry = self.entries.remove(key);
+    let entry = self
+      .entries
+      .remove(key)
+      .or_else(|| self.unmerged.remove(key)?.pop());
 
     if let Some(entry) = entry {
       for parent in entry.parents() {
@@ -391,7 +459,7 @@ impl Index {
   }
 
   pub fn num_entries(&self) -> u32 {
-    self.entries.len() as u32
+    (self.entries.len() + self.unmerged.values().flatten().count()) as u32
   }
 
   pub fn is_tracked(&self, key: &OsStr) -> bool {
@@ -399,7 +467,16 @@ impl Index {
   }
 
   pub fn is_tracked_file(&self, key: &OsStr) -> bool {
-    self.entries.contains_key(key)
+    self.entries.contains_key(key) || self.unmerged.contains_key(key)
+  }
+
+  pub fn has_unmerged(&self) -> bool {
+    !self.unmerged.is_empty()
+  }
+
+  // all the conflicted paths, and their entries sorted by stage
+  pub fn unmerged(&self) -> impl Iterator<Item = (&OsString, &Vec<IndexEntry>)> {
+    self.unmerged.iter()
   }
 
   pub fn entry_for(&self, key: &OsStr) -> Option<&IndexEntry> {
@@ -408,7 +485,7 @@ impl Index {
 }
 
 impl IndexEntry {
-  pub fn new(key: OsString, path: &PathBuf) -> Result<Self> {
+  pub fn new(key: OsString, path: &Path) -> Result<Self> {
     let meta = path.metadata()?;
     let sha = util::compute_sha_for_path(path, Some(&meta))?;
 
@@ -428,6 +505,36 @@ impl IndexEntry {
     }
   }
 
+  // An entry for something we got out of a tree, rather than off of disk. We
+  // don't have any real stat information, but we do know the size, which is
+  // enough to make Status look at the content if need be.
+  pub fn new_from_tree(name: OsString, sha: Sha, mode: &Mode, size: u32) -> Self {
+    let flags = EntryFlags::from_path(&name);
+    let meta = EntryMeta {
+      mode: mode.into(),
+      size,
+      ..EntryMeta::default()
+    };
+
+    IndexEntry {
+      meta,
+      sha,
+      flags,
+      name,
+      changed: true,
+    }
+  }
+
+  pub fn stage(&self) -> u8 {
+    self.flags.stage()
+  }
+
+  pub fn with_stage(mut self, stage: u8) -> Self {
+    self.flags.set_stage(stage);
+    self.changed = true;
+    self
+  }
+
   pub fn as_bytes(&self) -> Vec<u8> {
     // 64 bytes is constant, plus a filename, so allow some room for that
     let mut ret = Vec::with_capacity(100);
@@ -554,6 +661,16 @@ impl EntryFlags {
   pub fn is_extended(&self) -> bool {
     self.storage().get(1).unwrap()
   }
+
+  pub fn stage(&self) -> u8 {
+    let bit = |n| self.storage().get(n).unwrap() as u8;
+    (bit(2) << 1) | bit(3)
+  }
+
+  pub fn set_stage(&mut self, stage: u8) {
+    self.0.set(2, stage & 0b10 != 0);
+    self.0.set(3, stage & 0b01 != 0);
+  }
 }
 
 impl From<&[u8; 2]> for EntryFlags {
@@ -599,8 +716,8 @@ mod tests {
     let dir = tempdir();
     let f = dir.child("foo.txt");
     f.write_str("").unwrap();
-    let entry = IndexEntry::new("foo.txt".into(), &f.path().to_path_buf())
-      .expect("couldn't create entry");
+    let entry =
+      IndexEntry::new("foo.txt".into(), f.path()).expect("couldn't create entry");
 
     assert_eq!(entry.mode(), 0o100644);
     assert_eq!(entry.sha, EMPTY_SHA.into());
@@ -614,7 +731,7 @@ mod tests {
     idx.add(entry);
 
     assert_eq!(idx.num_entries(), 1);
-    assert_eq!(idx.changed, true);
+    assert!(idx.changed);
   }
 
   #[test]
@@ -671,4 +788,38 @@ mod tests {
     );
     assert!(idx.parents.is_empty());
   }
+
+  #[test]
+  fn unmerged_entries() {
+    let mut idx = index_with_entries(&["alice.txt", "nested/bob.txt"]);
+
+    idx.add_unmerged(vec![
+      new_empty_entry("nested/bob.txt").with_stage(1),
+      new_empty_entry("nested/bob.txt").with_stage(3),
+    ]);
+
+    assert_eq!(idx.num_entries(), 3);
+    assert!(idx.has_unmerged());
+    assert!(idx.is_tracked_file("nested/bob.txt".as_ref()));
+    assert!(idx.is_tracked("nested".as_ref()));
+    assert!(idx.entry_for("nested/bob.txt".as_ref()).is_none());
+
+    let stages = idx.unmerged().next().unwrap().1;
+    assert_eq!(stages.iter().map(|e| e.stage()).collect::<Vec<_>>(), [1, 3]);
+
+    // and they survive a round trip through the file
+    let dir = tempdir();
+    let path = dir.child("index").path().to_path_buf();
+    idx.lockfile = Lockfile::new(path.clone());
+    idx.force_write().unwrap();
+
+    let mut reloaded = Index::new(path);
+    reloaded.load().unwrap();
+    assert_eq!(reloaded.num_entries(), 3);
+    assert_eq!(reloaded.unmerged().next().unwrap().1.len(), 2);
+
+    idx.add(new_empty_entry("nested/bob.txt"));
+    assert!(!idx.has_unmerged());
+    assert_eq!(idx.num_entries(), 2);
+  }
 }
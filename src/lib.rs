@@ -1,10 +1,17 @@
 // modules
+mod attributes;
 pub mod cmd;
+mod commit_graph;
+mod config;
 mod diff;
 mod errors;
+mod graph;
+mod ignore;
 mod index;
 mod lockfile;
 mod object;
+mod pack;
+mod pretty;
 mod repo;
 pub mod util;
 
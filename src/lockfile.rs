@@ -31,7 +31,7 @@ impl Lockfile {
     }
   }
 
-  pub fn lock(&self) -> Result<FileLock> {
+  pub fn lock(&self) -> Result<FileLock<'_>> {
     let file = OpenOptions::new()
       .write(true)
       .create_new(true)
@@ -42,7 +42,7 @@ impl Lockfile {
 
     Ok(FileLock {
       file,
-      lockfile: &self,
+      lockfile: self,
     })
   }
 
@@ -138,7 +138,7 @@ mod tests {
     let mut lock = lockfile.lock().unwrap();
     assert!(lockfile.is_locked());
 
-    assert_eq!(lockfile.lock_path.is_file(), true, "locked a file");
+    assert!(lockfile.lock_path.is_file(), "locked a file");
 
     lock.write_all(b"hello\n").unwrap();
     lock.commit().expect("could not commit lockfile");
@@ -152,7 +152,7 @@ mod tests {
       .unwrap();
 
     assert_eq!(s, "hello\n", "content is correct in real file");
-    assert_eq!(lockfile.lock_path.is_file(), false, "locked file is gone");
+    assert!(!lockfile.lock_path.is_file(), "locked file is gone");
   }
 
   #[test]
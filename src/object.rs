@@ -16,11 +16,11 @@ pub use blob::Blob;
 pub use commit::Commit;
 pub use commit::Person;
 pub use tag::Tag;
-pub use tree::{PathEntry, Tree, TreeItem};
+pub use tree::{Mode, PathEntry, Tree, TreeItem};
 
 // object is a pretty generic name, but hey
 #[derive(Debug)]
-#[allow(unused)]
+#[allow(unused, clippy::large_enum_variant)]
 pub enum Object {
   Blob(Blob),
   Commit(Commit),
@@ -58,39 +58,94 @@ pub trait GitObject: std::fmt::Debug {
   }
 }
 
+// The error for an object of this type whose content we couldn't parse,
+// because of whatever's wrong at offset.
+pub fn malformed(
+  kind: &str,
+  content: &[u8],
+  offset: usize,
+  reason: &str,
+) -> PidgitError {
+  let mut sha = Sha1::new();
+  sha.update(format!("{} {}\0", kind, content.len()).as_bytes());
+  sha.update(content);
+
+  PidgitError::MalformedObject {
+    kind: kind.to_string(),
+    sha: Box::new(sha.into()),
+    offset,
+    reason: reason.to_string(),
+  }
+}
+
 impl Object {
   pub fn from_git_db(path: &Path) -> Result<Self> {
+    let (string_type, content) = Self::read_loose(path)?;
+    Self::from_raw(&string_type, content)
+  }
+
+  // Read the type and content out of a loose object file, without parsing the
+  // content at all.
+  pub fn read_loose(path: &Path) -> Result<(String, Vec<u8>)> {
+    let sha = util::sha_from_path(path);
+
     if !path.is_file() {
-      let sha = util::sha_from_path(&path);
       return Err(PidgitError::ObjectNotFound(sha));
     }
 
+    let bad_header = |reason: &str| PidgitError::MalformedObject {
+      kind:   "object".to_string(),
+      sha:    Box::new(sha.clone().into()),
+      offset: 0,
+      reason: reason.to_string(),
+    };
+
     let f = File::open(path)?;
     let mut zfile = BufReader::new(ZlibDecoder::new(f));
 
     let mut buf = vec![];
     zfile.read_until(b'\0', &mut buf)?;
-    buf.pop(); // ignore null terminator
 
-    let s = std::str::from_utf8(&buf)?;
-    let bits = s.split(" ").collect::<Vec<_>>();
+    if buf.pop() != Some(b'\0') {
+      return Err(bad_header("header is not terminated"));
+    }
+
+    // the header is "<type> <size>"
+    let header =
+      std::str::from_utf8(&buf).map_err(|_| bad_header("bad header"))?;
+    let (string_type, size) = header
+      .split_once(' ')
+      .ok_or_else(|| bad_header("bad header"))?;
 
-    let string_type = bits[0];
+    let size = size
+      .parse::<usize>()
+      .map_err(|_| bad_header("bad size in header"))?;
 
     // We could be smarter and not eagerly read objects into memory, but I think
     // this is fine for now.
     let mut content = vec![];
     zfile.read_to_end(&mut content)?;
 
-    let ret = match string_type {
-      "blob" => Object::Blob(Blob::from_content(content)),
-      "commit" => Object::Commit(Commit::from_content(content)),
-      "tag" => Object::Tag(Tag::from_content(content)),
-      "tree" => Object::Tree(Tree::from_content(content)),
-      _ => panic!("unknown object type {}", s),
-    };
+    if content.len() != size {
+      return Err(bad_header("size in header is wrong"));
+    }
+
+    Ok((string_type.to_string(), content))
+  }
 
-    Ok(ret)
+  // Build an object out of its type and its content (without a header),
+  // however we happened to get them.
+  pub fn from_raw(string_type: &str, content: Vec<u8>) -> Result<Self> {
+    Ok(match string_type {
+      "blob" => Object::Blob(Blob::from_content(content)),
+      "commit" => Object::Commit(Commit::from_content(content)?),
+      "tag" => Object::Tag(Tag::from_content(content)?),
+      "tree" => Object::Tree(Tree::from_content(content)?),
+      _ => {
+        let reason = format!("unknown object type {:?}", string_type);
+        return Err(malformed(string_type, &content, 0, &reason));
+      },
+    })
   }
 
   // consume self, turning into a GitObject
@@ -112,6 +167,7 @@ impl Object {
     }
   }
 
+  #[allow(clippy::wrong_self_convention)]
   pub fn as_blob(self) -> Result<Blob> {
     match self {
       Object::Blob(blob) => Ok(blob),
@@ -119,6 +175,7 @@ impl Object {
     }
   }
 
+  #[allow(clippy::wrong_self_convention)]
   pub fn as_commit(self) -> Result<Commit> {
     match self {
       Object::Commit(commit) => Ok(commit),
@@ -126,6 +183,7 @@ impl Object {
     }
   }
 
+  #[allow(clippy::wrong_self_convention)]
   pub fn as_tag(self) -> Result<Tag> {
     match self {
       Object::Tag(tag) => Ok(tag),
@@ -133,6 +191,7 @@ impl Object {
     }
   }
 
+  #[allow(clippy::wrong_self_convention)]
   pub fn as_tree(self) -> Result<Tree> {
     match self {
       Object::Tree(tree) => Ok(tree),
@@ -35,7 +35,7 @@ impl Blob {
 
   pub fn from_path(path: &PathBuf) -> Result<Self> {
     let mut content = vec![];
-    let mut reader = BufReader::new(File::open(&path)?);
+    let mut reader = BufReader::new(File::open(path)?);
     reader.read_to_end(&mut content)?;
     Ok(Self::from_content(content))
   }
@@ -1,11 +1,11 @@
-use chrono::{DateTime, FixedOffset};
+use chrono::{DateTime, FixedOffset, Local};
 use std::fmt;
-use std::io::prelude::*;
-use std::io::BufReader;
 
-use crate::object::Object;
+use crate::config::Config;
+use crate::object::malformed;
 use crate::prelude::*;
 
+#[derive(Clone)]
 pub struct Commit {
   pub tree:        Sha,      // sha
   pub parent_shas: Vec<Sha>, // shas
@@ -64,7 +64,7 @@ impl GitObject for Commit {
 
     lines.push("".to_string());
 
-    lines.push(format!("{}", self.message));
+    lines.push(self.message.to_string());
 
     lines.join("\n").as_bytes().to_vec()
   }
@@ -75,112 +75,171 @@ impl GitObject for Commit {
 }
 
 impl Commit {
-  // really, this should be TryFrom, because we unwrap() a bunch of io errors in
-  // here, but if we panic, it's only because there's something really weird
-  // going on and we couldn't recover anyway.
-  pub fn from_content(content: Vec<u8>) -> Self {
-    use std::io::Cursor;
-
-    // a commit has:
-    // - a tree
-    // - zero or more parents
-    // - an author
-    // - a committer
-    // - (maybe, which I will ignore here...Signed-Off-By, other stuff?)
-    // - a blank line
-    // - a message
-
-    let mut reader = Cursor::new(&content);
-    let len = reader.get_ref().len();
-
+  // a commit has:
+  // - a tree
+  // - zero or more parents
+  // - an author
+  // - a committer
+  // - maybe some other headers (encoding, gpgsig, ...), which we skip
+  // - a blank line
+  // - a message
+  pub fn from_content(content: Vec<u8>) -> Result<Self> {
     let mut tree = None;
     let mut author = None;
     let mut committer = None;
     let mut parents = vec![];
 
-    while (reader.position() as usize) < len {
-      let mut line = String::new();
-      reader.read_line(&mut line).unwrap();
-      line.pop();
-
-      let words = line.split(" ").collect::<Vec<_>>();
+    let headers = parse_headers("commit", &content)?;
 
-      // empty string
-      if words.len() == 1 {
-        break;
-      }
+    for (offset, key, value) in &headers.fields {
+      let bad = |reason: &str| malformed("commit", &content, *offset, reason);
 
-      match words[0] {
-        "tree" => tree = Some(words[1].to_string()),
+      match *key {
+        "tree" => tree = Some(parse_sha(value).ok_or_else(|| bad("bad tree"))?),
+        "parent" => {
+          parents.push(parse_sha(value).ok_or_else(|| bad("bad parent"))?);
+        },
         "author" => {
-          author = Some(parse_author_line(&words[1..].join(" ")));
+          author =
+            Some(parse_field_ident("commit", &content, *offset, key, value)?)
         },
         "committer" => {
-          committer = Some(parse_author_line(&words[1..].join(" ")));
+          committer =
+            Some(parse_field_ident("commit", &content, *offset, key, value)?)
         },
-        "parent" => parents.push(words[1].into()),
-        _ => break,
+        _ => {},
       }
     }
 
-    // rest
-    let mut message = String::new();
-    reader.read_to_string(&mut message).unwrap();
-
-    Self {
-      tree: tree.expect("did not find tree").into(),
+    let missing = |what: &str| {
+      malformed(
+        "commit",
+        &content,
+        headers.end,
+        &format!("missing {}", what),
+      )
+    };
+
+    Ok(Self {
+      tree:        tree.ok_or_else(|| missing("tree"))?,
       parent_shas: parents,
-      author: author.expect("did not find author"),
-      committer: committer.expect("did not find committer"),
-      message,
-      content: Some(content),
-    }
+      author:      author.ok_or_else(|| missing("author"))?,
+      committer:   committer.ok_or_else(|| missing("committer"))?,
+      message:     headers.message,
+      content:     Some(content),
+    })
   }
 }
 
-fn parse_author_line(line: &str) -> Person {
-  // probably there's a better way to do this...
-  let mut reader = BufReader::new(line.as_bytes());
+// The headers of a commit or tag, with the offset each starts at, and the
+// message after them.
+pub(super) struct Headers<'a> {
+  pub fields:  Vec<(usize, &'a str, &'a str)>,
+  pub end:     usize,
+  pub message: String,
+}
 
-  let mut name = vec![];
-  reader.read_until(b'<', &mut name).unwrap();
-  name.pop();
+// Split the content of a commit or tag into "key value" header lines, up to
+// the first blank line; the rest is the message. A line starting with a space
+// continues the previous header (like a gpg signature), so we skip those.
+pub(super) fn parse_headers<'a>(
+  kind: &str,
+  content: &'a [u8],
+) -> Result<Headers<'a>> {
+  let mut fields = vec![];
+  let mut offset = 0;
+
+  while offset < content.len() {
+    let len = content[offset..]
+      .iter()
+      .position(|&b| b == b'\n')
+      .unwrap_or(content.len() - offset);
 
-  let mut email = vec![];
-  reader.read_until(b'>', &mut email).unwrap();
-  email.pop();
+    let line = &content[offset..offset + len];
+    let start = offset;
+    offset += len + 1;
 
-  let mut date = String::new();
-  reader.read_to_string(&mut date).unwrap();
+    if line.is_empty() {
+      break;
+    }
+
+    if line[0] == b' ' {
+      continue;
+    }
 
-  let dt = DateTime::parse_from_str(date.trim(), "%s %z").unwrap();
+    let line = std::str::from_utf8(line).map_err(|_| {
+      malformed(kind, content, start, "header is not valid utf-8")
+    })?;
 
-  Person {
-    name:  String::from_utf8(name).unwrap().trim().to_string(),
-    email: String::from_utf8(email).unwrap().trim().to_string(),
-    date:  dt,
+    match line.split_once(' ') {
+      Some((key, value)) => fields.push((start, key, value)),
+      None => return Err(malformed(kind, content, start, "bad header line")),
+    }
   }
+
+  let end = offset.min(content.len());
+  let message = String::from_utf8_lossy(&content[end..]).to_string();
+
+  Ok(Headers {
+    fields,
+    end,
+    message,
+  })
+}
+
+pub(super) fn parse_sha(hex: &str) -> Option<Sha> {
+  if hex.len() == 40 && hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+    Some(hex.into())
+  } else {
+    None
+  }
+}
+
+// an ident in a "key value" header that starts at offset
+pub(super) fn parse_field_ident(
+  kind: &str,
+  content: &[u8],
+  offset: usize,
+  key: &str,
+  value: &str,
+) -> Result<Person> {
+  parse_ident(value).map_err(|(at, reason)| {
+    malformed(kind, content, offset + key.len() + 1 + at, reason)
+  })
+}
+
+// Parse something like "Name <email> 1600000000 -0400", or say where in it
+// (and why) we couldn't.
+fn parse_ident(line: &str) -> std::result::Result<Person, (usize, &'static str)> {
+  let lt = line.find('<').ok_or((0, "missing email"))?;
+  let gt = match line[lt..].find('>') {
+    Some(n) => lt + n,
+    None => return Err((lt, "unterminated email")),
+  };
+
+  let date = DateTime::parse_from_str(line[gt + 1..].trim(), "%s %z")
+    .map_err(|_| (gt + 1, "bad date"))?;
+
+  Ok(Person {
+    name: line[..lt].trim().to_string(),
+    email: line[lt + 1..gt].trim().to_string(),
+    date,
+  })
 }
 
 impl Commit {
   // passing the repo here is bunk
-  pub fn parents(&self, repo: &Repository) -> Vec<Commit> {
+  pub fn parents(&self, repo: &Repository) -> Result<Vec<Commit>> {
     self
       .parent_shas
       .iter()
-      .filter_map(|sha| {
-        if let Object::Commit(c) = repo.object_for_sha(sha).unwrap() {
-          Some(c)
-        } else {
-          None
-        }
-      })
+      .map(|sha| repo.object_for_sha(sha)?.as_commit())
       .collect()
   }
 
   // first parent, which is almost certainly wrong for merges
   pub fn parent(&self, repo: &Repository) -> Option<Commit> {
-    self.parent_shas.get(0).and_then(|sha| {
+    self.parent_shas.first().and_then(|sha| {
       repo
         .try_object_for_sha(sha)
         .and_then(|obj| obj.as_commit().ok())
@@ -188,11 +247,7 @@ impl Commit {
   }
 
   pub fn title(&self) -> &str {
-    let idx = self
-      .message
-      .find('\n')
-      .or_else(|| Some(self.message.len() as usize))
-      .unwrap();
+    let idx = self.message.find('\n').unwrap_or(self.message.len());
 
     &self.message[0..idx]
   }
@@ -202,6 +257,51 @@ impl Commit {
   }
 }
 
+impl Person {
+  // Whoever is running this command, right now, as an author: from
+  // GIT_AUTHOR_NAME and GIT_AUTHOR_EMAIL if they're set, then from user.name
+  // and user.email.
+  pub fn author(config: &Config) -> Self {
+    Self::from_env("AUTHOR", config, |name| std::env::var(name).ok())
+  }
+
+  // The same, but as a committer, from GIT_COMMITTER_NAME and
+  // GIT_COMMITTER_EMAIL.
+  pub fn committer(config: &Config) -> Self {
+    Self::from_env("COMMITTER", config, |name| std::env::var(name).ok())
+  }
+
+  // role is AUTHOR or COMMITTER, and var looks up an environment variable
+  // (so tests don't have to touch the real one). Like git's non-strict ident,
+  // we make something up if neither the environment nor the config tells us.
+  pub(crate) fn from_env(
+    role: &str,
+    config: &Config,
+    var: impl Fn(&str) -> Option<String>,
+  ) -> Self {
+    let user = var("USER").unwrap_or_else(|| "pidgit".to_string());
+
+    let now = Local::now();
+
+    Person {
+      name:  var(&format!("GIT_{}_NAME", role))
+        .or_else(|| config.get("user.name").map(String::from))
+        .unwrap_or_else(|| user.clone()),
+      email: var(&format!("GIT_{}_EMAIL", role))
+        .or_else(|| config.get("user.email").map(String::from))
+        .unwrap_or_else(|| format!("{}@localhost", user)),
+      date:  now.with_timezone(now.offset()),
+    }
+  }
+
+  // parse something like "Name <email> 1600000000 -0400"
+  pub fn from_ident(ident: &str) -> Result<Self> {
+    parse_ident(ident).map_err(|(_, reason)| {
+      PidgitError::Generic(format!("invalid ident {:?}: {}", ident, reason))
+    })
+  }
+}
+
 impl fmt::Display for Person {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
     write!(f, "{} <{}>", self.name, self.email)
@@ -1,22 +1,131 @@
-use crate::object::GitObject;
+use std::fmt;
+
+use super::commit::{parse_field_ident, parse_headers, parse_sha};
+use crate::object::{malformed, Person};
+use crate::prelude::*;
 
-#[derive(Debug)]
 pub struct Tag {
-  content: Vec<u8>,
+  pub object:  Sha,            // the tagged object
+  pub kind:    String,         // its type
+  pub name:    String,         // the name of the tag (sans refs/tags)
+  pub tagger:  Option<Person>, // very old tags might not have one
+  pub message: String,
+  pub content: Option<Vec<u8>>,
+}
+
+impl fmt::Debug for Tag {
+  #[rustfmt::skip]
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("Tag")
+      .field("object", &self.object)
+      .field("kind", &self.kind)
+      .field("name", &self.name)
+      .field("tagger", &self.tagger)
+      .field("message", &self.message)
+      .finish()
+  }
 }
 
 impl GitObject for Tag {
   fn raw_content(&self) -> Vec<u8> {
-    self.content.clone()
+    if let Some(c) = &self.content {
+      return c.clone();
+    }
+
+    let mut lines = vec![];
+
+    lines.push(format!("object {}", self.object));
+    lines.push(format!("type {}", self.kind));
+    lines.push(format!("tag {}", self.name));
+
+    if let Some(tagger) = &self.tagger {
+      lines.push(format!("tagger {} {}", tagger, tagger.date.format("%s %z")));
+    }
+
+    lines.push("".to_string());
+    lines.push(self.message.to_string());
+
+    lines.join("\n").as_bytes().to_vec()
   }
 
   fn type_str(&self) -> &str {
     "tag"
   }
+
+  // Like git cat-file -p, this is the raw content, but with a human-readable
+  // date for the tagger.
+  fn pretty(&self) -> Vec<u8> {
+    let mut lines = vec![
+      format!("object {}", self.object),
+      format!("type {}", self.kind),
+      format!("tag {}", self.name),
+    ];
+
+    if let Some(tagger) = &self.tagger {
+      lines.push(format!(
+        "tagger {} {}",
+        tagger,
+        tagger.date.format("%a %b %-d %H:%M:%S %Y %z")
+      ));
+    }
+
+    lines.push("".to_string());
+    lines.push(self.message.trim_end().to_string());
+
+    lines.join("\n").as_bytes().to_vec()
+  }
 }
 
 impl Tag {
-  pub fn from_content(content: Vec<u8>) -> Self {
-    Self { content }
+  // a tag has:
+  // - the object it points to
+  // - that object's type
+  // - the name of the tag
+  // - (usually) a tagger
+  // - a blank line
+  // - a message
+  pub fn from_content(content: Vec<u8>) -> Result<Self> {
+    let mut object = None;
+    let mut kind = None;
+    let mut name = None;
+    let mut tagger = None;
+
+    let headers = parse_headers("tag", &content)?;
+
+    for (offset, key, value) in &headers.fields {
+      match *key {
+        "object" => {
+          object = Some(
+            parse_sha(value)
+              .ok_or_else(|| malformed("tag", &content, *offset, "bad object"))?,
+          )
+        },
+        "type" => kind = Some(value.to_string()),
+        "tag" => name = Some(value.to_string()),
+        "tagger" => {
+          tagger = Some(parse_field_ident("tag", &content, *offset, key, value)?)
+        },
+        _ => {},
+      }
+    }
+
+    let missing = |what: &str| {
+      malformed("tag", &content, headers.end, &format!("missing {}", what))
+    };
+
+    Ok(Self {
+      object: object.ok_or_else(|| missing("object"))?,
+      kind: kind.ok_or_else(|| missing("type"))?,
+      name: name.ok_or_else(|| missing("tag name"))?,
+      tagger,
+      message: headers.message,
+      content: Some(content),
+    })
+  }
+
+  pub fn title(&self) -> &str {
+    let idx = self.message.find('\n').unwrap_or(self.message.len());
+
+    &self.message[0..idx]
   }
 }
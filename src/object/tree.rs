@@ -3,10 +3,11 @@ use std::collections::HashMap;
 use std::ffi::OsString;
 use std::fmt;
 use std::fs::Metadata;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 // use crate::object::Blob;
 use crate::index::{Index, IndexEntry};
+use crate::object::malformed;
 use crate::prelude::*;
 
 #[derive(Clone)]
@@ -39,6 +40,8 @@ pub enum Mode {
   Tree,
   Executable,
   Normal,
+  Symlink, // the content is where it points
+  Gitlink, // a submodule, so the sha is a commit we don't have
 }
 
 impl fmt::Debug for Tree {
@@ -85,43 +88,45 @@ impl Tree {
     }
   }
 
-  pub fn from_content(content: Vec<u8>) -> Self {
-    use std::io::prelude::*;
-    use std::io::Cursor;
+  // a tree is made of entries, where each entry entry is:
+  // mode filename NULL 20-bytes-of-sha
+  pub fn from_content(content: Vec<u8>) -> Result<Self> {
     use std::os::unix::ffi::OsStringExt;
 
-    let err = "malformed tree entry";
-
-    // a tree is made of entries, where each entry entry is:
-    // mode filename NULL 20-bytes-of-sha
     let mut entries = vec![];
+    let mut offset = 0;
 
-    let mut reader = Cursor::new(&content);
-    let len = reader.get_ref().len();
+    while offset < content.len() {
+      let rest = &content[offset..];
+      let bad = |reason: &str| malformed("tree", &content, offset, reason);
 
-    while (reader.position() as usize) < len {
-      let mut mode_buf = vec![];
-      reader.read_until(b' ', &mut mode_buf).expect(err);
-      mode_buf.pop();
-      let mode = Mode::from(&mode_buf[0..]);
+      let space = rest.iter().position(|&b| b == b' ');
+      let nul = rest.iter().position(|&b| b == b'\0');
 
-      let mut filename = vec![];
-      reader.read_until(b'\0', &mut filename).expect(err);
-      filename.pop();
+      let (space, nul) = match (space, nul) {
+        (Some(s), Some(n)) if s < n => (s, n),
+        _ => return Err(bad("truncated entry")),
+      };
 
-      let mut sha = [0u8; sha1::DIGEST_LENGTH];
-      reader.read_exact(&mut sha).expect(err);
+      let mode = Mode::from_bytes(&rest[..space]).ok_or_else(|| {
+        let mode = String::from_utf8_lossy(&rest[..space]);
+        bad(&format!("unknown mode {:?}", mode))
+      })?;
 
-      let p = OsString::from_vec(filename);
+      let sha = rest
+        .get(nul + 1..nul + 1 + sha1::DIGEST_LENGTH)
+        .ok_or_else(|| bad("truncated sha"))?;
 
       entries.push(PathEntry {
         mode,
-        sha: sha.into(),
-        path: p.into(),
+        sha: Sha::from(hex::encode(sha)),
+        path: OsString::from_vec(rest[space + 1..nul].to_vec()).into(),
       });
+
+      offset += nul + 1 + sha1::DIGEST_LENGTH;
     }
 
-    Self::build(entries)
+    Ok(Self::build(entries))
   }
 
   // assumes entries are correctly sorted!
@@ -184,9 +189,9 @@ impl Tree {
     ret
   }
 
-  pub fn entries(&self) -> EntryIterator {
+  pub fn entries(&self) -> EntryIterator<'_> {
     EntryIterator {
-      tree: &self,
+      tree: self,
       idx:  0,
     }
   }
@@ -222,13 +227,13 @@ impl PathEntry {
     ret
   }
 
-  pub fn from_path(path: &PathBuf) -> Result<Self> {
+  pub fn from_path(path: &Path) -> Result<Self> {
     let meta = path.metadata()?;
     let mode = Mode::from(&meta);
     let sha = util::compute_sha_for_path(path, Some(&meta))?;
 
     Ok(PathEntry {
-      path: path.clone(),
+      path: path.to_path_buf(),
       mode,
       sha,
     })
@@ -277,14 +282,13 @@ impl From<std::cell::Ref<'_, Index>> for Tree {
 
 impl PartialOrd for PathEntry {
   fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-    // git compares files a little weirdly, so we must coerce to strings
-    format!("{}", self.path.display())
-      .partial_cmp(&format!("{}", other.path.display()))
+    Some(self.cmp(other))
   }
 }
 
 impl Ord for PathEntry {
   fn cmp(&self, other: &Self) -> Ordering {
+    // git compares files a little weirdly, so we must coerce to strings
     format!("{}", self.path.display()).cmp(&format!("{}", other.path.display()))
   }
 }
@@ -326,7 +330,7 @@ impl TreeItem {
       TreeItem::Entry(e) => format!(
         "{} {} {}    {}",
         e.mode.long(),
-        "blob",
+        e.mode.object_type(),
         e.sha,
         PathBuf::from(e.path.file_name().unwrap()).display(),
       ),
@@ -335,11 +339,25 @@ impl TreeItem {
 }
 
 impl Mode {
+  // the mode as it's written in a tree object
+  pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+    match bytes {
+      b"40000" => Some(Self::Tree),
+      b"100644" => Some(Self::Normal),
+      b"100755" => Some(Self::Executable),
+      b"120000" => Some(Self::Symlink),
+      b"160000" => Some(Self::Gitlink),
+      _ => None,
+    }
+  }
+
   pub fn short(&self) -> &'static str {
     match self {
       Self::Tree => "40000",
       Self::Normal => "100644",
       Self::Executable => "100755",
+      Self::Symlink => "120000",
+      Self::Gitlink => "160000",
     }
   }
 
@@ -349,6 +367,15 @@ impl Mode {
       _ => self.short(),
     }
   }
+
+  // the kind of object an entry with this mode points to
+  pub fn object_type(&self) -> &'static str {
+    match self {
+      Self::Tree => "tree",
+      Self::Gitlink => "commit",
+      _ => "blob",
+    }
+  }
 }
 
 impl From<&Metadata> for Mode {
@@ -367,17 +394,6 @@ impl From<&Metadata> for Mode {
   }
 }
 
-impl From<&[u8]> for Mode {
-  fn from(bytes: &[u8]) -> Self {
-    match bytes {
-      b"40000" => Self::Tree,
-      b"100644" => Self::Normal,
-      b"100755" => Self::Executable,
-      _ => panic!("unknown mode {:?}", bytes),
-    }
-  }
-}
-
 impl From<u32> for Mode {
   fn from(mode: u32) -> Self {
     let mode_str = format!("{:0>6o}", mode);
@@ -385,6 +401,8 @@ impl From<u32> for Mode {
       "040000" => Self::Tree,
       "100644" => Self::Normal,
       "100755" => Self::Executable,
+      "120000" => Self::Symlink,
+      "160000" => Self::Gitlink,
       _ => panic!("unknown mode {:?}", mode_str),
     }
   }
@@ -395,7 +413,9 @@ impl From<&Mode> for u32 {
     match mode {
       Mode::Tree => 0o040000,
       Mode::Normal => 0o100644,
-      Mode::Executable => 0o100644,
+      Mode::Executable => 0o100755,
+      Mode::Symlink => 0o120000,
+      Mode::Gitlink => 0o160000,
     }
   }
 }
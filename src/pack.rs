@@ -0,0 +1,523 @@
+pub mod delta;
+mod index;
+mod writer;
+
+pub use writer::{write_pack, PackEntry, PackStats};
+
+use index::PackIndex;
+
+use flate2::read::ZlibDecoder;
+use log::{debug, trace};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::{BufReader, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use crate::object::Object;
+use crate::prelude::*;
+
+const PACK_SIGNATURE: &[u8; 4] = b"PACK";
+
+// Every object in a pack starts with a header encoding its type and
+// (inflated) size. The two delta types refer to some other object as a base.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PackedType {
+  Commit,
+  Tree,
+  Blob,
+  Tag,
+  OfsDelta,
+  RefDelta,
+}
+
+// A pack is a .pack file and its .idx file, which live next to each other in
+// objects/pack.
+#[derive(Debug)]
+pub struct Pack {
+  path:  PathBuf,
+  index: PackIndex,
+}
+
+fn pack_error<T>(s: &str) -> Result<T> {
+  Err(PidgitError::Pack(s.to_string()))
+}
+
+// Find all the packs in this objects dir; the idx files are the source of
+// truth, because a pack without an index is useless to us.
+pub fn load_packs(objects_dir: &Path) -> Result<Vec<Pack>> {
+  let pack_dir = objects_dir.join("pack");
+  if !pack_dir.is_dir() {
+    return Ok(vec![]);
+  }
+
+  let mut idx_paths = std::fs::read_dir(pack_dir)?
+    .filter_map(std::result::Result::ok)
+    .map(|e| e.path())
+    .filter(|p| p.extension().is_some_and(|ext| ext == "idx"))
+    .collect::<Vec<_>>();
+
+  idx_paths.sort();
+
+  idx_paths.iter().map(|p| Pack::open(p)).collect()
+}
+
+impl Pack {
+  pub fn open(idx_path: &Path) -> Result<Self> {
+    let path = idx_path.with_extension("pack");
+    let index = PackIndex::load(idx_path)?;
+
+    // - 4-byte signature 'PACK', then a 4-byte version number (2 or 3), then
+    //   a 4-byte number of objects.
+    let mut header = [0u8; 12];
+    File::open(&path)?.read_exact(&mut header)?;
+
+    if &header[0..4] != PACK_SIGNATURE {
+      return pack_error(&format!("{} is not a packfile", path.display()));
+    }
+
+    let mut version = [0u8; 4];
+    version.copy_from_slice(&header[4..8]);
+    let version = u32::from_be_bytes(version);
+
+    if version != 2 && version != 3 {
+      return pack_error(&format!("unsupported pack version {}", version));
+    }
+
+    debug!("loaded pack at {:?}", path);
+
+    Ok(Self { path, index })
+  }
+
+  pub fn path(&self) -> &PathBuf {
+    &self.path
+  }
+
+  pub fn idx_path(&self) -> PathBuf {
+    self.path.with_extension("idx")
+  }
+
+  pub fn contains(&self, sha: &Sha) -> bool {
+    self.index.contains(sha)
+  }
+
+  pub fn shas_with_prefix(&self, prefix: &str) -> Vec<Sha> {
+    self.index.shas_with_prefix(prefix)
+  }
+
+  pub fn shas(&self) -> Vec<Sha> {
+    self.index.shas()
+  }
+
+  pub fn read_object(&self, sha: &Sha) -> Result<Object> {
+    let (kind, content) = self.read_raw(sha)?;
+    Object::from_raw(kind.type_str(), content)
+  }
+
+  pub fn read_raw(&self, sha: &Sha) -> Result<(PackedType, Vec<u8>)> {
+    let offset = match self.index.offset_for(sha) {
+      Some(offset) => offset,
+      None => return Err(PidgitError::ObjectNotFound(sha.hexdigest())),
+    };
+
+    trace!("reading {} from {:?} at {}", sha, self.path, offset);
+
+    let mut file = File::open(&self.path)?;
+    self.read_at(&mut file, offset)
+  }
+
+  // Read the object at offset, resolving any deltas along the way. The type
+  // returned is never one of the delta types.
+  fn read_at(
+    &self,
+    file: &mut File,
+    offset: u64,
+  ) -> Result<(PackedType, Vec<u8>)> {
+    let mut deltas = vec![];
+    let mut seen = HashSet::new();
+    let mut offset = offset;
+
+    // walk down the chain to something that isn't a delta, then apply the
+    // deltas from the bottom up
+    loop {
+      if !seen.insert(offset) {
+        return pack_error(&format!("delta chain at offset {} loops", offset));
+      }
+
+      let (kind, base, data) = self.read_entry(file, offset)?;
+
+      match base {
+        Some(base_offset) => {
+          deltas.push(data);
+          offset = base_offset;
+        },
+        None => {
+          let mut data = data;
+          for delta in deltas.iter().rev() {
+            data = delta::apply(&data, delta)?;
+          }
+
+          return Ok((kind, data));
+        },
+      }
+    }
+  }
+
+  // Read a single entry, returning its type, the offset of its base (if it's
+  // a delta), and its inflated data.
+  fn read_entry(
+    &self,
+    file: &mut File,
+    offset: u64,
+  ) -> Result<(PackedType, Option<u64>, Vec<u8>)> {
+    file.seek(SeekFrom::Start(offset))?;
+    let mut reader = BufReader::new(&*file);
+
+    let (kind, size) = read_entry_header(&mut reader)?;
+
+    let base = match kind {
+      PackedType::OfsDelta => {
+        let distance = read_offset_distance(&mut reader)?;
+        if distance == 0 || distance > offset {
+          return pack_error("delta base offset out of range");
+        }
+
+        Some(offset - distance)
+      },
+      PackedType::RefDelta => {
+        let mut sha = [0u8; sha1::DIGEST_LENGTH];
+        reader.read_exact(&mut sha)?;
+        let sha = Sha::from(sha);
+
+        // We don't handle thin packs, so the base must be in here somewhere.
+        match self.index.offset_for(&sha) {
+          Some(base_offset) => Some(base_offset),
+          None => return Err(PidgitError::ObjectNotFound(sha.hexdigest())),
+        }
+      },
+      _ => None,
+    };
+
+    // the size is only a claim, so don't trust it with an allocation; one
+    // byte past it is enough to know it was wrong
+    let mut data = vec![];
+    ZlibDecoder::new(reader)
+      .take(size as u64 + 1)
+      .read_to_end(&mut data)?;
+
+    if data.len() != size {
+      return pack_error(&format!(
+        "object at offset {} has wrong size (want {}, have {})",
+        offset,
+        size,
+        data.len()
+      ));
+    }
+
+    Ok((kind, base, data))
+  }
+}
+
+// The first byte has a continuation bit, then three bits of type, then four
+// bits of size; subsequent bytes have a continuation bit, then seven more bits
+// of size.
+fn read_entry_header<R: Read>(reader: &mut R) -> Result<(PackedType, usize)> {
+  let mut byte = [0u8; 1];
+  reader.read_exact(&mut byte)?;
+
+  let kind = PackedType::from_bits((byte[0] >> 4) & 0x7)?;
+  let mut size = (byte[0] & 0x0f) as usize;
+  let mut shift = 4;
+
+  while byte[0] & 0x80 != 0 {
+    reader.read_exact(&mut byte)?;
+    size = match shift_in(size, byte[0], shift) {
+      Some(size) => size,
+      None => return pack_error("object size too large"),
+    };
+    shift += 7;
+  }
+
+  Ok((kind, size))
+}
+
+// Add the low 7 bits of byte to a little-endian varint, shifted into place,
+// unless some of them would fall off the end.
+fn shift_in(value: usize, byte: u8, shift: u32) -> Option<usize> {
+  let bits = (byte & 0x7f) as usize;
+  let shifted = bits.checked_shl(shift)?;
+
+  if shifted >> shift != bits {
+    return None;
+  }
+
+  Some(value | shifted)
+}
+
+// This is a different varint encoding than the header: big-endian, and with
+// an off-by-one on every continuation byte so that there's only one way to
+// encode each number.
+fn read_offset_distance<R: Read>(reader: &mut R) -> Result<u64> {
+  let mut byte = [0u8; 1];
+  reader.read_exact(&mut byte)?;
+
+  let mut distance = (byte[0] & 0x7f) as u64;
+
+  while byte[0] & 0x80 != 0 {
+    reader.read_exact(&mut byte)?;
+    distance = match distance.checked_add(1).and_then(|d| d.checked_mul(128)) {
+      Some(d) => d | (byte[0] & 0x7f) as u64,
+      None => return pack_error("offset distance too large"),
+    };
+  }
+
+  Ok(distance)
+}
+
+impl PackedType {
+  pub fn from_type_str(s: &str) -> Result<Self> {
+    match s {
+      "commit" => Ok(Self::Commit),
+      "tree" => Ok(Self::Tree),
+      "blob" => Ok(Self::Blob),
+      "tag" => Ok(Self::Tag),
+      _ => pack_error(&format!("cannot pack object of type {}", s)),
+    }
+  }
+
+  fn bits(&self) -> u8 {
+    match self {
+      Self::Commit => 1,
+      Self::Tree => 2,
+      Self::Blob => 3,
+      Self::Tag => 4,
+      Self::OfsDelta => 6,
+      Self::RefDelta => 7,
+    }
+  }
+
+  fn from_bits(bits: u8) -> Result<Self> {
+    match bits {
+      1 => Ok(Self::Commit),
+      2 => Ok(Self::Tree),
+      3 => Ok(Self::Blob),
+      4 => Ok(Self::Tag),
+      6 => Ok(Self::OfsDelta),
+      7 => Ok(Self::RefDelta),
+      _ => pack_error(&format!("unknown object type {}", bits)),
+    }
+  }
+
+  pub fn type_str(&self) -> &'static str {
+    match self {
+      Self::Commit => "commit",
+      Self::Tree => "tree",
+      Self::Blob => "blob",
+      Self::Tag => "tag",
+      Self::OfsDelta => "ofs-delta",
+      Self::RefDelta => "ref-delta",
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::test_prelude::*;
+  use flate2::{write::ZlibEncoder, Compression};
+  use sha1::Sha1;
+  use std::io::Write;
+
+  use crate::object::{Blob, GitObject};
+  use crate::util::Sha;
+
+  fn entry(kind: u8, size: usize, extra: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut ret = vec![];
+    let mut byte = (kind << 4) | (size & 0x0f) as u8;
+    let mut rest = size >> 4;
+
+    while rest > 0 {
+      ret.push(byte | 0x80);
+      byte = (rest & 0x7f) as u8;
+      rest >>= 7;
+    }
+
+    ret.push(byte);
+    ret.extend(extra);
+
+    let mut z = ZlibEncoder::new(vec![], Compression::default());
+    z.write_all(data).unwrap();
+    ret.extend(z.finish().unwrap());
+    ret
+  }
+
+  // write a pack containing these entries (sha, raw entry bytes) to the test
+  // repo, along with a matching index
+  fn write_pack(tr: &TestRepo, entries: Vec<(Sha, Vec<u8>)>) {
+    let mut pack = b"PACK".to_vec();
+    pack.extend(2u32.to_be_bytes().iter());
+    pack.extend((entries.len() as u32).to_be_bytes().iter());
+
+    let mut offsets = vec![];
+    for (sha, bytes) in entries {
+      offsets.push((sha.bytes(), pack.len() as u32));
+      pack.extend(bytes);
+    }
+
+    let pack_sum = Sha1::from(&pack).digest().bytes();
+    pack.extend(pack_sum.iter());
+
+    offsets.sort();
+
+    let mut idx = b"\xfftOc".to_vec();
+    idx.extend(2u32.to_be_bytes().iter());
+    for byte in 0..=255u8 {
+      let n = offsets.iter().filter(|(s, _)| s[0] <= byte).count() as u32;
+      idx.extend(n.to_be_bytes().iter());
+    }
+
+    offsets.iter().for_each(|(s, _)| idx.extend(s));
+    offsets
+      .iter()
+      .for_each(|_| idx.extend(0u32.to_be_bytes().iter()));
+    offsets
+      .iter()
+      .for_each(|(_, o)| idx.extend(o.to_be_bytes().iter()));
+    idx.extend(pack_sum.iter());
+    let idx_sum = Sha1::from(&idx).digest().bytes();
+    idx.extend(idx_sum.iter());
+
+    let dir = tr.repo.git_dir().join("objects/pack");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("pack-test.pack"), pack).unwrap();
+    std::fs::write(dir.join("pack-test.idx"), idx).unwrap();
+  }
+
+  #[test]
+  fn read_packed_objects() {
+    let tr = new_empty_repo();
+
+    let base = Blob::from_content(b"hello, world\n".to_vec());
+    let ofs = Blob::from_content(b"hello, world\ngoodbye\n".to_vec());
+    let refd = Blob::from_content(b"world\n".to_vec());
+
+    let base_entry = entry(3, 13, &[], &base.raw_content());
+    let base_len = 12 + base_entry.len();
+
+    // copy all 13 bytes of the base, then insert "goodbye\n"
+    let mut delta = vec![13, 21, 0x90, 13, 8];
+    delta.extend(b"goodbye\n");
+    let ofs_entry = entry(6, delta.len(), &[base_len as u8 - 12], &delta);
+
+    // copy 6 bytes from offset 7
+    let delta = vec![13, 6, 0x91, 7, 6];
+    let ref_entry = entry(7, delta.len(), &base.sha().bytes(), &delta);
+
+    write_pack(
+      &tr,
+      vec![
+        (base.sha(), base_entry),
+        (ofs.sha(), ofs_entry),
+        (refd.sha(), ref_entry),
+      ],
+    );
+
+    assert!(tr.repo.object_for_sha(&ofs.sha()).is_err());
+    tr.repo.reload_packs().unwrap();
+
+    for blob in &[base, ofs, refd] {
+      let obj = tr.repo.object_for_sha(&blob.sha()).unwrap();
+      assert_eq!(obj.sha(), blob.sha());
+      assert_eq!(obj.as_blob().unwrap().raw_content(), blob.raw_content());
+    }
+  }
+
+  #[test]
+  fn resolve_packed_prefix() {
+    let tr = new_empty_repo();
+
+    let blob = Blob::from_content(b"packed\n".to_vec());
+    write_pack(
+      &tr,
+      vec![(blob.sha(), entry(3, 7, &[], &blob.raw_content()))],
+    );
+    tr.repo.reload_packs().unwrap();
+
+    let short = blob.sha().short(7);
+    let obj = tr.repo.resolve_object(&short).unwrap();
+    assert_eq!(obj.sha(), blob.sha());
+    assert!(tr.repo.has_object(&blob.sha()));
+
+    // and we don't write a loose copy of something we already have
+    tr.repo.write_object(&blob).unwrap();
+    assert!(!tr.repo.path_for_sha(&blob.sha()).exists());
+  }
+
+  #[test]
+  fn bad_delta_bases() {
+    let tr = new_empty_repo();
+
+    let ofs = Blob::from_content(b"ofs\n".to_vec());
+    let a = Blob::from_content(b"a\n".to_vec());
+    let b = Blob::from_content(b"b\n".to_vec());
+    let delta = vec![2, 2, 0x90, 2];
+
+    // an ofs-delta that's its own base, and two ref-deltas that are each
+    // other's
+    write_pack(
+      &tr,
+      vec![
+        (ofs.sha(), entry(6, delta.len(), &[0], &delta)),
+        (a.sha(), entry(7, delta.len(), &b.sha().bytes(), &delta)),
+        (b.sha(), entry(7, delta.len(), &a.sha().bytes(), &delta)),
+      ],
+    );
+    tr.repo.reload_packs().unwrap();
+
+    let err = tr.repo.object_for_sha(&ofs.sha()).unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      "could not read packfile: delta base offset out of range"
+    );
+
+    let err = tr.repo.object_for_sha(&a.sha()).unwrap_err();
+    assert!(err.to_string().ends_with("loops"), "{}", err);
+  }
+
+  #[test]
+  fn unordered_fanout() {
+    let tr = new_empty_repo();
+    let blob = Blob::from_content(b"blob\n".to_vec());
+    let data = b"blob\n";
+    write_pack(&tr, vec![(blob.sha(), entry(3, data.len(), &[], data))]);
+
+    // claim lots of objects start with 0x00, and then fewer after that
+    let path = tr.repo.git_dir().join("objects/pack/pack-test.idx");
+    let mut idx = std::fs::read(&path).unwrap();
+    idx[8..12].copy_from_slice(&100u32.to_be_bytes());
+    std::fs::write(&path, idx).unwrap();
+
+    let err = tr.repo.reload_packs().unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      "could not read packfile: bad pack index: fanout table is not in order"
+    );
+  }
+
+  #[test]
+  fn varint_overflow() {
+    use super::{read_entry_header, read_offset_distance};
+
+    let mut header = vec![0xb0];
+    header.extend(vec![0xff; 10]);
+    header.push(0x01);
+    let err = read_entry_header(&mut &header[..]).unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      "could not read packfile: object size too large"
+    );
+
+    let mut distance = vec![0xff; 10];
+    distance.push(0x01);
+    assert!(read_offset_distance(&mut &distance[..]).is_err());
+  }
+}
@@ -0,0 +1,316 @@
+use crate::prelude::*;
+
+// A delta is a pair of varint sizes (source, then target), followed by a
+// series of instructions. Each instruction is either a copy from the base
+// object (high bit set), or an insert of literal bytes (high bit clear, the
+// rest of the byte is how many literal bytes follow).
+
+fn delta_error<T>(s: &str) -> Result<T> {
+  Err(PidgitError::Pack(format!("bad delta: {}", s)))
+}
+
+struct DeltaReader<'d> {
+  data: &'d [u8],
+  pos:  usize,
+}
+
+impl<'d> DeltaReader<'d> {
+  fn is_done(&self) -> bool {
+    self.pos >= self.data.len()
+  }
+
+  fn byte(&mut self) -> Result<u8> {
+    match self.data.get(self.pos) {
+      Some(b) => {
+        self.pos += 1;
+        Ok(*b)
+      },
+      None => delta_error("unexpected end of data"),
+    }
+  }
+
+  fn bytes(&mut self, n: usize) -> Result<&'d [u8]> {
+    match self.data.get(self.pos..self.pos + n) {
+      Some(slice) => {
+        self.pos += n;
+        Ok(slice)
+      },
+      None => delta_error("unexpected end of data"),
+    }
+  }
+
+  // little-endian base-128, low 7 bits first
+  fn size(&mut self) -> Result<usize> {
+    let mut size = 0;
+    let mut shift = 0;
+
+    loop {
+      let byte = self.byte()?;
+      size = match super::shift_in(size, byte, shift) {
+        Some(size) => size,
+        None => return delta_error("size too large"),
+      };
+      shift += 7;
+
+      if byte & 0x80 == 0 {
+        return Ok(size);
+      }
+    }
+  }
+}
+
+pub fn apply(base: &[u8], delta: &[u8]) -> Result<Vec<u8>> {
+  let mut reader = DeltaReader {
+    data: delta,
+    pos:  0,
+  };
+
+  let src_size = reader.size()?;
+  if src_size != base.len() {
+    return delta_error(&format!(
+      "base size mismatch (want {}, have {})",
+      src_size,
+      base.len()
+    ));
+  }
+
+  let dst_size = reader.size()?;
+  let mut out = Vec::with_capacity(dst_size);
+
+  while !reader.is_done() {
+    let cmd = reader.byte()?;
+
+    if cmd & 0x80 != 0 {
+      // copy: the low four bits say which offset bytes are present, the next
+      // three which size bytes are.
+      let mut offset = 0usize;
+      for i in 0..4 {
+        if cmd & (1 << i) != 0 {
+          offset |= (reader.byte()? as usize) << (8 * i);
+        }
+      }
+
+      let mut size = 0usize;
+      for i in 0..3 {
+        if cmd & (0x10 << i) != 0 {
+          size |= (reader.byte()? as usize) << (8 * i);
+        }
+      }
+
+      if size == 0 {
+        size = 0x10000;
+      }
+
+      match base.get(offset..offset + size) {
+        Some(slice) => out.extend_from_slice(slice),
+        None => return delta_error("copy out of range of base"),
+      }
+    } else if cmd != 0 {
+      out.extend_from_slice(reader.bytes(cmd as usize)?);
+    } else {
+      return delta_error("reserved instruction 0");
+    }
+  }
+
+  if out.len() != dst_size {
+    return delta_error(&format!(
+      "result size mismatch (want {}, have {})",
+      dst_size,
+      out.len()
+    ));
+  }
+
+  Ok(out)
+}
+
+// We index the base in blocks of this many bytes, and don't bother copying
+// anything shorter than this.
+const BLOCK_SIZE: usize = 16;
+
+// Highly repetitive input would make for enormous buckets in the block index,
+// so like git, we just stop remembering positions after a while.
+const BUCKET_LIMIT: usize = 64;
+
+// Copies can technically be up to 0xffffff bytes, but git has always split
+// them at 64k, so we do too.
+const MAX_COPY: usize = 0x10000;
+
+// Inserts are limited by the seven bits available in the instruction byte.
+const MAX_INSERT: usize = 0x7f;
+
+fn write_size(out: &mut Vec<u8>, mut size: usize) {
+  loop {
+    let byte = (size & 0x7f) as u8;
+    size >>= 7;
+
+    if size == 0 {
+      out.push(byte);
+      return;
+    }
+
+    out.push(byte | 0x80);
+  }
+}
+
+fn write_insert(out: &mut Vec<u8>, data: &[u8]) {
+  for chunk in data.chunks(MAX_INSERT) {
+    out.push(chunk.len() as u8);
+    out.extend_from_slice(chunk);
+  }
+}
+
+fn write_copy(out: &mut Vec<u8>, mut offset: usize, mut len: usize) {
+  while len > 0 {
+    let size = len.min(MAX_COPY);
+    let mut cmd = 0x80u8;
+    let mut args = vec![];
+
+    for i in 0..4 {
+      let byte = ((offset >> (8 * i)) & 0xff) as u8;
+      if byte != 0 {
+        cmd |= 1 << i;
+        args.push(byte);
+      }
+    }
+
+    // a size of exactly 0x10000 is encoded as no size bytes at all
+    let encoded_size = if size == MAX_COPY { 0 } else { size };
+    for i in 0..3 {
+      let byte = ((encoded_size >> (8 * i)) & 0xff) as u8;
+      if byte != 0 {
+        cmd |= 0x10 << i;
+        args.push(byte);
+      }
+    }
+
+    out.push(cmd);
+    out.extend(args);
+
+    offset += size;
+    len -= size;
+  }
+}
+
+// Compute a delta that turns base into target. This is a pretty naive version
+// of what git does in diff-delta.c: we index every block of the base, then walk
+// the target looking for the longest match we can find at each position,
+// emitting literal inserts for anything we can't find.
+pub fn compute(base: &[u8], target: &[u8]) -> Vec<u8> {
+  use std::collections::HashMap;
+
+  let mut out = vec![];
+  write_size(&mut out, base.len());
+  write_size(&mut out, target.len());
+
+  let mut blocks: HashMap<&[u8], Vec<usize>> = HashMap::new();
+  for start in (0..base.len().saturating_sub(BLOCK_SIZE - 1)).step_by(BLOCK_SIZE)
+  {
+    let bucket = blocks.entry(&base[start..start + BLOCK_SIZE]).or_default();
+    if bucket.len() < BUCKET_LIMIT {
+      bucket.push(start);
+    }
+  }
+
+  let mut pos = 0;
+  let mut pending_start = 0;
+
+  while pos < target.len() {
+    let best = target
+      .get(pos..pos + BLOCK_SIZE)
+      .and_then(|block| blocks.get(block))
+      .and_then(|candidates| {
+        candidates
+          .iter()
+          .map(|&start| {
+            let len = base[start..]
+              .iter()
+              .zip(&target[pos..])
+              .take_while(|(a, b)| a == b)
+              .count();
+            (start, len)
+          })
+          .max_by_key(|&(_, len)| len)
+      });
+
+    match best {
+      Some((start, len)) if len >= BLOCK_SIZE => {
+        write_insert(&mut out, &target[pending_start..pos]);
+        write_copy(&mut out, start, len);
+        pos += len;
+        pending_start = pos;
+      },
+      _ => pos += 1,
+    }
+  }
+
+  write_insert(&mut out, &target[pending_start..]);
+
+  out
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn copy_and_insert() {
+    let base = b"the quick brown fox";
+
+    #[rustfmt::skip]
+    let delta = vec![
+      19, 24,                     // sizes
+      0x80 | 0x01 | 0x10, 4, 11,  // copy 11 bytes from offset 4
+      4, b' ', b'c', b'a', b't',  // insert " cat"
+      0x80 | 0x10, 4,             // copy 4 bytes from offset 0
+      5, b'd', b'o', b'g', b'!', b'\n', // insert "dog!\n"
+    ];
+
+    let out = apply(base, &delta).unwrap();
+    assert_eq!(out, b"quick brown catthe dog!\n".to_vec());
+  }
+
+  #[test]
+  fn size_mismatch() {
+    let base = b"abc";
+    let delta = vec![4, 1, 1, b'x'];
+    assert!(apply(base, &delta).is_err());
+
+    let delta = vec![3, 2, 1, b'x'];
+    assert!(apply(base, &delta).is_err());
+  }
+
+  #[test]
+  fn copy_out_of_range() {
+    let base = b"abc";
+    let delta = vec![3, 5, 0x80 | 0x01 | 0x10, 1, 5];
+    assert!(apply(base, &delta).is_err());
+  }
+
+  #[test]
+  fn compute_round_trip() {
+    let base = (0..200)
+      .map(|n| format!("line number {}\n", n))
+      .collect::<String>()
+      .into_bytes();
+
+    let mut target = base.clone();
+    target.splice(100..120, b"something new in the middle".iter().cloned());
+    target.extend(b"and a new ending\n");
+
+    let delta = compute(&base, &target);
+    assert!(delta.len() < target.len() / 4, "delta is reasonably small");
+    assert_eq!(apply(&base, &delta).unwrap(), target);
+
+    // and some degenerate cases
+    for (a, b) in &[(&b""[..], &b"new"[..]), (b"old", b""), (b"", b"")] {
+      assert_eq!(apply(a, &compute(a, b)).unwrap(), b.to_vec());
+    }
+  }
+
+  #[test]
+  fn compute_large_copy() {
+    let base = vec![b'x'; 0x10000 * 2 + 100];
+    let delta = compute(&base, &base);
+    assert_eq!(apply(&base, &delta).unwrap(), base);
+  }
+}
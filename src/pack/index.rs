@@ -0,0 +1,175 @@
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+
+use crate::prelude::*;
+
+const IDX_SIGNATURE: &[u8; 4] = b"\xfftOc";
+const IDX_VERSION: u32 = 2;
+const FANOUT_SIZE: usize = 256;
+const LARGE_OFFSET_FLAG: u32 = 0x8000_0000;
+
+// An in-memory representation of a version 2 pack index. The whole thing is
+// small enough (28 bytes or so per object) that we just read it all at once.
+#[derive(Debug)]
+pub struct PackIndex {
+  fanout:  [u32; FANOUT_SIZE],
+  shas:    Vec<[u8; sha1::DIGEST_LENGTH]>,
+  offsets: Vec<u64>,
+}
+
+fn idx_error<T>(s: &str) -> Result<T> {
+  Err(PidgitError::Pack(format!("bad pack index: {}", s)))
+}
+
+fn read_u32(buf: &[u8], pos: usize) -> Result<u32> {
+  match buf.get(pos..pos + 4) {
+    Some(bytes) => {
+      let mut word = [0u8; 4];
+      word.copy_from_slice(bytes);
+      Ok(u32::from_be_bytes(word))
+    },
+    None => idx_error("unexpected end of file"),
+  }
+}
+
+impl PackIndex {
+  // parse this, based on
+  // https://github.com/git/git/blob/master/Documentation/technical/pack-format.txt
+  // We only support version 2 indexes, which is what git has written by
+  // default since 1.5.2.
+  pub fn load(path: &Path) -> Result<Self> {
+    let mut raw = vec![];
+    File::open(path)?.read_to_end(&mut raw)?;
+
+    // - A 4-byte magic number \377tOc, then a 4-byte version number (= 2)
+    if raw.len() < 8 || &raw[0..4] != IDX_SIGNATURE {
+      return idx_error("missing signature (version 1 indexes unsupported)");
+    }
+
+    let version = read_u32(&raw, 4)?;
+    if version != IDX_VERSION {
+      return idx_error(&format!("unsupported version {}", version));
+    }
+
+    // - A 256-entry fan-out table. The N-th entry of this table records the
+    //   number of objects whose first byte of object name is less than or
+    //   equal to N.
+    let mut pos = 8;
+    let mut fanout = [0u32; FANOUT_SIZE];
+    for n in 0..FANOUT_SIZE {
+      fanout[n] = read_u32(&raw, pos)?;
+      pos += 4;
+
+      // otherwise the buckets will slice out of bounds
+      if n > 0 && fanout[n] < fanout[n - 1] {
+        return idx_error("fanout table is not in order");
+      }
+    }
+
+    let count = fanout[FANOUT_SIZE - 1] as usize;
+
+    // make sure the rest of the tables are actually there before we go
+    // slicing into them: shas, crcs, and 4-byte offsets, plus two trailing
+    // checksums.
+    let min_len = pos + count * (sha1::DIGEST_LENGTH + 8) + 40;
+    if raw.len() < min_len {
+      return idx_error("file is truncated");
+    }
+
+    // - A table of sorted object names.
+    let mut shas = Vec::with_capacity(count);
+    for _ in 0..count {
+      let mut sha = [0u8; sha1::DIGEST_LENGTH];
+      sha.copy_from_slice(&raw[pos..pos + sha1::DIGEST_LENGTH]);
+      shas.push(sha);
+      pos += sha1::DIGEST_LENGTH;
+    }
+
+    // - A table of 4-byte CRC32 values of the packed object data, which we
+    //   don't bother checking.
+    pos += count * 4;
+
+    // - A table of 4-byte offset values. If the MSB is set, the remaining 31
+    //   bits are an index into the following table of 8-byte offsets.
+    let large_base = pos + count * 4;
+    let mut offsets = Vec::with_capacity(count);
+    for _ in 0..count {
+      let small = read_u32(&raw, pos)?;
+      pos += 4;
+
+      if small & LARGE_OFFSET_FLAG == 0 {
+        offsets.push(small as u64);
+        continue;
+      }
+
+      let large_pos = large_base + 8 * (small & !LARGE_OFFSET_FLAG) as usize;
+      let hi = read_u32(&raw, large_pos)? as u64;
+      let lo = read_u32(&raw, large_pos + 4)? as u64;
+      offsets.push(hi << 32 | lo);
+    }
+
+    Ok(Self {
+      fanout,
+      shas,
+      offsets,
+    })
+  }
+
+  // the range of positions in the sha table whose first byte is `byte`
+  fn bucket(&self, byte: u8) -> (usize, usize) {
+    let hi = self.fanout[byte as usize] as usize;
+    let lo = if byte == 0 {
+      0
+    } else {
+      self.fanout[byte as usize - 1] as usize
+    };
+
+    (lo, hi)
+  }
+
+  fn position_of(&self, sha: &Sha) -> Option<usize> {
+    // don't use sha.bytes() here, which panics on bad hex
+    let bytes = hex::decode(sha.hexdigest()).ok()?;
+    if bytes.len() != sha1::DIGEST_LENGTH {
+      return None;
+    }
+
+    let (lo, hi) = self.bucket(bytes[0]);
+    self.shas[lo..hi]
+      .binary_search_by(|probe| probe[..].cmp(&bytes[..]))
+      .ok()
+      .map(|n| lo + n)
+  }
+
+  pub fn offset_for(&self, sha: &Sha) -> Option<u64> {
+    self.position_of(sha).map(|n| self.offsets[n])
+  }
+
+  pub fn contains(&self, sha: &Sha) -> bool {
+    self.position_of(sha).is_some()
+  }
+
+  pub fn shas(&self) -> Vec<Sha> {
+    self.shas.iter().map(hex::encode).map(Sha::from).collect()
+  }
+
+  // prefix is a hex string, which must be at least two characters long
+  pub fn shas_with_prefix(&self, prefix: &str) -> Vec<Sha> {
+    let first = match prefix
+      .get(0..2)
+      .and_then(|s| u8::from_str_radix(s, 16).ok())
+    {
+      Some(byte) => byte,
+      None => return vec![],
+    };
+
+    let (lo, hi) = self.bucket(first);
+    self.shas[lo..hi]
+      .iter()
+      .map(hex::encode)
+      .filter(|hex| hex.starts_with(prefix))
+      .map(Sha::from)
+      .collect()
+  }
+}
@@ -0,0 +1,275 @@
+use flate2::{write::ZlibEncoder, Compression, Crc};
+use log::debug;
+use sha1::Sha1;
+use std::collections::VecDeque;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+use super::{delta, PackedType};
+use crate::prelude::*;
+use crate::Lockfile;
+
+// How many objects back we look for a delta base, and how long we let delta
+// chains get. These are git's defaults.
+const WINDOW: usize = 10;
+const MAX_DEPTH: usize = 50;
+
+// Not worth trying to deltify anything smaller than this.
+const MIN_DELTA_SIZE: usize = 50;
+
+const LARGE_OFFSET: u64 = 0x7fff_ffff;
+
+// Something to be written into a pack. The name is a hint about where we found
+// the object (a filename, for blobs and trees), which we use to put similar
+// objects next to each other so that they make good delta bases.
+#[derive(Debug)]
+pub struct PackEntry {
+  pub sha:     Sha,
+  pub kind:    PackedType,
+  pub content: Vec<u8>,
+  pub name:    String,
+}
+
+#[derive(Debug)]
+pub struct PackStats {
+  pub path:    PathBuf,
+  pub objects: usize,
+  pub deltas:  usize,
+}
+
+struct Written {
+  sha:    Vec<u8>,
+  offset: u64,
+  crc:    u32,
+}
+
+// Write all these entries into a new pack (and index) in pack_dir. The pack is
+// named after its checksum, like git does.
+pub fn write_pack(
+  pack_dir: &Path,
+  mut entries: Vec<PackEntry>,
+) -> Result<PackStats> {
+  // Sort so that similar things are near each other, and bigger things come
+  // first: it's cheaper to delete than to insert, so the big ones make better
+  // bases.
+  entries.sort_by(|a, b| {
+    (a.kind.bits(), &a.name, b.content.len()).cmp(&(
+      b.kind.bits(),
+      &b.name,
+      a.content.len(),
+    ))
+  });
+
+  let bases = find_delta_bases(&entries);
+
+  let mut pack = b"PACK".to_vec();
+  pack.extend(2u32.to_be_bytes().iter());
+  pack.extend((entries.len() as u32).to_be_bytes().iter());
+
+  let mut written: Vec<Written> = Vec::with_capacity(entries.len());
+  let mut deltas = 0;
+
+  for (n, entry) in entries.iter().enumerate() {
+    let offset = pack.len() as u64;
+
+    let bytes = match &bases[n] {
+      Some((base_idx, data)) => {
+        deltas += 1;
+        let distance = offset - written[*base_idx].offset;
+        let mut bytes = entry_header(PackedType::OfsDelta, data.len());
+        bytes.extend(offset_distance(distance));
+        bytes.extend(deflate(data)?);
+        bytes
+      },
+      None => {
+        let mut bytes = entry_header(entry.kind, entry.content.len());
+        bytes.extend(deflate(&entry.content)?);
+        bytes
+      },
+    };
+
+    let mut crc = Crc::new();
+    crc.update(&bytes);
+
+    written.push(Written {
+      sha: entry.sha.bytes(),
+      offset,
+      crc: crc.sum(),
+    });
+
+    pack.extend(bytes);
+  }
+
+  let checksum = Sha1::from(&pack).digest().bytes();
+  pack.extend(checksum.iter());
+
+  let name = format!("pack-{}", hex::encode(checksum));
+  let pack_path = pack_dir.join(format!("{}.pack", name));
+
+  std::fs::create_dir_all(pack_dir)?;
+
+  // The pack has to be in place before the index, because the existence of
+  // the index is what tells readers that the pack is there.
+  write_file(&pack_path, &pack)?;
+  write_file(
+    &pack_dir.join(format!("{}.idx", name)),
+    &build_index(written, &checksum),
+  )?;
+
+  debug!(
+    "wrote {} objects ({} deltas) to {:?}",
+    entries.len(),
+    deltas,
+    pack_path
+  );
+
+  Ok(PackStats {
+    path: pack_path,
+    objects: entries.len(),
+    deltas,
+  })
+}
+
+fn write_file(path: &Path, content: &[u8]) -> Result<()> {
+  let lockfile = Lockfile::new(path.to_path_buf());
+  let mut lock = lockfile.lock()?;
+  lock.write_all(content)?;
+  lock.commit()
+}
+
+// For every entry, maybe pick an earlier entry to be its delta base, returning
+// the index of that base and the delta itself.
+fn find_delta_bases(entries: &[PackEntry]) -> Vec<Option<(usize, Vec<u8>)>> {
+  let mut bases: Vec<Option<(usize, Vec<u8>)>> =
+    Vec::with_capacity(entries.len());
+  let mut depths = vec![0; entries.len()];
+  let mut window: VecDeque<usize> = VecDeque::with_capacity(WINDOW);
+
+  for (n, entry) in entries.iter().enumerate() {
+    let target = &entry.content;
+    let mut best: Option<(usize, Vec<u8>)> = None;
+
+    if target.len() >= MIN_DELTA_SIZE {
+      for &candidate in window.iter() {
+        let base = &entries[candidate];
+
+        if base.kind != entry.kind || depths[candidate] >= MAX_DEPTH {
+          continue;
+        }
+
+        // if the sizes are wildly different, this won't be a good delta
+        if base.content.len() / 4 > target.len()
+          || target.len() / 4 > base.content.len()
+        {
+          continue;
+        }
+
+        let data = delta::compute(&base.content, target);
+        let best_len = best.as_ref().map_or(target.len() / 2, |b| b.1.len());
+
+        if data.len() < best_len {
+          best = Some((candidate, data));
+        }
+      }
+    }
+
+    if let Some((base, _)) = &best {
+      depths[n] = depths[*base] + 1;
+    }
+
+    bases.push(best);
+
+    if window.len() == WINDOW {
+      window.pop_front();
+    }
+
+    window.push_back(n);
+  }
+
+  bases
+}
+
+fn deflate(data: &[u8]) -> Result<Vec<u8>> {
+  let mut z = ZlibEncoder::new(vec![], Compression::default());
+  z.write_all(data)?;
+  Ok(z.finish()?)
+}
+
+// the inverse of read_entry_header
+fn entry_header(kind: PackedType, size: usize) -> Vec<u8> {
+  let mut ret = vec![];
+  let mut byte = (kind.bits() << 4) | (size & 0x0f) as u8;
+  let mut rest = size >> 4;
+
+  while rest > 0 {
+    ret.push(byte | 0x80);
+    byte = (rest & 0x7f) as u8;
+    rest >>= 7;
+  }
+
+  ret.push(byte);
+  ret
+}
+
+// the inverse of read_offset_distance
+fn offset_distance(mut distance: u64) -> Vec<u8> {
+  let mut ret = vec![(distance & 0x7f) as u8];
+  distance >>= 7;
+
+  while distance > 0 {
+    distance -= 1;
+    ret.push(0x80 | (distance & 0x7f) as u8);
+    distance >>= 7;
+  }
+
+  ret.reverse();
+  ret
+}
+
+// See PackIndex::load for the details of this format.
+fn build_index(mut written: Vec<Written>, pack_checksum: &[u8]) -> Vec<u8> {
+  written.sort_by(|a, b| a.sha.cmp(&b.sha));
+
+  let mut idx = b"\xfftOc".to_vec();
+  idx.extend(2u32.to_be_bytes().iter());
+
+  let mut count = 0;
+  for byte in 0..=255u8 {
+    while count < written.len() && written[count].sha[0] <= byte {
+      count += 1;
+    }
+
+    idx.extend((count as u32).to_be_bytes().iter());
+  }
+
+  for w in &written {
+    idx.extend(&w.sha);
+  }
+
+  for w in &written {
+    idx.extend(w.crc.to_be_bytes().iter());
+  }
+
+  let mut large = vec![];
+  for w in &written {
+    let small = if w.offset > LARGE_OFFSET {
+      large.push(w.offset);
+      0x8000_0000 | (large.len() as u32 - 1)
+    } else {
+      w.offset as u32
+    };
+
+    idx.extend(small.to_be_bytes().iter());
+  }
+
+  for offset in large {
+    idx.extend(offset.to_be_bytes().iter());
+  }
+
+  idx.extend(pack_checksum);
+
+  let checksum = Sha1::from(&idx).digest().bytes();
+  idx.extend(checksum.iter());
+
+  idx
+}
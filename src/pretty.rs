@@ -0,0 +1,427 @@
+use chrono::{DateTime, FixedOffset, Local};
+use std::collections::HashMap;
+
+use crate::object::{Commit, Person};
+use crate::prelude::*;
+
+// how many hex digits we use when abbreviating shas
+const ABBREV: usize = 8;
+
+// How to print a commit: one of the presets, or a user-supplied format string
+// full of %-placeholders.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Format {
+  Oneline,
+  Short,
+  Medium,
+  Full,
+  Fuller,
+  Raw,
+  User(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateFormat {
+  Default,
+  Iso,
+  Relative,
+  Unix,
+  Short,
+}
+
+// Everything we need to turn commits into text.
+#[derive(Debug)]
+pub struct Pretty {
+  format:      Format,
+  dates:       DateFormat,
+  decorations: HashMap<Sha, Vec<String>>,
+  abbrev:      bool,
+}
+
+impl Format {
+  // Like git, anything with a % in it counts as a format string, even
+  // without the "format:" prefix. (We don't distinguish format: from
+  // tformat:; every commit's output ends with a newline.)
+  pub fn parse(s: &str) -> Result<Self> {
+    let user = |format: &str| Ok(Format::User(format.to_string()));
+
+    match s {
+      "oneline" => Ok(Format::Oneline),
+      "short" => Ok(Format::Short),
+      "medium" => Ok(Format::Medium),
+      "full" => Ok(Format::Full),
+      "fuller" => Ok(Format::Fuller),
+      "raw" => Ok(Format::Raw),
+      _ if s.starts_with("format:") => user(&s["format:".len()..]),
+      _ if s.starts_with("tformat:") => user(&s["tformat:".len()..]),
+      _ if s.contains('%') => user(s),
+      _ => Err(PidgitError::Generic(format!(
+        "invalid --pretty format: {}",
+        s
+      ))),
+    }
+  }
+
+  // whether there's a blank line between each commit's output
+  pub fn spaced(&self) -> bool {
+    !matches!(self, Format::Oneline | Format::User(_))
+  }
+}
+
+impl DateFormat {
+  pub const NAMES: &'static [&'static str] =
+    &["default", "iso", "relative", "unix", "short"];
+
+  pub fn parse(s: &str) -> Result<Self> {
+    match s {
+      "default" => Ok(DateFormat::Default),
+      "iso" => Ok(DateFormat::Iso),
+      "relative" => Ok(DateFormat::Relative),
+      "unix" => Ok(DateFormat::Unix),
+      "short" => Ok(DateFormat::Short),
+      _ => Err(PidgitError::Generic(format!("unknown date format {}", s))),
+    }
+  }
+
+  pub fn format(&self, date: &DateTime<FixedOffset>) -> String {
+    let fmt = match self {
+      DateFormat::Default => "%a %b %-d %H:%M:%S %Y %z",
+      DateFormat::Iso => "%Y-%m-%d %H:%M:%S %z",
+      DateFormat::Unix => "%s",
+      DateFormat::Short => "%Y-%m-%d",
+      DateFormat::Relative => {
+        return relative_date(date.timestamp(), Local::now().timestamp());
+      },
+    };
+
+    date.format(fmt).to_string()
+  }
+}
+
+// This is git's show_date_relative, more or less verbatim: each unit gets
+// used until it's about 1.5 of the next one.
+fn relative_date(then: i64, now: i64) -> String {
+  if then > now {
+    return "in the future".to_string();
+  }
+
+  let plural = |n: i64, unit: &str| {
+    format!("{} {}{}", n, unit, if n == 1 { "" } else { "s" })
+  };
+
+  let diff = now - then;
+  if diff < 90 {
+    return format!("{} ago", plural(diff, "second"));
+  }
+
+  let minutes = (diff + 30) / 60;
+  if minutes < 90 {
+    return format!("{} ago", plural(minutes, "minute"));
+  }
+
+  let hours = (minutes + 30) / 60;
+  if hours < 36 {
+    return format!("{} ago", plural(hours, "hour"));
+  }
+
+  let days = (hours + 12) / 24;
+  if days < 14 {
+    return format!("{} ago", plural(days, "day"));
+  }
+
+  if days < 70 {
+    return format!("{} ago", plural((days + 3) / 7, "week"));
+  }
+
+  if days < 365 {
+    return format!("{} ago", plural((days + 15) / 30, "month"));
+  }
+
+  // less than five years: say "years, months"
+  if days < 1825 {
+    let total_months = (days * 12 * 2 + 365) / (365 * 2);
+    let (years, months) = (total_months / 12, total_months % 12);
+
+    if months == 0 {
+      return format!("{} ago", plural(years, "year"));
+    }
+
+    return format!("{}, {} ago", plural(years, "year"), plural(months, "month"));
+  }
+
+  format!("{} ago", plural((days + 183) / 365, "year"))
+}
+
+impl Pretty {
+  pub fn new(
+    repo: &Repository,
+    format: Format,
+    dates: DateFormat,
+  ) -> Result<Self> {
+    // only go hunting through refs if we need to
+    let decorations = match &format {
+      Format::User(format) if format.contains("%d") => load_decorations(repo)?,
+      _ => HashMap::new(),
+    };
+
+    Ok(Self {
+      format,
+      dates,
+      decorations,
+      abbrev: false,
+    })
+  }
+
+  // Like --abbrev-commit: shorten the sha at the top of each commit. Raw
+  // output always has the whole thing, as git's does.
+  pub fn abbrev_commit(mut self) -> Self {
+    self.abbrev = true;
+    self
+  }
+
+  pub fn format(&self) -> &Format {
+    &self.format
+  }
+
+  pub fn show(&self, commit: &Commit) -> String {
+    let sha = commit.sha();
+    let name = if self.abbrev {
+      sha.short(ABBREV)
+    } else {
+      sha.hexdigest()
+    };
+
+    let mut lines = match &self.format {
+      Format::Oneline => return format!("{} {}", name, subject(commit)),
+      Format::User(format) => return self.expand(format, commit),
+      Format::Raw => {
+        let mut lines = vec![format!("commit {}", sha)];
+        let raw = commit.raw_content();
+        let raw = String::from_utf8_lossy(&raw);
+        lines.extend(raw.lines().take_while(|l| !l.is_empty()).map(String::from));
+        lines
+      },
+      _ => vec![format!("commit {}", name)],
+    };
+
+    if commit.parent_shas.len() > 1 && self.format != Format::Raw {
+      let parents = commit
+        .parent_shas
+        .iter()
+        .map(|p| p.short(ABBREV))
+        .collect::<Vec<_>>();
+      lines.push(format!("Merge: {}", parents.join(" ")));
+    }
+
+    let date = |who: &Person| self.dates.format(&who.date);
+
+    match self.format {
+      Format::Short => {
+        lines.push(format!("Author: {}", commit.author));
+      },
+      Format::Medium => {
+        lines.push(format!("Author: {}", commit.author));
+        lines.push(format!("Date:   {}", date(&commit.author)));
+      },
+      Format::Full => {
+        lines.push(format!("Author: {}", commit.author));
+        lines.push(format!("Commit: {}", commit.committer));
+      },
+      Format::Fuller => {
+        lines.push(format!("Author:     {}", commit.author));
+        lines.push(format!("AuthorDate: {}", date(&commit.author)));
+        lines.push(format!("Commit:     {}", commit.committer));
+        lines.push(format!("CommitDate: {}", date(&commit.committer)));
+      },
+      _ => (),
+    }
+
+    lines.push("".to_string());
+
+    // short only gets the first paragraph
+    let message = commit.message.trim_end().lines();
+    let message: Vec<_> = if self.format == Format::Short {
+      message
+        .skip_while(|l| l.trim().is_empty())
+        .take_while(|l| !l.trim().is_empty())
+        .collect()
+    } else {
+      message.collect()
+    };
+
+    for line in message {
+      lines.push(format!("    {}", line));
+    }
+
+    lines.join("\n")
+  }
+
+  // Fill in the placeholders in a user format. Anything we don't understand
+  // is left alone, like git does.
+  fn expand(&self, format: &str, commit: &Commit) -> String {
+    let sha = commit.sha();
+    let mut out = String::new();
+    let mut rest = format;
+
+    while let Some(idx) = rest.find('%') {
+      out.push_str(&rest[..idx]);
+      rest = &rest[idx + 1..];
+
+      let parents = |len: Option<usize>| {
+        commit
+          .parent_shas
+          .iter()
+          .map(|p| len.map_or(p.hexdigest(), |len| p.short(len)))
+          .collect::<Vec<_>>()
+          .join(" ")
+      };
+
+      let who = |c: char| match c {
+        'a' => Some(&commit.author),
+        'c' => Some(&commit.committer),
+        _ => None,
+      };
+
+      let mut chars = rest.chars();
+      let first = chars.next();
+      let second = chars.next();
+
+      let (expansion, len) = match (first, second) {
+        (Some('%'), _) => (Some("%".to_string()), 1),
+        (Some('n'), _) => (Some("\n".to_string()), 1),
+        (Some('H'), _) => (Some(sha.hexdigest()), 1),
+        (Some('h'), _) => (Some(sha.short(ABBREV)), 1),
+        (Some('T'), _) => (Some(commit.tree().hexdigest()), 1),
+        (Some('t'), _) => (Some(commit.tree().short(ABBREV)), 1),
+        (Some('P'), _) => (Some(parents(None)), 1),
+        (Some('p'), _) => (Some(parents(Some(ABBREV))), 1),
+        (Some('s'), _) => (Some(subject(commit)), 1),
+        (Some('b'), _) => (Some(body(commit)), 1),
+        (Some('d'), _) => (Some(self.decoration(&sha)), 1),
+        (Some(c), Some(field)) if who(c).is_some() => {
+          let person = who(c).unwrap();
+
+          match field {
+            'n' => (Some(person.name.clone()), 2),
+            'e' => (Some(person.email.clone()), 2),
+            'd' => (Some(self.dates.format(&person.date)), 2),
+            _ => (None, 0),
+          }
+        },
+        _ => (None, 0),
+      };
+
+      match expansion {
+        Some(s) => {
+          out.push_str(&s);
+          rest = &rest[len..];
+        },
+        None => out.push('%'),
+      }
+    }
+
+    out.push_str(rest);
+    out
+  }
+
+  // like " (HEAD -> main, tag: v1)", or nothing if no refs point here
+  fn decoration(&self, sha: &Sha) -> String {
+    match self.decorations.get(sha) {
+      Some(names) => format!(" ({})", names.join(", ")),
+      None => "".to_string(),
+    }
+  }
+}
+
+// The subject is the first paragraph of the message, joined onto one line.
+fn subject(commit: &Commit) -> String {
+  commit
+    .message
+    .lines()
+    .skip_while(|l| l.trim().is_empty())
+    .take_while(|l| !l.trim().is_empty())
+    .map(str::trim)
+    .collect::<Vec<_>>()
+    .join(" ")
+}
+
+// The body is everything after the subject.
+fn body(commit: &Commit) -> String {
+  let lines = commit
+    .message
+    .lines()
+    .skip_while(|l| l.trim().is_empty())
+    .skip_while(|l| !l.trim().is_empty())
+    .skip_while(|l| l.trim().is_empty())
+    .collect::<Vec<_>>();
+
+  if lines.is_empty() {
+    return "".to_string();
+  }
+
+  format!("{}\n", lines.join("\n").trim_end())
+}
+
+// Names for every commit that refs point at, in the order git prints them:
+// HEAD first, then everything else in reverse order by name.
+fn load_decorations(repo: &Repository) -> Result<HashMap<Sha, Vec<String>>> {
+  let mut ret: HashMap<Sha, Vec<String>> = HashMap::new();
+  let grefs = repo.grefs();
+
+  for (name, sha) in grefs.all_refs()?.into_iter().rev() {
+    let short = if let Some(tag) = name.strip_prefix("refs/tags/") {
+      format!("tag: {}", tag)
+    } else if let Some(branch) = name.strip_prefix("refs/heads/") {
+      branch.to_string()
+    } else if let Some(remote) = name.strip_prefix("refs/remotes/") {
+      remote.to_string()
+    } else {
+      name.clone()
+    };
+
+    // annotated tags decorate the commit they point to
+    let sha = repo.peel_tag(&sha).unwrap_or(sha);
+    ret.entry(sha).or_default().push(short);
+  }
+
+  if let Ok(head) = grefs.resolve("HEAD") {
+    let names = ret.entry(head).or_default();
+    let branch = grefs.current_branch()?;
+
+    let label = match branch.strip_prefix("refs/heads/") {
+      Some(short) => {
+        names.retain(|n| n != short);
+        format!("HEAD -> {}", short)
+      },
+      None => "HEAD".to_string(),
+    };
+
+    names.insert(0, label);
+  }
+
+  Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::relative_date;
+
+  #[test]
+  fn relative_dates() {
+    let now = 1_600_000_000;
+    let ago = |secs: i64| relative_date(now - secs, now);
+
+    assert_eq!(ago(1), "1 second ago");
+    assert_eq!(ago(89), "89 seconds ago");
+    assert_eq!(ago(90), "2 minutes ago");
+    assert_eq!(ago(60 * 60), "60 minutes ago");
+    assert_eq!(ago(3 * 60 * 60), "3 hours ago");
+    assert_eq!(ago(3 * 86400), "3 days ago");
+    assert_eq!(ago(20 * 86400), "3 weeks ago");
+    assert_eq!(ago(100 * 86400), "3 months ago");
+    assert_eq!(ago(365 * 86400), "1 year ago");
+    assert_eq!(ago(500 * 86400), "1 year, 4 months ago");
+    assert_eq!(ago(3000 * 86400), "8 years ago");
+    assert_eq!(relative_date(now + 10, now), "in the future");
+  }
+}
@@ -1,19 +1,33 @@
+mod checkout;
+mod commit_graph;
+mod fsck;
+mod gc;
 mod grefs;
+mod merge;
+mod reset;
+mod rev_list;
 mod status;
+pub use checkout::{diff_entries, TreeChange};
 pub use grefs::Grefs;
+pub use rev_list::{RevList, SortOrder};
 pub use status::{ChangeType, Status};
 
 use flate2::{write::ZlibEncoder, Compression};
 use log::{debug, trace};
 use std::cell::{Ref, RefCell, RefMut};
-use std::collections::{BTreeMap, BTreeSet, HashSet};
-use std::ffi::OsString;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::{OsStr, OsString};
 use std::fs::{DirBuilder, File};
 use std::io::prelude::*;
 use std::path::{Path, PathBuf};
 
+use crate::attributes::WorkspaceAttributes;
+use crate::commit_graph::{CommitGraph, CommitNode};
+use crate::config::{self, Config};
+use crate::ignore::{self, IgnoreList, Pattern};
 use crate::index::Index;
-use crate::object::{Blob, Commit, Object, Person, Tree};
+use crate::object::{Blob, Commit, Mode, Object, Person, Tree};
+use crate::pack::{self, Pack};
 use crate::prelude::*;
 
 const GIT_DIR_NAME: &str = ".pidgit";
@@ -34,44 +48,49 @@ pub struct Repository {
   git_dir:   PathBuf,
   index:     RefCell<Index>,
   grefs:     RefCell<Grefs>,
+  packs:     RefCell<Vec<Pack>>,
+  graph:     RefCell<Option<CommitGraph>>,
 }
 
 #[derive(Debug)]
 pub struct Workspace {
-  path:     PathBuf,
-  ignore:   HashSet<OsString>,
-  ftignore: HashSet<OsString>,
+  path:    PathBuf,
+  git_dir: PathBuf,
 }
 
-// TODO for later
-fn default_ignore() -> HashSet<OsString> {
-  let mut ignore: HashSet<OsString> = HashSet::new();
-  ignore.insert(".git".into());
-  ignore.insert(".pidgit".into());
-  ignore.insert("target".into());
-  ignore.insert(".DS_Store".into());
-  ignore
-}
+// Never worth looking inside, whatever the ignore files say.
+const ALWAYS_IGNORED: &[&str] = &[".git", ".pidgit"];
+
+// A broken commit-graph isn't fatal: we can always read the commits instead.
+fn load_commit_graph(git_dir: &Path) -> Option<CommitGraph> {
+  let path = git_dir.join("objects/info/commit-graph");
+  if !path.is_file() {
+    return None;
+  }
 
-fn default_ftignore() -> HashSet<OsString> {
-  let mut ftignore: HashSet<OsString> = HashSet::new();
-  ftignore.insert("swp".into());
-  ftignore.insert("swo".into());
-  ftignore
+  match CommitGraph::load(&path) {
+    Ok(graph) => Some(graph),
+    Err(err) => {
+      debug!("ignoring commit-graph: {}", err);
+      None
+    },
+  }
 }
 
 impl Repository {
   // these paths must be canonicalized
   fn new(work_dir: &Path, git_dir: &Path) -> Result<Self> {
     let workspace = Workspace {
-      path:     work_dir.to_path_buf(),
-      ignore:   default_ignore(),
-      ftignore: default_ftignore(),
+      path:    work_dir.to_path_buf(),
+      git_dir: git_dir.to_path_buf(),
     };
 
     let mut index = Index::new(git_dir.join("index"));
     index.load()?;
 
+    let packs = pack::load_packs(&git_dir.join("objects"))?;
+    let graph = load_commit_graph(git_dir);
+
     debug!("loading git repo at {:?}", git_dir);
 
     Ok(Repository {
@@ -79,6 +98,8 @@ impl Repository {
       git_dir: git_dir.to_path_buf(),
       index: RefCell::new(index),
       grefs: RefCell::new(Grefs::new(git_dir.to_path_buf())),
+      packs: RefCell::new(packs),
+      graph: RefCell::new(graph),
     })
   }
 
@@ -144,11 +165,27 @@ impl Repository {
     &self.git_dir
   }
 
+  // Read fresh every time, so we always see what's on disk now.
+  pub fn config(&self) -> Result<Config> {
+    Config::load(&config::files(Some(&self.git_dir)))
+  }
+
+  // Whoever is running this command, as a committer: for commits, tags and
+  // reflogs.
+  pub fn whoami(&self) -> Result<Person> {
+    Ok(Person::committer(&self.config()?))
+  }
+
+  // Whoever is running this command, as the author of a new commit.
+  pub fn author(&self) -> Result<Person> {
+    Ok(Person::author(&self.config()?))
+  }
+
   pub fn workspace(&self) -> &Workspace {
     &self.workspace
   }
 
-  pub fn grefs(&self) -> Ref<Grefs> {
+  pub fn grefs(&self) -> Ref<'_, Grefs> {
     self.grefs.borrow()
   }
 
@@ -171,8 +208,85 @@ impl Repository {
       .map_err(|e| e.into())
   }
 
+  // Loose objects win, if we have them; otherwise we look in the packs.
   pub fn object_for_sha(&self, sha: &Sha) -> Result<Object> {
-    Object::from_git_db(&self.path_for_sha(sha))
+    let path = self.path_for_sha(sha);
+    if path.is_file() {
+      return Object::from_git_db(&path);
+    }
+
+    for pack in self.packs.borrow().iter() {
+      if pack.contains(sha) {
+        return pack.read_object(sha);
+      }
+    }
+
+    Err(PidgitError::ObjectNotFound(sha.hexdigest()))
+  }
+
+  // like object_for_sha, but returns the type and content without parsing
+  pub fn raw_object_for_sha(&self, sha: &Sha) -> Result<(String, Vec<u8>)> {
+    let path = self.path_for_sha(sha);
+    if path.is_file() {
+      return Object::read_loose(&path);
+    }
+
+    for pack in self.packs.borrow().iter() {
+      if pack.contains(sha) {
+        let (kind, content) = pack.read_raw(sha)?;
+        return Ok((kind.type_str().to_string(), content));
+      }
+    }
+
+    Err(PidgitError::ObjectNotFound(sha.hexdigest()))
+  }
+
+  // call this if something has written a new pack behind our back
+  pub fn reload_packs(&self) -> Result<()> {
+    let packs = pack::load_packs(&self.git_dir.join("objects"))?;
+    self.packs.replace(packs);
+    Ok(())
+  }
+
+  // call this if we've written a new commit-graph
+  pub fn reload_commit_graph(&self) {
+    self.graph.replace(load_commit_graph(&self.git_dir));
+  }
+
+  pub fn commit_graph(&self) -> Ref<'_, Option<CommitGraph>> {
+    self.graph.borrow()
+  }
+
+  // The parents, tree, and date of a commit, from the commit-graph if we can,
+  // which is much cheaper than reading the commit itself.
+  pub fn commit_node(&self, sha: &Sha) -> Result<CommitNode> {
+    if let Some(graph) = self.graph.borrow().as_ref() {
+      if let Some(node) = graph.lookup(sha)? {
+        return Ok(node);
+      }
+    }
+
+    let commit = self.object_for_sha(sha)?.as_commit()?;
+    Ok(CommitNode::from(&commit))
+  }
+
+  pub fn has_object(&self, sha: &Sha) -> bool {
+    self.path_for_sha(sha).is_file()
+      || self.packs.borrow().iter().any(|p| p.contains(sha))
+  }
+
+  // If sha is an annotated tag, follow it (and any tags it points to) to
+  // whatever it ultimately tags. Returns None for anything that isn't a tag.
+  pub fn peel_tag(&self, sha: &Sha) -> Option<Sha> {
+    let mut peeled = None;
+    let mut current = sha.clone();
+
+    while let Ok(Object::Tag(tag)) = self.object_for_sha(&current) {
+      current = tag.object;
+      peeled = Some(current.clone());
+    }
+
+    peeled
   }
 
   pub fn try_object_for_sha(&self, sha: &Sha) -> Option<Object> {
@@ -186,11 +300,12 @@ impl Repository {
   }
 
   pub fn write_object(&self, obj: &dyn GitObject) -> Result<()> {
-    let path = self.path_for_sha(&obj.sha());
+    let sha = obj.sha();
+    let path = self.path_for_sha(&sha);
 
     // I am ignoring, here, the possibility that this path exists and might
     // somehow conflict??
-    if path.is_file() {
+    if self.has_object(&sha) {
       return Ok(());
     }
 
@@ -198,7 +313,7 @@ impl Repository {
     std::fs::create_dir_all(path.parent().unwrap())?;
 
     let file = File::create(&path)
-      .expect(&format!("error creating path {}", path.display()));
+      .unwrap_or_else(|_| panic!("error creating path {}", path.display()));
 
     let mut e = ZlibEncoder::new(file, Compression::default());
 
@@ -211,8 +326,33 @@ impl Repository {
 
   pub fn resolve_object(&self, name: &str) -> Result<Object> {
     trace!("resolving {}", name);
-    util::resolve_revision(name, self)
-      .ok_or(PidgitError::ObjectNotFound(name.into()))
+    util::resolve_revision(name, self)?
+      .ok_or_else(|| PidgitError::ObjectNotFound(name.into()))
+  }
+
+  // Anything we can check out must eventually be a commit, though it might be
+  // an annotated tag pointing to one.
+  pub fn resolve_commit(&self, name: &str) -> Result<Commit> {
+    let sha = self.resolve_object(name)?.sha();
+    let sha = self.peel_tag(&sha).unwrap_or(sha);
+
+    self.object_for_sha(&sha)?.as_commit()
+  }
+
+  // The sha of the tree this names: trees are themselves, and commits (and
+  // tags that point at them) have one.
+  pub fn resolve_tree(&self, name: &str) -> Result<Sha> {
+    let mut obj = self.resolve_object(name)?;
+
+    if let Some(sha) = self.peel_tag(&obj.sha()) {
+      obj = self.object_for_sha(&sha)?;
+    }
+
+    match obj {
+      Object::Tree(tree) => Ok(tree.sha()),
+      Object::Commit(commit) => Ok(commit.tree().clone()),
+      _ => Err(PidgitError::InvalidObject("tree")),
+    }
   }
 
   pub fn resolve_ref(&self, refstr: &str) -> Result<Object> {
@@ -233,43 +373,39 @@ impl Repository {
       return self.object_for_sha(&sha.into());
     }
 
-    let not_found = Err(PidgitError::ObjectNotFound(sha.to_string()));
-
-    // we need to walk the objects dir
+    // we need to walk the objects dir, and also check the packs
     let (prefix, rest) = sha.split_at(2);
     let base = self.git_dir.join(format!("objects/{}", &prefix));
 
-    if !base.is_dir() {
-      return not_found;
+    let mut found = BTreeSet::new();
+
+    if base.is_dir() {
+      for e in std::fs::read_dir(base)?.filter_map(std::result::Result::ok) {
+        let name = e.file_name().to_string_lossy().to_string();
+        if name.starts_with(rest) {
+          found.insert(format!("{}{}", prefix, name));
+        }
+      }
+    }
+
+    for pack in self.packs.borrow().iter() {
+      found.extend(pack.shas_with_prefix(sha).iter().map(|s| s.hexdigest()));
     }
 
-    let paths = std::fs::read_dir(base)?
-      .filter(|e| e.is_ok())
-      .map(|e| e.unwrap().path())
-      .filter(|path| {
-        path
-          .file_name()
-          .unwrap()
-          .to_str()
-          .unwrap()
-          .starts_with(&rest)
-      })
-      .collect::<Vec<_>>();
-
-    trace!("sha prefix match found {} paths", paths.len());
-
-    match paths.len() {
-      0 => not_found,
-      1 => Object::from_git_db(&paths[0]),
+    trace!("sha prefix match found {} objects", found.len());
+
+    match found.len() {
+      0 => Err(PidgitError::ObjectNotFound(sha.to_string())),
+      1 => self.object_for_sha(&found.into_iter().next().unwrap().into()),
       _ => Err(PidgitError::ObjectNotFound(format!("{} is ambiguous", sha))),
     }
   }
 
-  pub fn index(&self) -> Ref<Index> {
+  pub fn index(&self) -> Ref<'_, Index> {
     self.index.borrow()
   }
 
-  pub fn index_mut(&self) -> RefMut<Index> {
+  pub fn index_mut(&self) -> RefMut<'_, Index> {
     self.index.borrow_mut()
   }
 
@@ -279,15 +415,20 @@ impl Repository {
   }
 
   pub fn status(&self) -> Result<Status> {
-    Status::generate(&self)
+    Status::generate(self)
   }
 
   pub fn write_tree(&self, tree: &Tree) -> Result<()> {
     tree.traverse(&|t| self.write_object(t))
   }
 
-  pub fn head(&self) -> Option<Commit> {
-    self.resolve_ref("HEAD").and_then(|c| c.as_commit()).ok()
+  // None if we're on an unborn branch; an error if HEAD is broken somehow
+  pub fn head(&self) -> Result<Option<Commit>> {
+    match self.resolve_ref("HEAD") {
+      Ok(obj) => obj.as_commit().map(Some),
+      Err(PidgitError::RefNotFound(_)) => Ok(None),
+      Err(err) => Err(err),
+    }
   }
 
   pub fn commit(
@@ -296,20 +437,37 @@ impl Repository {
     author: Person,
     committer: Person,
   ) -> Result<Commit> {
+    if self.index().has_unmerged() {
+      return Err(PidgitError::Generic(
+        "Committing is not possible because you have unmerged files.".into(),
+      ));
+    }
+
     let head = self.resolve_object("HEAD").ok();
+    let merge_head = self.merge_head();
 
-    let parents = if let Some(head) = head {
+    let mut parents = if let Some(head) = head {
       vec![head.into_inner().sha()]
     } else {
       vec![]
     };
 
+    parents.extend(merge_head.clone());
+
     let mut msg = message.to_string();
 
     if !msg.ends_with("\n") {
-      msg.push_str("\n");
+      msg.push('\n');
     }
 
+    let reflog_msg = if parents.is_empty() {
+      "commit (initial)"
+    } else if merge_head.is_some() {
+      "commit (merge)"
+    } else {
+      "commit"
+    };
+
     let tree = Tree::from(self.index());
 
     let commit = Commit {
@@ -324,7 +482,15 @@ impl Repository {
     // we write the tree, then write the commit.
     self.write_tree(&tree)?;
     self.write_object(&commit)?;
-    self.grefs().update_head(&commit.sha())?;
+    self.grefs().update_head(
+      &commit.sha(),
+      &commit.committer,
+      &format!("{}: {}", reflog_msg, commit.title()),
+    )?;
+
+    if merge_head.is_some() {
+      self.clear_merge_state()?;
+    }
 
     Ok(commit)
   }
@@ -344,20 +510,22 @@ impl Workspace {
   }
 
   pub fn list_files(&self) -> Result<BTreeSet<OsString>> {
-    self.list_files_from_base(&self.path)
+    self.list_files_from_base(&self.path, &mut self.ignore_list()?)
   }
 
   pub fn list_files_from_base(
     &self,
     base: &PathBuf,
+    ignore: &mut IgnoreList,
   ) -> Result<BTreeSet<OsString>> {
     let mut entries = BTreeSet::new();
 
-    let list = self.list_dir(base)?;
+    let list = self.list_dir(base, ignore)?;
 
     for (pathstr, stat) in list {
       if stat.is_dir() {
-        entries.extend(self.list_files_from_base(&self.path.join(pathstr))?);
+        let dir = self.path.join(pathstr);
+        entries.extend(self.list_files_from_base(&dir, ignore)?);
       } else {
         entries.insert(pathstr);
       }
@@ -366,15 +534,32 @@ impl Workspace {
     Ok(entries)
   }
 
+  // Everything in the directory at raw_base (or just raw_base, if it's a
+  // file) that isn't ignored, either itself or by being in an ignored
+  // directory.
   pub fn list_dir(
     &self,
     raw_base: &PathBuf,
+    ignore: &mut IgnoreList,
   ) -> Result<BTreeMap<OsString, std::fs::Metadata>> {
-    let base = if raw_base.is_relative() {
-      self.canonicalize(&raw_base)
-    } else {
-      raw_base.clone()
-    };
+    self.enter_ignore_dir(ignore, raw_base)?;
+
+    let mut ret = self.list_dir_all(raw_base)?;
+    ret.retain(|path, stat| {
+      ignore
+        .matching_with_parents(Path::new(path), stat.is_dir())
+        .is_none_or(|p| p.negated)
+    });
+
+    Ok(ret)
+  }
+
+  // Like list_dir, but including ignored things (though never the git dir).
+  pub fn list_dir_all(
+    &self,
+    raw_base: &PathBuf,
+  ) -> Result<BTreeMap<OsString, std::fs::Metadata>> {
+    let base = self.absolute(raw_base);
 
     if !base.exists() {
       return Err(PidgitError::PathspecNotFound(
@@ -384,46 +569,184 @@ impl Workspace {
 
     let mut ret = BTreeMap::new();
 
-    let relativize = |p: &PathBuf| {
-      p.canonicalize()
-        .expect("bad canonicalize")
-        .strip_prefix(&self.path)
-        .unwrap()
-        .to_path_buf()
-    };
-
     if base.is_file() {
-      ret.insert(relativize(&base).into(), base.metadata()?);
+      ret.insert(self.relativize(&base).into(), base.metadata()?);
       return Ok(ret);
     }
 
     for e in std::fs::read_dir(base)?.filter_map(std::result::Result::ok) {
       let path = e.path();
 
-      if self.ignore.contains(path.file_name().unwrap()) {
+      if ALWAYS_IGNORED
+        .iter()
+        .any(|name| path.file_name() == Some(name.as_ref()))
+      {
         continue;
       }
 
-      if let Some(ext) = path.extension() {
-        if self.ftignore.contains(ext) {
-          continue;
-        }
-      }
-
-      ret.insert(relativize(&path).into(), path.metadata()?);
+      ret.insert(self.relativize(&path).into(), path.metadata()?);
     }
 
     Ok(ret)
   }
 
+  fn absolute(&self, raw_base: &PathBuf) -> PathBuf {
+    if raw_base.is_relative() {
+      self.canonicalize(&raw_base)
+    } else {
+      raw_base.clone()
+    }
+  }
+
+  fn relativize(&self, path: &Path) -> PathBuf {
+    path
+      .canonicalize()
+      .expect("bad canonicalize")
+      .strip_prefix(&self.path)
+      .unwrap()
+      .to_path_buf()
+  }
+
+  // Get ignore ready for the things list_dir(raw_base) would return.
+  pub fn enter_ignore_dir(
+    &self,
+    ignore: &mut IgnoreList,
+    raw_base: &PathBuf,
+  ) -> Result<()> {
+    let base = self.absolute(raw_base);
+
+    let dir = match base.is_dir() {
+      true => self.relativize(&base),
+      false => match base.parent() {
+        Some(parent) if parent.exists() => self.relativize(parent),
+        _ => PathBuf::new(),
+      },
+    };
+
+    ignore.enter_dir(&self.path, &dir)
+  }
+
+  // Everything that decides what's ignored, lowest precedence first:
+  // core.excludesFile, info/exclude, then the ignore files in every directory
+  // from the top down to wherever we're looking, which the list reads as it
+  // goes. Make one of these for each walk over the work tree.
+  pub fn ignore_list(&self) -> Result<IgnoreList> {
+    let mut list = IgnoreList::new();
+    let config = self.config()?;
+
+    if let Some(path) = ignore::excludes_file(&config) {
+      list.add_file(&path, &path, Path::new(""))?;
+    }
+
+    let exclude = self.git_dir.join("info/exclude");
+    let source = exclude.strip_prefix(&self.path).unwrap_or(&exclude);
+    list.add_file(&exclude, source, Path::new(""))?;
+
+    Ok(list)
+  }
+
+  // Everything that decides how content is converted on its way in and out of
+  // the work tree. This reads the config, so load it once per command.
+  pub fn attributes(&self) -> Result<WorkspaceAttributes> {
+    WorkspaceAttributes::load(&self.path, &self.git_dir, self.config()?)
+  }
+
+  fn config(&self) -> Result<Config> {
+    Config::load(&config::files(Some(&self.git_dir)))
+  }
+
+  // The pattern that decides whether path (relative to the top) is ignored,
+  // if any, taking into account the directories above it.
+  pub fn check_ignore(
+    &self,
+    ignore: &mut IgnoreList,
+    path: &Path,
+  ) -> Result<Option<Pattern>> {
+    let is_dir = self.canonicalize(&path).is_dir();
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    ignore.enter_dir(&self.path, dir)?;
+    Ok(ignore.matching_with_parents(path, is_dir).cloned())
+  }
+
   pub fn stat(&self, relpath: &PathBuf) -> Result<std::fs::Metadata> {
     Ok(self.canonicalize(relpath).metadata()?)
   }
 
-  pub fn read_blob<P>(&self, relpath: &P) -> Result<Blob>
+  // Write content to the file at relpath, creating any directories it needs,
+  // and return its new stat.
+  pub fn write_file<P>(
+    &self,
+    relpath: &P,
+    content: &[u8],
+    mode: &Mode,
+    attrs: &WorkspaceAttributes,
+  ) -> Result<std::fs::Metadata>
+  where
+    P: AsRef<Path>,
+  {
+    use std::os::unix::fs::PermissionsExt;
+
+    let content =
+      attrs.convert_to_worktree(relpath.as_ref(), content.to_vec())?;
+
+    // a new .gitattributes changes what applies to everything written after it
+    if relpath.as_ref().file_name() == Some(OsStr::new(".gitattributes")) {
+      attrs.forget(relpath.as_ref().parent().unwrap_or_else(|| Path::new("")));
+    }
+
+    let path = self.canonicalize(relpath);
+    std::fs::create_dir_all(path.parent().unwrap())?;
+
+    // an empty directory might be left over where this file wants to be
+    if path.is_dir() {
+      std::fs::remove_dir(&path)?;
+    }
+
+    std::fs::write(&path, &content)?;
+
+    let perms = match mode {
+      Mode::Executable => 0o755,
+      _ => 0o644,
+    };
+
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(perms))?;
+
+    Ok(path.metadata()?)
+  }
+
+  // Remove the file at relpath (if it's there), and any directories that
+  // leaves empty.
+  pub fn remove_file<P>(&self, relpath: &P) -> Result<()>
+  where
+    P: AsRef<Path>,
+  {
+    let path = self.canonicalize(relpath);
+
+    if path.is_file() {
+      std::fs::remove_file(&path)?;
+    }
+
+    for dir in path.ancestors().skip(1) {
+      if dir == self.path || std::fs::remove_dir(dir).is_err() {
+        break;
+      }
+    }
+
+    Ok(())
+  }
+
+  // The blob we'd store for the file at relpath, which might not be exactly
+  // what's on disk, depending on its attributes.
+  pub fn read_blob<P>(
+    &self,
+    relpath: &P,
+    attrs: &WorkspaceAttributes,
+  ) -> Result<Blob>
   where
     P: AsRef<Path>,
   {
-    Blob::from_path(&self.canonicalize(relpath))
+    let content = std::fs::read(self.canonicalize(relpath))?;
+    let content = attrs.convert_to_git(relpath.as_ref(), content)?;
+    Ok(Blob::from_content(content))
   }
 }
@@ -0,0 +1,308 @@
+use log::debug;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use crate::index::IndexEntry;
+use crate::object::{Commit, PathEntry, TreeItem};
+use crate::prelude::*;
+
+// For every path that differs between two trees, what it was and what it will
+// be (None meaning it doesn't exist on that side).
+pub type TreeDiff = BTreeMap<OsString, (Option<PathEntry>, Option<PathEntry>)>;
+
+// The same, for one path, when the order matters.
+#[derive(Debug)]
+pub struct TreeChange {
+  pub path: PathBuf,
+  pub old:  Option<PathEntry>,
+  pub new:  Option<PathEntry>,
+}
+
+// Pair up two sets of entries (from trees, the index, or the workspace) by
+// their keys, keeping the ones that aren't the same on both sides.
+pub fn diff_entries<K: Ord>(
+  old: BTreeMap<K, PathEntry>,
+  new: BTreeMap<K, PathEntry>,
+) -> Vec<TreeChange> {
+  let mut old = old.into_iter().peekable();
+  let mut new = new.into_iter().peekable();
+  let mut changes = vec![];
+
+  loop {
+    let order = match (old.peek(), new.peek()) {
+      (Some((a, _)), Some((b, _))) => a.cmp(b),
+      (Some(_), None) => Ordering::Less,
+      (None, Some(_)) => Ordering::Greater,
+      (None, None) => break,
+    };
+
+    let (a, b) = match order {
+      Ordering::Less => (old.next().map(|e| e.1), None),
+      Ordering::Greater => (None, new.next().map(|e| e.1)),
+      Ordering::Equal => (old.next().map(|e| e.1), new.next().map(|e| e.1)),
+    };
+
+    if let (Some(a), Some(b)) = (&a, &b) {
+      if a.sha == b.sha && a.mode == b.mode {
+        continue;
+      }
+    }
+
+    let path = a.as_ref().or(b.as_ref()).unwrap().path.clone();
+    changes.push(TreeChange {
+      path,
+      old: a,
+      new: b,
+    });
+  }
+
+  changes
+}
+
+impl Repository {
+  // Every blob in the tree with this sha, keyed by its full path. The paths in
+  // the entries are full paths, too.
+  pub fn flat_tree(&self, sha: &Sha) -> Result<BTreeMap<OsString, PathEntry>> {
+    let mut ret = BTreeMap::new();
+    self.flatten_tree_into(sha, Path::new(""), &mut ret)?;
+    Ok(ret)
+  }
+
+  fn flatten_tree_into(
+    &self,
+    sha: &Sha,
+    prefix: &Path,
+    into: &mut BTreeMap<OsString, PathEntry>,
+  ) -> Result<()> {
+    let tree = self.object_for_sha(sha)?.as_tree()?;
+
+    for (path, item) in tree.entries() {
+      if let TreeItem::Entry(e) = item {
+        let fullpath = prefix.join(path);
+
+        if e.is_tree() {
+          self.flatten_tree_into(e.sha(), &fullpath, into)?;
+        } else {
+          let entry = PathEntry {
+            path: fullpath.clone(),
+            ..e.clone()
+          };
+
+          into.insert(fullpath.into(), entry);
+        }
+      }
+    }
+
+    Ok(())
+  }
+
+  // Compare two trees; either can be None, which is treated as empty.
+  pub fn diff_trees(
+    &self,
+    old: Option<&Sha>,
+    new: Option<&Sha>,
+  ) -> Result<TreeDiff> {
+    let diff = self
+      .tree_changes(old, new, true)?
+      .into_iter()
+      .map(|c| (c.path.into_os_string(), (c.old, c.new)))
+      .collect();
+
+    Ok(diff)
+  }
+
+  // Every path that differs between two trees (either of which can be None),
+  // in the order git lists them. Unless recursive, a subtree that changed is a
+  // change of its own, rather than whatever changed inside it.
+  pub fn tree_changes(
+    &self,
+    old: Option<&Sha>,
+    new: Option<&Sha>,
+    recursive: bool,
+  ) -> Result<Vec<TreeChange>> {
+    let mut changes = vec![];
+    self.tree_changes_into(old, new, Path::new(""), recursive, &mut changes)?;
+    Ok(changes)
+  }
+
+  fn tree_changes_into(
+    &self,
+    old: Option<&Sha>,
+    new: Option<&Sha>,
+    prefix: &Path,
+    recursive: bool,
+    into: &mut Vec<TreeChange>,
+  ) -> Result<()> {
+    let old = self.tree_level(old, prefix)?;
+    let new = self.tree_level(new, prefix)?;
+
+    for change in diff_entries(old, new) {
+      let entry = change.old.as_ref().or(change.new.as_ref()).unwrap();
+
+      // subtrees only ever pair up with subtrees, thanks to their keys
+      if recursive && entry.is_tree() {
+        let subtree = |e: &Option<PathEntry>| e.as_ref().map(|e| e.sha.clone());
+
+        self.tree_changes_into(
+          subtree(&change.old).as_ref(),
+          subtree(&change.new).as_ref(),
+          &change.path,
+          recursive,
+          into,
+        )?;
+      } else {
+        into.push(change);
+      }
+    }
+
+    Ok(())
+  }
+
+  // The entries directly inside the tree with this sha (or nothing, if it's
+  // None), with full paths, and keyed so that they sort the way git sorts
+  // them: as if trees had a trailing slash.
+  fn tree_level(
+    &self,
+    sha: Option<&Sha>,
+    prefix: &Path,
+  ) -> Result<BTreeMap<Vec<u8>, PathEntry>> {
+    let mut ret = BTreeMap::new();
+
+    let sha = match sha {
+      Some(sha) => sha,
+      None => return Ok(ret),
+    };
+
+    for (_, item) in self.object_for_sha(sha)?.as_tree()?.entries() {
+      if let TreeItem::Entry(e) = item {
+        let mut key = e.path.as_os_str().as_bytes().to_vec();
+        if e.is_tree() {
+          key.push(b'/');
+        }
+
+        let entry = PathEntry {
+          path: prefix.join(&e.path),
+          ..e.clone()
+        };
+
+        ret.insert(key, entry);
+      }
+    }
+
+    Ok(ret)
+  }
+
+  // Move the workspace and the index from HEAD to the tree of target, leaving
+  // HEAD itself alone. Paths that don't change between the two trees are left
+  // untouched (so local changes to them carry over), but if we'd have to
+  // overwrite local changes to any other path, we refuse to do anything.
+  pub fn migrate_to(&self, target: &Commit) -> Result<()> {
+    let current = self.head()?.map(|c| c.tree().clone());
+    let diff = self.diff_trees(current.as_ref(), Some(target.tree()))?;
+
+    self.check_migration_conflicts(&diff, "checkout")?;
+
+    let workspace = self.workspace();
+    let attrs = workspace.attributes()?;
+    let mut index = self.index_mut();
+
+    // deletions first, so that a file can replace a directory (or vice versa)
+    for (path, _) in diff.iter().filter(|(_, (_, new))| new.is_none()) {
+      debug!("checkout: removing {:?}", path);
+      workspace.remove_file(path)?;
+      index.remove(path);
+    }
+
+    for (path, (_, new)) in diff.iter() {
+      let entry = match new {
+        Some(entry) => entry,
+        None => continue,
+      };
+
+      debug!("checkout: writing {:?}", path);
+
+      let blob = self.object_for_sha(entry.sha())?.as_blob()?;
+      let stat =
+        workspace.write_file(path, &blob.raw_content(), entry.mode(), &attrs)?;
+
+      index.add(IndexEntry::new_from_data(
+        path.clone(),
+        entry.sha().clone(),
+        stat,
+      ));
+    }
+
+    index.write()
+  }
+
+  // Make sure that applying diff won't lose anything in the workspace. op is
+  // what we're doing, for the error message.
+  pub(super) fn check_migration_conflicts(
+    &self,
+    diff: &TreeDiff,
+    op: &str,
+  ) -> Result<()> {
+    let status = self.status()?;
+    let index = self.index();
+
+    let mut changed = vec![];
+    let mut untracked = vec![];
+
+    for (path, (_, new)) in diff {
+      if status.index_diff().contains_key(path)
+        || status.workspace_diff().contains_key(path)
+      {
+        changed.push(path);
+      } else if new.is_some()
+        && !index.is_tracked_file(path)
+        && self.workspace().canonicalize(path).exists()
+      {
+        untracked.push(path);
+      }
+    }
+
+    let format_paths = |paths: &[&OsString]| {
+      paths
+        .iter()
+        .map(|p| format!("\t{}\n", Path::new(p).display()))
+        .collect::<String>()
+    };
+
+    let hint = match op {
+      "checkout" => "switch branches",
+      other => other,
+    };
+
+    let mut msg = String::new();
+
+    if !changed.is_empty() {
+      msg.push_str(&format!(
+        "Your local changes to the following files would be overwritten by \
+         {}:\n{}Please commit your changes before you {}.\n",
+        op,
+        format_paths(&changed),
+        hint
+      ));
+    }
+
+    if !untracked.is_empty() {
+      msg.push_str(&format!(
+        "The following untracked working tree files would be overwritten by \
+         {}:\n{}Please move or remove them before you {}.\n",
+        op,
+        format_paths(&untracked),
+        hint
+      ));
+    }
+
+    if msg.is_empty() {
+      return Ok(());
+    }
+
+    msg.push_str("Aborting");
+    Err(PidgitError::Generic(msg))
+  }
+}
@@ -0,0 +1,103 @@
+use log::debug;
+use std::collections::HashMap;
+
+use crate::commit_graph::{self, CommitNode};
+use crate::object::Object;
+use crate::prelude::*;
+
+// Writing and checking objects/info/commit-graph. Reading it is in repo.rs,
+// next to commit_node().
+impl Repository {
+  // Write a commit-graph of every commit reachable from our refs and HEAD,
+  // replacing any we had before. Returns how many commits are in it.
+  pub fn write_commit_graph(&self) -> Result<usize> {
+    let mut queue = vec![];
+
+    let refs = self.grefs().all_refs()?.into_iter().map(|(_, sha)| sha);
+    for sha in refs.chain(self.grefs().resolve("HEAD").ok()) {
+      let sha = self.peel_tag(&sha).unwrap_or(sha);
+
+      if let Ok(Object::Commit(_)) = self.object_for_sha(&sha) {
+        queue.push(sha);
+      }
+    }
+
+    // this deliberately doesn't use commit_node(), so that we don't copy
+    // anything wrong from the old graph
+    let mut nodes = HashMap::new();
+
+    while let Some(sha) = queue.pop() {
+      if nodes.contains_key(&sha) {
+        continue;
+      }
+
+      let commit = self.object_for_sha(&sha)?.as_commit()?;
+      queue.extend(commit.parent_shas.iter().cloned());
+      nodes.insert(sha, CommitNode::from(&commit));
+    }
+
+    let count = nodes.len();
+
+    self.create_dir("objects/info")?;
+    commit_graph::write_graph(
+      &self.git_dir.join("objects/info/commit-graph"),
+      nodes,
+    )?;
+    self.reload_commit_graph();
+
+    debug!("wrote commit-graph with {} commits", count);
+
+    Ok(count)
+  }
+
+  // Check the commit-graph against its checksum and against the commits in
+  // the object database, returning the first problem we find. Not having a
+  // commit-graph at all is fine.
+  pub fn verify_commit_graph(&self) -> Result<()> {
+    let path = self.git_dir.join("objects/info/commit-graph");
+    if !path.is_file() {
+      return Ok(());
+    }
+
+    let graph = commit_graph::CommitGraph::load(&path)?;
+    let bad = |msg: String| Err(PidgitError::CommitGraph(msg));
+
+    if !graph.checksum_matches() {
+      return bad("incorrect checksum".into());
+    }
+
+    if !graph.shas_in_order() {
+      return bad("commit ids are out of order".into());
+    }
+
+    for n in 0..graph.count() {
+      let sha = graph.sha_at(n);
+      let node = graph.node_at(n)?;
+      let want = CommitNode::from(&self.object_for_sha(&sha)?.as_commit()?);
+
+      if node.tree != want.tree {
+        return bad(format!("wrong tree for {}: {}", sha, node.tree));
+      }
+
+      if node.parents != want.parents {
+        return bad(format!("wrong parents for {}", sha));
+      }
+
+      if node.date != want.date {
+        return bad(format!("wrong date for {}: {}", sha, node.date));
+      }
+
+      let mut generation = 0;
+      for parent in &node.parents {
+        let parent = graph.lookup(parent)?;
+        generation = generation.max(parent.map_or(0, |p| p.generation));
+      }
+
+      if node.generation != (generation + 1).min(commit_graph::GENERATION_MAX) {
+        return bad(format!("wrong generation for {}: {}", sha, node.generation));
+      }
+    }
+
+    Ok(())
+  }
+}
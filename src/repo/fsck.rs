@@ -0,0 +1,368 @@
+use log::debug;
+use sha1::Sha1;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::PathBuf;
+
+use crate::object::{Mode, Object};
+use crate::prelude::*;
+
+const OBJECT_TYPES: &[&str] = &["blob", "tree", "commit", "tag"];
+
+// What fsck found: the problems (which make it fail), and the objects nothing
+// reachable refers to. Dangling objects are the unreachable ones that nothing
+// at all refers to, which is usually all you care about.
+#[derive(Debug, Default)]
+pub struct FsckReport {
+  pub errors:      Vec<String>,
+  pub unreachable: Vec<Unreachable>,
+}
+
+#[derive(Debug)]
+pub struct Unreachable {
+  pub sha:      Sha,
+  pub kind:     String,
+  pub dangling: bool,
+}
+
+// A link from one object to another, and the type the target should be.
+type Link = (Sha, &'static str);
+
+impl Repository {
+  // Check every object in the database, loose or packed: that its content
+  // matches its sha, that it's well-formed, and that everything it refers to
+  // exists. Then check that the refs point at real things, and find anything
+  // unreachable.
+  //
+  // Like git, we check the raw content ourselves rather than parsing the
+  // objects normally, so that a broken object is something to report, not
+  // something to fall over on.
+  pub fn fsck(&self) -> Result<FsckReport> {
+    let mut report = FsckReport::default();
+
+    let mut names: BTreeMap<String, Option<PathBuf>> = BTreeMap::new();
+    for (sha, path) in self.loose_objects()? {
+      names.insert(sha.hexdigest(), Some(path));
+    }
+
+    for pack in self.packs.borrow().iter() {
+      for sha in pack.shas() {
+        names.entry(sha.hexdigest()).or_insert(None);
+      }
+    }
+
+    let mut kinds: HashMap<Sha, String> = HashMap::new();
+    let mut links: Vec<(Sha, Vec<Link>)> = vec![];
+
+    for (hex, path) in names {
+      let sha = Sha::from(hex);
+
+      let raw = match &path {
+        Some(path) => Object::read_loose(path),
+        None => self.raw_object_for_sha(&sha),
+      };
+
+      let (kind, content) = match raw {
+        Ok(raw) => raw,
+        Err(err) => {
+          report
+            .errors
+            .push(format!("{}: object corrupt or missing: {}", sha, err));
+          continue;
+        },
+      };
+
+      let mut hasher = Sha1::new();
+      hasher.update(format!("{} {}\0", kind, content.len()).as_bytes());
+      hasher.update(&content);
+      let actual = Sha::from(hasher);
+
+      if actual != sha {
+        report.errors.push(format!(
+          "{}: hash mismatch (content hashes to {})",
+          sha, actual
+        ));
+        continue;
+      }
+
+      let checked = match kind.as_str() {
+        "blob" => Ok(vec![]),
+        "tree" => check_tree(&content),
+        "commit" => check_commit(&content),
+        "tag" => check_tag(&content),
+        _ => Err(format!("unknown object type {:?}", kind)),
+      };
+
+      match checked {
+        Ok(found) => links.push((sha.clone(), found)),
+        Err(err) => report
+          .errors
+          .push(format!("{}: invalid {}: {}", sha, kind, err)),
+      }
+
+      kinds.insert(sha, kind);
+    }
+
+    debug!("fsck: checked {} objects", kinds.len());
+
+    let mut referenced = HashSet::new();
+
+    for (sha, found) in &links {
+      for (target, want) in found {
+        referenced.insert(target.clone());
+
+        match kinds.get(target) {
+          None => report.errors.push(format!(
+            "broken link from {} {} to {} {}",
+            kinds[sha], sha, want, target
+          )),
+          Some(kind) if kind != want => report.errors.push(format!(
+            "{} {} points to {}, which is a {}, not a {}",
+            kinds[sha], sha, target, kind, want
+          )),
+          _ => {},
+        }
+      }
+    }
+
+    let roots = self.fsck_roots(&kinds, &mut report.errors)?;
+
+    // everything reachable from the roots, as far as we can tell
+    let links = links.into_iter().collect::<HashMap<_, _>>();
+    let mut reachable = HashSet::new();
+    let mut queue = roots;
+
+    while let Some(sha) = queue.pop() {
+      if !reachable.insert(sha.clone()) {
+        continue;
+      }
+
+      if let Some(found) = links.get(&sha) {
+        queue.extend(found.iter().map(|(target, _)| target.clone()));
+      }
+    }
+
+    for (sha, kind) in kinds {
+      if !reachable.contains(&sha) {
+        report.unreachable.push(Unreachable {
+          dangling: !referenced.contains(&sha),
+          sha,
+          kind,
+        });
+      }
+    }
+
+    report.unreachable.sort_by_key(|u| u.sha.hexdigest());
+
+    Ok(report)
+  }
+
+  // The places we start looking for reachable objects from: HEAD, the refs,
+  // their reflogs, and the index. Refs have to point at something real (and
+  // branches at commits).
+  fn fsck_roots(
+    &self,
+    kinds: &HashMap<Sha, String>,
+    errors: &mut Vec<String>,
+  ) -> Result<Vec<Sha>> {
+    let mut roots = vec![];
+
+    let mut refs = self.grefs().all_refs()?;
+
+    // an unborn branch is fine; HEAD pointing at garbage isn't
+    if let Ok(sha) = self.grefs().resolve("HEAD") {
+      refs.push(("HEAD".to_string(), sha));
+    }
+
+    for (name, sha) in &refs {
+      let must_be_commit = name == "HEAD" || name.starts_with("refs/heads/");
+
+      match kinds.get(sha) {
+        None => errors.push(format!("{}: invalid sha1 pointer {}", name, sha)),
+        Some(kind) if must_be_commit && kind != "commit" => {
+          errors.push(format!("{}: not a commit", name))
+        },
+        _ => roots.push(sha.clone()),
+      }
+    }
+
+    // reflog entries for things that have since been pruned aren't a problem
+    for (name, _) in &refs {
+      for entry in self.grefs().reflog(name)? {
+        let shas = entry.old.into_iter().chain(std::iter::once(entry.new));
+        roots.extend(shas.filter(|sha| kinds.contains_key(sha)));
+      }
+    }
+
+    for entry in self.index().entries() {
+      if entry.mode() == u32::from(&Mode::Gitlink) {
+        continue; // a submodule, whose commit lives in its own repository
+      }
+
+      if kinds.contains_key(&entry.sha) {
+        roots.push(entry.sha.clone());
+      } else {
+        errors.push(format!(
+          "index entry {} points to missing blob {}",
+          PathBuf::from(&entry.name).display(),
+          entry.sha
+        ));
+      }
+    }
+
+    Ok(roots)
+  }
+}
+
+fn parse_sha(hex: &str) -> std::result::Result<Sha, String> {
+  let ok = hex.len() == 40
+    && hex
+      .bytes()
+      .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b));
+
+  if ok {
+    Ok(Sha::from(hex))
+  } else {
+    Err(format!("bad sha {:?}", hex))
+  }
+}
+
+// "Name <email> 1234567890 +0000"
+fn check_ident(ident: &str) -> std::result::Result<(), String> {
+  let bad = || Err(format!("bad identity {:?}", ident));
+
+  let (lt, gt) = match (ident.find('<'), ident.find('>')) {
+    (Some(lt), Some(gt)) if lt < gt => (lt, gt),
+    _ => return bad(),
+  };
+
+  if lt == 0 || !ident[..lt].ends_with(' ') {
+    return bad();
+  }
+
+  let date = ident[gt + 1..].trim_start().split(' ').collect::<Vec<_>>();
+
+  let tz_ok = |tz: &str| {
+    tz.len() == 5
+      && (tz.starts_with('+') || tz.starts_with('-'))
+      && tz[1..].bytes().all(|b| b.is_ascii_digit())
+  };
+
+  match date.as_slice() {
+    [ts, tz] if !ts.is_empty() && ts.bytes().all(|b| b.is_ascii_digit()) => {
+      if tz_ok(tz) {
+        Ok(())
+      } else {
+        bad()
+      }
+    },
+    _ => bad(),
+  }
+}
+
+// The header lines of a commit or tag: everything before the first blank line.
+fn header_lines(content: &[u8]) -> Vec<String> {
+  let text = String::from_utf8_lossy(content);
+  let header = text.split("\n\n").next().unwrap_or_default();
+  header.lines().map(String::from).collect()
+}
+
+// tree, parents, author, committer, in that order; anything after that is
+// fine (encoding, signatures, and so on).
+fn check_commit(content: &[u8]) -> std::result::Result<Vec<Link>, String> {
+  let lines = header_lines(content);
+  let mut lines = lines.iter().peekable();
+  let mut links = vec![];
+
+  match lines.next().and_then(|l| l.strip_prefix("tree ")) {
+    Some(hex) => links.push((parse_sha(hex)?, "tree")),
+    None => return Err("missing tree".into()),
+  }
+
+  while let Some(hex) = lines.peek().and_then(|l| l.strip_prefix("parent ")) {
+    links.push((parse_sha(hex)?, "commit"));
+    lines.next();
+  }
+
+  for field in &["author ", "committer "] {
+    match lines.next().and_then(|l| l.strip_prefix(field)) {
+      Some(ident) => check_ident(ident)?,
+      None => return Err(format!("missing {}", field.trim())),
+    }
+  }
+
+  Ok(links)
+}
+
+// object, type, tag, and (usually) tagger
+fn check_tag(content: &[u8]) -> std::result::Result<Vec<Link>, String> {
+  let lines = header_lines(content);
+  let mut lines = lines.iter();
+
+  let mut field = |name: &str| match lines.next() {
+    Some(line) if line.starts_with(name) => Ok(line[name.len()..].to_string()),
+    _ => Err(format!("missing {}", name.trim())),
+  };
+
+  let sha = parse_sha(&field("object ")?)?;
+  let kind = field("type ")?;
+  let name = field("tag ")?;
+
+  let kind = match OBJECT_TYPES.iter().find(|t| **t == kind) {
+    Some(kind) => *kind,
+    None => return Err(format!("bad type {:?}", kind)),
+  };
+
+  if name.is_empty() {
+    return Err("empty tag name".into());
+  }
+
+  if let Ok(tagger) = field("tagger ") {
+    check_ident(&tagger)?;
+  }
+
+  Ok(vec![(sha, kind)])
+}
+
+// a series of "<mode> <name>\0<20-byte sha>"
+fn check_tree(content: &[u8]) -> std::result::Result<Vec<Link>, String> {
+  let mut links = vec![];
+  let mut names = HashSet::new();
+  let mut rest = content;
+
+  while !rest.is_empty() {
+    let space = rest.iter().position(|&b| b == b' ');
+    let nul = rest.iter().position(|&b| b == b'\0');
+
+    let (space, nul) = match (space, nul) {
+      (Some(s), Some(n)) if s < n && n + 21 <= rest.len() => (s, n),
+      _ => return Err("truncated entry".into()),
+    };
+
+    let mode = &rest[..space];
+    let name = &rest[space + 1..nul];
+    let sha = Sha::from(hex::encode(&rest[nul + 1..nul + 21]));
+
+    if name.is_empty() || name == b"." || name == b".." || name.contains(&b'/') {
+      return Err(format!("bad name {:?}", String::from_utf8_lossy(name)));
+    }
+
+    if !names.insert(name.to_vec()) {
+      let name = String::from_utf8_lossy(name);
+      return Err(format!("duplicate entry {:?}", name));
+    }
+
+    match mode {
+      b"40000" => links.push((sha, "tree")),
+      b"100644" | b"100755" | b"120000" => links.push((sha, "blob")),
+      b"160000" => {}, // a submodule's commit isn't ours to check
+      _ => {
+        let mode = String::from_utf8_lossy(mode);
+        return Err(format!("bad mode {:?}", mode));
+      },
+    }
+
+    rest = &rest[nul + 21..];
+  }
+
+  Ok(links)
+}
@@ -0,0 +1,219 @@
+use log::{debug, trace};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use crate::object::{Mode, Object, TreeItem};
+use crate::pack::{self, PackEntry, PackStats, PackedType};
+use crate::prelude::*;
+
+// Housekeeping for the object database: working out what's reachable, moving
+// loose objects into packs, and cleaning up after ourselves.
+impl Repository {
+  // Everything reachable from our refs, HEAD, reflogs, and the index. The string is a
+  // name hint for the packer (the filename we found a blob or tree under).
+  pub fn reachable_objects(&self) -> Result<Vec<(Sha, String)>> {
+    let mut queue: Vec<(Sha, String)> = vec![];
+
+    if let Ok(sha) = self.grefs().resolve("HEAD") {
+      queue.push((sha, "".into()));
+    }
+
+    for (_, sha) in self.grefs().all_refs()? {
+      queue.push((sha, "".into()));
+    }
+
+    // like git, we consider anything in a reflog to be reachable (as long as
+    // it's still around)
+    let mut logged = vec!["HEAD".to_string()];
+    logged.extend(self.grefs().all_refs()?.into_iter().map(|(name, _)| name));
+
+    for name in logged {
+      for entry in self.grefs().reflog(&name)? {
+        let shas = entry.old.into_iter().chain(std::iter::once(entry.new));
+        queue.extend(
+          shas
+            .filter(|sha| self.has_object(sha))
+            .map(|sha| (sha, "".into())),
+        );
+      }
+    }
+
+    for entry in self.index().entries() {
+      let name = PathBuf::from(&entry.name);
+      let basename = name.file_name().unwrap_or_default();
+      queue.push((entry.sha.clone(), basename.to_string_lossy().into()));
+    }
+
+    let mut seen = HashSet::new();
+    let mut ret = vec![];
+
+    while let Some((sha, name)) = queue.pop() {
+      if !seen.insert(sha.clone()) {
+        continue;
+      }
+
+      match self.object_for_sha(&sha)? {
+        Object::Commit(commit) => {
+          queue.push((commit.tree().clone(), "".into()));
+          for parent in &commit.parent_shas {
+            queue.push((parent.clone(), "".into()));
+          }
+        },
+        Object::Tree(tree) => {
+          for (path, item) in tree.entries() {
+            if let TreeItem::Entry(e) = item {
+              // a submodule's commit isn't in our database
+              if e.mode == Mode::Gitlink {
+                continue;
+              }
+
+              queue.push((e.sha().clone(), path.to_string_lossy().into()));
+            }
+          }
+        },
+        Object::Tag(tag) => queue.push((tag.object, "".into())),
+        Object::Blob(_) => {},
+      }
+
+      ret.push((sha, name));
+    }
+
+    trace!("found {} reachable objects", ret.len());
+
+    Ok(ret)
+  }
+
+  // All the loose objects in the database, and their paths.
+  pub fn loose_objects(&self) -> Result<Vec<(Sha, PathBuf)>> {
+    let mut ret = vec![];
+
+    let object_dir = self.git_dir.join("objects");
+    for dir in std::fs::read_dir(object_dir)?.filter_map(std::result::Result::ok)
+    {
+      let prefix = dir.file_name().to_string_lossy().to_string();
+
+      if prefix.len() != 2 || !dir.path().is_dir() {
+        continue;
+      }
+
+      for e in std::fs::read_dir(dir.path())?.filter_map(std::result::Result::ok)
+      {
+        let rest = e.file_name().to_string_lossy().to_string();
+        if rest.len() == 38 && rest.chars().all(|c| c.is_ascii_hexdigit()) {
+          ret.push((format!("{}{}", prefix, rest).into(), e.path()));
+        }
+      }
+    }
+
+    Ok(ret)
+  }
+
+  // Write a new pack of reachable objects. If all is false, we only pack the
+  // objects that are loose; if it's true, we pack everything reachable, no
+  // matter where it lives. Returns None if there was nothing to pack.
+  pub fn repack(&self, all: bool) -> Result<Option<PackStats>> {
+    let mut entries = vec![];
+
+    for (sha, name) in self.reachable_objects()? {
+      if !all && !self.path_for_sha(&sha).is_file() {
+        continue;
+      }
+
+      let (type_str, content) = self.raw_object_for_sha(&sha)?;
+
+      entries.push(PackEntry {
+        sha,
+        kind: PackedType::from_type_str(&type_str)?,
+        content,
+        name,
+      });
+    }
+
+    if entries.is_empty() {
+      return Ok(None);
+    }
+
+    let stats = pack::write_pack(&self.git_dir.join("objects/pack"), entries)?;
+    self.reload_packs()?;
+
+    Ok(Some(stats))
+  }
+
+  // Remove every pack except the one at keep (which is presumably one we just
+  // wrote containing everything we care about).
+  pub fn remove_packs_except(&self, keep: &PathBuf) -> Result<usize> {
+    let mut removed = 0;
+
+    for pack in self.packs.borrow().iter() {
+      if pack.path() == keep {
+        continue;
+      }
+
+      debug!("removing redundant pack {:?}", pack.path());
+
+      // idx first, so that nobody goes looking in a half-deleted pack
+      std::fs::remove_file(pack.idx_path())?;
+      std::fs::remove_file(pack.path())?;
+      removed += 1;
+    }
+
+    self.reload_packs()?;
+
+    Ok(removed)
+  }
+
+  // Remove loose objects that also exist in a pack.
+  pub fn prune_packed(&self) -> Result<usize> {
+    let mut removed = 0;
+
+    for (sha, path) in self.loose_objects()? {
+      if self.packs.borrow().iter().any(|p| p.contains(&sha)) {
+        std::fs::remove_file(&path)?;
+        self.remove_empty_parent(&path);
+        removed += 1;
+      }
+    }
+
+    Ok(removed)
+  }
+
+  // Remove unreachable loose objects that haven't been modified since expire.
+  // Anything newer than that might be part of some operation in progress, so
+  // we leave it alone.
+  pub fn prune_loose(&self, expire: SystemTime) -> Result<usize> {
+    let reachable = self
+      .reachable_objects()?
+      .into_iter()
+      .map(|(sha, _)| sha)
+      .collect::<HashSet<_>>();
+
+    let mut removed = 0;
+
+    for (sha, path) in self.loose_objects()? {
+      if reachable.contains(&sha) {
+        continue;
+      }
+
+      if path.metadata()?.modified()? > expire {
+        continue;
+      }
+
+      debug!("pruning unreachable object {}", sha);
+
+      std::fs::remove_file(&path)?;
+      self.remove_empty_parent(&path);
+      removed += 1;
+    }
+
+    Ok(removed)
+  }
+
+  // objects/xx/ directories are useless once they're empty
+  fn remove_empty_parent(&self, path: &std::path::Path) {
+    if let Some(dir) = path.parent() {
+      // this fails if it's not empty, which is fine
+      let _ = std::fs::remove_dir(dir);
+    }
+  }
+}
@@ -1,12 +1,18 @@
-use log::trace;
+mod reflog;
+
+pub use reflog::ReflogEntry;
+
+use log::{debug, trace};
 use std::{
+  collections::BTreeMap,
   fs::File,
   io::prelude::*,
   path::{Path, PathBuf},
 };
 
+use crate::lockfile::{FileLock, Lockfile};
+use crate::object::Person;
 use crate::prelude::*;
-use crate::Lockfile;
 
 // This is _so_ silly, but: the word "ref" is already super common in Rust
 // code, and I want to avoid ambiguity. Internally, anything that is a git
@@ -18,6 +24,18 @@ pub struct Grefs {
   git_dir: PathBuf,
 }
 
+const PACKED_REFS: &str = "packed-refs";
+const PACKED_REFS_HEADER: &str =
+  "# pack-refs with: peeled fully-peeled sorted \n";
+
+// A single entry in packed-refs. If the ref points at an annotated tag, peeled
+// is the (non-tag) object that tag eventually points to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackedRef {
+  pub sha:    Sha,
+  pub peeled: Option<Sha>,
+}
+
 impl Grefs {
   pub fn new(git_dir: PathBuf) -> Self {
     Grefs { git_dir }
@@ -44,12 +62,20 @@ impl Grefs {
 
   // this returns a sha
   pub fn resolve(&self, refstr: &str) -> Result<Sha> {
-    let path = self.path_for_name(refstr);
-    if path.is_none() {
-      return Err(PidgitError::RefNotFound(refstr.into()));
+    let name = self
+      .path_for_name(refstr)
+      .ok_or_else(|| PidgitError::RefNotFound(refstr.into()))?;
+
+    // loose refs always win over packed ones
+    if !self.path_exists(&name) {
+      return self
+        .packed_refs()?
+        .remove(&name)
+        .map(|packed| packed.sha)
+        .ok_or(PidgitError::RefNotFound(name));
     }
 
-    let res = self.read_file(path.unwrap());
+    let res = self.read_file(&name);
 
     // if we got an error and we're looking for a symref, return a better error.
     if let Err(PidgitError::Io(err)) = res {
@@ -70,12 +96,17 @@ impl Grefs {
     }
   }
 
+  // This returns the full name of the ref (which, for loose refs, is also its
+  // path relative to the git dir).
   pub fn path_for_name(&self, name: &str) -> Option<String> {
+    let packed = self.packed_refs().unwrap_or_default();
+    let exists = |name: &str| self.path_exists(name) || packed.contains_key(name);
+
     // this algorithm directly from git rev-parse docs
     for prefix in &["", "refs/", "refs/tags/", "refs/heads/", "refs/remotes/"] {
       let joined = format!("{}{}", prefix, name);
 
-      if self.path_exists(&joined) {
+      if exists(&joined) {
         trace!("resolving {}, found at {}", name, joined);
         return Some(joined);
       }
@@ -83,8 +114,7 @@ impl Grefs {
 
     // also check head of remotes
     let remote_head = format!("refs/remotes/{}/HEAD", name);
-    if self.path_exists(&remote_head) {
-      trace!("resolving {}, found at {}", name, remote_head);
+    if exists(&remote_head) {
       trace!("resolving {}, found at {}", name, remote_head);
       return Some(remote_head);
     }
@@ -93,24 +123,281 @@ impl Grefs {
     None
   }
 
-  pub fn update_head(&self, new_sha: &Sha) -> Result<()> {
+  // Parse the packed-refs file, if there is one. The format is one
+  // "<sha> <refname>" per line, where a line like "^<sha>" gives the peeled
+  // value of the ref on the line before it. Lines starting with # are
+  // comments (in practice, there's one header line describing the traits of
+  // the file).
+  pub fn packed_refs(&self) -> Result<BTreeMap<String, PackedRef>> {
+    let mut ret = BTreeMap::new();
+
+    if !self.path_exists(PACKED_REFS) {
+      return Ok(ret);
+    }
+
+    let content = self.read_file(PACKED_REFS)?;
+    let mut last: Option<String> = None;
+
+    for line in content.lines() {
+      if line.starts_with('#') || line.is_empty() {
+        continue;
+      }
+
+      if let Some(peeled) = line.strip_prefix('^') {
+        let prev = last.as_ref().and_then(|name| ret.get_mut(name));
+
+        match prev {
+          Some(PackedRef { peeled: p, .. }) => *p = Some(peeled.into()),
+          None => {
+            return Err(PidgitError::Generic(format!(
+              "unexpected peeled line in packed-refs: {}",
+              line
+            )))
+          },
+        }
+
+        continue;
+      }
+
+      let (sha, name) = match line.split_once(' ') {
+        Some((sha, name)) if sha.len() == 40 => (sha, name),
+        _ => {
+          return Err(PidgitError::Generic(format!(
+            "unparseable line in packed-refs: {}",
+            line
+          )))
+        },
+      };
+
+      ret.insert(
+        name.to_string(),
+        PackedRef {
+          sha:    sha.into(),
+          peeled: None,
+        },
+      );
+
+      last = Some(name.to_string());
+    }
+
+    Ok(ret)
+  }
+
+  // Move refs into packed-refs. By default, we only pack tags (and anything
+  // that was already packed), because branches move around so much that
+  // packing them isn't worth it; all packs everything. If prune is set, we
+  // remove the loose copies afterward. The peel function is used to find
+  // what tags point at, since we don't have access to the object database.
+  pub fn pack_refs<F>(&self, all: bool, prune: bool, peel: F) -> Result<usize>
+  where
+    F: Fn(&Sha) -> Option<Sha>,
+  {
+    let lockfile = Lockfile::new(self.git_dir.join(PACKED_REFS));
+    let lock = lockfile.lock()?;
+
+    let mut packed = self.packed_refs()?;
+    let mut loose = vec![];
+
+    for (name, sha) in self.loose_refs()? {
+      if !all && !name.starts_with("refs/tags/") && !packed.contains_key(&name) {
+        continue;
+      }
+
+      // symrefs stay where they are
+      if self.read_file(&name)?.starts_with("ref: ") {
+        continue;
+      }
+
+      let peeled = peel(&sha);
+      packed.insert(
+        name.clone(),
+        PackedRef {
+          sha: sha.clone(),
+          peeled,
+        },
+      );
+      loose.push((name, sha));
+    }
+
+    self.write_packed_refs(lock, &packed)?;
+
+    if prune {
+      for (name, sha) in &loose {
+        self.prune_loose_ref(name, sha)?;
+      }
+    }
+
+    Ok(loose.len())
+  }
+
+  fn write_packed_refs(
+    &self,
+    mut lock: FileLock,
+    packed: &BTreeMap<String, PackedRef>,
+  ) -> Result<()> {
+    let mut out = PACKED_REFS_HEADER.to_string();
+
+    for (name, packed_ref) in packed {
+      out.push_str(&format!("{} {}\n", packed_ref.sha, name));
+
+      if let Some(peeled) = &packed_ref.peeled {
+        out.push_str(&format!("^{}\n", peeled));
+      }
+    }
+
+    lock.write_all(out.as_bytes())?;
+    lock.commit()
+  }
+
+  // Remove a loose ref that we've just packed, as long as nobody has changed
+  // it out from under us, and tidy up any directories that leaves empty.
+  fn prune_loose_ref(&self, name: &str, sha: &Sha) -> Result<()> {
+    let lockfile = Lockfile::new(self.git_dir.join(name));
+    let lock = lockfile.lock()?;
+
+    if self.read_file(name)? != sha.hexdigest() {
+      debug!("not pruning {}, which has changed", name);
+      return lock.rollback();
+    }
+
+    std::fs::remove_file(self.git_dir.join(name))?;
+    lock.rollback()?;
+
+    // refs/heads and friends stick around, even if empty
+    let mut dir = PathBuf::from(name);
+    while dir.pop() && dir.components().count() > 2 {
+      if std::fs::remove_dir(self.git_dir.join(&dir)).is_err() {
+        break;
+      }
+    }
+
+    Ok(())
+  }
+
+  // Every ref under refs/, as (full name, sha) pairs, sorted by name. Loose
+  // refs shadow packed ones with the same name.
+  pub fn all_refs(&self) -> Result<Vec<(String, Sha)>> {
+    let mut refs = self
+      .packed_refs()?
+      .into_iter()
+      .map(|(name, packed)| (name, packed.sha))
+      .collect::<BTreeMap<_, _>>();
+
+    refs.extend(self.loose_refs()?);
+
+    Ok(refs.into_iter().collect())
+  }
+
+  // Like all_refs, but only the ones that live in files under refs/.
+  fn loose_refs(&self) -> Result<Vec<(String, Sha)>> {
+    let mut names = vec![];
+    self.collect_ref_names(&self.git_dir.join("refs"), &mut names)?;
+    names.sort();
+
+    let mut ret = vec![];
+    for name in names {
+      // a ref that's pointing somewhere bogus isn't worth dying over
+      if let Ok(sha) = self.resolve(&name) {
+        ret.push((name, sha));
+      }
+    }
+
+    Ok(ret)
+  }
+
+  fn collect_ref_names(&self, dir: &Path, names: &mut Vec<String>) -> Result<()> {
+    if !dir.is_dir() {
+      return Ok(());
+    }
+
+    for e in std::fs::read_dir(dir)?.filter_map(std::result::Result::ok) {
+      let path = e.path();
+
+      if path.is_dir() {
+        self.collect_ref_names(&path, names)?;
+      } else if path.extension().is_none_or(|ext| ext != "lock") {
+        let rel = path.strip_prefix(&self.git_dir).unwrap();
+        names.push(rel.to_string_lossy().to_string());
+      }
+    }
+
+    Ok(())
+  }
+
+  // The full name of the branch HEAD points at, or just "HEAD" if it's
+  // detached.
+  pub fn current_branch(&self) -> Result<String> {
+    let raw = self.read_file("HEAD")?;
+
+    match raw.strip_prefix("ref: ") {
+      Some(name) => Ok(name.to_string()),
+      None => Ok("HEAD".to_string()),
+    }
+  }
+
+  pub fn update_head(
+    &self,
+    new_sha: &Sha,
+    who: &Person,
+    message: &str,
+  ) -> Result<()> {
     // we must read the content of .git/HEAD. If that's a gitref, we find the
     // open that other file instead. If it's not a gitref, it must be a sha
     // (i.e., we're in detached head mode), so we lock the head file itself.
-    let raw = self.read_file("HEAD")?;
+    let ref_path = self.current_branch()?;
+    let old = self.resolve("HEAD").ok();
 
-    let ref_path = if raw.starts_with("ref: ") {
-      raw.trim_start_matches("ref: ")
-    } else {
-      // must be a sha
-      "HEAD"
+    self.update_ref_file(&ref_path, &new_sha.hexdigest())?;
+
+    let entry = ReflogEntry {
+      old,
+      new: new_sha.clone(),
+      who: who.clone(),
+      message: message.to_string(),
+    };
+
+    if ref_path != "HEAD" {
+      self.append_reflog(&ref_path, &entry)?;
+    }
+
+    self.append_reflog("HEAD", &entry)
+  }
+
+  // Point HEAD somewhere new: at a branch (given its full name), or, if branch
+  // is None, directly at sha. Either way, sha is what HEAD will resolve to.
+  pub fn set_head(
+    &self,
+    branch: Option<&str>,
+    sha: &Sha,
+    who: &Person,
+    message: &str,
+  ) -> Result<()> {
+    let old = self.resolve("HEAD").ok();
+
+    let content = match branch {
+      Some(name) => format!("ref: {}", name),
+      None => sha.hexdigest(),
     };
 
-    self.update_ref_file(ref_path, &new_sha.hexdigest())
+    self.update_ref_file("HEAD", &content)?;
+
+    self.append_reflog(
+      "HEAD",
+      &ReflogEntry {
+        old,
+        new: sha.clone(),
+        who: who.clone(),
+        message: message.to_string(),
+      },
+    )
   }
 
   fn update_ref_file(&self, ref_path: &str, new_sha: &str) -> Result<()> {
-    let lockfile = Lockfile::new(self.git_dir.join(ref_path));
+    // a packed ref might not have its directory any more
+    let path = self.git_dir.join(ref_path);
+    std::fs::create_dir_all(path.parent().unwrap())?;
+
+    let lockfile = Lockfile::new(path);
     let mut lock = lockfile.lock()?;
 
     lock.write_all(format!("{}\n", new_sha).as_bytes())?;
@@ -119,23 +406,81 @@ impl Grefs {
     Ok(())
   }
 
-  pub fn create_branch(&self, refname: &str, sha: &Sha) -> Result<()> {
-    if !util::is_valid_refname(refname) {
-      return Err(PidgitError::InvalidRefName(refname.to_string()));
-    }
+  // Create a new ref with this full name (like refs/tags/v1.0), which must not
+  // already exist.
+  pub fn create_ref(&self, name: &str, sha: &Sha) -> Result<()> {
+    let short = name.splitn(3, '/').nth(2).unwrap_or(name);
 
-    let pathstr = format!("refs/heads/{}", refname);
+    if !util::is_valid_refname(short) {
+      return Err(PidgitError::InvalidRefName(short.to_string()));
+    }
 
-    if self.resolve(&pathstr).is_ok() {
+    if self.resolve(name).is_ok() {
       return Err(PidgitError::Generic(format!(
-        "A branch named '{}' already exists",
-        refname
+        "A {} named '{}' already exists",
+        if name.starts_with("refs/tags/") {
+          "tag"
+        } else {
+          "branch"
+        },
+        short
       )));
     }
 
-    // create parent dir!
-    std::fs::create_dir_all(PathBuf::from(&pathstr).parent().unwrap())?;
+    self.update_ref_file(name, &sha.hexdigest())
+  }
+
+  pub fn create_branch(
+    &self,
+    refname: &str,
+    sha: &Sha,
+    who: &Person,
+    message: &str,
+  ) -> Result<()> {
+    let pathstr = format!("refs/heads/{}", refname);
+
+    self.create_ref(&pathstr, sha)?;
+
+    self.append_reflog(
+      &pathstr,
+      &ReflogEntry {
+        old:     None,
+        new:     sha.clone(),
+        who:     who.clone(),
+        message: message.to_string(),
+      },
+    )
+  }
+
+  // Delete the ref with this full name, wherever it happens to live, along
+  // with its reflog. Returns the sha it used to point to.
+  pub fn delete_ref(&self, name: &str) -> Result<Sha> {
+    let sha = match (self.path_exists(name), self.packed_refs()?.get(name)) {
+      (true, _) => self.read_file(name)?.into(),
+      (false, Some(packed)) => packed.sha.clone(),
+      (false, None) => return Err(PidgitError::RefNotFound(name.to_string())),
+    };
+
+    let lockfile = Lockfile::new(self.git_dir.join(PACKED_REFS));
+    let lock = lockfile.lock()?;
+
+    let mut packed = self.packed_refs()?;
+
+    if packed.remove(name).is_some() {
+      self.write_packed_refs(lock, &packed)?;
+    } else {
+      lock.rollback()?;
+    }
+
+    if self.path_exists(name) {
+      std::fs::remove_file(self.git_dir.join(name))?;
+    }
+
+    let log = format!("logs/{}", name);
+    if self.path_exists(&log) {
+      std::fs::remove_file(self.git_dir.join(log))?;
+    }
 
-    self.update_ref_file(&pathstr, &sha.hexdigest())
+    Ok(sha)
   }
 }
@@ -0,0 +1,131 @@
+use std::fs::OpenOptions;
+use std::io::prelude::*;
+
+use super::Grefs;
+use crate::object::Person;
+use crate::prelude::*;
+
+const NULL_SHA: &str = "0000000000000000000000000000000000000000";
+
+// One line of a reflog. A ref that didn't exist before the update (like a
+// brand new branch) has no old value.
+#[derive(Debug, Clone)]
+pub struct ReflogEntry {
+  pub old:     Option<Sha>,
+  pub new:     Sha,
+  pub who:     Person,
+  pub message: String,
+}
+
+impl ReflogEntry {
+  // <old> <new> <name> <<email>> <timestamp> <tz>\t<message>
+  fn parse(line: &str) -> Result<Self> {
+    let bad = || PidgitError::Generic(format!("bad reflog line: {}", line));
+
+    let (header, message) = line.split_once('\t').unwrap_or((line, ""));
+    let mut hunks = header.splitn(3, ' ');
+
+    let old = hunks.next().ok_or_else(bad)?;
+    let new = hunks.next().ok_or_else(bad)?;
+    let who = hunks.next().ok_or_else(bad)?;
+
+    if old.len() != 40 || new.len() != 40 {
+      return Err(bad());
+    }
+
+    Ok(Self {
+      old:     if old == NULL_SHA {
+        None
+      } else {
+        Some(old.into())
+      },
+      new:     new.into(),
+      who:     Person::from_ident(who).map_err(|_| bad())?,
+      message: message.to_string(),
+    })
+  }
+
+  fn to_line(&self) -> String {
+    let old = self
+      .old
+      .as_ref()
+      .map_or_else(|| NULL_SHA.to_string(), |sha| sha.hexdigest());
+
+    // newlines would break the format, so git squashes them, and so do we
+    let message = self.message.lines().collect::<Vec<_>>().join(" ");
+
+    format!(
+      "{} {} {} {}\t{}\n",
+      old,
+      self.new,
+      self.who,
+      self.who.date.format("%s %z"),
+      message
+    )
+  }
+}
+
+impl Grefs {
+  // Git only keeps reflogs for HEAD, branches, and remote-tracking branches,
+  // unless someone has gone to the trouble of creating one.
+  fn should_log(&self, name: &str) -> bool {
+    name == "HEAD"
+      || name.starts_with("refs/heads/")
+      || name.starts_with("refs/remotes/")
+      || self.path_exists(format!("logs/{}", name))
+  }
+
+  pub fn append_reflog(&self, name: &str, entry: &ReflogEntry) -> Result<()> {
+    if !self.should_log(name) {
+      return Ok(());
+    }
+
+    let path = self.git_dir.join("logs").join(name);
+    std::fs::create_dir_all(path.parent().unwrap())?;
+
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(entry.to_line().as_bytes())?;
+
+    Ok(())
+  }
+
+  // The reflog for the ref with this full name, oldest entry first (which is
+  // the order they're stored in).
+  pub fn reflog(&self, name: &str) -> Result<Vec<ReflogEntry>> {
+    let path = format!("logs/{}", name);
+
+    if !self.path_exists(&path) {
+      return Ok(vec![]);
+    }
+
+    self
+      .read_file(&path)?
+      .lines()
+      .filter(|line| !line.is_empty())
+      .map(ReflogEntry::parse)
+      .collect()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn round_trip() {
+    let line = format!(
+      "{} {} Pidgit <pidgit@example.com> 1600000000 -0400\tcommit: hi there",
+      NULL_SHA,
+      "a".repeat(40),
+    );
+
+    let entry = ReflogEntry::parse(&line).unwrap();
+    assert!(entry.old.is_none());
+    assert_eq!(entry.new, "a".repeat(40).into());
+    assert_eq!(entry.who.email, "pidgit@example.com");
+    assert_eq!(entry.message, "commit: hi there");
+
+    assert_eq!(entry.to_line(), format!("{}\n", line));
+    assert!(ReflogEntry::parse("not a reflog line").is_err());
+  }
+}
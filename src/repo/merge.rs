@@ -0,0 +1,467 @@
+use log::debug;
+use std::collections::{BTreeMap, BinaryHeap, HashMap, HashSet};
+use std::ffi::OsString;
+use std::path::Path;
+
+use crate::attributes::WorkspaceAttributes;
+use crate::diff;
+use crate::index::IndexEntry;
+use crate::object::{Blob, Commit, Mode, PathEntry};
+use crate::prelude::*;
+
+const MERGE_HEAD: &str = "MERGE_HEAD";
+const MERGE_MSG: &str = "MERGE_MSG";
+
+// flags for merge base calculation
+const PARENT1: u8 = 1 << 0;
+const PARENT2: u8 = 1 << 1;
+const STALE: u8 = 1 << 2;
+const RESULT: u8 = 1 << 3;
+
+// A path we couldn't merge on our own. The index has its stages, and the
+// workspace has our best attempt at it.
+#[derive(Debug, PartialEq, Eq)]
+pub struct MergeConflict {
+  pub path: OsString,
+  pub kind: ConflictKind,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ConflictKind {
+  Content,
+  AddAdd,
+  DeletedByUs,
+  DeletedByThem,
+}
+
+impl MergeConflict {
+  // this is what git says, more or less
+  pub fn describe(&self, ours: &str, theirs: &str) -> String {
+    let path = Path::new(&self.path).display();
+
+    match self.kind {
+      ConflictKind::Content => {
+        format!("CONFLICT (content): Merge conflict in {}", path)
+      },
+      ConflictKind::AddAdd => {
+        format!("CONFLICT (add/add): Merge conflict in {}", path)
+      },
+      ConflictKind::DeletedByUs => format!(
+        "CONFLICT (modify/delete): {} deleted in {} and modified in {}. \
+         Version {} of {} left in tree.",
+        path, ours, theirs, theirs, path
+      ),
+      ConflictKind::DeletedByThem => format!(
+        "CONFLICT (modify/delete): {} deleted in {} and modified in {}. \
+         Version {} of {} left in tree.",
+        path, theirs, ours, ours, path
+      ),
+    }
+  }
+}
+
+impl Repository {
+  // The best common ancestors of two commits: those that aren't themselves
+  // ancestors of any other common ancestor. There's usually just one, but
+  // criss-cross merges can give us more.
+  pub fn merge_bases(&self, one: &Sha, two: &Sha) -> Result<Vec<Sha>> {
+    self.merge_bases_many(one, std::slice::from_ref(two))
+  }
+
+  // The best common ancestors of one and (any of) others, which is what you'd
+  // get by merging one with a hypothetical merge of all the others. This is
+  // what git merge-base does when given more than two commits.
+  pub fn merge_bases_many(&self, one: &Sha, others: &[Sha]) -> Result<Vec<Sha>> {
+    if others.contains(one) {
+      return Ok(vec![one.clone()]);
+    }
+
+    let candidates = self.paint_down(one, others)?;
+    self.remove_redundant(candidates)
+  }
+
+  // The best common ancestors of all of these commits together, as you'd use
+  // for an octopus merge.
+  pub fn merge_bases_octopus(&self, commits: &[Sha]) -> Result<Vec<Sha>> {
+    let (first, rest) = match commits.split_first() {
+      Some(split) => split,
+      None => return Ok(vec![]),
+    };
+
+    let mut bases = vec![first.clone()];
+
+    for sha in rest {
+      let mut next = vec![];
+
+      for base in &bases {
+        for found in self.merge_bases(base, sha)? {
+          if !next.contains(&found) {
+            next.push(found);
+          }
+        }
+      }
+
+      bases = next;
+    }
+
+    self.remove_redundant(bases)
+  }
+
+  pub fn merge_base(&self, one: &Sha, two: &Sha) -> Result<Option<Sha>> {
+    Ok(self.merge_bases(one, two)?.into_iter().next())
+  }
+
+  // Drop any of these commits that are ancestors of another one.
+  fn remove_redundant(&self, candidates: Vec<Sha>) -> Result<Vec<Sha>> {
+    let mut redundant = HashSet::new();
+
+    for a in &candidates {
+      for b in &candidates {
+        if a != b && !redundant.contains(b) && self.is_ancestor(a, b)? {
+          redundant.insert(a.clone());
+          break;
+        }
+      }
+    }
+
+    Ok(
+      candidates
+        .into_iter()
+        .filter(|sha| !redundant.contains(sha))
+        .collect(),
+    )
+  }
+
+  // Is ancestor reachable from descendant? (A commit is its own ancestor.)
+  // With generation numbers from the commit-graph, we needn't look at
+  // anything older than ancestor, which can't possibly reach it.
+  pub fn is_ancestor(&self, ancestor: &Sha, descendant: &Sha) -> Result<bool> {
+    let min_generation = self.commit_node(ancestor)?.generation;
+
+    let mut seen = HashSet::new();
+    let mut queue = vec![descendant.clone()];
+
+    while let Some(sha) = queue.pop() {
+      if &sha == ancestor {
+        return Ok(true);
+      }
+
+      if !seen.insert(sha.clone()) {
+        continue;
+      }
+
+      let node = self.commit_node(&sha)?;
+      if node.generation >= min_generation {
+        queue.extend(node.parents);
+      }
+    }
+
+    Ok(false)
+  }
+
+  // Walk back from one and the others, newest first, marking each commit with
+  // which side(s) it's reachable from. Anything reachable from both sides is a
+  // candidate, and its ancestors are stale: they can't be any better. We stop
+  // when everything left to look at is stale.
+  fn paint_down(&self, one: &Sha, others: &[Sha]) -> Result<Vec<Sha>> {
+    let mut flags: HashMap<Sha, u8> = HashMap::new();
+    let mut queue = BinaryHeap::new();
+    let mut result = vec![];
+
+    let enqueue = |queue: &mut BinaryHeap<_>, sha: &Sha| -> Result<()> {
+      let node = self.commit_node(sha)?;
+      queue.push((node.date, sha.hexdigest()));
+      Ok(())
+    };
+
+    flags.insert(one.clone(), PARENT1);
+    enqueue(&mut queue, one)?;
+
+    for sha in others {
+      if flags.insert(sha.clone(), PARENT2).is_none() {
+        enqueue(&mut queue, sha)?;
+      }
+    }
+
+    let all_stale = |queue: &BinaryHeap<(i64, String)>,
+                     flags: &HashMap<Sha, u8>| {
+      queue
+        .iter()
+        .all(|(_, hex)| flags[&Sha::from(hex.as_str())] & STALE != 0)
+    };
+
+    while !all_stale(&queue, &flags) {
+      let (_, hex) = queue.pop().unwrap();
+      let sha = Sha::from(hex);
+      let node = self.commit_node(&sha)?;
+
+      let sha_flags = flags.get_mut(&sha).unwrap();
+      let mut paint = *sha_flags & (PARENT1 | PARENT2 | STALE);
+
+      if paint == PARENT1 | PARENT2 {
+        if *sha_flags & RESULT == 0 {
+          *sha_flags |= RESULT;
+          result.push(sha.clone());
+        }
+
+        paint |= STALE;
+      }
+
+      for parent in &node.parents {
+        let parent_flags = flags.entry(parent.clone()).or_default();
+
+        if *parent_flags & paint == paint {
+          continue;
+        }
+
+        *parent_flags |= paint;
+        enqueue(&mut queue, parent)?;
+      }
+    }
+
+    // anything we marked stale after finding it isn't a real result
+    Ok(
+      result
+        .into_iter()
+        .filter(|sha| flags[sha] & STALE == 0)
+        .collect(),
+    )
+  }
+
+  // Merge the tree of theirs into the tree of ours (which must be HEAD),
+  // using base as the common ancestor. Everything that merges cleanly is
+  // written to the workspace and index; anything that doesn't is written
+  // with conflict markers (or left alone, for binary files), recorded as
+  // unmerged in the index, and returned.
+  pub fn merge_trees(
+    &self,
+    base: Option<&Sha>,
+    ours: &Commit,
+    theirs: &Commit,
+    theirs_name: &str,
+  ) -> Result<Vec<MergeConflict>> {
+    let base_tree = match base {
+      Some(sha) => {
+        self.flat_tree(self.object_for_sha(sha)?.as_commit()?.tree())?
+      },
+      None => BTreeMap::new(),
+    };
+
+    let our_tree = self.flat_tree(ours.tree())?;
+    let their_tree = self.flat_tree(theirs.tree())?;
+
+    // we'd better not clobber anything theirs is going to touch
+    let diff = self.diff_trees(Some(ours.tree()), Some(theirs.tree()))?;
+    self.check_migration_conflicts(&diff, "merge")?;
+
+    let attrs = self.workspace().attributes()?;
+
+    let same = |a: Option<&PathEntry>, b: Option<&PathEntry>| match (a, b) {
+      (Some(a), Some(b)) => a.sha == b.sha && a.mode == b.mode,
+      (None, None) => true,
+      _ => false,
+    };
+
+    let mut conflicts = vec![];
+
+    for path in diff.keys() {
+      let b = base_tree.get(path);
+      let o = our_tree.get(path);
+      let t = their_tree.get(path);
+
+      if same(o, b) {
+        // only they changed it, so take their version
+        debug!("merge: taking {:?} from {}", path, theirs_name);
+        match t {
+          Some(entry) => {
+            self.checkout_entry(path, &entry.sha, &entry.mode, &attrs)?
+          },
+          None => {
+            self.workspace().remove_file(path)?;
+            self.index_mut().remove(path);
+          },
+        }
+      } else if same(t, b) {
+        // only we changed it, so there's nothing to do
+        continue;
+      } else if let Some(kind) =
+        self.merge_path(path, b, o, t, theirs_name, &attrs)?
+      {
+        conflicts.push(MergeConflict {
+          path: path.clone(),
+          kind,
+        });
+      }
+    }
+
+    self.index().write()?;
+
+    Ok(conflicts)
+  }
+
+  // Both sides changed this path (and not in the same way). Returns the kind
+  // of conflict, if we couldn't sort it out.
+  fn merge_path(
+    &self,
+    path: &OsString,
+    base: Option<&PathEntry>,
+    ours: Option<&PathEntry>,
+    theirs: Option<&PathEntry>,
+    theirs_name: &str,
+    attrs: &WorkspaceAttributes,
+  ) -> Result<Option<ConflictKind>> {
+    let (o, t) = match (ours, theirs) {
+      (Some(o), Some(t)) => (o, t),
+      (None, Some(t)) => {
+        // leave their version in the tree, so there's something to look at
+        self.checkout_entry(path, &t.sha, &t.mode, attrs)?;
+        self.record_conflict(path, base, ours, theirs)?;
+        return Ok(Some(ConflictKind::DeletedByUs));
+      },
+      (Some(_), None) => {
+        self.record_conflict(path, base, ours, theirs)?;
+        return Ok(Some(ConflictKind::DeletedByThem));
+      },
+      (None, None) => {
+        // both sides deleted it, so they agree; just make sure it's gone
+        self.index_mut().remove(path);
+        return Ok(None);
+      },
+    };
+
+    // if only one side changed the mode, take it
+    let mode = match base {
+      Some(b) if b.mode == o.mode => t.mode.clone(),
+      _ => o.mode.clone(),
+    };
+
+    let read = |entry: Option<&PathEntry>| -> Result<Vec<u8>> {
+      match entry {
+        Some(e) => Ok(self.object_for_sha(&e.sha)?.as_blob()?.raw_content()),
+        None => Ok(vec![]),
+      }
+    };
+
+    let (b_bytes, o_bytes, t_bytes) = (read(base)?, read(ours)?, read(theirs)?);
+
+    let texts = (
+      std::str::from_utf8(&b_bytes),
+      std::str::from_utf8(&o_bytes),
+      std::str::from_utf8(&t_bytes),
+    );
+
+    let kind = if base.is_some() {
+      ConflictKind::Content
+    } else {
+      ConflictKind::AddAdd
+    };
+
+    // we can't do much with binary files, so we just leave ours in place
+    let merged = match texts {
+      (Ok(b), Ok(o), Ok(t)) => diff::merge(b, o, t),
+      _ => {
+        self.record_conflict(path, base, ours, theirs)?;
+        return Ok(Some(kind));
+      },
+    };
+
+    let content = merged.to_string("HEAD", theirs_name);
+
+    if merged.is_clean() {
+      let blob = Blob::from_content(content.into_bytes());
+      self.write_object(&blob)?;
+      self.checkout_entry(path, &blob.sha(), &mode, attrs)?;
+      return Ok(None);
+    }
+
+    self
+      .workspace()
+      .write_file(path, content.as_bytes(), &mode, attrs)?;
+    self.record_conflict(path, base, ours, theirs)?;
+
+    Ok(Some(kind))
+  }
+
+  // write the blob with this sha to path, and add it to the index
+  fn checkout_entry(
+    &self,
+    path: &OsString,
+    sha: &Sha,
+    mode: &Mode,
+    attrs: &WorkspaceAttributes,
+  ) -> Result<()> {
+    let blob = self.object_for_sha(sha)?.as_blob()?;
+    let stat =
+      self
+        .workspace()
+        .write_file(path, &blob.raw_content(), mode, attrs)?;
+
+    self.index_mut().add(IndexEntry::new_from_data(
+      path.clone(),
+      sha.clone(),
+      stat,
+    ));
+
+    Ok(())
+  }
+
+  fn record_conflict(
+    &self,
+    path: &OsString,
+    base: Option<&PathEntry>,
+    ours: Option<&PathEntry>,
+    theirs: Option<&PathEntry>,
+  ) -> Result<()> {
+    let mut entries = vec![];
+
+    for (stage, entry) in (1..).zip(&[base, ours, theirs]) {
+      if let Some(e) = entry {
+        let size = self.object_for_sha(&e.sha)?.get_ref().size();
+        entries.push(
+          IndexEntry::new_from_tree(
+            path.clone(),
+            e.sha.clone(),
+            &e.mode,
+            size as u32,
+          )
+          .with_stage(stage),
+        );
+      }
+    }
+
+    self.index_mut().add_unmerged(entries);
+    Ok(())
+  }
+
+  // The commit we're in the middle of merging, if we are.
+  pub fn merge_head(&self) -> Option<Sha> {
+    let raw = std::fs::read_to_string(self.git_dir.join(MERGE_HEAD)).ok()?;
+    Some(raw.trim().into())
+  }
+
+  pub fn merge_message(&self) -> Option<String> {
+    std::fs::read_to_string(self.git_dir.join(MERGE_MSG)).ok()
+  }
+
+  // Remember that we're merging sha, so that the next commit gets it as a
+  // parent (and the message as a default).
+  pub fn start_merge(&self, sha: &Sha, message: &str) -> Result<()> {
+    std::fs::write(
+      self.git_dir.join(MERGE_HEAD),
+      format!("{}\n", sha.hexdigest()),
+    )?;
+    std::fs::write(self.git_dir.join(MERGE_MSG), message)?;
+    Ok(())
+  }
+
+  pub fn clear_merge_state(&self) -> Result<()> {
+    for name in &[MERGE_HEAD, MERGE_MSG] {
+      let path = self.git_dir.join(name);
+      if path.exists() {
+        std::fs::remove_file(path)?;
+      }
+    }
+
+    Ok(())
+  }
+}
@@ -0,0 +1,94 @@
+use std::collections::BTreeSet;
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+
+use crate::index::IndexEntry;
+use crate::prelude::*;
+
+impl Repository {
+  // Make the index match the tree with this sha. If paths isn't empty, we only
+  // touch entries at or under those paths. Entries that already match the tree
+  // keep their stat info, so that we don't have to rehash everything later.
+  pub fn reset_index(&self, tree: &Sha, paths: &[PathBuf]) -> Result<()> {
+    let target = self.flat_tree(tree)?;
+    let wanted = |key: &OsStr| {
+      paths.is_empty() || paths.iter().any(|p| Path::new(key).starts_with(p))
+    };
+
+    let mut index = self.index_mut();
+
+    let stale = index
+      .keys()
+      .chain(index.unmerged().map(|(key, _)| key))
+      .filter(|key| wanted(key) && !target.contains_key(*key))
+      .cloned()
+      .collect::<Vec<_>>();
+
+    for key in stale {
+      index.remove(&key);
+    }
+
+    for (path, entry) in target.iter().filter(|(path, _)| wanted(path)) {
+      if let Some(existing) = index.entry_for(path) {
+        if existing.sha == entry.sha && entry.mode == existing.mode() {
+          continue;
+        }
+      }
+
+      let size = self.object_for_sha(&entry.sha)?.get_ref().size();
+
+      index.add(IndexEntry::new_from_tree(
+        path.clone(),
+        entry.sha.clone(),
+        &entry.mode,
+        size as u32,
+      ));
+    }
+
+    index.write()
+  }
+
+  // Make the workspace match the index exactly. Anything in previous (that is,
+  // what was tracked before the index changed) that's no longer in the index
+  // gets removed; untracked files are left alone.
+  pub fn reset_workspace(&self, previous: &BTreeSet<OsString>) -> Result<()> {
+    let workspace = self.workspace();
+    let attrs = workspace.attributes()?;
+    let mut index = self.index_mut();
+
+    for path in previous.iter().filter(|p| !index.is_tracked_file(p)) {
+      workspace.remove_file(path)?;
+    }
+
+    let entries = index
+      .entries()
+      .map(|e| (e.name.clone(), e.sha.clone(), e.mode()))
+      .collect::<Vec<_>>();
+
+    for (path, sha, mode) in entries {
+      let blob = self.object_for_sha(&sha)?.as_blob()?;
+      let stat =
+        workspace.write_file(&path, &blob.raw_content(), &mode.into(), &attrs)?;
+
+      index.add(IndexEntry::new_from_data(path, sha, stat));
+    }
+
+    index.write()
+  }
+
+  // Everything that's tracked right now, either in HEAD or in the index.
+  pub fn tracked_paths(&self) -> Result<BTreeSet<OsString>> {
+    let index = self.index();
+    let mut ret = index
+      .keys()
+      .chain(index.unmerged().map(|(key, _)| key))
+      .cloned()
+      .collect::<BTreeSet<_>>();
+
+    if let Some(head) = self.head()? {
+      ret.extend(self.flat_tree(head.tree())?.into_keys());
+    }
+
+    Ok(ret)
+  }
+}
@@ -0,0 +1,659 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+
+use crate::commit_graph::CommitNode;
+use crate::diff;
+use crate::object::{Commit, Mode, Object, PathEntry, TreeItem};
+use crate::prelude::*;
+use crate::util::RevArg;
+
+// flags for commits we've come across
+const SEEN: u8 = 1 << 0; // it's been queued
+const ADDED: u8 = 1 << 1; // its parents have been queued
+const UNINTERESTING: u8 = 1 << 2; // it's reachable from an excluded commit
+const TREESAME: u8 = 1 << 3; // it doesn't change any of the paths we care about
+const LEFT: u8 = 1 << 4; // it's reachable from the left side of A...B
+
+// how alike (in percent) two files have to be for --follow to call it a rename
+const RENAME_THRESHOLD: usize = 50;
+
+// how many more uninteresting commits to walk once everything left looks
+// uninteresting, in case one of them excludes something we've already seen
+const SLOP: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortOrder {
+  Date, // newest first (by committer date)
+  Topo, // never show a parent before all of its children
+}
+
+// A revision walker, like git's: give it some commits to start from (and some
+// to exclude, along with everything reachable from them), and it yields every
+// commit reachable from the former but not the latter.
+//
+// Given some paths, it only yields commits that change them, and simplifies
+// history the way git does by default: if a merge doesn't change the paths
+// relative to one of its parents, we only follow that parent.
+#[derive(Debug)]
+pub struct RevList<'r> {
+  repo:     &'r Repository,
+  flags:    HashMap<Sha, u8>,
+  parents:  HashMap<Sha, Vec<Sha>>, // after simplification
+  queue:    BinaryHeap<QueueItem>,
+  queued:   usize, // how many of the commits in the queue are interesting
+  output:   VecDeque<Commit>,
+  excluded: Vec<Sha>, // the commits we were told to exclude
+  order:    SortOrder,
+  paths:    Vec<PathBuf>,
+  follow:   bool,
+  rewrite:  bool,
+  limited:  bool,
+  prepared: bool,
+  counter:  usize,
+}
+
+// Newest commits come off the queue first; ties go to whichever got there
+// first.
+#[derive(Debug)]
+struct QueueItem {
+  date: i64,
+  seq:  Reverse<usize>,
+  sha:  Sha,
+  node: CommitNode,
+}
+
+impl<'r> RevList<'r> {
+  pub fn new(repo: &'r Repository) -> Self {
+    Self {
+      repo,
+      flags: HashMap::new(),
+      parents: HashMap::new(),
+      queue: BinaryHeap::new(),
+      queued: 0,
+      output: VecDeque::new(),
+      excluded: vec![],
+      order: SortOrder::Date,
+      paths: vec![],
+      follow: false,
+      rewrite: false,
+      limited: false,
+      prepared: false,
+      counter: 0,
+    }
+  }
+
+  pub fn set_order(&mut self, order: SortOrder) {
+    self.order = order;
+  }
+
+  // Only show commits that change these paths (relative to the top of the
+  // workspace; the empty path means everything).
+  pub fn set_paths(&mut self, paths: Vec<PathBuf>) {
+    self.paths = paths;
+  }
+
+  // Keep following the (single) path we were given across renames.
+  pub fn set_follow(&mut self, follow: bool) {
+    self.follow = follow;
+  }
+
+  // Rewrite the parents of the commits we yield to be the nearest ancestors we
+  // also yield, so that they make a connected graph. This means walking the
+  // whole history before yielding anything.
+  pub fn rewrite_parents(&mut self) {
+    self.rewrite = true;
+    self.limited = true;
+  }
+
+  // Add a revision from the command line, which might be a range (see
+  // util::resolve_range).
+  pub fn add_rev(&mut self, rev: &str) -> Result<()> {
+    for arg in util::resolve_range(rev, self.repo)? {
+      self.add_arg(&arg)?;
+    }
+
+    Ok(())
+  }
+
+  pub fn add_arg(&mut self, arg: &RevArg) -> Result<()> {
+    let sha = self.peel(arg.sha())?;
+
+    match arg {
+      RevArg::Include(_) => self.include(&sha),
+      RevArg::Exclude(_) => self.exclude(&sha),
+      RevArg::Left(_) => self.enqueue(&sha, LEFT),
+    }
+  }
+
+  pub fn include(&mut self, sha: &Sha) -> Result<()> {
+    self.enqueue(sha, 0)
+  }
+
+  pub fn exclude(&mut self, sha: &Sha) -> Result<()> {
+    self.limited = true;
+    self.excluded.push(sha.clone());
+    self.enqueue(sha, UNINTERESTING)
+  }
+
+  // Whether this commit came from the left side of a symmetric range (A...B).
+  pub fn is_left(&self, sha: &Sha) -> bool {
+    self.flag(sha, LEFT)
+  }
+
+  // revisions have to name commits, or tags pointing to them
+  fn peel(&self, sha: &Sha) -> Result<Sha> {
+    let sha = self.repo.peel_tag(sha).unwrap_or_else(|| sha.clone());
+
+    match self.repo.object_for_sha(&sha)? {
+      Object::Commit(_) => Ok(sha),
+      _ => Err(PidgitError::Generic(format!("{} is not a commit", sha))),
+    }
+  }
+
+  fn flag(&self, sha: &Sha, flag: u8) -> bool {
+    self.flags.get(sha).is_some_and(|f| f & flag != 0)
+  }
+
+  fn enqueue(&mut self, sha: &Sha, flags: u8) -> Result<()> {
+    let existing = self.flags.entry(sha.clone()).or_default();
+    let already_seen = *existing & SEEN != 0;
+
+    if already_seen {
+      *existing |= flags & !UNINTERESTING;
+
+      if flags & UNINTERESTING != 0 {
+        self.set_uninteresting(sha);
+        self.mark_uninteresting(sha);
+      }
+
+      return Ok(());
+    }
+
+    *existing |= SEEN | flags;
+
+    if flags & UNINTERESTING == 0 {
+      self.queued += 1;
+    }
+
+    // we only read the whole commit once we know we're going to yield it
+    let node = self.repo.commit_node(sha)?;
+    self.counter += 1;
+
+    self.queue.push(QueueItem {
+      date: node.date,
+      seq: Reverse(self.counter),
+      sha: sha.clone(),
+      node,
+    });
+
+    Ok(())
+  }
+
+  // Flag a commit we've seen as uninteresting, keeping count of what's left in
+  // the queue, and return whether it was interesting before.
+  fn set_uninteresting(&mut self, sha: &Sha) -> bool {
+    let flags = self.flags.get_mut(sha).unwrap();
+
+    if *flags & UNINTERESTING != 0 {
+      return false;
+    }
+
+    // it's still in the queue if we haven't added its parents yet
+    if *flags & ADDED == 0 {
+      self.queued -= 1;
+    }
+
+    *flags |= UNINTERESTING;
+    true
+  }
+
+  // Everything we already know to be reachable from sha is uninteresting, too.
+  fn mark_uninteresting(&mut self, sha: &Sha) {
+    let mut stack = vec![sha.clone()];
+
+    while let Some(sha) = stack.pop() {
+      let parents = self.parents.get(&sha).cloned().unwrap_or_default();
+
+      for parent in parents {
+        if self.set_uninteresting(&parent) {
+          stack.push(parent);
+        }
+      }
+    }
+  }
+
+  // Take the next commit off the queue.
+  fn pop(&mut self) -> Option<QueueItem> {
+    let item = self.queue.pop()?;
+
+    if !self.flag(&item.sha, UNINTERESTING) {
+      self.queued -= 1;
+    }
+
+    Some(item)
+  }
+
+  fn add_parents(&mut self, sha: &Sha, node: &CommitNode) -> Result<()> {
+    if self.flag(sha, ADDED) {
+      return Ok(());
+    }
+
+    *self.flags.get_mut(sha).unwrap() |= ADDED;
+
+    let parents = if self.paths.is_empty() || self.flag(sha, UNINTERESTING) {
+      node.parents.clone()
+    } else {
+      self.simplify(sha, node)?
+    };
+
+    let inherited = self.flags[sha] & (UNINTERESTING | LEFT);
+
+    for parent in &parents {
+      self.enqueue(parent, inherited)?;
+    }
+
+    self.parents.insert(sha.clone(), parents);
+
+    Ok(())
+  }
+
+  // Mark this commit TREESAME if it doesn't change our paths relative to one
+  // of its parents (or, for a root commit, if they don't exist), and return the
+  // parents we should keep walking.
+  fn simplify(&mut self, sha: &Sha, node: &CommitNode) -> Result<Vec<Sha>> {
+    if node.parents.is_empty() {
+      if !self.paths_changed(None, Some(&node.tree))? {
+        *self.flags.get_mut(sha).unwrap() |= TREESAME;
+      }
+
+      return Ok(vec![]);
+    }
+
+    for parent in &node.parents {
+      let tree = self.repo.commit_node(parent)?.tree;
+
+      if !self.paths_changed(Some(&tree), Some(&node.tree))? {
+        *self.flags.get_mut(sha).unwrap() |= TREESAME;
+        return Ok(vec![parent.clone()]);
+      }
+    }
+
+    if self.follow && node.parents.len() == 1 {
+      let parent = self.repo.commit_node(&node.parents[0])?;
+      self.follow_rename(&parent.tree, &node.tree)?;
+    }
+
+    Ok(node.parents.clone())
+  }
+
+  fn paths_changed(&self, old: Option<&Sha>, new: Option<&Sha>) -> Result<bool> {
+    for path in &self.paths {
+      let components = path.iter().collect::<Vec<_>>();
+
+      if self.path_changed(old.cloned(), new.cloned(), &components)? {
+        return Ok(true);
+      }
+    }
+
+    Ok(false)
+  }
+
+  // Walk down both trees along the path, stopping as soon as the two sides
+  // have the same sha (which, for subtrees, means everything under them is the
+  // same, too).
+  fn path_changed(
+    &self,
+    old: Option<Sha>,
+    new: Option<Sha>,
+    components: &[&OsStr],
+  ) -> Result<bool> {
+    if old == new {
+      return Ok(false);
+    }
+
+    let (first, rest) = match components.split_first() {
+      Some(split) => split,
+      None => return Ok(true),
+    };
+
+    let lookup = |tree: &Option<Sha>| match tree {
+      Some(sha) => self.entry_in(sha, Path::new(first)),
+      None => Ok(None),
+    };
+
+    let (a, b) = (lookup(&old)?, lookup(&new)?);
+
+    if let (Some(a), Some(b)) = (&a, &b) {
+      if a.sha == b.sha && a.mode == b.mode {
+        return Ok(false);
+      }
+    }
+
+    if a.is_none() && b.is_none() {
+      return Ok(false);
+    }
+
+    if rest.is_empty() {
+      return Ok(true);
+    }
+
+    let subtree = |e: Option<PathEntry>| e.filter(|e| e.is_tree()).map(|e| e.sha);
+    self.path_changed(subtree(a), subtree(b), rest)
+  }
+
+  // The entry with this name directly inside a tree.
+  fn entry_in(&self, tree: &Sha, name: &Path) -> Result<Option<PathEntry>> {
+    let tree = self.repo.object_for_sha(tree)?.as_tree()?;
+
+    let found = tree.entries().find_map(|(_, item)| match item {
+      TreeItem::Entry(e) if e.path == name => Some(e.clone()),
+      _ => None,
+    });
+
+    Ok(found)
+  }
+
+  // If the path we're following was added between these two trees, see if it
+  // came from a file that went away at the same time, and follow that instead.
+  fn follow_rename(&mut self, old: &Sha, new: &Sha) -> Result<()> {
+    let path = self.paths[0].clone();
+    let diff = self.repo.diff_trees(Some(old), Some(new))?;
+
+    let added = match diff.get(path.as_os_str()) {
+      Some((None, Some(entry))) => entry.clone(),
+      _ => return Ok(()),
+    };
+
+    let deleted = diff
+      .values()
+      .filter_map(|(old, new)| match (old, new) {
+        (Some(old), None) => Some(old),
+        _ => None,
+      })
+      .collect::<Vec<_>>();
+
+    // an exact copy is the best we can do; otherwise, whatever's most alike
+    let exact = deleted.iter().find(|e| e.sha == added.sha);
+
+    let source = match exact {
+      Some(entry) => Some(entry.path.clone()),
+      None => {
+        let content = self.blob_text(&added.sha)?;
+        let mut best = None;
+
+        for entry in deleted {
+          let score =
+            diff::similarity(self.blob_text(&entry.sha)?, content.clone());
+
+          if score >= RENAME_THRESHOLD
+            && best.as_ref().is_none_or(|(s, _)| score > *s)
+          {
+            best = Some((score, entry.path.clone()));
+          }
+        }
+
+        best.map(|(_, path)| path)
+      },
+    };
+
+    if let Some(source) = source {
+      self.paths = vec![source];
+    }
+
+    Ok(())
+  }
+
+  fn blob_text(&self, sha: &Sha) -> Result<String> {
+    let (_, content) = self.repo.raw_object_for_sha(sha)?;
+    Ok(String::from_utf8_lossy(&content).to_string())
+  }
+
+  // Like git, we're done once everything left to look at is uninteresting,
+  // and has been for a few commits: dates can be skewed (or just the same), so
+  // an uninteresting commit can come off the queue after one it excludes. The
+  // commit we just took off had this date, and the slop we had left.
+  fn still_interesting(&self, date: i64, slop: usize) -> usize {
+    match self.queue.peek() {
+      None => 0,
+      Some(next) if date <= next.date => SLOP,
+      _ if self.queued == 0 => slop - 1,
+      _ => SLOP,
+    }
+  }
+
+  // When there are excluded commits, we have to walk far enough to know what
+  // they exclude before we can output anything; for topo order, we need the
+  // whole lot up front.
+  fn prepare(&mut self) -> Result<()> {
+    self.prepared = true;
+
+    if !self.limited && self.order == SortOrder::Date {
+      return Ok(());
+    }
+
+    let mut slop = SLOP;
+
+    while let Some(item) = self.pop() {
+      self.add_parents(&item.sha, &item.node)?;
+
+      if self.flag(&item.sha, UNINTERESTING) {
+        slop = self.still_interesting(item.date, slop);
+
+        if slop == 0 {
+          break;
+        }
+
+        continue;
+      }
+
+      let commit = self.repo.object_for_sha(&item.sha)?.as_commit()?;
+      self.output.push_back(commit);
+    }
+
+    // we might have found out about some of these after we'd added them
+    let flags = &self.flags;
+    self
+      .output
+      .retain(|c| flags[&c.sha()] & (UNINTERESTING | TREESAME) == 0);
+
+    if self.rewrite {
+      self.rewrite_output_parents();
+    }
+
+    if self.order == SortOrder::Topo {
+      self.sort_topo();
+    }
+
+    Ok(())
+  }
+
+  // Like git: count each commit's children, then start from the tips (in date
+  // order) and use a stack, so that lines of history stay together.
+  fn sort_topo(&mut self) {
+    let commits = std::mem::take(&mut self.output)
+      .into_iter()
+      .map(|c| (c.sha(), c))
+      .collect::<Vec<_>>();
+
+    let mut indegree: HashMap<Sha, usize> =
+      commits.iter().map(|(sha, _)| (sha.clone(), 0)).collect();
+
+    for (_, commit) in &commits {
+      for parent in &commit.parent_shas {
+        if let Some(n) = indegree.get_mut(parent) {
+          *n += 1;
+        }
+      }
+    }
+
+    let mut stack = commits
+      .iter()
+      .filter(|(sha, _)| indegree[sha] == 0)
+      .map(|(sha, _)| sha.clone())
+      .collect::<Vec<_>>();
+    stack.reverse();
+
+    let mut by_sha = commits.into_iter().collect::<HashMap<_, _>>();
+
+    while let Some(sha) = stack.pop() {
+      let commit = by_sha.remove(&sha).unwrap();
+
+      for parent in &commit.parent_shas {
+        if let Some(n) = indegree.get_mut(parent) {
+          *n -= 1;
+          if *n == 0 {
+            stack.push(parent.clone());
+          }
+        }
+      }
+
+      self.output.push_back(commit);
+    }
+  }
+
+  // Every tree and blob reachable from these commits (which should be ones
+  // we've yielded), with the path we first found each at, leaving out anything
+  // reachable from the excluded commits on the edge of the walk. Like git's,
+  // they come in the order we find them, depth first.
+  pub fn objects(&self, commits: &[Commit]) -> Result<Vec<(Sha, PathBuf)>> {
+    let mut seen = HashSet::new();
+
+    let mut edges = self.excluded.clone();
+    for commit in commits {
+      let parents = commit.parent_shas.iter();
+      edges.extend(parents.filter(|p| self.flag(p, UNINTERESTING)).cloned());
+    }
+
+    for sha in edges {
+      let tree = self.repo.commit_node(&sha)?.tree;
+      self.walk_tree(&tree, Path::new(""), &mut seen, &mut vec![])?;
+    }
+
+    let mut ret = vec![];
+    for commit in commits {
+      self.walk_tree(commit.tree(), Path::new(""), &mut seen, &mut ret)?;
+    }
+
+    Ok(ret)
+  }
+
+  fn walk_tree(
+    &self,
+    sha: &Sha,
+    path: &Path,
+    seen: &mut HashSet<Sha>,
+    found: &mut Vec<(Sha, PathBuf)>,
+  ) -> Result<()> {
+    if !seen.insert(sha.clone()) {
+      return Ok(());
+    }
+
+    found.push((sha.clone(), path.to_path_buf()));
+
+    let tree = self.repo.object_for_sha(sha)?.as_tree()?;
+
+    for (_, item) in tree.entries() {
+      if let TreeItem::Entry(e) = item {
+        let subpath = path.join(&e.path);
+
+        if e.is_tree() {
+          self.walk_tree(&e.sha, &subpath, seen, found)?;
+        } else if e.mode == Mode::Gitlink {
+          continue; // like git, we don't list submodules' commits
+        } else if seen.insert(e.sha.clone()) {
+          found.push((e.sha.clone(), subpath));
+        }
+      }
+    }
+
+    Ok(())
+  }
+
+  // Each parent we don't output gets replaced by its nearest ancestor we do
+  // output (if there is one). Anything we skipped has only one parent left
+  // after simplification, so there's only ever one line to follow.
+  fn rewrite_output_parents(&mut self) {
+    let mut output = std::mem::take(&mut self.output);
+    let shown = output.iter().map(|c| c.sha()).collect::<HashSet<_>>();
+
+    for commit in output.iter_mut() {
+      let mut rewritten: Vec<Sha> = vec![];
+
+      for parent in &commit.parent_shas {
+        let mut sha = parent.clone();
+
+        let found = loop {
+          if shown.contains(&sha) {
+            break Some(sha);
+          }
+
+          match self.parents.get(&sha).and_then(|p| p.first()) {
+            Some(next) if !self.flag(&sha, UNINTERESTING) => sha = next.clone(),
+            _ => break None,
+          }
+        };
+
+        if let Some(sha) = found {
+          if !rewritten.contains(&sha) {
+            rewritten.push(sha);
+          }
+        }
+      }
+
+      commit.parent_shas = rewritten;
+    }
+
+    self.output = output;
+  }
+
+  fn next_commit(&mut self) -> Result<Option<Commit>> {
+    if !self.prepared {
+      self.prepare()?;
+    }
+
+    if self.limited || self.order == SortOrder::Topo {
+      return Ok(self.output.pop_front());
+    }
+
+    while let Some(item) = self.pop() {
+      self.add_parents(&item.sha, &item.node)?;
+
+      if !self.flag(&item.sha, TREESAME) {
+        let commit = self.repo.object_for_sha(&item.sha)?.as_commit()?;
+        return Ok(Some(commit));
+      }
+    }
+
+    Ok(None)
+  }
+}
+
+impl Iterator for RevList<'_> {
+  type Item = Result<Commit>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    self.next_commit().transpose()
+  }
+}
+
+impl PartialEq for QueueItem {
+  fn eq(&self, other: &Self) -> bool {
+    self.cmp(other) == Ordering::Equal
+  }
+}
+
+impl Eq for QueueItem {}
+
+impl PartialOrd for QueueItem {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl Ord for QueueItem {
+  fn cmp(&self, other: &Self) -> Ordering {
+    (self.date, self.seq).cmp(&(other.date, other.seq))
+  }
+}
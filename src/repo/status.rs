@@ -2,8 +2,9 @@ use std::cell::RefMut;
 use std::collections::BTreeMap;
 use std::ffi::OsString;
 use std::fs::Metadata;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
+use crate::ignore::IgnoreList;
 use crate::index::Index;
 use crate::object::{PathEntry, TreeItem};
 use crate::prelude::*;
@@ -17,6 +18,7 @@ struct InnerStatus<'r> {
   untracked:      BTreeMap<OsString, ChangeType>,
   index_diff:     BTreeMap<OsString, ChangeType>,
   workspace_diff: BTreeMap<OsString, ChangeType>,
+  conflicts:      BTreeMap<OsString, ConflictType>,
   head_diff:      BTreeMap<OsString, PathEntry>,
 }
 
@@ -26,6 +28,7 @@ pub struct Status {
   untracked:      BTreeMap<OsString, ChangeType>,
   index_diff:     BTreeMap<OsString, ChangeType>,
   workspace_diff: BTreeMap<OsString, ChangeType>,
+  conflicts:      BTreeMap<OsString, ConflictType>,
   head_diff:      BTreeMap<OsString, PathEntry>,
 }
 
@@ -38,6 +41,19 @@ pub enum ChangeType {
   Untracked,
 }
 
+// For unmerged paths, which sides of the merge had them (from the stages
+// present in the index).
+#[derive(Debug, Eq, PartialEq, Hash)]
+pub enum ConflictType {
+  BothModified,
+  BothAdded,
+  BothDeleted,
+  DeletedByUs,
+  DeletedByThem,
+  AddedByUs,
+  AddedByThem,
+}
+
 impl Status {
   pub fn generate(repo: &Repository) -> Result<Self> {
     let mut helper = InnerStatus::new(repo);
@@ -46,6 +62,7 @@ impl Status {
       untracked:      helper.untracked,
       index_diff:     helper.index_diff,
       workspace_diff: helper.workspace_diff,
+      conflicts:      helper.conflicts,
       head_diff:      helper.head_diff,
       stats:          helper.stats,
     })
@@ -58,7 +75,7 @@ impl Status {
   }
 
   pub fn has_untracked_changes(&self) -> bool {
-    self.untracked.len() > 0
+    !self.untracked.is_empty()
   }
 
   pub fn index_diff(&self) -> &BTreeMap<OsString, ChangeType> {
@@ -66,7 +83,7 @@ impl Status {
   }
 
   pub fn has_index_changes(&self) -> bool {
-    self.index_diff.len() > 0
+    !self.index_diff.is_empty()
   }
 
   pub fn workspace_diff(&self) -> &BTreeMap<OsString, ChangeType> {
@@ -74,7 +91,15 @@ impl Status {
   }
 
   pub fn has_workspace_changes(&self) -> bool {
-    self.workspace_diff.len() > 0
+    !self.workspace_diff.is_empty()
+  }
+
+  pub fn conflicts(&self) -> &BTreeMap<OsString, ConflictType> {
+    &self.conflicts
+  }
+
+  pub fn has_conflicts(&self) -> bool {
+    !self.conflicts.is_empty()
   }
 
   pub fn head_diff(&self) -> &BTreeMap<OsString, PathEntry> {
@@ -96,15 +121,17 @@ impl<'r> InnerStatus<'r> {
       head_diff: BTreeMap::new(),
       index_diff: BTreeMap::new(),
       workspace_diff: BTreeMap::new(),
+      conflicts: BTreeMap::new(),
     }
   }
 
   pub fn check(&mut self) -> Result<()> {
     let workspace = self.repo.workspace();
+    let mut ignore = workspace.ignore_list()?;
 
-    self.scan_workspace(workspace.root())?;
+    self.scan_workspace(workspace.root(), &mut ignore)?;
     self.load_head()?;
-    self.detect_changes();
+    self.detect_changes()?;
 
     // update the index, in case any of the stats have changed
     self.index.write()?;
@@ -112,18 +139,35 @@ impl<'r> InnerStatus<'r> {
     Ok(())
   }
 
-  fn scan_workspace(&mut self, base: &PathBuf) -> Result<()> {
+  fn scan_workspace(
+    &mut self,
+    base: &PathBuf,
+    ignore: &mut IgnoreList,
+  ) -> Result<()> {
     let ws = self.repo.workspace();
-    for (path_str, stat) in ws.list_dir(base)? {
+
+    // ignored files still matter if they're tracked
+    for (path_str, stat) in ws.list_dir_all(base)? {
       let is_dir = stat.is_dir();
 
+      // looking inside a subdirectory moves the ignore list down into it
+      let dir = Path::new(&path_str)
+        .parent()
+        .unwrap_or_else(|| Path::new(""));
+      ignore.enter_dir(ws.root(), dir)?;
+
       if self.index.is_tracked(&path_str) {
         if is_dir {
-          self.scan_workspace(&ws.canonicalize(&path_str))?;
+          self.scan_workspace(&ws.canonicalize(&path_str), ignore)?;
         } else if stat.is_file() {
           self.stats.insert(path_str.clone(), stat.clone());
         }
-      } else if self.is_trackable(&path_str, &stat) {
+      } else if ignore
+        .matching_with_parents(path_str.as_ref(), is_dir)
+        .is_some_and(|p| !p.negated)
+      {
+        continue;
+      } else if self.is_trackable(&path_str, &stat, ignore)? {
         let suffix = if is_dir {
           std::path::MAIN_SEPARATOR.to_string()
         } else {
@@ -141,29 +185,51 @@ impl<'r> InnerStatus<'r> {
   }
 
   // a path is trackable iff it contains a file somewhere inside it.
-  fn is_trackable(&self, path: &OsString, stat: &Metadata) -> bool {
+  fn is_trackable(
+    &self,
+    path: &OsString,
+    stat: &Metadata,
+    ignore: &mut IgnoreList,
+  ) -> Result<bool> {
     if stat.is_file() {
-      return !self.index.is_tracked(&path);
+      return Ok(!self.index.is_tracked(path));
     }
 
     if !stat.is_dir() {
-      return false;
+      return Ok(false);
     }
 
     let ws = self.repo.workspace();
 
-    ws.list_dir(&path.into())
-      .expect(&format!("could not list dir {:?}", path))
-      .iter()
-      .any(|(path, stat)| self.is_trackable(path, stat))
+    for (path, stat) in ws.list_dir(&path.into(), ignore)? {
+      if self.is_trackable(&path, &stat, ignore)? {
+        return Ok(true);
+      }
+    }
+
+    Ok(false)
   }
 
-  fn detect_changes(&mut self) {
-    self.check_index();
+  fn detect_changes(&mut self) -> Result<()> {
+    self.check_index()?;
     self.check_head();
+    self.check_conflicts()
+  }
+
+  fn check_conflicts(&mut self) -> Result<()> {
+    for (path, entries) in self.index.unmerged() {
+      let stages = entries.iter().map(|e| e.stage()).collect::<Vec<_>>();
+      self
+        .conflicts
+        .insert(path.clone(), ConflictType::from_stages(&stages)?);
+    }
+
+    Ok(())
   }
 
-  fn check_index(&mut self) {
+  fn check_index(&mut self) -> Result<()> {
+    let attrs = self.repo.workspace().attributes()?;
+
     // Check the working tree: for every file in the index, if our stat is
     // different than it, it's changed.
     for entry in self.index.entries_mut() {
@@ -191,11 +257,7 @@ impl<'r> InnerStatus<'r> {
       }
 
       // Check the content
-      let sha = util::compute_sha_for_path(
-        &self.repo.workspace().canonicalize(path),
-        Some(stat),
-      )
-      .expect("could not calculate sha");
+      let sha = self.repo.workspace().read_blob(path, &attrs)?.sha();
 
       if sha == entry.sha {
         // if we've gotten here, we know the index stat time is stale
@@ -207,6 +269,8 @@ impl<'r> InnerStatus<'r> {
           .insert(path.clone(), ChangeType::Modified);
       }
     }
+
+    Ok(())
   }
 
   fn check_head(&mut self) {
@@ -236,19 +300,17 @@ impl<'r> InnerStatus<'r> {
   }
 
   fn load_head(&mut self) -> Result<()> {
-    let head = self.repo.head();
-    if head.is_none() {
-      return Ok(());
-    }
+    let head = match self.repo.head()? {
+      Some(head) => head,
+      None => return Ok(()),
+    };
 
-    let head = head.unwrap();
-    let tree = head.tree();
-    self.read_tree(&tree, &PathBuf::from(""))?;
+    self.read_tree(head.tree(), &PathBuf::from(""))?;
 
     Ok(())
   }
 
-  fn read_tree(&mut self, sha: &Sha, prefix: &PathBuf) -> Result<()> {
+  fn read_tree(&mut self, sha: &Sha, prefix: &Path) -> Result<()> {
     let tree = self.repo.object_for_sha(sha)?.as_tree()?;
 
     for (path, entry) in tree.entries() {
@@ -285,3 +347,47 @@ impl ChangeType {
     }
   }
 }
+
+impl ConflictType {
+  // Which of base (1), ours (2) and theirs (3) are in the index says what
+  // happened; git writes every one of these, so anything else is corrupt.
+  pub fn from_stages(stages: &[u8]) -> Result<Self> {
+    match stages {
+      [1, 2, 3] => Ok(Self::BothModified),
+      [2, 3] => Ok(Self::BothAdded),
+      [1, 2] => Ok(Self::DeletedByThem),
+      [1, 3] => Ok(Self::DeletedByUs),
+      [1] => Ok(Self::BothDeleted),
+      [2] => Ok(Self::AddedByUs),
+      [3] => Ok(Self::AddedByThem),
+      _ => Err(PidgitError::Index(format!(
+        "impossible set of conflict stages: {:?}",
+        stages
+      ))),
+    }
+  }
+
+  pub fn display(&self) -> &'static str {
+    match self {
+      Self::BothModified => "UU",
+      Self::BothAdded => "AA",
+      Self::BothDeleted => "DD",
+      Self::DeletedByUs => "DU",
+      Self::DeletedByThem => "UD",
+      Self::AddedByUs => "AU",
+      Self::AddedByThem => "UA",
+    }
+  }
+
+  pub fn long_display(&self) -> &'static str {
+    match self {
+      Self::BothModified => "both modified",
+      Self::BothAdded => "both added",
+      Self::BothDeleted => "both deleted",
+      Self::DeletedByUs => "deleted by us",
+      Self::DeletedByThem => "deleted by them",
+      Self::AddedByUs => "added by us",
+      Self::AddedByThem => "added by them",
+    }
+  }
+}
@@ -7,6 +7,7 @@ pub use predicates::prelude::*;
 pub use serial_test::serial;
 
 use super::repo::Repository;
+use super::util::Sha;
 use std::io::Cursor;
 
 // this is just so that the tempdir won't be dropped before the repo is
@@ -31,6 +32,37 @@ pub fn new_empty_repo() -> TestRepo {
   TestRepo { dir, repo }
 }
 
+// Builds this, where each commit's message is its name, and they were made a
+// minute apart in the order A, B, D, E, C:
+//
+//   A - B - C     (main)
+//        \
+//         D - E   (topic)
+pub fn new_with_topic_history() -> TestRepo {
+  let tr = new_empty_repo();
+
+  tr.commit_file_at("A", 0);
+  tr.commit_file_at("B", 1);
+  tr.run_pidgit(vec!["switch", "-c", "topic"]).unwrap();
+  tr.commit_file_at("D", 2);
+  tr.commit_file_at("E", 3);
+  tr.run_pidgit(vec!["switch", "main"]).unwrap();
+  tr.commit_file_at("C", 4);
+
+  tr
+}
+
+// The same, with topic merged into main as F, which is made now.
+//
+//   A - B - C ----- F   (main)
+//        \         /
+//         D ----- E     (topic)
+pub fn new_with_merge() -> TestRepo {
+  let tr = new_with_topic_history();
+  tr.run_pidgit(vec!["merge", "topic", "-m", "F"]).unwrap();
+  tr
+}
+
 impl TestRepo {
   pub fn run_pidgit(&self, args: Vec<&str>) -> Result<String> {
     let mut app = pidgit::new();
@@ -50,24 +82,24 @@ impl TestRepo {
       .dir
       .child(filename)
       .write_str(content)
-      .expect(&format!("could not write file at {}", filename));
+      .unwrap_or_else(|_| panic!("could not write file at {}", filename));
   }
 
   pub fn mkdir(&self, path: &str) {
     let child = self.dir.child(path);
-    std::fs::create_dir_all(&child.path()).unwrap();
+    std::fs::create_dir_all(child.path()).unwrap();
     assert!(child.path().is_dir())
   }
 
   pub fn rm_file(&self, path: &str) {
     let child = self.dir.child(path);
-    std::fs::remove_file(&child.path()).expect("could not remove file");
+    std::fs::remove_file(child.path()).expect("could not remove file");
     assert!(!child.path().exists())
   }
 
   pub fn rm_rf(&self, path: &str) {
     let child = self.dir.child(path);
-    std::fs::remove_dir_all(&child.path()).expect("could not remove dir");
+    std::fs::remove_dir_all(child.path()).expect("could not remove dir");
     assert!(!child.path().exists())
   }
 
@@ -80,12 +112,59 @@ impl TestRepo {
     std::fs::set_permissions(path, perms).expect("could not chmod");
   }
 
+  // Write a loose object with whatever content we like, even something we
+  // can't parse, and return its sha.
+  pub fn write_raw_object(&self, kind: &str, content: &[u8]) -> Sha {
+    use flate2::{write::ZlibEncoder, Compression};
+    use std::io::Write;
+
+    let mut data = format!("{} {}\0", kind, content.len()).into_bytes();
+    data.extend(content);
+
+    let sha = Sha::from(sha1::Sha1::from(&data));
+    let path = self.repo.path_for_sha(&sha);
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+
+    let mut e = ZlibEncoder::new(
+      std::fs::File::create(path).unwrap(),
+      Compression::default(),
+    );
+    e.write_all(&data).unwrap();
+    e.finish().unwrap();
+
+    sha
+  }
+
   #[rustfmt::skip]
   pub fn commit_all(&self) {
     self.run_pidgit(vec!["add", "."]).expect("bad add");
     self.commit("auto commit message").expect("could not commit");
   }
 
+  // Commit a new file named after the commit, with the name as the message,
+  // and return the new HEAD.
+  pub fn commit_file(&self, name: &str) -> Sha {
+    self.write_file(&format!("{}.txt", name), name);
+    self.run_pidgit(vec!["add", "."]).expect("bad add");
+    self.commit(name).expect("could not commit");
+    self.repo.resolve_object("HEAD").unwrap().sha()
+  }
+
+  // Likewise, but made a fixed number of minutes after some day in 2020, for
+  // tests that care about the order of commits.
+  pub fn commit_file_at(&self, name: &str, minutes: i64) -> Sha {
+    use crate::object::{GitObject, Person};
+
+    self.write_file(&format!("{}.txt", name), name);
+    self.run_pidgit(vec!["add", "."]).expect("bad add");
+
+    let when = 1_600_000_000 + minutes * 60;
+    let ident = format!("Pidgit <pidgit@example.com> {} +0000", when);
+    let who = Person::from_ident(&ident).unwrap();
+    let commit = self.repo.commit(name, who.clone(), who);
+    commit.expect("could not commit").sha()
+  }
+
   pub fn commit(&self, message: &str) -> Result<()> {
     use crate::object::Person;
     use chrono::Local;
@@ -2,7 +2,9 @@ mod rev_parse;
 mod sha;
 mod wrapping_vec;
 
-pub use rev_parse::{is_valid_refname, resolve_revision};
+pub use rev_parse::{
+  is_valid_refname, resolve_range, resolve_revision, split_range, RevArg,
+};
 pub use sha::Sha;
 pub use wrapping_vec::WrappingVec;
 
@@ -10,6 +12,7 @@ use ansi_term::{ANSIGenericString, Style};
 use sha1::Sha1;
 use std::fs::Metadata;
 use std::path::{Path, PathBuf};
+use terminal_size::Width;
 
 use crate::prelude::*;
 
@@ -24,7 +27,7 @@ pub fn find_repo() -> Option<Repository> {
         eprintln!("{:?}", err);
         None
       },
-      |repo| Some(repo),
+      Some,
     );
   }
 
@@ -37,8 +40,7 @@ pub fn find_repo() -> Option<Repository> {
   let repo = pwd
     .unwrap()
     .ancestors()
-    .filter(|p| p.join(".pidgit").is_dir())
-    .next()
+    .find(|p| p.join(".pidgit").is_dir())
     .map(|p| Repository::from_work_tree(p).unwrap());
 
   repo
@@ -61,7 +63,7 @@ pub fn compute_sha_for_path(path: &Path, meta: Option<&Metadata>) -> Result<Sha>
   use std::fs::File;
   use std::io::{BufRead, BufReader};
 
-  let mut reader = BufReader::new(File::open(&path)?);
+  let mut reader = BufReader::new(File::open(path)?);
   let mut sha = Sha1::new();
   let len = match meta {
     Some(meta) => meta.len(),
@@ -79,13 +81,30 @@ pub fn compute_sha_for_path(path: &Path, meta: Option<&Metadata>) -> Result<Sha>
       break;
     }
 
-    sha.update(&buf);
+    sha.update(buf);
     reader.consume(len);
   }
 
   Ok(sha.into())
 }
 
+// How wide the terminal is, the way git works it out: $COLUMNS if that's set,
+// or whatever the terminal on stdout says, or 80 if that isn't one.
+pub fn term_columns() -> usize {
+  if let Some(n) = std::env::var("COLUMNS")
+    .ok()
+    .and_then(|s| s.parse::<usize>().ok())
+    .filter(|&n| n > 0)
+  {
+    return n;
+  }
+
+  match terminal_size::terminal_size() {
+    Some((Width(w), _)) if w > 0 => w as usize,
+    _ => 80,
+  }
+}
+
 fn should_color() -> bool {
   use atty::Stream;
 
@@ -96,7 +115,7 @@ fn should_color() -> bool {
   atty::is(Stream::Stdout)
 }
 
-pub fn colored(s: &str, style: Style) -> ANSIGenericString<str> {
+pub fn colored(s: &str, style: Style) -> ANSIGenericString<'_, str> {
   if should_color() {
     style.paint(s)
   } else {
@@ -1,7 +1,9 @@
+use regex::Regex;
 use std::collections::HashSet;
 
-use crate::object::Object;
+use crate::object::{Object, TreeItem};
 use crate::prelude::*;
+use crate::repo::RevList;
 
 // docs from git-check-ref-name
 // 1.  They can include slash / for hierarchical (directory) grouping, but no
@@ -65,72 +67,473 @@ fn is_valid_refname_allow_at(s: &str) -> bool {
   }
 }
 
-#[allow(unused)]
 #[derive(Debug, PartialEq, Eq)]
 enum Revision {
   Ref(String),
   Parent(Box<Revision>),
+  NthParent(Box<Revision>, usize), // rev^0 is the commit itself
   Ancestor(Box<Revision>, u32),
+  Reflog(String, usize), // an empty name means the current branch
+  Upstream(String),      // likewise
+  PreviousBranch(usize), // @{-N}
+  Peel(Box<Revision>, Option<String>), // None for ^{}
+  Path(Box<Revision>, String),
+  Index(u8, String), // :path, or :stage:path
+  MessageSearch(String),
+}
+
+// Find the first character matching pred that isn't inside {braces}, since
+// things like @{-1} and ^{tree} can contain almost anything.
+fn find_outside_braces<F>(s: &str, pred: F) -> Option<usize>
+where
+  F: Fn(char) -> bool,
+{
+  let mut depth = 0;
+
+  for (i, c) in s.char_indices() {
+    match c {
+      '{' => depth += 1,
+      '}' => depth -= 1,
+      c if depth == 0 && pred(c) => return Some(i),
+      _ => (),
+    }
+  }
+
+  None
 }
 
-#[allow(unused)]
 // Parse a revision into an AST (a Revision enum). These boxes are kind of
 // annoying, but such is life.
 fn parse_rev(revision: &str) -> Option<Revision> {
-  if revision.ends_with('^') {
-    let rev = parse_rev(&revision[0..revision.len() - 1]);
-    rev.map(|parent| Revision::Parent(Box::new(parent)))
-  } else if revision.contains('~') {
-    // regex would really be better here
-    let hunks = revision.split('~').take(2).collect::<Vec<_>>();
-    let n = if let Ok(num) = hunks[1].parse::<u32>() {
-      num
-    } else {
+  // these take over the whole string, so they come first
+  if let Some(pattern) = revision.strip_prefix(":/") {
+    return Some(Revision::MessageSearch(pattern.to_string()));
+  }
+
+  if let Some(rest) = revision.strip_prefix(':') {
+    let (stage, path) = match rest.as_bytes() {
+      [n @ b'0'..=b'3', b':', ..] => (n - b'0', &rest[2..]),
+      _ => (0, rest),
+    };
+
+    return Some(Revision::Index(stage, path.to_string()));
+  }
+
+  if let Some(colon) = find_outside_braces(revision, |c| c == ':') {
+    let (rev, path) = (&revision[..colon], &revision[colon + 1..]);
+    return parse_rev(rev).map(|rev| Revision::Path(Box::new(rev), path.into()));
+  }
+
+  // a name, followed by any number of ^ and ~ suffixes
+  let split = find_outside_braces(revision, |c| c == '^' || c == '~')
+    .unwrap_or(revision.len());
+
+  let (base, mut rest) = revision.split_at(split);
+  let mut rev = parse_base(base)?;
+
+  while !rest.is_empty() {
+    let op = rest.chars().next().unwrap();
+    rest = &rest[1..];
+
+    // peeling, like ^{tree}
+    if op == '^' && rest.starts_with('{') {
+      let close = rest.find('}')?;
+      let kind = &rest[1..close];
+      rest = &rest[close + 1..];
+
+      let kind = match kind {
+        "" => None,
+        "commit" | "tree" | "blob" | "tag" => Some(kind.to_string()),
+        _ => return None,
+      };
+
+      rev = Revision::Peel(Box::new(rev), kind);
+      continue;
+    }
+
+    let digits = rest
+      .find(|c: char| !c.is_ascii_digit())
+      .unwrap_or(rest.len());
+    let (num, remainder) = rest.split_at(digits);
+    rest = remainder;
+
+    // after the number, we need another suffix (or nothing)
+    if !rest.is_empty() && !rest.starts_with(&['^', '~'][..]) {
       return None;
+    }
+
+    rev = match (op, num) {
+      ('^', "") => Revision::Parent(Box::new(rev)),
+      ('^', n) => Revision::NthParent(Box::new(rev), n.parse().ok()?),
+      ('~', "") => Revision::Ancestor(Box::new(rev), 1),
+      ('~', n) => Revision::Ancestor(Box::new(rev), n.parse().ok()?),
+      _ => unreachable!(),
+    };
+  }
+
+  Some(rev)
+}
+
+// The part of a revision before any suffixes: a name, maybe with @{something}.
+fn parse_base(base: &str) -> Option<Revision> {
+  if let Some(n) = base.strip_prefix("@{-").and_then(|s| s.strip_suffix('}')) {
+    return n.parse().ok().map(Revision::PreviousBranch);
+  }
+
+  if base.ends_with('}') && base.contains("@{") {
+    let at = base.rfind("@{").unwrap();
+    let (name, rest) = base.split_at(at);
+    let inner = &rest[2..rest.len() - 1];
+
+    if !name.is_empty() && !is_valid_refname(name) {
+      return None;
+    }
+
+    return match inner {
+      "upstream" | "u" => Some(Revision::Upstream(name.to_string())),
+      _ => inner
+        .parse::<usize>()
+        .ok()
+        .map(|n| Revision::Reflog(name.to_string(), n)),
     };
+  }
 
-    parse_rev(hunks[0]).map(|ancestor| Revision::Ancestor(Box::new(ancestor), n))
-  } else if is_valid_refname_allow_at(revision) {
-    let name = if revision == "@" { "HEAD" } else { revision };
+  if is_valid_refname_allow_at(base) {
+    let name = if base == "@" { "HEAD" } else { base };
     Some(Revision::Ref(name.to_string()))
   } else {
     None
   }
 }
 
-fn resolve_rev(revision: &Revision, repo: &Repository) -> Option<Object> {
+// Like ? for the Option half of a Result<Option<T>>: nothing there means
+// nothing to return.
+macro_rules! found {
+  ($e:expr) => {
+    match $e {
+      Some(val) => val,
+      None => return Ok(None),
+    }
+  };
+}
+
+// A missing object or ref just means this revision doesn't resolve; anything
+// else (an object we can't parse, say) is a real error.
+fn not_found<T>(res: Result<T>) -> Result<Option<T>> {
+  match res {
+    Ok(val) => Ok(Some(val)),
+    Err(PidgitError::ObjectNotFound(_))
+    | Err(PidgitError::RefNotFound(_))
+    | Err(PidgitError::InvalidRefName(_)) => Ok(None),
+    Err(err) => Err(err),
+  }
+}
+
+fn load(repo: &Repository, sha: &Sha) -> Result<Option<Object>> {
+  not_found(repo.object_for_sha(sha))
+}
+
+fn resolve_rev(revision: &Revision, repo: &Repository) -> Result<Option<Object>> {
   match revision {
-    Revision::Ref(refname) => repo
-      .resolve_ref(refname)
-      .or_else(|_| repo.resolve_sha(refname))
-      .ok()
-      .and_then(|obj| Some(obj)),
-    Revision::Parent(rev) => resolve_rev(rev, repo).and_then(|obj| match obj {
-      Object::Commit(commit) => commit.parent(repo).map(|c| Object::Commit(c)),
-      _ => None,
-    }),
+    Revision::Ref(refname) => match not_found(repo.resolve_ref(refname))? {
+      Some(obj) => Ok(Some(obj)),
+      None => not_found(repo.resolve_sha(refname)),
+    },
+    Revision::Parent(rev) => nth_parent(repo, found!(resolve_rev(rev, repo)?), 1),
+    Revision::NthParent(rev, n) => {
+      nth_parent(repo, found!(resolve_rev(rev, repo)?), *n)
+    },
     Revision::Ancestor(ref rev, mut n) => {
-      resolve_rev(rev, repo).and_then(|obj| {
-        let mut commit = match obj {
-          Object::Commit(commit) => Some(commit),
-          _ => None,
-        };
-
-        while commit.is_some() && n > 0 {
-          commit = commit.unwrap().parent(repo);
-          n -= 1;
-        }
-
-        commit.map(|c| Object::Commit(c))
-      })
+      let obj = found!(resolve_rev(rev, repo)?);
+      let mut commit = found!(peel(repo, obj, Some("commit"))?);
+
+      while n > 0 {
+        commit = found!(nth_parent(repo, commit, 1)?);
+        n -= 1;
+      }
+
+      Ok(Some(commit))
+    },
+    Revision::Reflog(name, n) => {
+      let grefs = repo.grefs();
+
+      let full_name = if name.is_empty() {
+        found!(grefs.current_branch().ok())
+      } else {
+        found!(grefs.path_for_name(name))
+      };
+
+      let reflog = found!(grefs.reflog(&full_name).ok());
+      let entry = found!(reflog.into_iter().rev().nth(*n));
+      load(repo, &entry.new)
+    },
+    Revision::Upstream(name) => {
+      let upstream = found!(upstream_for(repo, name));
+      not_found(repo.resolve_ref(&upstream))
+    },
+    Revision::PreviousBranch(n) => {
+      let name = found!(previous_branch(repo, *n));
+      resolve_revision(&name, repo)
+    },
+    Revision::Peel(rev, kind) => {
+      peel(repo, found!(resolve_rev(rev, repo)?), kind.as_deref())
     },
+    Revision::Path(rev, path) => {
+      let obj = found!(resolve_rev(rev, repo)?);
+      let tree = found!(peel(repo, obj, Some("tree"))?);
+      lookup_path(repo, tree, path)
+    },
+    Revision::Index(stage, path) => {
+      let index = repo.index();
+      let key = std::ffi::OsStr::new(path);
+
+      let entry = if *stage == 0 {
+        index.entry_for(key)
+      } else {
+        index
+          .unmerged()
+          .find(|(k, _)| k.as_os_str() == key)
+          .and_then(|(_, entries)| entries.iter().find(|e| e.stage() == *stage))
+      };
+
+      load(repo, &found!(entry).sha)
+    },
+    Revision::MessageSearch(pattern) => search_messages(repo, pattern),
+  }
+}
+
+fn nth_parent(
+  repo: &Repository,
+  obj: Object,
+  n: usize,
+) -> Result<Option<Object>> {
+  let commit = found!(peel(repo, obj, Some("commit"))?);
+
+  if n == 0 {
+    return Ok(Some(commit));
+  }
+
+  let commit = commit.as_commit()?;
+  let sha = found!(commit.parent_shas.get(n - 1));
+  load(repo, sha)
+}
+
+// Dig through tags (and, for trees, commits) until we find the kind of object
+// we're after. With no kind, we just get rid of any tags.
+fn peel(
+  repo: &Repository,
+  obj: Object,
+  kind: Option<&str>,
+) -> Result<Option<Object>> {
+  if kind == Some("tag") {
+    return match obj {
+      Object::Tag(_) => Ok(Some(obj)),
+      _ => Ok(None),
+    };
+  }
+
+  let mut obj = obj;
+  while let Object::Tag(tag) = obj {
+    obj = found!(load(repo, &tag.object)?);
+  }
+
+  match (kind, obj) {
+    (None, obj) => Ok(Some(obj)),
+    (Some("commit"), obj @ Object::Commit(_)) => Ok(Some(obj)),
+    (Some("blob"), obj @ Object::Blob(_)) => Ok(Some(obj)),
+    (Some("tree"), obj @ Object::Tree(_)) => Ok(Some(obj)),
+    (Some("tree"), Object::Commit(commit)) => load(repo, commit.tree()),
+    _ => Ok(None),
+  }
+}
+
+// The object at this path inside a tree (or the tree itself, for an empty
+// path).
+fn lookup_path(
+  repo: &Repository,
+  tree: Object,
+  path: &str,
+) -> Result<Option<Object>> {
+  let mut obj = tree;
+
+  for name in path.split('/').filter(|s| !s.is_empty()) {
+    let tree = found!(obj.as_tree().ok());
+
+    let sha = found!(tree.entries().find_map(|(_, item)| match item {
+      TreeItem::Entry(e) if e.path.as_os_str() == name => Some(e.sha.clone()),
+      _ => None,
+    }));
+
+    obj = found!(load(repo, &sha)?);
+  }
+
+  Ok(Some(obj))
+}
+
+// The youngest commit reachable from any ref whose message matches.
+fn search_messages(repo: &Repository, pattern: &str) -> Result<Option<Object>> {
+  let re = found!(Regex::new(pattern).ok());
+  let mut revs = RevList::new(repo);
+
+  let refs = repo.grefs().all_refs()?;
+  let head = repo.grefs().resolve("HEAD").ok();
+
+  for sha in refs.into_iter().map(|(_, sha)| sha).chain(head) {
+    let sha = repo.peel_tag(&sha).unwrap_or(sha);
+
+    if let Some(Object::Commit(_)) = load(repo, &sha)? {
+      revs.include(&sha)?;
+    }
+  }
+
+  for commit in revs {
+    let commit = commit?;
+
+    if re.is_match(&commit.message) {
+      return Ok(Some(Object::Commit(commit)));
+    }
+  }
+
+  Ok(None)
+}
+
+// The full name of the ref this branch is tracking, from its branch.*.remote
+// and branch.*.merge config. An empty name means the current branch.
+fn upstream_for(repo: &Repository, name: &str) -> Option<String> {
+  let full = if name.is_empty() {
+    repo.grefs().current_branch().ok()?
+  } else {
+    repo.grefs().path_for_name(name)?
+  };
+
+  let branch = full.strip_prefix("refs/heads/")?;
+  let config = repo.config().ok()?;
+  let remote = config.get(&format!("branch.{}.remote", branch))?;
+  let merge = config.get(&format!("branch.{}.merge", branch))?;
+
+  // a remote of "." means another local branch
+  if remote == "." {
+    return Some(merge.to_string());
   }
+
+  let merged = merge.strip_prefix("refs/heads/").unwrap_or(merge);
+  Some(format!("refs/remotes/{}/{}", remote, merged))
+}
+
+// The branch (or commit) we were on before the nth-last checkout.
+fn previous_branch(repo: &Repository, n: usize) -> Option<String> {
+  let reflog = repo.grefs().reflog("HEAD").ok()?;
+
+  reflog
+    .iter()
+    .rev()
+    .filter_map(|e| e.message.strip_prefix("checkout: moving from "))
+    .nth(n.checked_sub(1)?)
+    .and_then(|m| m.split(" to ").next())
+    .map(String::from)
 }
 
 // This is here, rather than in the repo impl, so that we don't have to leak the
 // Revision enum, which isn't generally useful elsewhere.
-pub fn resolve_revision(revstr: &str, repo: &Repository) -> Option<Object> {
-  parse_rev(revstr).and_then(|rev| resolve_rev(&rev, repo))
+pub fn resolve_revision(
+  revstr: &str,
+  repo: &Repository,
+) -> Result<Option<Object>> {
+  match parse_rev(revstr) {
+    Some(rev) => resolve_rev(&rev, repo),
+    None => Ok(None),
+  }
+}
+
+// What one revision argument means for a walk, once any range syntax has been
+// expanded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RevArg {
+  Include(Sha),
+  Exclude(Sha),
+  Left(Sha), // included, from the left side of A...B
+}
+
+impl RevArg {
+  pub fn sha(&self) -> &Sha {
+    match self {
+      RevArg::Include(sha) | RevArg::Exclude(sha) | RevArg::Left(sha) => sha,
+    }
+  }
+
+  // what it means after --not
+  pub fn negate(self) -> Self {
+    match self {
+      RevArg::Include(sha) | RevArg::Left(sha) => RevArg::Exclude(sha),
+      RevArg::Exclude(sha) => RevArg::Include(sha),
+    }
+  }
+}
+
+// Split A..B or A...B into its sides, and whether it was the symmetric one.
+// The dots have to come before any colon, so that rev:path can have dots in
+// its path (and :/message in its message).
+pub fn split_range(revstr: &str) -> Option<(&str, &str, bool)> {
+  let end = find_outside_braces(revstr, |c| c == ':').unwrap_or(revstr.len());
+  let dots = revstr[..end].find("..")?;
+
+  match revstr[dots..].strip_prefix("...") {
+    Some(right) => Some((&revstr[..dots], right, true)),
+    None => Some((&revstr[..dots], &revstr[dots + 2..], false)),
+  }
+}
+
+// Expand a revision that might be a range: A..B, A...B (either side of which
+// defaults to HEAD), ^A, B^@ (all of B's parents), or B^! (B, but none of its
+// parents). Anything else is a single object to include.
+pub fn resolve_range(revstr: &str, repo: &Repository) -> Result<Vec<RevArg>> {
+  let commit = |name: &str| -> Result<Sha> {
+    let name = if name.is_empty() { "HEAD" } else { name };
+    let obj = peel(repo, repo.resolve_object(name)?, Some("commit"))?
+      .ok_or_else(|| PidgitError::ObjectNotFound(name.into()))?;
+
+    Ok(obj.sha())
+  };
+
+  if let Some((left, right, true)) = split_range(revstr) {
+    let (left, right) = (commit(left)?, commit(right)?);
+
+    let mut ret =
+      vec![RevArg::Include(right.clone()), RevArg::Left(left.clone())];
+    for base in repo.merge_bases(&left, &right)? {
+      ret.push(RevArg::Exclude(base));
+    }
+
+    return Ok(ret);
+  }
+
+  if let Some((from, to, false)) = split_range(revstr) {
+    return Ok(vec![
+      RevArg::Include(commit(to)?),
+      RevArg::Exclude(commit(from)?),
+    ]);
+  }
+
+  let parents = |name: &str| -> Result<Vec<Sha>> {
+    let sha = commit(name)?;
+    Ok(repo.object_for_sha(&sha)?.as_commit()?.parent_shas)
+  };
+
+  if let Some(name) = revstr.strip_suffix("^@") {
+    return Ok(parents(name)?.into_iter().map(RevArg::Include).collect());
+  }
+
+  if let Some(name) = revstr.strip_suffix("^!") {
+    let mut ret = vec![RevArg::Include(commit(name)?)];
+    ret.extend(parents(name)?.into_iter().map(RevArg::Exclude));
+    return Ok(ret);
+  }
+
+  if let Some(name) = revstr.strip_prefix('^') {
+    return Ok(vec![RevArg::Exclude(commit(name)?)]);
+  }
+
+  Ok(vec![RevArg::Include(repo.resolve_object(revstr)?.sha())])
 }
 
 #[cfg(test)]
@@ -211,8 +614,132 @@ mod tests {
       ))))
     );
 
+    assert_eq!(parse_rev("@{2}"), Some(Reflog("".into(), 2)));
+
+    assert_eq!(
+      parse_rev("main@{1}~2"),
+      Some(Ancestor(Box::new(Reflog("main".into(), 1)), 2))
+    );
+
     assert_eq!(parse_rev("/../foo^"), None);
-    assert_eq!(parse_rev("apple:pie~3"), None);
+    assert_eq!(parse_rev("main@{yesterday}"), None);
     assert_eq!(parse_rev("foo~banana"), None);
+    assert_eq!(parse_rev("foo^{banana}"), None);
+    assert_eq!(parse_rev("foo^2x"), None);
+  }
+
+  #[test]
+  fn parse_extended() {
+    use Revision::*;
+
+    let head = || Box::new(Ref("HEAD".into()));
+
+    assert_eq!(parse_rev("HEAD^2"), Some(NthParent(head(), 2)));
+    assert_eq!(parse_rev("HEAD~"), Some(Ancestor(head(), 1)));
+    assert_eq!(
+      parse_rev("HEAD^2~3"),
+      Some(Ancestor(Box::new(NthParent(head(), 2)), 3))
+    );
+
+    assert_eq!(parse_rev("HEAD^{}"), Some(Peel(head(), None)));
+    assert_eq!(
+      parse_rev("v1^{tree}"),
+      Some(Peel(Box::new(Ref("v1".into())), Some("tree".into())))
+    );
+
+    // the colon splits a rev from a path, and the path can be anything
+    assert_eq!(
+      parse_rev("apple:pie~3"),
+      Some(Path(Box::new(Ref("apple".into())), "pie~3".into()))
+    );
+    assert_eq!(
+      parse_rev("HEAD^{commit}:dir/file"),
+      Some(Path(
+        Box::new(Peel(head(), Some("commit".into()))),
+        "dir/file".into()
+      ))
+    );
+
+    assert_eq!(parse_rev(":file"), Some(Index(0, "file".into())));
+    assert_eq!(parse_rev(":3:dir/file"), Some(Index(3, "dir/file".into())));
+    assert_eq!(
+      parse_rev(":/fix: thing"),
+      Some(MessageSearch("fix: thing".into()))
+    );
+
+    assert_eq!(parse_rev("@{u}"), Some(Upstream("".into())));
+    assert_eq!(parse_rev("main@{upstream}"), Some(Upstream("main".into())));
+    assert_eq!(parse_rev("@{-1}"), Some(PreviousBranch(1)));
+    assert_eq!(
+      parse_rev("@{-2}~1"),
+      Some(Ancestor(Box::new(PreviousBranch(2)), 1))
+    );
+  }
+
+  #[test]
+  fn resolve() {
+    use crate::test_prelude::*;
+
+    let tr = new_empty_repo();
+    tr.write_file("dir/file.txt", "first\n");
+    tr.run_pidgit(vec!["add", "."]).unwrap();
+    tr.commit("first").unwrap();
+    tr.run_pidgit(vec!["switch", "-c", "topic"]).unwrap();
+
+    tr.write_file("dir/file.txt", "second\n");
+    tr.run_pidgit(vec!["add", "."]).unwrap();
+    tr.commit("second").unwrap();
+    tr.run_pidgit(vec!["switch", "main"]).unwrap();
+
+    let sha =
+      |rev: &str| resolve_revision(rev, &tr.repo).unwrap().map(|o| o.sha());
+    let blob = |rev: &str| {
+      resolve_revision(rev, &tr.repo).unwrap().map(|o| match o {
+        Object::Blob(b) => b.string_content(),
+        _ => panic!("not a blob"),
+      })
+    };
+
+    let main = sha("main").unwrap();
+    let topic = sha("topic").unwrap();
+    assert_eq!(sha("topic^"), Some(main.clone()));
+    assert_eq!(sha("topic^1"), Some(main.clone()));
+    assert_eq!(sha("topic^0"), Some(topic.clone()));
+    assert_eq!(sha("topic^2"), None);
+
+    let main_commit = tr.repo.object_for_sha(&main).unwrap().as_commit().unwrap();
+    assert_eq!(sha("main^{tree}"), Some(main_commit.tree().clone()));
+    assert_eq!(sha("main^{blob}"), None);
+
+    assert_eq!(blob("topic:dir/file.txt").unwrap(), "second\n");
+    assert_eq!(blob("main:dir/file.txt").unwrap(), "first\n");
+    assert_eq!(blob(":dir/file.txt").unwrap(), "first\n");
+    assert_eq!(sha("main:nope"), None);
+
+    // dots in a path (or a message) don't make it a range
+    tr.write_file("dir/dots..txt", "dots\n");
+    tr.run_pidgit(vec!["add", "."]).unwrap();
+    tr.commit("with..dots").unwrap();
+
+    let include = |rev: &str| {
+      let sha = resolve_revision(rev, &tr.repo).unwrap().unwrap().sha();
+      assert_eq!(
+        resolve_range(rev, &tr.repo).unwrap(),
+        [RevArg::Include(sha)]
+      );
+    };
+
+    include("main:dir/dots..txt");
+    include(":dir/dots..txt");
+    include(":/with..dots");
+
+    assert_eq!(split_range("a..b"), Some(("a", "b", false)));
+    assert_eq!(split_range("a...b"), Some(("a", "b", true)));
+    assert_eq!(split_range("..b"), Some(("", "b", false)));
+    assert_eq!(split_range("a:b..c"), None);
+
+    assert_eq!(sha(":/sec"), Some(topic.clone()));
+    assert_eq!(sha(":/^first"), Some(main));
+    assert_eq!(sha("@{-1}"), Some(topic));
   }
 }
@@ -1,5 +1,6 @@
 use sha1::Sha1 as Sha1Obj;
 use std::fmt;
+use std::hash::{Hash, Hasher};
 
 pub enum Sha {
   Object(Sha1Obj),
@@ -47,6 +48,12 @@ impl fmt::Display for Sha {
 
 impl Eq for Sha {}
 
+impl Hash for Sha {
+  fn hash<H: Hasher>(&self, state: &mut H) {
+    self.hexdigest().hash(state)
+  }
+}
+
 impl PartialEq for Sha {
   fn eq(&self, other: &Self) -> bool {
     self.hexdigest() == other.hexdigest()
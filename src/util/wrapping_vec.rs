@@ -45,6 +45,10 @@ impl<T: Default + Clone> WrappingVec<T> {
     self.size
   }
 
+  pub fn is_empty(&self) -> bool {
+    self.size == 0
+  }
+
   pub fn get(&self, idx: usize) -> Option<&T> {
     self.storage.get(idx)
   }
@@ -52,7 +56,7 @@ impl<T: Default + Clone> WrappingVec<T> {
   // take ownership of an element, replacing it with the default
   pub fn take(&mut self, idx: usize) -> Option<T> {
     if self.get(idx).is_some() {
-      let el = std::mem::replace(&mut self.storage[idx], Default::default());
+      let el = std::mem::take(&mut self.storage[idx]);
       Some(el)
     } else {
       None